@@ -0,0 +1,32 @@
+//! Statistics of Postgres databases, the ones the `psql_stats` program shows, for other Rust programs to collect
+//! without running the program. <br>
+//! A `Connection` holds the connection parameters and, once `connect` succeeded, the session. Its methods run the
+//! statistics queries, e.g. `get_uptime`, `get_activity` or `collect` with one of the collectors `find_collector`
+//! finds by name, and return what they found as typed values or as JSON rows, failing with a `PGError`. Connecting
+//! prints why it failed on stderr and leaves the `Connection` unconnected, so its queries fail with
+//! `PGError::ClientEmpty`. <br>
+//! `set_timezone`, `set_timeouts` and `set_read_only` choose, once for the whole process, how every session opened
+//! afterwards is set up, as the command line options of the same names do. <br>
+//! `Menu` is the program's interactive menu over a `Connection`, which `run` shows at the prompt and `execute` runs
+//! a line of at a time.
+//!
+//! ```no_run
+//! use psql_stats::{find_collector, Connection, PGError};
+//!
+//! fn main() -> Result<(), PGError> {
+//!     let mut connection = Connection::default();
+//!     connection.apply_uri("postgresql://postgres@localhost:5432/postgres")?;
+//!     connection.connect();
+//!
+//!     let uptime = connection.get_uptime()?;
+//!     println!("Up for {}s, since {}", uptime.uptime.as_secs(), uptime.started_at);
+//!
+//!     let activity = find_collector("activity").expect("activity is one of the collectors");
+//!     println!("{}", connection.collect(activity, false)?);
+//!     Ok(())
+//! }
+//! ```
+
+mod psql_stats;
+
+pub use crate::psql_stats::*;
@@ -21,22 +21,138 @@
 ///     - [serde_json](https://docs.rs/serde_json/latest/serde_json/)
 ///         - For our JSON integration
 use clap::Parser;
-use colored::Colorize;
+use std::env;
 use std::io;
-use std::io::Write;
+use std::io::IsTerminal;
 use std::time::Duration;
 
-mod psql_stats;
-
-use psql_stats::help_menu;
-use psql_stats::welcome;
-use psql_stats::Args;
+mod cli;
+
+use cli::Args;
+
+use psql_stats::cloudsql_host;
+use psql_stats::prompt_password;
+use psql_stats::Config;
+use psql_stats::migrate_legacy_connections;
+use psql_stats::overall_status;
+use psql_stats::run_command;
+use psql_stats::Command;
+use psql_stats::run_tui;
+use psql_stats::summary;
+use psql_stats::serve;
+use psql_stats::Anonymizer;
+use psql_stats::record_report_metrics;
+use psql_stats::write_report;
+use psql_stats::shutdown_flag;
+use psql_stats::Notifier;
+use psql_stats::History;
+use psql_stats::PidFile;
+use psql_stats::Thresholds;
+use psql_stats::Defaults;
+use psql_stats::Overrides;
+use psql_stats::Menu;
+use psql_stats::MenuOptions;
 use psql_stats::Connection;
+use psql_stats::set_timezone;
+use psql_stats::set_timeouts;
+use psql_stats::set_read_only;
+use psql_stats::init_logging;
+use psql_stats::set_style;
+use tracing::error;
 
 fn main() {
     let args = Args::parse();
+    if init_logging(args.verbose, args.quiet, args.log_file.as_deref()).is_err() {
+        std::process::exit(1);
+    }
+    set_timezone(&args.timezone);
+    set_timeouts(args.connect_timeout, args.statement_timeout);
+    migrate_legacy_connections();
+
+    // Guide new users through choosing their preferences and creating a first connection
+    let interactive = args.serve.is_none() && !args.tui && !args.healthcheck && args.command.is_none() && io::stdin().is_terminal();
+    let mut first_connection = None;
+    let mut config = match Config::load() {
+        Some(c) => c,
+        None if interactive && Config::first_run() => {
+            let (c, profile) = Config::setup();
+            first_connection = profile;
+            c
+        }
+        None => Config::default(),
+    };
+    // psql_stats.toml sets defaults over the configuration file, for the connection loaded as well
+    let defaults = Defaults::load(args.load.as_deref());
+    let mut thresholds = Thresholds::default();
+    defaults.apply(&mut config, &mut thresholds);
+    // --no-color is short for --color never
+    let color = match args.no_color {
+        true => "never",
+        false => args.color.as_str(),
+    };
+    set_style(color, config.color, args.ascii);
+    // Windows consoles only understand the escape sequences colors are written with once asked to, older ones never do
+    #[cfg(windows)]
+    if colored::control::set_virtual_terminal(true).is_err() {
+        colored::control::set_override(false);
+    }
+    if args.raw {
+        config.table.human = false;
+    }
+    if let Some(format) = args.format {
+        config.output_format = format;
+    }
+    if let Some(limit) = args.limit {
+        config.table.max_rows = limit;
+    }
+    if let Some(width) = args.max_col_width {
+        config.table.max_width = width;
+    }
+    if args.no_pager {
+        config.table.pager = false;
+    }
+    if args.read_only {
+        config.read_only = true;
+    }
+    if args.read_write {
+        config.read_only = false;
+    }
+    set_read_only(config.read_only);
+    if let Some(locale) = args.locale {
+        config.table.locale = Some(locale);
+    }
+    if let Some(path) = &args.metrics_db {
+        config.metrics_db = Some(path.clone());
+    }
+    // Thresholds and alerts given on the command line override those of psql_stats.toml, also when --serve reads it
+    // again
+    let threshold_args = [
+        ("io_read_warn_ms", args.io_read_warn_ms),
+        ("io_read_crit_ms", args.io_read_crit_ms),
+        ("connections_warn_percent", args.connections_warn_percent),
+        ("connections_crit_percent", args.connections_crit_percent),
+        ("cache_hit_warn_percent", args.cache_hit_warn_percent),
+        ("cache_hit_crit_percent", args.cache_hit_crit_percent),
+        ("replication_lag_warn_secs", args.replication_lag_warn_secs),
+        ("replication_lag_crit_secs", args.replication_lag_crit_secs),
+        ("wraparound_warn_percent", args.wraparound_warn_percent),
+        ("wraparound_crit_percent", args.wraparound_crit_percent),
+        ("sequence_warn_percent", args.sequence_warn_percent),
+        ("sequence_crit_percent", args.sequence_crit_percent),
+        ("long_query_warn_secs", args.long_query_warn_secs),
+        ("long_query_crit_secs", args.long_query_crit_secs),
+    ];
+    let overrides = Overrides {
+        connection: args.load.clone(),
+        alert_webhook: args.alert_webhook.clone(),
+        alert_cooldown: args.alert_cooldown,
+        thresholds: threshold_args.into_iter().filter_map(|(name, threshold)| Some((name, threshold?))).collect(),
+    };
+    overrides.apply(&mut config, &mut thresholds);
 
     let loaded_connection: Option<String> = args.load;
+    // Name of the connection in the menu, where more can be opened next to it
+    let first_name = loaded_connection.clone().unwrap_or("default".to_string());
 
     let mut connection: Connection = Connection {
         client: None,
@@ -45,36 +161,98 @@ fn main() {
         user: "".to_string(),
         port: "".to_string(),
         password: "".to_string(),
+        sslmode: "".to_string(),
+        sslrootcert: "".to_string(),
+        sslcert: "".to_string(),
+        sslkey: "".to_string(),
+        auth: "".to_string(),
+        aws_region: "".to_string(),
+        target_session_attrs: "".to_string(),
+        ssh_host: "".to_string(),
+        ssh_user: "".to_string(),
+        ssh_key: "".to_string(),
+        tunnel: None,
+        connected_to: None,
+        collectors: Vec::new(),
+        privileges: Default::default(),
+        capabilities: Default::default(),
+        server_version: 0,
+        transaction: Default::default(),
+        schema: "".to_string(),
+        search_path: "".to_string(),
+        timezone: "".to_string(),
+        queries: 0,
     };
 
-    if let Some(..) = loaded_connection {
-        if let Some(..)  = args.password {
-            connection = match connection.read_from_json(loaded_connection.unwrap(),args.password.as_ref().unwrap().to_string()) {
-                Ok(mut c) => {
-                    println!("Connection found, loading information.");
-                    c.password = match args.password {
-                        Some(s) => {
-                            println!("Password provided.");
-                            s
-                        }
-                        None => "".to_string(),
-                    };
-                    c
-                }
-                Err(e) => {
-                    eprintln!("Error: {}", e);
-                    std::process::exit(1);
+    if let Some(connection_name) = loaded_connection {
+        // Saved connections have no password unless kept in the keyring, it is asked for when connecting unless given
+        // with -W
+        let password = args.password.clone().unwrap_or_default();
+        connection = match Connection::read_from_json(&connection_name, password, args.use_keyring) {
+            Ok(c) => {
+                println!("Connection found, loading information.");
+                if args.password.is_some() {
+                    println!("Password provided.");
                 }
+                c
             }
-
+            Err(e) => {
+                error!("{}", e);
+                std::process::exit(1);
+            }
+        };
+        if let Some(mode) = args.sslmode {
+            connection.sslmode = mode;
+        }
+        if let Some(path) = args.sslrootcert {
+            connection.sslrootcert = path;
         }
-        connection.connect();
+        if let (Some(cert), Some(key)) = (args.sslcert, args.sslkey) {
+            connection.sslcert = cert;
+            connection.sslkey = key;
+        }
+        if let Some(method) = args.auth {
+            connection.auth = method;
+        }
+        if let Some(region) = args.aws_region {
+            connection.aws_region = region;
+        }
+        if let Some(attrs) = args.target_session_attrs {
+            connection.target_session_attrs = attrs;
+        }
+        if let Some(bastion) = args.ssh_host {
+            connection.ssh_host = bastion;
+        }
+        if let Some(user) = args.ssh_user {
+            connection.ssh_user = user;
+        }
+        if let Some(key) = args.ssh_key {
+            connection.ssh_key = key;
+        }
+    } else if let Some(profile) =
+        first_connection.filter(|_| {
+            args.host.is_none() && args.cloudsql_instance.is_none() && args.uri.is_none() && args.uri_argument.is_none()
+        })
+    {
+        // Use the connection just created during setup
+        connection = profile;
+        connection.password = args.password.unwrap_or_default();
    } else {
-        connection.host = match args.host {
-            Some(s) => s,
-            None => "localhost".to_string(),
+        connection.host = match (args.host, args.cloudsql_instance) {
+            (Some(s), _) => s,
+            (None, Some(instance)) => match cloudsql_host(&args.cloudsql_dir, &instance) {
+                Ok(h) => h,
+                Err(e) => {
+                    error!("{}", e);
+                    std::process::exit(1);
+                }
+            },
+            // Like libpq, PGHOST is only a default, so it does not get in the way of --cloudsql-instance
+            (None, None) => env::var("PGHOST").ok().filter(|h| !h.is_empty()).unwrap_or("localhost".to_string()),
         };
 
+        let dbname_given = args.dbname.is_some();
+
         // If DBName is none, then it will be set to the username, if that is none, then it is set
         // to DB Postgres
         connection.dbname = match args.dbname {
@@ -88,7 +266,7 @@ fn main() {
         connection.user = match args.user {
             Some(s) => s,
             None => {
-                eprintln!("Error: need to specify a username");
+                error!("need to specify a username");
                 std::process::exit(1)
             }
         };
@@ -104,166 +282,139 @@ fn main() {
             None => "".to_string(),
         };
 
-        connection.connect();
-    }
-
-    welcome();
-    help_menu();
-    loop {
-        print!("Connection status: ");
-        match connection.client {
-            Some(ref mut c) => match c.is_valid(Duration::new(3, 0)) {
-                Ok(_) => {
-                    println!("{}", "Connected".green().bold());
-                }
-                Err(e) => {
-                    eprintln!("Error: {}", e);
-                    println!("{}", "Not Connected".red().bold());
-                }
-            },
-            None => {
-                println!("{}", "Not Connected".red());
+        connection.sslmode = args.sslmode.unwrap_or_default();
+        connection.sslrootcert = args.sslrootcert.unwrap_or_default();
+        connection.sslcert = args.sslcert.unwrap_or_default();
+        connection.sslkey = args.sslkey.unwrap_or_default();
+        connection.auth = args.auth.unwrap_or_default();
+        connection.aws_region = args.aws_region.unwrap_or_default();
+        connection.target_session_attrs = args.target_session_attrs.unwrap_or_default();
+        connection.ssh_host = args.ssh_host.unwrap_or_default();
+        connection.ssh_user = args.ssh_user.unwrap_or_default();
+        connection.ssh_key = args.ssh_key.unwrap_or_default();
+
+        // What a URI names overrides the options above, and the database defaults to the URI's user as well
+        if let Some(uri) = args.uri.or(args.uri_argument) {
+            if !dbname_given {
+                connection.dbname.clear();
             }
-        }
-
-        print!("Please enter an option: ");
-        io::stdout().flush().expect("Could not flush");
-
-        let mut input = String::new();
-        io::stdin()
-            .read_line(&mut input)
-            .expect("Could not read input!");
-
-        let command = input.trim();
-
-        match command {
-            // Exit program
-            "0" => {
-                println!("Exiting...");
-                break;
+            if let Err(e) = connection.apply_uri(&uri) {
+                error!("{}", e);
+                std::process::exit(1);
             }
-
-            // Save connection
-            "1" => {
-                println!("Please enter the what you wish to name this connection.");
-                let mut conn_name_input: String = String::new();
-                io::stdin()
-                    .read_line(&mut conn_name_input)
-                    .expect("Could not read input");
-                let conn_name = conn_name_input.trim();
-
-                match connection.write_to_json(conn_name.to_string()) {
-                    Ok(b) => {
-                        if b {
-                            println!("Successfully saved your connection.")
-                        }
-                    }
-
-                    Err(e) => {
-                        eprintln!("Error: {}", e);
-                    }
-                }
+            if connection.dbname.is_empty() {
+                connection.dbname = connection.user.clone();
             }
+        }
+    }
 
-            "2" => match connection.get_uptime() {
-                Ok(_rows) => {}
-                Err(e) => {
-                    eprintln!("Error: {}", e);
-                }
-            },
+    // Offline, the saved data is browsed without connecting, and managing the saved connections needs no server
+    if let Some(schema) = args.schema.as_ref().or(defaults.schema.as_ref()) {
+        connection.schema = schema.clone();
+    }
+    if !args.offline && args.command.as_ref().is_none_or(|c| c.needs_connection()) {
+        if args.password_prompt {
+            connection.password = prompt_password(&connection.user, &connection.host).unwrap_or_default();
+        }
+        connection.connect_or_prompt();
+    }
 
-            // Display current running version of postgres
-            "3" => match connection.version() {
-                Ok(row) => match row.try_get::<_, String>(0) {
-                    Ok(v) => {
-                        println!("Current running version: {}", v);
-                    }
-                    Err(e) => {
-                        eprintln!("Error: {}", e);
-                    }
-                },
-                Err(e) => {
-                    eprintln!("Error: {}", e);
-                }
-            },
+    let mut history = args.history.as_ref().map(|path| {
+        History::new(
+            path,
+            args.history_max_size,
+            args.history_retention_days,
+            args.history_max_total,
+        )
+    });
+
+
+    // Run the health checks once and report the result through the exit code
+    if args.healthcheck || matches!(args.command, Some(Command::Healthcheck)) {
+        let checks = connection.healthcheck(&thresholds);
+        println!("{}", summary(&checks));
+        for check in &checks {
+            println!("\t{:<11} {:<8} {}", check.name, check.status, check.message);
+        }
+        connection.close();
+        std::process::exit(overall_status(&checks).exit_code());
+    }
 
-            // Display all public tables
-            "4" => match connection.get_all_public_tables() {
-                Ok(rows) => {
-                    println!("Public Tables: ");
-                    for row in rows {
-                        if let Ok(s) = row.try_get::<_, String>(0) {
-                            println!("\t\u{25C6} {}", s);
-                        }
-                    }
+    // Run a single command for scripts and cron jobs, reporting failure through the exit code
+    if let Some(command) = &args.command {
+        let result = match command {
+            // The report runs the health checks as well, with the thresholds given
+            Command::Report { anonymize, yaml, file } => {
+                let mut report = connection.report(args.all_databases, &thresholds);
+                if let Err(e) = record_report_metrics(&config, &connection.label(), &report) {
+                    error!("{}", e);
                 }
-                Err(e) => {
-                    eprintln!("Error: {}", e);
+                if *anonymize {
+                    Anonymizer::default().anonymize(&mut report);
                 }
-            },
+                write_report(&report, *yaml, file.as_deref()).map(|path| println!("Report written to {}", path))
+            }
+            _ => run_command(
+                command,
+                &mut connection,
+                &config,
+                args.all_databases,
+                history.as_mut(),
+                args.offline,
+                args.use_keyring,
+            ),
+        };
+        connection.close();
+        if let Err(e) = result {
+            error!("{}", e);
+            std::process::exit(1);
+        }
+        return;
+    }
 
-            // Display all extensions
-            "5" => match connection.get_extensions() {
-                Ok(rows) => {
-                    println!("Installed extensions:");
-                    for row in rows {
-                        if let Ok(s) = row.try_get::<_, String>(0) {
-                            println!("\t\u{25C6} {}", s);
-                        }
-                    }
-                }
+    // Take over the terminal with the panes until the user quits
+    if args.tui {
+        let notifier = Notifier::new(&config, &thresholds);
+        let result = run_tui(&mut connection, &config.table, Duration::from_secs(config.watch_interval), notifier);
+        connection.close();
+        if let Err(e) = result {
+            error!("{}", e);
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    // Serve statistics over HTTP instead of starting the interactive menu
+    if let Some(addr) = args.serve {
+        let pid_file = match args.pid_file {
+            Some(ref path) => match PidFile::create(path) {
+                Ok(f) => Some(f),
                 Err(e) => {
-                    eprintln!("Error: {}", e);
+                    error!("could not write pid file: {}", e);
+                    std::process::exit(1);
                 }
             },
+            None => None,
+        };
 
-            "6" => {
-                println!("Sorry, the custom query function not been implemented yet!");
-            }
-
-            // Attempt to reestablish connection
-            "7" => {
-                connection.connect();
-            }
-
-            // Load a connection
-            "8" => {
-                let mut connection_name = "".to_string();
-                let mut connection_password = "".to_string();
-                print!("Connection name: ");
-                io::stdout().flush().expect("Could not flush");
-
-
-                io::stdin()
-                    .read_line(&mut connection_name)
-                    .expect("Could not read input.");
-
-
-                print!("Password: ");
-                io::stdout().flush().expect("Could not flush");
-                io::stdin()
-                    .read_line(&mut connection_password)
-                    .expect("Could not read input.");
-
-                println!("Entered: {}", connection_name);
+        let shutdown = shutdown_flag();
+        let result = serve(&mut connection, &addr, &shutdown, config, &thresholds, &overrides, history);
+        connection.close();
+        drop(pid_file);
 
-                match connection.read_from_json(connection_name.to_string(), connection_password.to_string()) {
-                    Ok(_) => {
-                        println!("Connection found, please enter the password: ");
-                        io::stdin()
-                            .read_line(&mut connection_password)
-                            .expect("Could not read password");
-                        connection.password = connection_password;
-                        connection.connect();
-                    }
-                    Err(e) => {
-                        eprintln!("Error: {}", e);
-                    }
-                }
-            }
-            _ => {
-                help_menu();
-            }
+        if let Err(e) = result {
+            error!("{}", e);
+            std::process::exit(1);
         }
+        return;
     }
+
+    // Otherwise read options and commands at the prompt until the user exits
+    let options = MenuOptions {
+        all_databases: args.all_databases,
+        offline: args.offline,
+        use_keyring: args.use_keyring,
+        replicas: args.replicas,
+    };
+    Menu::new(&first_name, connection, config, thresholds, history, options).run();
 }
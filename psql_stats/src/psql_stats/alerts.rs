@@ -0,0 +1,247 @@
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use serde_json::{json, Value};
+use tracing::{debug, error, info};
+use ureq::Agent;
+
+use super::healthcheck::{above, replication_check, Check, Status, Thresholds, REPLICATION_HEALTH_QUERY};
+use super::humanize::human_duration;
+use super::saturation::saturation_check;
+use super::{one_line, Config, Connection};
+
+/// How long posting an alert may take, so a webhook that does not answer cannot hold up the dashboard or the server
+const WEBHOOK_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// The query running the longest in a client session other than this one. `backend_type` is read through `to_jsonb`
+/// as Postgres 9.6 lacks it; from 10 on it leaves out walsenders, which look busy for as long as they stream.
+const LONGEST_QUERY_QUERY: &str = r#"
+    SELECT a.pid,
+           a.usename,
+           a.datname,
+           extract(epoch FROM now() - a.query_start)::float8 AS seconds,
+           a.query
+    FROM pg_stat_activity a
+    WHERE a.state = 'active'
+    AND a.pid <> pg_backend_pid()
+    AND coalesce(to_jsonb(a) ->> 'backend_type', 'client backend') = 'client backend'
+    ORDER BY a.query_start
+    LIMIT 1
+"#;
+
+/// Posts to a webhook when a threshold is crossed while watching a connection with `dashboard`, `--tui` or `--serve`:
+/// replication lagging, connections nearing the limit or a query running for long, see `alert_checks`. <br>
+/// The payload is Slack's, a `text` to show, with the connection, check, status and message alongside for other
+/// receivers. A check is posted when it crosses a threshold, gets worse, or is still not resolved
+/// `Config::alert_cooldown` seconds after it was last posted, and once more when it is resolved, so a problem that
+/// lasts does not post on every refresh.
+pub struct Notifier {
+    webhook: String,
+    cooldown: Duration,
+    interval: Duration,
+    thresholds: Thresholds,
+    agent: Agent,
+    /// Status last posted of each check, and when, by connection label and check name. Checks that are fine are left
+    /// out
+    posted: HashMap<(String, &'static str), (Status, Instant)>,
+    last_run: Option<Instant>,
+}
+
+impl Notifier {
+    /// The notifier posting to `Config::alert_webhook` with the `thresholds`, or `None` when no webhook is configured
+    pub fn new(config: &Config, thresholds: &Thresholds) -> Option<Notifier> {
+        let webhook = config.alert_webhook.clone()?;
+        let agent = Agent::config_builder().timeout_global(Some(WEBHOOK_TIMEOUT)).build().into();
+        Some(Notifier {
+            webhook,
+            cooldown: Duration::from_secs(config.alert_cooldown),
+            interval: Duration::from_secs(config.watch_interval),
+            thresholds: thresholds.clone(),
+            agent,
+            posted: HashMap::new(),
+            last_run: None,
+        })
+    }
+
+    /// Takes the webhook, cooldown, interval and `thresholds` of a configuration read again, e.g. by `--serve` when its
+    /// files change, remembering the alerts already posted so those still open are not posted again. A `config`
+    /// without a webhook keeps the current one.
+    pub fn reconfigure(&mut self, config: &Config, thresholds: &Thresholds) {
+        if let Some(webhook) = &config.alert_webhook {
+            self.webhook = webhook.clone();
+        }
+        self.cooldown = Duration::from_secs(config.alert_cooldown);
+        self.interval = Duration::from_secs(config.watch_interval);
+        self.thresholds = thresholds.clone();
+    }
+
+    /// The thresholds the checks are held against
+    pub fn thresholds(&self) -> &Thresholds {
+        &self.thresholds
+    }
+
+    /// Runs `check` on `connection` unless it ran less than `Config::watch_interval` seconds ago, for loops that wake
+    /// up more often, such as `--serve` waiting for requests
+    pub fn poll(&mut self, connection: &mut Connection) {
+        if self.last_run.is_some_and(|last| last.elapsed() < self.interval) {
+            return;
+        }
+        self.last_run = Some(Instant::now());
+        self.check(connection);
+    }
+
+    /// Runs the alert checks on `connection` and posts those that call for it to the webhook. A webhook that cannot
+    /// be reached is reported without stopping the caller.
+    pub fn check(&mut self, connection: &mut Connection) {
+        let label = connection.label();
+        for check in alert_checks(connection, &self.thresholds) {
+            let key = (label.clone(), check.name);
+            let now = Instant::now();
+            if !should_post(self.posted.get(&key).copied(), check.status, now, self.cooldown) {
+                continue;
+            }
+            self.post(&label, &check);
+            match check.status {
+                Status::Ok => self.posted.remove(&key),
+                status => self.posted.insert(key, (status, now)),
+            };
+        }
+    }
+
+    fn post(&self, label: &str, check: &Check) {
+        let status = match check.status {
+            Status::Ok => "RESOLVED".to_string(),
+            status => status.to_string(),
+        };
+        let text = format!("[{}] {} on {}: {}", status, check.name, label, check.message);
+        let payload = json!({
+            "text": text,
+            "connection": label,
+            "check": check.name,
+            "status": status,
+            "message": check.message,
+        });
+        match self
+            .agent
+            .post(&self.webhook)
+            .header("Content-Type", "application/json")
+            .send(payload.to_string())
+        {
+            Ok(_) => info!("posted the alert {}", text),
+            Err(e) => error!("could not post the alert {} to the webhook: {}", text, e),
+        }
+    }
+}
+
+/// Whether a check now at `status` is posted, given the status it was last posted at and when: when it crosses a
+/// threshold, gets worse, is still not resolved `cooldown` after it was last posted, or is resolved
+fn should_post(previous: Option<(Status, Instant)>, status: Status, now: Instant, cooldown: Duration) -> bool {
+    match previous {
+        None => status != Status::Ok,
+        Some(_) if status == Status::Ok => true,
+        Some((posted, at)) => status > posted || now.duration_since(at) >= cooldown,
+    }
+}
+
+/// The checks alerts watch: whether the connection is up, how far replication lags, how many connections are in use
+/// against the limit and how long the longest query has been running. <br>
+/// A check that cannot be evaluated, e.g. for lack of privileges, is left out rather than alerted about, unless the
+/// connection itself was lost.
+fn alert_checks(connection: &mut Connection, thresholds: &Thresholds) -> Vec<Check> {
+    if connection.client.is_none() {
+        return vec![Check {
+            name: "connection",
+            status: Status::Crit,
+            message: "could not connect to the database".to_string(),
+        }];
+    }
+
+    let mut checks = Vec::new();
+    let mut lost = None;
+    match connection.query_json(REPLICATION_HEALTH_QUERY) {
+        Ok(rows) => checks.push(replication_check(&rows[0], thresholds)),
+        Err(e) if e.is_connection_lost() => lost = Some(e),
+        Err(e) => debug!("no replication alerts: {}", e),
+    }
+    match connection.connection_limits() {
+        Ok(limits) => checks.push(saturation_check(&limits, thresholds)),
+        Err(e) if e.is_connection_lost() => lost = Some(e),
+        Err(e) => debug!("no connection alerts: {}", e),
+    }
+    match connection.query_json(LONGEST_QUERY_QUERY) {
+        Ok(rows) => checks.push(long_query_check(rows.get(0), thresholds)),
+        Err(e) if e.is_connection_lost() => lost = Some(e),
+        Err(e) => debug!("no long-running query alerts: {}", e),
+    }
+
+    let connected = match lost {
+        Some(e) => Check {
+            name: "connection",
+            status: Status::Crit,
+            message: format!("lost the connection: {}", e),
+        },
+        None => Check {
+            name: "connection",
+            status: Status::Ok,
+            message: "connected".to_string(),
+        },
+    };
+    checks.insert(0, connected);
+    checks
+}
+
+/// A query running for long holds its locks and keeps vacuum from removing the rows it may still see
+fn long_query_check(row: Option<&Value>, thresholds: &Thresholds) -> Check {
+    let seconds = row.and_then(|r| r["seconds"].as_f64()).unwrap_or(0.0);
+    let message = match row {
+        Some(row) if seconds >= thresholds.long_query_warn_secs => format!(
+            "session {} ({} on {}) has been running a query for {}: {}",
+            row["pid"],
+            row["usename"].as_str().unwrap_or(""),
+            row["datname"].as_str().unwrap_or(""),
+            human_duration(seconds),
+            one_line(row["query"].as_str().unwrap_or(""), 80)
+        ),
+        _ => format!("no query running for longer than {}", human_duration(thresholds.long_query_warn_secs)),
+    };
+    Check {
+        name: "long-query",
+        status: above(seconds, thresholds.long_query_warn_secs, thresholds.long_query_crit_secs),
+        message,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn checks_are_posted_when_crossing_a_threshold_and_resolved() {
+        let now = Instant::now();
+        let cooldown = Duration::from_secs(900);
+        assert!(!should_post(None, Status::Ok, now, cooldown));
+        assert!(should_post(None, Status::Warn, now, cooldown));
+        assert!(should_post(Some((Status::Warn, now)), Status::Ok, now, cooldown));
+    }
+
+    #[test]
+    fn repeats_wait_for_the_cooldown_unless_worse() {
+        let posted = Instant::now();
+        let cooldown = Duration::from_secs(900);
+        let soon = posted + Duration::from_secs(60);
+        assert!(!should_post(Some((Status::Warn, posted)), Status::Warn, soon, cooldown));
+        assert!(!should_post(Some((Status::Crit, posted)), Status::Warn, soon, cooldown));
+        assert!(should_post(Some((Status::Warn, posted)), Status::Crit, soon, cooldown));
+        assert!(should_post(Some((Status::Warn, posted)), Status::Warn, posted + cooldown, cooldown));
+    }
+
+    #[test]
+    fn long_queries_name_the_session() {
+        let thresholds = Thresholds::default();
+        let row = json!({"pid": 4242, "usename": "etl", "datname": "shop", "seconds": 2000.0, "query": "SELECT 1"});
+        let check = long_query_check(Some(&row), &thresholds);
+        assert_eq!(check.status, Status::Crit);
+        assert!(check.message.starts_with("session 4242 (etl on shop)"), "{}", check.message);
+        assert_eq!(long_query_check(None, &thresholds).status, Status::Ok);
+    }
+}
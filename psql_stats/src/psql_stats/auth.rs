@@ -0,0 +1,189 @@
+use std::env;
+use std::path::Path;
+
+use hmac::{Hmac, Mac};
+use postgres::error::SqlState;
+use sha2::{Digest, Sha256};
+use tracing::{error, warn};
+
+use super::{redact, PGError};
+
+/// Ways of authenticating: with the given password, or with a short-lived AWS RDS IAM token generated on connect
+pub const AUTH_METHODS: &[&str] = &["password", "rds-iam"];
+
+/// Seconds an RDS IAM token is valid for, RDS does not accept more than 15 minutes
+const RDS_TOKEN_LIFETIME: u64 = 900;
+
+/// Returns the host to connect to for the Cloud SQL instance `instance`, given as `project:region:instance`: the
+/// directory holding the unix socket the Cloud SQL Auth Proxy creates for it under `socket_dir`. <br>
+/// The proxy must already be running, it is not started here: it performs the connector handshake with the
+/// instance's IAM credentials and ephemeral certificates, so no public IP or host/port is needed. A warning is
+/// printed when the socket does not exist yet.
+pub fn cloudsql_host(socket_dir: &str, instance: &str) -> Result<String, PGError> {
+    // Projects in a domain are written as "example.com:project", giving four parts
+    let parts: Vec<&str> = instance.split(':').collect();
+    if !(3..=4).contains(&parts.len()) || parts.iter().any(|p| p.is_empty()) {
+        return Err(PGError::InvalidInstanceName);
+    }
+
+    let host = Path::new(socket_dir).join(instance);
+    if !host.exists() {
+        warn!(
+            "{} does not exist, is the Cloud SQL Auth Proxy running with --unix-socket {}?",
+            host.display(),
+            socket_dir
+        );
+    }
+    Ok(host.to_string_lossy().to_string())
+}
+
+/// Describes a failed connection attempt, with the reason a TLS handshake failed, e.g. a certificate that could not
+/// be verified
+pub(crate) fn describe_connect_error(error: &postgres::Error) -> String {
+    let mut message = redact(&error.to_string());
+    if message.contains("TLS handshake") {
+        if let Some(source) = std::error::Error::source(error) {
+            message = format!("{}: {}", message, source);
+        }
+    }
+    message
+}
+
+/// Whether a connection attempt failed because the server wants a password other than the one given, which is
+/// empty when none was
+pub(crate) fn password_rejected(error: &postgres::Error) -> bool {
+    error.code() == Some(&SqlState::INVALID_PASSWORD) || error.to_string().contains("password missing")
+}
+
+/// Asks for the password of `user` on `host` on the terminal, without echoing what is typed, so it stays out of the
+/// shell history and the process list as `-W` would not. <br>
+/// Returns `None` when nothing was entered or there is no terminal to ask on.
+pub fn prompt_password(user: &str, host: &str) -> Option<String> {
+    match rpassword::prompt_password(format!("Password for {}@{}: ", user, host)) {
+        Ok(password) if !password.is_empty() => Some(password),
+        Ok(_) => None,
+        Err(e) => {
+            error!("could not read the password: {}", e);
+            None
+        }
+    }
+}
+
+/// Generates an AWS RDS IAM authentication token for `user` on `host`:`port`, to be sent as the password. <br>
+/// The token is a SigV4 presigned `connect` request, signed with the credentials in the standard `AWS_ACCESS_KEY_ID`,
+/// `AWS_SECRET_ACCESS_KEY` and (optional) `AWS_SESSION_TOKEN` environment variables. `region` falls back to
+/// `AWS_REGION` and `AWS_DEFAULT_REGION` when empty. Tokens expire after 15 minutes but only have to be valid when
+/// connecting, so a new one is generated for every connection attempt.
+pub(crate) fn rds_auth_token(
+    host: &str,
+    port: &str,
+    user: &str,
+    region: &str,
+    unix_time: u64,
+) -> Result<String, PGError> {
+    let variable = |name: &str| env::var(name).ok().filter(|v| !v.is_empty());
+    let (access_key, secret_key) = match (variable("AWS_ACCESS_KEY_ID"), variable("AWS_SECRET_ACCESS_KEY")) {
+        (Some(a), Some(s)) => (a, s),
+        _ => return Err(PGError::AwsCredentialsMissing),
+    };
+    let region = match Some(region.to_string())
+        .filter(|r| !r.is_empty())
+        .or_else(|| variable("AWS_REGION"))
+        .or_else(|| variable("AWS_DEFAULT_REGION"))
+    {
+        Some(r) => r,
+        None => return Err(PGError::AwsCredentialsMissing),
+    };
+
+    let endpoint = format!("{}:{}", host, if port.is_empty() { "5432" } else { port });
+    let timestamp = amz_timestamp(unix_time);
+    let date = &timestamp[..8];
+    let scope = format!("{}/{}/rds-db/aws4_request", date, region);
+
+    let mut query = vec![
+        ("Action", "connect".to_string()),
+        ("DBUser", user.to_string()),
+        ("X-Amz-Algorithm", "AWS4-HMAC-SHA256".to_string()),
+        ("X-Amz-Credential", format!("{}/{}", access_key, scope)),
+        ("X-Amz-Date", timestamp.clone()),
+        ("X-Amz-Expires", RDS_TOKEN_LIFETIME.to_string()),
+        ("X-Amz-SignedHeaders", "host".to_string()),
+    ];
+    if let Some(token) = variable("AWS_SESSION_TOKEN") {
+        query.push(("X-Amz-Security-Token", token));
+    }
+    query.sort();
+    let query = query
+        .iter()
+        .map(|(k, v)| format!("{}={}", uri_encode(k), uri_encode(v)))
+        .collect::<Vec<String>>()
+        .join("&");
+
+    let canonical_request = format!(
+        "GET\n/\n{}\nhost:{}\n\nhost\n{}",
+        query,
+        endpoint,
+        hex(&Sha256::digest(b""))
+    );
+    let string_to_sign = format!(
+        "AWS4-HMAC-SHA256\n{}\n{}\n{}",
+        timestamp,
+        scope,
+        hex(&Sha256::digest(canonical_request.as_bytes()))
+    );
+
+    let mut key = hmac_sha256(format!("AWS4{}", secret_key).as_bytes(), date.as_bytes());
+    for part in [region.as_str(), "rds-db", "aws4_request"] {
+        key = hmac_sha256(&key, part.as_bytes());
+    }
+    let signature = hex(&hmac_sha256(&key, string_to_sign.as_bytes()));
+
+    Ok(format!("{}/?{}&X-Amz-Signature={}", endpoint, query, signature))
+}
+
+fn hmac_sha256(key: &[u8], data: &[u8]) -> Vec<u8> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC accepts keys of any length");
+    mac.update(data);
+    mac.finalize().into_bytes().to_vec()
+}
+
+pub(crate) fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Percent-encodes everything except the unreserved characters, as SigV4 requires
+fn uri_encode(text: &str) -> String {
+    text.bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => (b as char).to_string(),
+            _ => format!("%{:02X}", b),
+        })
+        .collect()
+}
+
+/// Formats a unix timestamp as the UTC `YYYYMMDDTHHMMSSZ` form used by SigV4
+fn amz_timestamp(unix_time: u64) -> String {
+    let days = (unix_time / 86_400) as i64;
+    let seconds = unix_time % 86_400;
+
+    // Converts days since the epoch to a civil date, see http://howardhinnant.github.io/date_algorithms.html
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let day_of_era = z - era * 146_097;
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    format!(
+        "{:04}{:02}{:02}T{:02}{:02}{:02}Z",
+        year,
+        month,
+        day,
+        seconds / 3600,
+        (seconds % 3600) / 60,
+        seconds % 60
+    )
+}
@@ -0,0 +1,81 @@
+use postgres::error::SqlState;
+use serde_json::Value;
+
+use super::{shorten_queries, Connection, PGError};
+
+/// Query describing the session `$1`, other than this one, to confirm it is the one to stop
+const BACKEND_QUERY: &str = r#"
+    SELECT pid, usename, datname, application_name, client_addr, state, query_start, query
+    FROM pg_stat_activity
+    WHERE pid = $1
+    AND pid <> pg_backend_pid()
+"#;
+
+/// How to stop a session, see `Connection::signal_backend`
+#[derive(Clone, Copy)]
+pub enum Signal {
+    /// Stop the query the session is running, leaving it connected
+    Cancel,
+    /// End the session, disconnecting its client
+    Terminate,
+}
+
+impl Signal {
+    /// Asks whether to go ahead, saying what will happen to the session
+    pub fn question(&self) -> &'static str {
+        match self {
+            Signal::Cancel => "Cancel the query this session is running? The session stays connected.",
+            Signal::Terminate => {
+                "Terminate this session? Its query is stopped, its open transaction rolled back and its client \
+                 disconnected."
+            }
+        }
+    }
+
+    /// Says what was done to the session `pid`
+    pub fn done(&self, pid: i32) -> String {
+        match self {
+            Signal::Cancel => format!("Cancelled the query of session {}, it is still connected.", pid),
+            Signal::Terminate => format!("Terminated session {}.", pid),
+        }
+    }
+
+    fn function(&self) -> &'static str {
+        match self {
+            Signal::Cancel => "pg_cancel_backend",
+            Signal::Terminate => "pg_terminate_backend",
+        }
+    }
+}
+
+impl Connection {
+    /// Looks up the session `pid` in `pg_stat_activity`: its user, database, application, client address, state and
+    /// query, put on one line and cut off at `query_width` characters. <br>
+    /// Returns `NoSuchBackend` when no other session has that pid.
+    pub fn describe_backend(&mut self, pid: i32, query_width: usize) -> Result<Value, PGError> {
+        let mut rows = self.query_json_with(BACKEND_QUERY, &[&pid])?;
+        if rows.as_array().is_none_or(|r| r.is_empty()) {
+            return Err(PGError::NoSuchBackend);
+        }
+        shorten_queries(&mut rows, query_width);
+        Ok(rows)
+    }
+
+    /// Cancels the query of the session `pid` with `pg_cancel_backend`, or ends the session with
+    /// `pg_terminate_backend`. <br>
+    /// Returns `SignalDenied` when the role may not signal that session, and `NoSuchBackend` when it has already
+    /// gone.
+    pub fn signal_backend(&mut self, pid: i32, signal: Signal) -> Result<(), PGError> {
+        let client = match &mut self.client {
+            Some(c) => c,
+            None => return Err(PGError::ClientEmpty),
+        };
+        match client.query_one(&format!("SELECT {}($1)", signal.function()), &[&pid]) {
+            Ok(row) if row.get::<_, bool>(0) => Ok(()),
+            // The server only warns, and returns false, for a pid that is not a session
+            Ok(_) => Err(PGError::NoSuchBackend),
+            Err(e) if e.code() == Some(&SqlState::INSUFFICIENT_PRIVILEGE) => Err(PGError::SignalDenied),
+            Err(e) => Err(PGError::query(format!("signalling the session {}", pid), e)),
+        }
+    }
+}
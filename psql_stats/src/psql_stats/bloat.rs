@@ -0,0 +1,50 @@
+/// Query measuring the dead tuples and free space of every table and materialized view and the unused space of every
+/// B-tree index with `pgstattuple` and `pgstatindex`, rather than estimating them from statistics like
+/// `INDEX_STATS_QUERY`. <br>
+/// Both read the relation in full, so relations over 1 GB are listed with a note instead of being measured: the
+/// functions are strict and given `NULL` in place of the relations they should not read. <br>
+/// A B-tree counts as wasting the space its leaf pages leave free, 10% of which its default fillfactor keeps free on
+/// purpose, and its empty and deleted pages.
+pub const PRECISE_BLOAT_QUERY: &str = r#"
+            WITH relations AS (
+                SELECT n.nspname AS schema,
+                       c.relname AS relation,
+                       c.oid,
+                       c.relkind = 'i' AS is_index,
+                       pg_relation_size(c.oid) AS size,
+                       pg_relation_size(c.oid) <= 1073741824 AS measured
+                FROM pg_class c
+                JOIN pg_namespace n ON n.oid = c.relnamespace
+                LEFT JOIN pg_am am ON am.oid = c.relam
+                WHERE (c.relkind IN ('r', 'm') OR (c.relkind = 'i' AND am.amname = 'btree'))
+                  AND c.relpersistence <> 't'
+                  AND n.nspname NOT IN ('pg_catalog', 'information_schema')
+                  AND n.nspname !~ '^pg_toast'
+            ),
+            wasted AS (
+                SELECT r.*,
+                       coalesce(
+                           t.dead_tuple_len + t.free_space,
+                           CASE WHEN i.leaf_pages > 0 THEN
+                               (i.leaf_pages * (100 - i.avg_leaf_density) / 100 + i.empty_pages + i.deleted_pages)
+                                   * current_setting('block_size')::bigint
+                           END
+                       ) AS wasted,
+                       t.dead_tuple_percent,
+                       i.avg_leaf_density
+                FROM relations r
+                LEFT JOIN LATERAL pgstattuple(CASE WHEN NOT r.is_index AND r.measured THEN r.oid END) t ON true
+                LEFT JOIN LATERAL pgstatindex(CASE WHEN r.is_index AND r.measured THEN r.oid END) i ON true
+            )
+            SELECT schema,
+                   relation,
+                   CASE WHEN is_index THEN 'index' ELSE 'table' END AS kind,
+                   size,
+                   round(wasted)::bigint AS wasted_bytes,
+                   round((100.0 * wasted / nullif(size, 0))::numeric, 1) AS wasted_percent,
+                   round(dead_tuple_percent::numeric, 1) AS dead_tuple_percent,
+                   round(avg_leaf_density::numeric, 1) AS leaf_density,
+                   CASE WHEN NOT measured THEN 'skipped, larger than 1 GB' END AS note
+            FROM wasted
+            ORDER BY wasted DESC NULLS LAST, size DESC
+        "#;
@@ -0,0 +1,49 @@
+use serde_json::Value;
+
+use super::{Connection, PGError};
+
+/// Query listing the user tables and indexes with the blocks read from shared buffers (`blocks_hit`) and from the
+/// operating system, its cache or the disk (`blocks_read`), since statistics were last reset, those read the most
+/// first. `hit_percent` is the cache hit ratio of each relation and `read_percent` its share of all the blocks read,
+/// so the relations behind a low overall ratio stand out. Tables count the blocks of their heap only, their indexes
+/// are listed on their own with `table_name` naming the table. Relations never read are left out.
+pub const CACHE_STATS_QUERY: &str = r#"
+    WITH relations AS (
+        SELECT schemaname AS schema,
+               relname AS relation,
+               'table' AS kind,
+               relname AS table_name,
+               pg_relation_size(relid) AS size_bytes,
+               coalesce(heap_blks_hit, 0) AS blocks_hit,
+               coalesce(heap_blks_read, 0) AS blocks_read
+        FROM pg_statio_user_tables
+        UNION ALL
+        SELECT schemaname,
+               indexrelname,
+               'index',
+               relname,
+               pg_relation_size(indexrelid),
+               coalesce(idx_blks_hit, 0),
+               coalesce(idx_blks_read, 0)
+        FROM pg_statio_user_indexes
+    )
+    SELECT schema,
+           relation,
+           kind,
+           table_name,
+           size_bytes,
+           blocks_hit,
+           blocks_read,
+           round(100.0 * blocks_hit / nullif(blocks_hit + blocks_read, 0), 2) AS hit_percent,
+           round(100.0 * blocks_read / nullif(sum(blocks_read) OVER (), 0), 1) AS read_percent
+    FROM relations
+    WHERE blocks_hit + blocks_read > 0
+    ORDER BY blocks_read DESC, schema, relation
+"#;
+
+impl Connection {
+    /// Lists the cache hits and reads of every user table and index, see `CACHE_STATS_QUERY`
+    pub fn get_cache_stats(&mut self) -> Result<Value, PGError> {
+        self.query_json(CACHE_STATS_QUERY)
+    }
+}
@@ -0,0 +1,196 @@
+use colored::Colorize;
+use postgres::Client;
+
+use super::style::glyph;
+use super::{Connection, PGError};
+
+/// An optional extension the deeper statistics come from, with what it adds and how it is enabled
+pub(crate) struct StatExtension {
+    pub name: &'static str,
+    /// What the extension makes available, shown next to it in the summary
+    pub provides: &'static str,
+    /// Package providing the extension when the server does not have it, e.g. postgresql-contrib
+    pub package: &'static str,
+    /// Whether the extension only works when loaded at server start through `shared_preload_libraries`
+    pub preload: bool,
+}
+
+/// The extensions probed on connect, see `probe`
+pub(crate) const STAT_EXTENSIONS: &[StatExtension] = &[
+    StatExtension {
+        name: "pg_stat_statements",
+        provides: "statements: the queries taking the most time, with their I/O, WAL and JIT timings",
+        package: "postgresql-contrib",
+        preload: true,
+    },
+    StatExtension {
+        name: "pg_buffercache",
+        provides: "which relations fill shared buffers and how many of their pages are dirty",
+        package: "postgresql-contrib",
+        preload: false,
+    },
+    StatExtension {
+        name: "pgstattuple",
+        provides: "exact dead tuple and free space counts of tables and indexes, where bloat is only estimated",
+        package: "postgresql-contrib",
+        preload: false,
+    },
+    StatExtension {
+        name: "pg_wait_sampling",
+        provides: "a sampled history of the wait events of every session, to see what queries wait on over time",
+        package: "pg_wait_sampling",
+        preload: true,
+    },
+    StatExtension {
+        name: "hypopg",
+        provides: "hypothetical indexes, to see whether the planner would use an index before building it",
+        package: "hypopg",
+        preload: false,
+    },
+];
+
+/// Whether `STAT_EXTENSIONS` are installed in the current database, with the preloaded libraries, in their order. <br>
+/// `preloaded` is NULL when the role may not read `shared_preload_libraries`, which needs `pg_read_all_settings`.
+const CAPABILITIES_QUERY: &str = r#"
+    SELECT a.installed_version,
+           a.name IS NOT NULL AS available,
+           n.name = ANY(string_to_array(replace(replace(s.setting, '"', ''), ' ', ''), ',')) AS preloaded
+    FROM unnest($1::text[]) WITH ORDINALITY AS n(name, position)
+    LEFT JOIN pg_available_extensions a ON a.name = n.name
+    LEFT JOIN pg_settings s ON s.name = 'shared_preload_libraries'
+    ORDER BY n.position
+"#;
+
+/// How far one of `STAT_EXTENSIONS` is from being usable in the current database
+#[derive(Clone, PartialEq, Eq)]
+pub enum ExtensionState {
+    /// Installed, and loaded at server start if it has to be, with its version
+    Enabled(String),
+    /// Installed, but missing from `shared_preload_libraries`, so querying it fails until the server is restarted
+    NotPreloaded,
+    /// The server has it, `CREATE EXTENSION` is missing, and adding it to `shared_preload_libraries` as well when
+    /// `needs_preload`
+    Available { needs_preload: bool },
+    /// The server does not have the extension's files, its package has to be installed first
+    Missing,
+}
+
+/// The `STAT_EXTENSIONS` usable in the database connected to, probed on connect by `probe`
+#[derive(Clone, Default)]
+pub struct Capabilities {
+    /// Whether the probe succeeded, nothing is reported when the extensions are unknown
+    pub probed: bool,
+    states: Vec<ExtensionState>,
+}
+
+impl Capabilities {
+    /// State of the extension `name`, one of `STAT_EXTENSIONS`, or `None` when it was not probed
+    pub fn state(&self, name: &str) -> Option<&ExtensionState> {
+        let index = STAT_EXTENSIONS.iter().position(|e| e.name == name)?;
+        self.states.get(index)
+    }
+
+    /// Whether a collector needing `extension` can be run, always when nothing is needed or the probe failed
+    pub fn allows(&self, extension: Option<&str>) -> bool {
+        match extension {
+            Some(name) if self.probed => matches!(self.state(name), Some(ExtensionState::Enabled(_))),
+            _ => true,
+        }
+    }
+
+    /// Returns `ExtensionRequired` if a collector needing `extension` cannot be run, so it is refused with a hint
+    /// instead of failing on a function that does not exist
+    pub fn check(&self, extension: Option<&str>) -> Result<(), PGError> {
+        match self.allows(extension) {
+            true => Ok(()),
+            false => Err(PGError::ExtensionRequired),
+        }
+    }
+
+    /// Describes which `STAT_EXTENSIONS` the current database can use, one line each, with how to enable the others:
+    /// installing the package, adding the library to `shared_preload_libraries` or `CREATE EXTENSION`. <br>
+    /// Returns `None` when the extensions could not be probed.
+    pub fn summary(&self) -> Option<String> {
+        if !self.probed {
+            return None;
+        }
+        let mut lines = vec!["Statistics extensions:".to_string()];
+        for (extension, state) in STAT_EXTENSIONS.iter().zip(&self.states) {
+            let line = match state {
+                ExtensionState::Enabled(version) => {
+                    let name = format!("{} {}", extension.name, version);
+                    format!("    {} {:<28} - {}", glyph("\u{2713}", "+"), name, extension.provides).green().to_string()
+                }
+                ExtensionState::NotPreloaded => format!(
+                    "    {} {:<28} - {}; add it to shared_preload_libraries and restart the server",
+                    glyph("\u{2717}", "x"),
+                    extension.name,
+                    extension.provides
+                )
+                .yellow()
+                .to_string(),
+                ExtensionState::Available { needs_preload } => format!(
+                    "    {} {:<28} - {}; run CREATE EXTENSION {};{}",
+                    glyph("\u{2717}", "x"),
+                    extension.name,
+                    extension.provides,
+                    extension.name,
+                    if *needs_preload { " and add it to shared_preload_libraries" } else { "" }
+                ),
+                ExtensionState::Missing => format!(
+                    "    {} {:<28} - {}; install the {} package on the server first",
+                    glyph("\u{2717}", "x"),
+                    extension.name,
+                    extension.provides,
+                    extension.package
+                )
+                .dimmed()
+                .to_string(),
+            };
+            lines.push(line);
+        }
+        Some(lines.join("\n"))
+    }
+}
+
+/// Checks which `STAT_EXTENSIONS` the database `client` is connected to can use. An extension needing preloading
+/// counts as enabled when the role cannot read `shared_preload_libraries`. If the probe fails the capabilities are
+/// left unknown.
+pub(crate) fn probe(client: &mut Client) -> Capabilities {
+    let names: Vec<&str> = STAT_EXTENSIONS.iter().map(|e| e.name).collect();
+    let rows = match client.query(CAPABILITIES_QUERY, &[&names]) {
+        Ok(rows) => rows,
+        Err(_) => return Capabilities::default(),
+    };
+    let states = STAT_EXTENSIONS
+        .iter()
+        .zip(&rows)
+        .map(|(extension, row)| {
+            let version: Option<String> = row.try_get(0).unwrap_or(None);
+            let available: bool = row.try_get(1).unwrap_or(false);
+            let preloaded: Option<bool> = row.try_get(2).unwrap_or(None);
+            match version {
+                Some(_) if extension.preload && preloaded == Some(false) => ExtensionState::NotPreloaded,
+                Some(version) => ExtensionState::Enabled(version),
+                None if available => ExtensionState::Available {
+                    needs_preload: extension.preload && preloaded == Some(false),
+                },
+                None => ExtensionState::Missing,
+            }
+        })
+        .collect();
+    Capabilities { probed: true, states }
+}
+
+impl Connection {
+    /// Probes the statistics extensions of the database connected to again, e.g. after a `CREATE EXTENSION`, and
+    /// returns their `Capabilities::summary`. <br>
+    /// Returns `ClientEmpty` when not connected and `UnexpectedResult` when the probe fails.
+    pub fn capabilities_summary(&mut self) -> Result<String, PGError> {
+        match &mut self.client {
+            Some(c) => self.capabilities = probe(c),
+            None => return Err(PGError::ClientEmpty),
+        }
+        self.capabilities.summary().ok_or(PGError::UnexpectedResult)
+    }
+}
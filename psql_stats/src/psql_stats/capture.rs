@@ -0,0 +1,334 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use serde_json::{json, Value};
+
+use super::delta::{changes, Delta};
+use super::humanize::{human_duration, parse_duration};
+use super::output::OutputFormatter;
+use super::{shorten_queries, Config, Connection, PGError};
+
+/// How often `Connection::capture_workload` samples the sessions
+const SAMPLE_INTERVAL: Duration = Duration::from_secs(1);
+
+/// How many query shapes, users and tables a capture lists
+const CAPTURE_TOP: usize = 10;
+
+/// Comment marking the queries of a capture, so the statements of the capture itself can be told apart in
+/// `pg_stat_statements`, which keeps the text of the first query of each shape, comments included
+const CAPTURE_MARKER: &str = "/* psql_stats capture */";
+
+/// Query listing the sessions of client backends but the capture's own, sampled every `SAMPLE_INTERVAL`
+const CAPTURE_ACTIVITY_QUERY: &str = r#"/* psql_stats capture */
+    SELECT pid, usename AS user, state, query
+    FROM pg_stat_activity
+    WHERE backend_type = 'client backend'
+      AND pid <> pg_backend_pid()
+"#;
+
+/// Query reading the transaction and row counters of the current database, at the start and end of a capture
+const CAPTURE_DATABASE_QUERY: &str = r#"/* psql_stats capture */
+    SELECT xact_commit AS commits,
+           xact_rollback AS rollbacks,
+           tup_returned + tup_fetched AS rows_read,
+           tup_inserted + tup_updated + tup_deleted AS rows_written
+    FROM pg_stat_database
+    WHERE datname = current_database()
+"#;
+
+/// Query reading the rows read and written per table of the current database, at the start and end of a capture
+const CAPTURE_TABLES_QUERY: &str = r#"/* psql_stats capture */
+    SELECT schemaname AS schema,
+           relname AS table_name,
+           seq_tup_read + coalesce(idx_tup_fetch, 0) AS rows_read,
+           n_tup_ins + n_tup_upd + n_tup_del AS rows_written,
+           seq_scan AS seq_scans,
+           coalesce(idx_scan, 0) AS index_scans
+    FROM pg_stat_user_tables
+"#;
+
+/// Query reading the calls, execution time and rows of every statement of the current database from
+/// `pg_stat_statements`, at the start and end of a capture. <br>
+/// The execution time is read through `to_jsonb` as the column was renamed from `total_time` to `total_exec_time` in
+/// version 1.8 of the extension.
+const CAPTURE_STATEMENTS_QUERY: &str = r#"/* psql_stats capture */
+    SELECT pg_get_userbyid(s.userid) AS user,
+           s.queryid::text AS query_id,
+           s.query,
+           s.calls,
+           coalesce(to_jsonb(s) ->> 'total_exec_time', to_jsonb(s) ->> 'total_time')::float8 AS total_ms,
+           s.rows
+    FROM pg_stat_statements s
+    WHERE s.dbid = (SELECT oid FROM pg_database WHERE datname = current_database())
+"#;
+
+/// How the two samples of `CAPTURE_TABLES_QUERY` are compared
+const TABLES_DELTA: Delta = Delta {
+    keys: &["schema", "table_name"],
+    counters: &["rows_read", "rows_written", "seq_scans", "index_scans"],
+    sort_by: "rows_read",
+};
+
+/// How the two samples of `CAPTURE_STATEMENTS_QUERY` are compared, the query text is a key so that it is kept
+const STATEMENTS_DELTA: Delta = Delta {
+    keys: &["user", "query_id", "query"],
+    counters: &["calls", "total_ms", "rows"],
+    sort_by: "total_ms",
+};
+
+/// What the sessions of one role did over a capture
+#[derive(Default)]
+struct UserActivity {
+    /// The sessions seen, active or not
+    sessions: BTreeSet<i64>,
+    /// How many times one of its sessions was seen running a query
+    active_samples: u64,
+}
+
+/// Parses the value of `capture --duration`, see `parse_duration`
+pub(crate) fn parse_capture_duration(text: &str) -> Result<u64, String> {
+    parse_duration(text).ok_or_else(|| "expected a duration such as 90, 30s, 5m or 1h".to_string())
+}
+
+impl Connection {
+    /// Watches the workload of the current database for `seconds`, as a lightweight alternative to logging every
+    /// statement: the sessions are sampled every second and the counters of `pg_stat_database`, `pg_stat_user_tables`
+    /// and, when the extension is enabled, `pg_stat_statements` are compared at the start and the end. <br>
+    /// Returns an object with a `summary` of the window (active sessions, transactions, rows read against written),
+    /// the top `query_shapes` by execution time, or by how often they were seen running without
+    /// `pg_stat_statements`, and the busiest `users` and `tables`.
+    pub fn capture_workload(&mut self, seconds: u64, query_width: usize) -> Result<Value, PGError> {
+        let statements = self.capabilities.probed && self.capabilities.allows(Some("pg_stat_statements"));
+
+        let first_database = self.query_json(CAPTURE_DATABASE_QUERY)?;
+        let first_tables = self.query_json(CAPTURE_TABLES_QUERY)?;
+        let first_statements = match statements {
+            true => Some(self.query_json(CAPTURE_STATEMENTS_QUERY)?),
+            false => None,
+        };
+
+        let window = Duration::from_secs(seconds);
+        let started = Instant::now();
+        let mut samples = 0u64;
+        let mut active_total = 0u64;
+        let mut active_peak = 0u64;
+        let mut users: BTreeMap<String, UserActivity> = BTreeMap::new();
+        let mut shapes: BTreeMap<String, u64> = BTreeMap::new();
+        while started.elapsed() < window {
+            let sampled_at = Instant::now();
+            let sessions = self.query_json(CAPTURE_ACTIVITY_QUERY)?;
+            let mut active = 0;
+            for session in sessions.as_array().into_iter().flatten() {
+                let user = users.entry(session["user"].as_str().unwrap_or("").to_string()).or_default();
+                user.sessions.insert(session["pid"].as_i64().unwrap_or(0));
+                if session["state"].as_str() == Some("active") {
+                    user.active_samples += 1;
+                    active += 1;
+                    *shapes.entry(query_shape(session["query"].as_str().unwrap_or(""))).or_default() += 1;
+                }
+            }
+            samples += 1;
+            active_total += active;
+            active_peak = active_peak.max(active);
+            let left = window.saturating_sub(started.elapsed());
+            thread::sleep(SAMPLE_INTERVAL.saturating_sub(sampled_at.elapsed()).min(left));
+        }
+        let elapsed = started.elapsed().as_secs_f64();
+
+        let last_database = self.query_json(CAPTURE_DATABASE_QUERY)?;
+        let last_tables = self.query_json(CAPTURE_TABLES_QUERY)?;
+        let statement_changes = match first_statements {
+            Some(first) => {
+                let last = self.query_json(CAPTURE_STATEMENTS_QUERY)?;
+                let mut rows = changes(&first, &last, &STATEMENTS_DELTA);
+                if let Value::Array(rows) = &mut rows {
+                    rows.retain(|row| !row["query"].as_str().unwrap_or("").contains(CAPTURE_MARKER));
+                }
+                Some(rows)
+            }
+            None => None,
+        };
+
+        let summary = summarize(&first_database[0], &last_database[0], elapsed, samples, active_total, active_peak);
+        let query_shapes = match &statement_changes {
+            Some(rows) => top_statements(rows, query_width),
+            None => top_shapes(&shapes, active_total, query_width),
+        };
+        Ok(json!({
+            "summary": summary,
+            "pg_stat_statements": statement_changes.is_some(),
+            "query_shapes": query_shapes,
+            "users": busiest_users(&users, active_total, statement_changes.as_ref()),
+            "tables": busiest_tables(&changes(&first_tables, &last_tables, &TABLES_DELTA)),
+        }))
+    }
+}
+
+/// Rounds `value` to `places` decimals
+fn round(value: f64, places: i32) -> f64 {
+    let factor = 10f64.powi(places);
+    (value * factor).round() / factor
+}
+
+/// The shape of a query, with its string and number literals replaced by `?` and on one line, so that the samples of
+/// the same statement run with other values are counted together
+fn query_shape(query: &str) -> String {
+    let mut shape = String::new();
+    let mut chars = query.chars().peekable();
+    let mut previous = ' ';
+    while let Some(c) = chars.next() {
+        if c == '\'' {
+            // Quotes within a literal are doubled, which reads as a literal ending and another starting
+            for c in chars.by_ref() {
+                if c == '\'' {
+                    break;
+                }
+            }
+            if !shape.ends_with('?') {
+                shape.push('?');
+            }
+        } else if c.is_ascii_digit() && !(previous.is_alphanumeric() || previous == '_' || previous == '$') {
+            while chars.peek().is_some_and(|c| c.is_ascii_digit() || *c == '.') {
+                chars.next();
+            }
+            shape.push('?');
+        } else {
+            shape.push(c);
+        }
+        previous = c;
+    }
+    shape.split_whitespace().collect::<Vec<&str>>().join(" ")
+}
+
+/// The summary of a capture: how long it ran, the sessions active on average and at most, and how much the
+/// transaction and row counters of `CAPTURE_DATABASE_QUERY` grew, with the share of the rows that were read
+fn summarize(first: &Value, last: &Value, elapsed: f64, samples: u64, active_total: u64, active_peak: u64) -> Value {
+    let grown = |column: &str| last[column].as_i64().unwrap_or(0) - first[column].as_i64().unwrap_or(0);
+    let (read, written) = (grown("rows_read"), grown("rows_written"));
+    json!({
+        "duration": human_duration(elapsed),
+        "samples": samples,
+        "average_active_sessions": round(active_total as f64 / samples.max(1) as f64, 2),
+        "peak_active_sessions": active_peak,
+        "commits": grown("commits"),
+        "commits_per_sec": round(grown("commits") as f64 / elapsed.max(1.0), 2),
+        "rollbacks": grown("rollbacks"),
+        "rows_read": read,
+        "rows_written": written,
+        "read_percent": match read + written {
+            0 => Value::Null,
+            total => json!(round(100.0 * read as f64 / total as f64, 1)),
+        },
+        "reads_per_write": match written {
+            0 => Value::Null,
+            _ => json!(round(read as f64 / written as f64, 1)),
+        },
+    })
+}
+
+/// The statements that took the most execution time over the capture, with their mean time per call
+fn top_statements(rows: &Value, query_width: usize) -> Value {
+    let mut top: Value = rows
+        .as_array()
+        .into_iter()
+        .flatten()
+        .take(CAPTURE_TOP)
+        .map(|row| {
+            let calls = row["calls"].as_f64().unwrap_or(0.0);
+            let total_ms = row["total_ms"].as_f64().unwrap_or(0.0);
+            json!({
+                "calls": row["calls"],
+                "total_ms": round(total_ms, 2),
+                "mean_ms": round(total_ms / calls.max(1.0), 2),
+                "rows": row["rows"],
+                "user": row["user"],
+                "query": row["query"],
+            })
+        })
+        .collect();
+    shorten_queries(&mut top, query_width);
+    top
+}
+
+/// The query shapes seen running most often over the capture, with their share of the samples of active sessions,
+/// as the time spent in them is only known from `pg_stat_statements`
+fn top_shapes(shapes: &BTreeMap<String, u64>, active_total: u64, query_width: usize) -> Value {
+    let mut sorted: Vec<(&String, &u64)> = shapes.iter().collect();
+    sorted.sort_by_key(|(_, samples)| std::cmp::Reverse(**samples));
+    let mut top: Value = sorted
+        .into_iter()
+        .take(CAPTURE_TOP)
+        .map(|(shape, samples)| {
+            json!({
+                "active_samples": samples,
+                "active_percent": round(100.0 * *samples as f64 / active_total.max(1) as f64, 1),
+                "query": shape,
+            })
+        })
+        .collect();
+    shorten_queries(&mut top, query_width);
+    top
+}
+
+/// The roles whose sessions were seen running queries most often, with how many sessions they had, and the calls
+/// and execution time of their statements when `pg_stat_statements` was read
+fn busiest_users(users: &BTreeMap<String, UserActivity>, active_total: u64, statements: Option<&Value>) -> Value {
+    let mut executed: BTreeMap<&str, (i64, f64)> = BTreeMap::new();
+    for row in statements.and_then(|rows| rows.as_array()).into_iter().flatten() {
+        let user = executed.entry(row["user"].as_str().unwrap_or("")).or_default();
+        user.0 += row["calls"].as_i64().unwrap_or(0);
+        user.1 += row["total_ms"].as_f64().unwrap_or(0.0);
+    }
+
+    let mut rows: Vec<(u64, f64, Value)> = users
+        .iter()
+        .map(|(name, activity)| {
+            let mut row = json!({
+                "user": name,
+                "sessions": activity.sessions.len(),
+                "active_samples": activity.active_samples,
+                "active_percent": round(100.0 * activity.active_samples as f64 / active_total.max(1) as f64, 1),
+            });
+            let (calls, total_ms) = executed.get(name.as_str()).copied().unwrap_or_default();
+            if statements.is_some() {
+                row["calls"] = json!(calls);
+                row["total_ms"] = json!(round(total_ms, 2));
+            }
+            (activity.active_samples, total_ms, row)
+        })
+        .collect();
+    rows.sort_by(|a, b| b.0.cmp(&a.0).then(b.1.total_cmp(&a.1)));
+    Value::Array(rows.into_iter().take(CAPTURE_TOP).map(|(_, _, row)| row).collect())
+}
+
+/// The tables with the most rows read and written over the capture, from the changes of `CAPTURE_TABLES_QUERY`
+fn busiest_tables(changes: &Value) -> Value {
+    let total = |row: &Value| row["rows_read"].as_i64().unwrap_or(0) + row["rows_written"].as_i64().unwrap_or(0);
+    let mut rows: Vec<Value> = changes.as_array().cloned().unwrap_or_default();
+    rows.retain(|row| total(row) > 0);
+    rows.sort_by_key(|row| std::cmp::Reverse(total(row)));
+    rows.truncate(CAPTURE_TOP);
+    Value::Array(rows)
+}
+
+/// Renders what `Connection::capture_workload` returns: in JSON as one object, otherwise the summary, query shapes,
+/// users and tables each in its own table
+pub fn render_capture(report: &Value, config: &Config) -> String {
+    if config.output_format == "json" {
+        return serde_json::to_string_pretty(report).expect("Failed to serialize the capture");
+    }
+    let formatter = OutputFormatter::new(config);
+    let shapes_heading = match report["pg_stat_statements"].as_bool() {
+        Some(true) => "Top query shapes by execution time",
+        _ => "Query shapes seen running most often (pg_stat_statements is not enabled, so no timings)",
+    };
+    let heading = format!("Workload over {}", report["summary"]["duration"].as_str().unwrap_or(""));
+    [
+        formatter.titled(&heading, formatter.rows(&json!([report["summary"]]))),
+        formatter.titled(shapes_heading, formatter.rows(&report["query_shapes"])),
+        formatter.titled("Busiest users", formatter.rows(&report["users"])),
+        formatter.titled("Busiest tables by rows read and written", formatter.rows(&report["tables"])),
+    ]
+    .join("\n")
+}
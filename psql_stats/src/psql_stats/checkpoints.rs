@@ -0,0 +1,218 @@
+use std::thread;
+use std::time::Duration;
+
+use serde_json::{json, Value};
+
+use super::humanize::{human_bytes, human_duration};
+use super::versions::{pick, QueryVariant};
+use super::{Connection, PGError};
+
+/// Server version from which the checkpoint counters moved from `pg_stat_bgwriter` to `pg_stat_checkpointer`
+pub(crate) const CHECKPOINTER_VIEW_VERSION: i32 = 170000;
+
+/// Query reading the checkpoint counters from `pg_stat_bgwriter`, with the seconds since they were reset and the
+/// settings deciding how often checkpoints should happen. `checkpoint_timeout` is in seconds, `max_wal_size` in bytes.
+const BGWRITER_CHECKPOINTS_QUERY: &str = r#"
+    SELECT checkpoints_timed, checkpoints_req,
+           extract(epoch FROM now() - stats_reset)::float8,
+           extract(epoch FROM current_setting('checkpoint_timeout')::interval)::float8,
+           pg_size_bytes(current_setting('max_wal_size'))
+    FROM pg_stat_bgwriter
+"#;
+
+/// Same as `BGWRITER_CHECKPOINTS_QUERY` from Postgres 17 on
+const CHECKPOINTER_CHECKPOINTS_QUERY: &str = r#"
+    SELECT num_timed, num_requested,
+           extract(epoch FROM now() - stats_reset)::float8,
+           extract(epoch FROM current_setting('checkpoint_timeout')::interval)::float8,
+           pg_size_bytes(current_setting('max_wal_size'))
+    FROM pg_stat_checkpointer
+"#;
+
+/// The form of `CHECKPOINTER_CHECKPOINTS_QUERY` for the servers before Postgres 17, see `versions::pick`
+const CHECKPOINTS_VARIANTS: &[QueryVariant] = &[QueryVariant {
+    before: CHECKPOINTER_VIEW_VERSION,
+    query: BGWRITER_CHECKPOINTS_QUERY,
+}];
+
+/// Share of requested checkpoints from which they are worth a warning, the odd one comes from a manual CHECKPOINT,
+/// a base backup or a server shutdown
+const REQUESTED_WARNING_SHARE: f64 = 0.1;
+
+/// Checkpoints are only said to come too often when they are this much closer together than `checkpoint_timeout`,
+/// as timed checkpoints drift a little past it
+const INTERVAL_WARNING_RATIO: f64 = 0.9;
+
+/// The checkpoint counters at one point in time, see `Connection::checkpoint_sample`
+struct CheckpointSample {
+    timed: i64,
+    requested: i64,
+    seconds_since_reset: f64,
+    timeout_seconds: f64,
+    max_wal_size: i64,
+}
+
+/// How often checkpoints happened over a period, and what triggered them
+pub struct CheckpointStats {
+    /// Length of the period in seconds
+    pub seconds: f64,
+    /// Checkpoints started because `checkpoint_timeout` elapsed
+    pub timed: i64,
+    /// Checkpoints started before that, mostly because `max_wal_size` of WAL was written
+    pub requested: i64,
+    /// `checkpoint_timeout` in seconds
+    pub timeout_seconds: f64,
+    /// `max_wal_size` in bytes
+    pub max_wal_size: i64,
+}
+
+impl CheckpointStats {
+    /// Average seconds between checkpoints, `None` when there was none
+    pub fn average_interval(&self) -> Option<f64> {
+        match self.timed + self.requested {
+            0 => None,
+            n => Some(self.seconds / n as f64),
+        }
+    }
+
+    /// Share of the checkpoints that were requested rather than timed, `None` when there was none
+    pub fn requested_share(&self) -> Option<f64> {
+        match self.timed + self.requested {
+            0 => None,
+            n => Some(self.requested as f64 / n as f64),
+        }
+    }
+
+    /// The stats as a single row, with durations and sizes written out
+    pub fn to_json(&self) -> Value {
+        json!([{
+            "period": human_duration(self.seconds),
+            "checkpoints": self.timed + self.requested,
+            "timed": self.timed,
+            "requested": self.requested,
+            "requested_percent": self.requested_share().map(|s| (s * 1000.0).round() / 10.0),
+            "average_interval": self.average_interval().map(human_duration),
+            "checkpoint_timeout": human_duration(self.timeout_seconds),
+            "max_wal_size": human_bytes(self.max_wal_size),
+        }])
+    }
+
+    /// Warns when checkpoints came more often than `checkpoint_timeout` implies, or were mostly requested, which
+    /// usually means `max_wal_size` fills up before the timeout. <br>
+    /// Suggests a `max_wal_size` that would hold the WAL written over a whole `checkpoint_timeout` at the same pace.
+    pub fn warnings(&self) -> Vec<String> {
+        let (interval, share) = match (self.average_interval(), self.requested_share()) {
+            (Some(interval), Some(share)) => (interval, share),
+            _ => return vec![],
+        };
+
+        let mut warnings = vec![];
+        // A single checkpoint in a period shorter than checkpoint_timeout says nothing about how often they come
+        let enough = self.timed + self.requested > 1 || self.seconds >= self.timeout_seconds;
+        let too_often = enough && interval < self.timeout_seconds * INTERVAL_WARNING_RATIO;
+        if too_often {
+            warnings.push(format!(
+                "Checkpoints happened every {} on average, more often than checkpoint_timeout ({}) implies.",
+                human_duration(interval),
+                human_duration(self.timeout_seconds)
+            ));
+        }
+        if share >= REQUESTED_WARNING_SHARE {
+            warnings.push(format!(
+                "{:.0}% of checkpoints were requested rather than timed, most likely because max_wal_size ({}) of \
+                 WAL was written before checkpoint_timeout elapsed.",
+                share * 100.0,
+                human_bytes(self.max_wal_size)
+            ));
+        }
+        if too_often && self.requested > 0 {
+            let suggested = self.max_wal_size as f64 * self.timeout_seconds / interval;
+            warnings.push(format!(
+                "Consider raising max_wal_size to about {} so checkpoints are spread out by checkpoint_timeout \
+                 instead, each one then writes fewer full page images.",
+                human_bytes(round_up_to_gigabyte(suggested))
+            ));
+        }
+        warnings
+    }
+}
+
+/// Rounds a size in bytes up to a whole number of gigabytes, as max_wal_size is usually set
+fn round_up_to_gigabyte(bytes: f64) -> i64 {
+    const GIGABYTE: f64 = 1024.0 * 1024.0 * 1024.0;
+    ((bytes / GIGABYTE).ceil() * GIGABYTE) as i64
+}
+
+impl Connection {
+    /// The version of the server as a number, e.g. 150004 for 15.4, to pick the views a version has. The one read on
+    /// connect unless it could not be read then
+    pub fn server_version_num(&mut self) -> Result<i32, PGError> {
+        let client = match &mut self.client {
+            Some(c) => c,
+            None => return Err(PGError::ClientEmpty),
+        };
+        if self.server_version > 0 {
+            return Ok(self.server_version);
+        }
+        client
+            .query_one("SELECT current_setting('server_version_num')::int", &[])
+            .map(|row| row.get::<_, i32>(0))
+            .map_err(|e| PGError::query("reading the server version", e))
+    }
+
+    /// Reads the checkpoint counters from `pg_stat_bgwriter`, or `pg_stat_checkpointer` from Postgres 17 on
+    fn checkpoint_sample(&mut self) -> Result<CheckpointSample, PGError> {
+        let version = self.server_version_num()?;
+        let client = match &mut self.client {
+            Some(c) => c,
+            None => return Err(PGError::ClientEmpty),
+        };
+        let query = pick(version, CHECKPOINTER_CHECKPOINTS_QUERY, CHECKPOINTS_VARIANTS);
+        let row = client.query_one(query, &[]).map_err(|e| PGError::query("reading the checkpoint statistics", e))?;
+        Ok(CheckpointSample {
+            timed: row.get(0),
+            requested: row.get(1),
+            seconds_since_reset: row.get::<_, Option<f64>>(2).unwrap_or(0.0),
+            timeout_seconds: row.get(3),
+            max_wal_size: row.get(4),
+        })
+    }
+
+    /// Counts the timed and requested checkpoints since the statistics were last reset or, given `seconds`, over
+    /// that many seconds from now, sampling the counters at both ends. <br>
+    /// See `CheckpointStats::warnings` for what to make of them.
+    pub fn get_checkpoint_stats(&mut self, seconds: Option<u64>) -> Result<CheckpointStats, PGError> {
+        let first = self.checkpoint_sample()?;
+        let seconds = match seconds {
+            Some(seconds) => seconds,
+            None => {
+                return Ok(CheckpointStats {
+                    seconds: first.seconds_since_reset,
+                    timed: first.timed,
+                    requested: first.requested,
+                    timeout_seconds: first.timeout_seconds,
+                    max_wal_size: first.max_wal_size,
+                })
+            }
+        };
+
+        thread::sleep(Duration::from_secs(seconds));
+        let last = self.checkpoint_sample()?;
+        let (period, timed, requested) = match last.seconds_since_reset >= first.seconds_since_reset {
+            true => (
+                last.seconds_since_reset - first.seconds_since_reset,
+                last.timed - first.timed,
+                last.requested - first.requested,
+            ),
+            // The statistics were reset in between, so the counters started over from there
+            false => (last.seconds_since_reset, last.timed, last.requested),
+        };
+        Ok(CheckpointStats {
+            seconds: period,
+            timed,
+            requested,
+            timeout_seconds: last.timeout_seconds,
+            max_wal_size: last.max_wal_size,
+        })
+    }
+}
@@ -0,0 +1,128 @@
+use postgres::types::ToSql;
+use serde_json::{Map, Value};
+use tracing::warn;
+
+use super::collectors::Collector;
+use super::{Connection, PGError};
+
+/// Databases that accept connections, leaving out templates
+const DATABASES_QUERY: &str = r#"
+    SELECT datname FROM pg_database
+    WHERE NOT datistemplate AND datallowconn
+    ORDER BY datname
+"#;
+
+/// Objects in the current database that depend on the role `$1`, i.e. everything `DROP ROLE` would complain about
+const ROLE_DEPENDENCIES_QUERY: &str = r#"
+    SELECT CASE d.deptype
+                WHEN 'o' THEN 'owner'
+                WHEN 'a' THEN 'privileges'
+                WHEN 'r' THEN 'policy'
+                WHEN 'i' THEN 'initial privileges'
+                ELSE d.deptype::text
+           END AS dependency,
+           pg_describe_object(d.classid, d.objid, d.objsubid) AS object
+    FROM pg_shdepend d
+    WHERE d.refclassid = 'pg_authid'::regclass
+    AND d.refobjid = (SELECT oid FROM pg_roles WHERE rolname = $1)
+    AND d.dbid = (SELECT oid FROM pg_database WHERE datname = current_database())
+    ORDER BY 1, 2
+"#;
+
+/// Shared objects (databases and tablespaces) that depend on the role `$1`
+const ROLE_SHARED_DEPENDENCIES_QUERY: &str = r#"
+    SELECT '(cluster)' AS database,
+           CASE d.deptype WHEN 'o' THEN 'owner' WHEN 'a' THEN 'privileges' ELSE d.deptype::text END AS dependency,
+           pg_describe_object(d.classid, d.objid, d.objsubid) AS object
+    FROM pg_shdepend d
+    WHERE d.refclassid = 'pg_authid'::regclass
+    AND d.refobjid = (SELECT oid FROM pg_roles WHERE rolname = $1)
+    AND d.dbid = 0
+    ORDER BY 2, 3
+"#;
+
+impl Connection {
+    /// Returns the name of every database on the server that accepts connections, excluding templates
+    pub fn databases(&mut self) -> Result<Vec<String>, PGError> {
+        match &mut self.client {
+            Some(ref mut c) => match c.query(DATABASES_QUERY, &[]) {
+                Ok(rows) => Ok(rows.iter().filter_map(|r| r.try_get(0).ok()).collect()),
+                Err(e) => Err(PGError::query("listing the databases", e)),
+            },
+
+            // Client is empty, cannot run a query
+            None => Err(PGError::ClientEmpty),
+        }
+    }
+
+    /// Runs `collector` in every database on the server and combines the rows, see `query_each_database`.
+    /// Cluster wide collectors are only run once.
+    pub fn query_all_databases(&mut self, collector: &Collector) -> Result<Value, PGError> {
+        let query = collector.query_for(self.server_version);
+        if !collector.per_database {
+            return self.query_json(query);
+        }
+        self.query_each_database(query, &[])
+    }
+
+    /// Runs `query` with `params` in every database on the server, reconnecting to each in turn, and combines
+    /// the rows with a leading `database` column. <br>
+    /// Databases that cannot be connected to are skipped with a warning, and the `Connection` is returned to its
+    /// original database afterwards.
+    pub fn query_each_database(
+        &mut self,
+        query: &str,
+        params: &[&(dyn ToSql + Sync)],
+    ) -> Result<Value, PGError> {
+        let original = self.dbname.clone();
+        let mut combined = Vec::new();
+
+        for database in self.databases()? {
+            if database != self.dbname {
+                if let Err(e) = self.switch_database(&database) {
+                    warn!("skipping database \"{}\": {}", database, e);
+                    continue;
+                }
+            }
+
+            match self.query_json_with(query, params) {
+                Ok(Value::Array(rows)) => {
+                    for row in rows {
+                        let mut labeled = Map::new();
+                        labeled.insert("database".to_string(), Value::String(database.clone()));
+                        if let Value::Object(columns) = row {
+                            labeled.extend(columns);
+                        }
+                        combined.push(Value::Object(labeled));
+                    }
+                }
+                Ok(_) => {}
+                Err(e) => warn!("query failed in database \"{}\": {}", database, e),
+            }
+        }
+
+        if self.dbname != original {
+            self.switch_database(&original)?;
+        }
+        Ok(Value::Array(combined))
+    }
+
+    /// Lists everything the role `role` owns or has been granted privileges on, in every database and on shared
+    /// objects such as databases and tablespaces, i.e. what would make `DROP ROLE` fail. <br>
+    /// Returns `MatchNotFound` if there is no such role.
+    pub fn role_dependencies(&mut self, role: &str) -> Result<Value, PGError> {
+        let exists = self.query_json_with("SELECT 1 FROM pg_roles WHERE rolname = $1", &[&role])?;
+        if exists.as_array().map(|r| r.is_empty()).unwrap_or(true) {
+            return Err(PGError::MatchNotFound);
+        }
+
+        let mut dependencies = match self.query_json_with(ROLE_SHARED_DEPENDENCIES_QUERY, &[&role])? {
+            Value::Array(rows) => rows,
+            _ => Vec::new(),
+        };
+        if let Value::Array(rows) = self.query_each_database(ROLE_DEPENDENCIES_QUERY, &[&role])? {
+            dependencies.extend(rows);
+        }
+        Ok(Value::Array(dependencies))
+    }
+}
@@ -0,0 +1,1088 @@
+use std::thread;
+use std::time::Duration;
+
+use serde_json::Value;
+use tracing::{error, warn};
+
+use super::bloat::PRECISE_BLOAT_QUERY;
+use super::cache::CACHE_STATS_QUERY;
+use super::constraints::{
+    INVALID_INDEXES_QUERY, INVALID_INDEXES_VARIANTS, NOT_VALID_CONSTRAINTS_QUERY, UNINDEXED_FOREIGN_KEYS_QUERY,
+};
+use super::delta::{rates, Delta};
+use super::history::{unix_now, History};
+use super::humanize::human_duration;
+use super::jit::JIT_QUERY;
+use super::locks::LOCKS_QUERY;
+use super::parallel::{PARALLEL_QUERY, PARALLEL_STATEMENTS_QUERY, PARALLEL_VARIANTS};
+use super::privileges::Privilege;
+use super::replicas::REPLICATION_QUERY;
+use super::roles::{ROLES_QUERY, TABLE_GRANTS_QUERY};
+use super::sequences::SEQUENCES_QUERY;
+use super::routines::ROUTINES_QUERY;
+use super::settings::{FILE_SETTINGS_QUERY, SETTINGS_QUERY, SETTING_OVERRIDES_QUERY};
+use super::sizes::{DATABASE_SIZES_QUERY, TABLESPACE_SIZES_QUERY};
+use super::slots::SLOTS_QUERY;
+use super::spills::{TEMP_FILES_QUERY, TEMP_STATEMENTS_QUERY};
+use super::versions::{pick, QueryVariant};
+use super::{
+    Connection, PGError, ACTIVITY_QUERY, DATABASE_STATS_QUERY, EXTENSIONS_QUERY, INDEX_STATS_QUERY, SCHEMAS_QUERY,
+    TABLES_QUERY, TABLE_STATS_QUERY, UPTIME_QUERY, VACUUM_STATS_QUERY, VERSION_QUERY,
+};
+
+/// A named statistics query. <br>
+/// Every collector can be run from the menu by typing its `name`, is served over HTTP at `/v1/<name>` and is
+/// included in reports.
+pub struct Collector {
+    /// Name used to run the collector
+    pub name: &'static str,
+    /// One line description shown in the help menu and above the results
+    pub title: &'static str,
+    /// A single statement without a trailing semicolon, so it can be wrapped by `Connection::query_json`
+    pub query: &'static str,
+    /// Forms of `query` for older servers, whose catalogs lack columns it reads, see `Collector::query_for`
+    pub variants: &'static [QueryVariant],
+    /// Whether the results depend on the database connected to, rather than being the same cluster wide
+    pub per_database: bool,
+    /// For collectors of cumulative counters, how to report rates against the previous sample in the history
+    pub delta: Option<Delta>,
+    /// Privileges needed to see all of the results, collectors needing superuser are hidden from other roles
+    pub requires: Privilege,
+    /// Extension the query needs, one of `capabilities::STAT_EXTENSIONS`. Collectors needing one the database does
+    /// not have are hidden and refused, see `Capabilities::check`
+    pub extension: Option<&'static str>,
+    /// Whether the query reads whole relations rather than the statistics the server keeps, so it adds I/O on a busy
+    /// server. It is run only when asked for, after a warning, and left out of reports
+    pub full_scan: bool,
+}
+
+impl Collector {
+    /// The form of `query` a server of `version`, a `server_version_num`, answers, see `versions::pick`
+    pub fn query_for(&self, version: i32) -> &'static str {
+        pick(version, self.query, self.variants)
+    }
+
+    /// How an error of the collector names what failed, see `PGError::with_context`
+    pub(crate) fn context(&self) -> String {
+        format!("the {} collector", self.name)
+    }
+}
+
+/// Every available collector
+pub const COLLECTORS: &[Collector] = &[
+    Collector {
+        name: "version",
+        title: "Server version",
+        query: VERSION_QUERY,
+        variants: &[],
+        per_database: false,
+        delta: None,
+        requires: Privilege::None,
+        extension: None,
+        full_scan: false,
+    },
+    Collector {
+        name: "uptime",
+        title: "Server uptime",
+        query: UPTIME_QUERY,
+        variants: &[],
+        per_database: false,
+        delta: None,
+        requires: Privilege::None,
+        extension: None,
+        full_scan: false,
+    },
+    Collector {
+        name: "tables",
+        title: "Tables in the search_path",
+        query: TABLES_QUERY,
+        variants: &[],
+        per_database: true,
+        delta: None,
+        requires: Privilege::None,
+        extension: None,
+        full_scan: false,
+    },
+    Collector {
+        name: "schemas",
+        title: "Schemas with their owner, tables, views and size",
+        query: SCHEMAS_QUERY,
+        variants: &[],
+        per_database: true,
+        delta: None,
+        requires: Privilege::None,
+        extension: None,
+        full_scan: false,
+    },
+    Collector {
+        name: "extensions",
+        title: "Installed extensions",
+        query: EXTENSIONS_QUERY,
+        variants: &[],
+        per_database: true,
+        delta: None,
+        requires: Privilege::None,
+        extension: None,
+        full_scan: false,
+    },
+    Collector {
+        name: "table-stats",
+        title: "Tables by size, with their estimated rows and sequential and index scans",
+        query: TABLE_STATS_QUERY,
+        variants: &[],
+        per_database: true,
+        delta: None,
+        requires: Privilege::None,
+        extension: None,
+        full_scan: false,
+    },
+    Collector {
+        name: "index-stats",
+        title: "Indexes with their scans and estimated bloat, unused and duplicate ones first",
+        query: INDEX_STATS_QUERY,
+        variants: &[],
+        per_database: true,
+        delta: None,
+        requires: Privilege::None,
+        extension: None,
+        full_scan: false,
+    },
+    Collector {
+        name: "vacuum-stats",
+        title: "Tables with their last vacuum and analyze and dead tuples, those overdue a vacuum first",
+        query: VACUUM_STATS_QUERY,
+        variants: &[],
+        per_database: true,
+        delta: None,
+        requires: Privilege::None,
+        extension: None,
+        full_scan: false,
+    },
+    Collector {
+        name: "precise-bloat",
+        title: "Dead tuples and free space of tables and B-tree indexes up to 1 GB, measured with pgstattuple",
+        query: PRECISE_BLOAT_QUERY,
+        variants: &[],
+        per_database: true,
+        delta: None,
+        requires: Privilege::None,
+        extension: Some("pgstattuple"),
+        full_scan: true,
+    },
+    Collector {
+        name: "activity",
+        title: "Sessions connected to the server",
+        query: ACTIVITY_QUERY,
+        variants: &[],
+        per_database: false,
+        delta: None,
+        requires: Privilege::Monitor,
+        extension: None,
+        full_scan: false,
+    },
+    Collector {
+        name: "connection-states",
+        title: "Client connections by application, user and database, and how many are active, idle or waiting",
+        query: CONNECTION_STATES_QUERY,
+        variants: &[],
+        per_database: false,
+        delta: None,
+        requires: Privilege::Monitor,
+        extension: None,
+        full_scan: false,
+    },
+    Collector {
+        name: "locks",
+        title: "Sessions waiting on locks and the sessions blocking them",
+        query: LOCKS_QUERY,
+        variants: &[],
+        per_database: false,
+        delta: None,
+        requires: Privilege::Monitor,
+        extension: None,
+        full_scan: false,
+    },
+    Collector {
+        name: "autovacuum",
+        title: "Running autovacuum workers with their table and phase, and how many are configured",
+        query: AUTOVACUUM_QUERY,
+        variants: &[],
+        per_database: false,
+        delta: None,
+        requires: Privilege::Monitor,
+        extension: None,
+        full_scan: false,
+    },
+    Collector {
+        name: "replication",
+        title: "Standbys streaming from this server, with their lag",
+        query: REPLICATION_QUERY,
+        variants: &[],
+        per_database: false,
+        delta: None,
+        requires: Privilege::Monitor,
+        extension: None,
+        full_scan: false,
+    },
+    Collector {
+        name: "replication-slots",
+        title: "Replication slots with the WAL they retain, inactive ones holding WAL back first",
+        query: SLOTS_QUERY,
+        variants: &[],
+        per_database: false,
+        delta: None,
+        requires: Privilege::None,
+        extension: None,
+        full_scan: false,
+    },
+    Collector {
+        name: "parallel",
+        title: "Parallel worker limits and the parallel workers running, to see whether parallelism is used",
+        query: PARALLEL_QUERY,
+        variants: PARALLEL_VARIANTS,
+        per_database: false,
+        delta: None,
+        requires: Privilege::None,
+        extension: None,
+        full_scan: false,
+    },
+    Collector {
+        name: "parallel-statements",
+        title: "Statements planning parallel workers and how many were launched (pg_stat_statements 1.12 on)",
+        query: PARALLEL_STATEMENTS_QUERY,
+        variants: &[],
+        per_database: false,
+        delta: None,
+        requires: Privilege::Monitor,
+        extension: Some("pg_stat_statements"),
+        full_scan: false,
+    },
+    Collector {
+        name: "jit",
+        title: "JIT settings, the share of statement time spent compiling and the statements compiling the most",
+        query: JIT_QUERY,
+        variants: &[],
+        per_database: false,
+        delta: None,
+        requires: Privilege::Monitor,
+        extension: Some("pg_stat_statements"),
+        full_scan: false,
+    },
+    Collector {
+        name: "temp-files",
+        title: "Temporary files written by every database, with work_mem",
+        query: TEMP_FILES_QUERY,
+        variants: &[],
+        per_database: false,
+        delta: None,
+        requires: Privilege::None,
+        extension: None,
+        full_scan: false,
+    },
+    Collector {
+        name: "temp-statements",
+        title: "Statements writing the most to temporary files, with how much per call",
+        query: TEMP_STATEMENTS_QUERY,
+        variants: &[],
+        per_database: false,
+        delta: None,
+        requires: Privilege::Monitor,
+        extension: Some("pg_stat_statements"),
+        full_scan: false,
+    },
+    Collector {
+        name: "settings",
+        title: "Settings changed from their defaults, with their source",
+        query: SETTINGS_QUERY,
+        variants: &[],
+        per_database: true,
+        delta: None,
+        requires: Privilege::None,
+        extension: None,
+        full_scan: false,
+    },
+    Collector {
+        name: "setting-overrides",
+        title: "Settings set per role or per database",
+        query: SETTING_OVERRIDES_QUERY,
+        variants: &[],
+        per_database: false,
+        delta: None,
+        requires: Privilege::None,
+        extension: None,
+        full_scan: false,
+    },
+    Collector {
+        name: "config-file",
+        title: "Configuration file entries with errors and overridden duplicates (requires superuser or a grant)",
+        query: FILE_SETTINGS_QUERY,
+        variants: &[],
+        per_database: false,
+        delta: None,
+        requires: Privilege::FileSettings,
+        extension: None,
+        full_scan: false,
+    },
+    Collector {
+        name: "role-auth",
+        title: "Role password methods and expiry (requires superuser)",
+        query: ROLE_AUTH_QUERY,
+        variants: &[],
+        per_database: false,
+        delta: None,
+        requires: Privilege::Superuser,
+        extension: None,
+        full_scan: false,
+    },
+    Collector {
+        name: "roles",
+        title: "Roles with their attributes, connection limit, expiry and memberships",
+        query: ROLES_QUERY,
+        variants: &[],
+        per_database: false,
+        delta: None,
+        requires: Privilege::None,
+        extension: None,
+        full_scan: false,
+    },
+    Collector {
+        name: "table-grants",
+        title: "Privileges granted on tables and views, per grantee",
+        query: TABLE_GRANTS_QUERY,
+        variants: &[],
+        per_database: true,
+        delta: None,
+        requires: Privilege::None,
+        extension: None,
+        full_scan: false,
+    },
+    Collector {
+        name: "default-privileges",
+        title: "Privileges granted by ALTER DEFAULT PRIVILEGES",
+        query: DEFAULT_PRIVILEGES_QUERY,
+        variants: &[],
+        per_database: true,
+        delta: None,
+        requires: Privilege::None,
+        extension: None,
+        full_scan: false,
+    },
+    Collector {
+        name: "rls",
+        title: "Row-level security policies",
+        query: RLS_QUERY,
+        variants: &[],
+        per_database: true,
+        delta: None,
+        requires: Privilege::None,
+        extension: None,
+        full_scan: false,
+    },
+    Collector {
+        name: "public-exposure",
+        title: "Objects granted to PUBLIC and unsafe SECURITY DEFINER functions",
+        query: PUBLIC_EXPOSURE_QUERY,
+        variants: &[],
+        per_database: true,
+        delta: None,
+        requires: Privilege::None,
+        extension: None,
+        full_scan: false,
+    },
+    Collector {
+        name: "functions",
+        title: "Functions and procedures with their arguments, language, volatility and security",
+        query: ROUTINES_QUERY,
+        variants: &[],
+        per_database: true,
+        delta: None,
+        requires: Privilege::None,
+        extension: None,
+        full_scan: false,
+    },
+    Collector {
+        name: "event-triggers",
+        title: "Event triggers with their functions and enabled state",
+        query: EVENT_TRIGGERS_QUERY,
+        variants: &[],
+        per_database: true,
+        delta: None,
+        requires: Privilege::None,
+        extension: None,
+        full_scan: false,
+    },
+    Collector {
+        name: "write-churn",
+        title: "Tables ranked by inserted, updated and deleted rows per second",
+        query: WRITE_CHURN_QUERY,
+        variants: &[],
+        per_database: true,
+        delta: Some(Delta {
+            keys: &["schema", "table_name"],
+            counters: &["inserts", "updates", "deletes", "writes"],
+            sort_by: "writes",
+        }),
+        requires: Privilege::None,
+        extension: None,
+        full_scan: false,
+    },
+    Collector {
+        name: "toast-reads",
+        title: "Blocks tables read from TOAST rather than the table itself, those reading mostly TOAST flagged",
+        query: TOAST_READS_QUERY,
+        variants: &[],
+        per_database: true,
+        delta: Some(Delta {
+            keys: &["schema", "table_name"],
+            counters: &["scans", "heap_blocks", "toast_blocks", "toast_blocks_from_disk"],
+            sort_by: "toast_blocks",
+        }),
+        requires: Privilege::None,
+        extension: None,
+        full_scan: false,
+    },
+    Collector {
+        name: "cache-stats",
+        title: "Blocks of every table and index found in shared buffers and read from disk, most read first",
+        query: CACHE_STATS_QUERY,
+        variants: &[],
+        per_database: true,
+        delta: Some(Delta {
+            keys: &["schema", "relation"],
+            counters: &["blocks_hit", "blocks_read"],
+            sort_by: "blocks_read",
+        }),
+        requires: Privilege::None,
+        extension: None,
+        full_scan: false,
+    },
+    Collector {
+        name: "table-activity",
+        title: "Scans, rows read and written, vacuums and analyzes per table",
+        query: TABLE_ACTIVITY_QUERY,
+        variants: &[],
+        per_database: true,
+        delta: Some(Delta {
+            keys: &["schema", "table_name"],
+            counters: &[
+                "seq_scans",
+                "seq_rows_read",
+                "index_scans",
+                "index_rows_fetched",
+                "inserts",
+                "updates",
+                "hot_updates",
+                "deletes",
+                "vacuums",
+                "analyzes",
+            ],
+            sort_by: "seq_rows_read",
+        }),
+        requires: Privilege::None,
+        extension: None,
+        full_scan: false,
+    },
+    Collector {
+        name: "database-stats",
+        title: "Transactions, cache hits, tuples, temporary files and deadlocks per database",
+        query: DATABASE_STATS_QUERY,
+        variants: &[],
+        per_database: false,
+        delta: Some(Delta {
+            keys: &["datname"],
+            counters: &[
+                "commits",
+                "rollbacks",
+                "blocks_read",
+                "blocks_hit",
+                "tuples_returned",
+                "tuples_fetched",
+                "tuples_inserted",
+                "tuples_updated",
+                "tuples_deleted",
+                "temp_files",
+                "temp_bytes",
+                "deadlocks",
+            ],
+            sort_by: "commits",
+        }),
+        requires: Privilege::None,
+        extension: None,
+        full_scan: false,
+    },
+    Collector {
+        name: "bgwriter",
+        title: "Buffers written by the background writer, checkpoints and backends",
+        query: BGWRITER_QUERY,
+        variants: &[],
+        per_database: false,
+        delta: Some(Delta {
+            keys: &[],
+            counters: &[
+                "checkpoints_timed",
+                "checkpoints_requested",
+                "buffers_checkpoint",
+                "buffers_clean",
+                "maxwritten_clean",
+                "buffers_backend",
+                "buffers_backend_fsync",
+                "buffers_alloc",
+            ],
+            sort_by: "buffers_alloc",
+        }),
+        requires: Privilege::None,
+        extension: None,
+        full_scan: false,
+    },
+    Collector {
+        name: "database-sizes",
+        title: "Databases by size, with their owner and default tablespace",
+        query: DATABASE_SIZES_QUERY,
+        variants: &[],
+        per_database: false,
+        delta: None,
+        requires: Privilege::None,
+        extension: None,
+        full_scan: false,
+    },
+    Collector {
+        name: "tablespace-sizes",
+        title: "Tablespaces by size, with their location and the databases using them",
+        query: TABLESPACE_SIZES_QUERY,
+        variants: &[],
+        per_database: false,
+        delta: None,
+        requires: Privilege::None,
+        extension: None,
+        full_scan: false,
+    },
+    Collector {
+        name: "sequence-usage",
+        title: "Sequences of integer columns by how much of their values is used up",
+        query: SEQUENCES_QUERY,
+        variants: &[],
+        per_database: true,
+        delta: None,
+        requires: Privilege::None,
+        extension: None,
+        full_scan: false,
+    },
+    Collector {
+        name: "not-valid-constraints",
+        title: "Constraints added NOT VALID and never validated",
+        query: NOT_VALID_CONSTRAINTS_QUERY,
+        variants: &[],
+        per_database: true,
+        delta: None,
+        requires: Privilege::None,
+        extension: None,
+        full_scan: false,
+    },
+    Collector {
+        name: "unindexed-foreign-keys",
+        title: "Foreign keys without an index on the referencing columns, largest table first",
+        query: UNINDEXED_FOREIGN_KEYS_QUERY,
+        variants: &[],
+        per_database: true,
+        delta: None,
+        requires: Privilege::None,
+        extension: None,
+        full_scan: false,
+    },
+    Collector {
+        name: "invalid-indexes",
+        title: "Indexes left invalid by a failed concurrent build, or still being built",
+        query: INVALID_INDEXES_QUERY,
+        variants: INVALID_INDEXES_VARIANTS,
+        per_database: true,
+        delta: None,
+        requires: Privilege::None,
+        extension: None,
+        full_scan: false,
+    },
+    Collector {
+        name: "undocumented",
+        title: "Tables and columns without a comment",
+        query: UNDOCUMENTED_QUERY,
+        variants: &[],
+        per_database: true,
+        delta: None,
+        requires: Privilege::None,
+        extension: None,
+        full_scan: false,
+    },
+    Collector {
+        name: "type-audit",
+        title: "Columns using problematic data types, per schema",
+        query: TYPE_AUDIT_QUERY,
+        variants: &[],
+        per_database: true,
+        delta: None,
+        requires: Privilege::None,
+        extension: None,
+        full_scan: false,
+    },
+];
+
+/// Returns the collector called `name`, if there is one
+pub fn find_collector(name: &str) -> Option<&'static Collector> {
+    COLLECTORS.iter().find(|c| c.name == name)
+}
+
+impl Connection {
+    /// Runs `collector`, in every database with `all_databases` (see `Connection::query_all_databases`). <br>
+    /// Collectors the connected role lacks the privileges for, or the database the extension, are refused before
+    /// querying, see `Privileges::check` and `Capabilities::check`.
+    pub fn collect(&mut self, collector: &Collector, all_databases: bool) -> Result<Value, PGError> {
+        self.privileges.check(collector.requires)?;
+        self.capabilities.check(collector.extension)?;
+        let rows = match all_databases {
+            true => self.query_all_databases(collector),
+            false => self.query_json(collector.query_for(self.server_version)),
+        };
+        rows.map_err(|e| e.with_context(collector.context()))
+    }
+}
+
+/// Runs `collector` and returns a heading describing the results along with the rows. <br>
+/// With `all_databases` the collector is run in every database, see `Connection::query_all_databases`. <br>
+/// When a `History` is given the sample is recorded in it, and collectors with a `Delta` report rates since the
+/// previous sample instead of their raw values.
+pub fn run_collector(
+    connection: &mut Connection,
+    collector: &Collector,
+    all_databases: bool,
+    history: Option<&mut History>,
+) -> Result<(String, Value), PGError> {
+    if collector.full_scan && connection.capabilities.allows(collector.extension) {
+        warn!(
+            "{} reads whole relations rather than statistics, which adds I/O on a busy server",
+            collector.name
+        );
+    }
+    let rows = connection.collect(collector, all_databases)?;
+    let history = match history {
+        Some(h) => h,
+        None => return Ok((collector.title.to_string(), rows)),
+    };
+
+    let label = connection.label();
+    let previous = history.latest(&label, collector.name);
+    if let Err(e) = history.append(&label, collector.name, &rows) {
+        error!("could not write history: {}", e);
+    }
+
+    match (&collector.delta, previous) {
+        (Some(delta), Some((taken_at, previous))) => {
+            let seconds = unix_now().saturating_sub(taken_at);
+            Ok((
+                format!("{} (since the previous sample {}s ago)", collector.title, seconds),
+                rates(&previous, &rows, delta, seconds as f64),
+            ))
+        }
+        _ => Ok((collector.title.to_string(), rows)),
+    }
+}
+
+/// Returns the most recent sample of `collector` recorded in `history` for the connection `label`, with a heading
+/// saying how old it is. <br>
+/// Used to browse the last known state of a database while it cannot be reached, see `--offline`.
+pub fn recorded_sample(history: &History, label: &str, collector: &Collector) -> Result<(String, Value), PGError> {
+    match history.latest(label, collector.name) {
+        Some((taken_at, rows)) => {
+            let age = human_duration(unix_now().saturating_sub(taken_at) as f64);
+            Ok((format!("{} (offline, recorded {} ago)", collector.title, age), rows))
+        }
+        None => Err(PGError::NoRecordedSample),
+    }
+}
+
+/// Takes two samples of `collector`, `seconds` apart, and returns a heading along with the per-second rate of its
+/// counters in between. <br>
+/// Returns `None` for collectors without a `Delta`, whose values are not cumulative.
+pub fn sample_rates(
+    connection: &mut Connection,
+    collector: &Collector,
+    all_databases: bool,
+    seconds: u64,
+) -> Option<Result<(String, Value), PGError>> {
+    let delta = collector.delta.as_ref()?;
+    let mut sample = || connection.collect(collector, all_databases);
+
+    let first = match sample() {
+        Ok(rows) => rows,
+        Err(e) => return Some(Err(e)),
+    };
+    thread::sleep(Duration::from_secs(seconds));
+    let second = match sample() {
+        Ok(rows) => rows,
+        Err(e) => return Some(Err(e)),
+    };
+
+    Some(Ok((
+        format!("{} (per second, over {}s)", collector.title, seconds),
+        rates(&first, &second, delta, seconds as f64),
+    )))
+}
+
+/// Roles with the method their password is stored with and when it expires. <br>
+/// Roles that can log in without any password are listed first. `pg_authid` is only readable by superusers.
+const ROLE_AUTH_QUERY: &str = r#"
+    SELECT rolname AS role,
+           rolcanlogin AS can_login,
+           CASE WHEN rolpassword IS NULL THEN 'none'
+                WHEN rolpassword LIKE 'SCRAM-SHA-256$%' THEN 'scram-sha-256'
+                WHEN rolpassword LIKE 'md5%' THEN 'md5'
+                ELSE 'unknown'
+           END AS auth_method,
+           rolvaliduntil AS valid_until,
+           CASE WHEN rolvaliduntil IS NULL THEN 'never expires'
+                WHEN rolvaliduntil = 'infinity' THEN 'never expires'
+                WHEN rolvaliduntil < now() THEN 'EXPIRED'
+                WHEN rolvaliduntil < now() + interval '14 days' THEN 'expires soon'
+                ELSE 'ok'
+           END AS expiry
+    FROM pg_authid
+    WHERE rolname NOT LIKE 'pg\_%'
+    ORDER BY rolcanlogin AND rolpassword IS NULL DESC, rolname
+"#;
+
+/// Grants that will be applied automatically to objects created in the future, per creating role and schema
+const DEFAULT_PRIVILEGES_QUERY: &str = r#"
+    SELECT pg_get_userbyid(d.defaclrole) AS role,
+           coalesce(n.nspname, '(all schemas)') AS schema,
+           CASE d.defaclobjtype
+                WHEN 'r' THEN 'tables'
+                WHEN 'S' THEN 'sequences'
+                WHEN 'f' THEN 'functions'
+                WHEN 'T' THEN 'types'
+                WHEN 'n' THEN 'schemas'
+           END AS object_type,
+           CASE WHEN a.grantee = 0 THEN 'PUBLIC' ELSE pg_get_userbyid(a.grantee) END AS grantee,
+           string_agg(a.privilege_type, ', ' ORDER BY a.privilege_type) AS privileges
+    FROM pg_default_acl d
+    LEFT JOIN pg_namespace n ON n.oid = d.defaclnamespace
+    CROSS JOIN LATERAL aclexplode(d.defaclacl) a
+    GROUP BY 1, 2, 3, 4
+    ORDER BY 1, 2, 3, 4
+"#;
+
+/// Tables with row-level security enabled or with policies defined, one row per policy. <br>
+/// Tables with RLS enabled but no policies get a single row flagging them, since they silently return no rows to
+/// anyone but the owner.
+const RLS_QUERY: &str = r#"
+    SELECT n.nspname AS schema,
+           c.relname AS table_name,
+           c.relrowsecurity AS rls_enabled,
+           c.relforcerowsecurity AS rls_forced,
+           coalesce(p.policyname, '(none, blocks all non-owner access)') AS policy,
+           p.permissive,
+           p.cmd AS command,
+           array_to_string(p.roles, ', ') AS roles,
+           p.qual AS using_expression,
+           p.with_check AS check_expression
+    FROM pg_class c
+    JOIN pg_namespace n ON n.oid = c.relnamespace
+    LEFT JOIN pg_policies p ON p.schemaname = n.nspname AND p.tablename = c.relname
+    WHERE c.relkind IN ('r', 'p')
+    AND (c.relrowsecurity OR p.policyname IS NOT NULL)
+    ORDER BY p.policyname IS NOT NULL, n.nspname, c.relname, p.policyname
+"#;
+
+/// Event triggers of the current database, with the function they run, the events and command tags they fire on and
+/// the extension that created them, if any. <br>
+/// Enabled triggers are listed first, since those are the ones that can make DDL fail.
+const EVENT_TRIGGERS_QUERY: &str = r#"
+    SELECT t.evtname AS name,
+           t.evtevent AS event,
+           coalesce(array_to_string(t.evttags, ', '), '(all commands)') AS command_tags,
+           t.evtfoid::regprocedure::text AS function,
+           CASE t.evtenabled
+               WHEN 'O' THEN 'enabled'
+               WHEN 'D' THEN 'disabled'
+               WHEN 'R' THEN 'replica only'
+               WHEN 'A' THEN 'always'
+           END AS state,
+           t.evtowner::regrole::text AS owner,
+           e.extname AS extension
+    FROM pg_event_trigger t
+    LEFT JOIN pg_depend d ON d.classid = 'pg_event_trigger'::regclass AND d.objid = t.oid AND d.deptype = 'e'
+    LEFT JOIN pg_extension e ON e.oid = d.refobjid
+    ORDER BY t.evtenabled = 'D', t.evtevent, t.evtname
+"#;
+
+/// Schemas, relations and functions any role can use through a grant to PUBLIC, plus SECURITY DEFINER functions
+/// whose search_path is unset or includes a schema other roles may be able to create objects in. <br>
+/// Write access and hijackable SECURITY DEFINER functions are rated high, everything else medium. Functions created
+/// by extensions are left out since they are not under the user's control.
+const PUBLIC_EXPOSURE_QUERY: &str = r#"
+    WITH user_functions AS (
+        SELECT p.*, n.nspname
+        FROM pg_proc p
+        JOIN pg_namespace n ON n.oid = p.pronamespace
+        WHERE n.nspname NOT IN ('pg_catalog', 'information_schema')
+        AND NOT EXISTS (
+            SELECT 1 FROM pg_depend d
+            WHERE d.classid = 'pg_proc'::regclass AND d.objid = p.oid AND d.deptype = 'e'
+        )
+    ),
+    exposure AS (
+        SELECT 'schema' AS object_type, n.nspname AS schema, n.nspname AS name,
+               string_agg(a.privilege_type, ', ') AS detail,
+               CASE WHEN bool_or(a.privilege_type = 'CREATE') THEN 'high' ELSE 'medium' END AS severity
+        FROM pg_namespace n
+        CROSS JOIN LATERAL aclexplode(n.nspacl) a
+        WHERE a.grantee = 0
+        AND n.nspname NOT IN ('pg_catalog', 'information_schema')
+        AND n.nspname NOT LIKE 'pg\_%'
+        GROUP BY n.nspname
+
+        UNION ALL
+
+        SELECT CASE c.relkind WHEN 'v' THEN 'view' WHEN 'm' THEN 'materialized view' ELSE 'table' END,
+               n.nspname, c.relname,
+               string_agg(a.privilege_type, ', '),
+               CASE WHEN bool_or(a.privilege_type IN ('INSERT', 'UPDATE', 'DELETE', 'TRUNCATE'))
+                    THEN 'high' ELSE 'medium' END
+        FROM pg_class c
+        JOIN pg_namespace n ON n.oid = c.relnamespace
+        CROSS JOIN LATERAL aclexplode(c.relacl) a
+        WHERE a.grantee = 0
+        AND c.relkind IN ('r', 'p', 'v', 'm', 'f')
+        AND n.nspname NOT IN ('pg_catalog', 'information_schema')
+        GROUP BY c.relkind, n.nspname, c.relname
+
+        UNION ALL
+
+        SELECT 'function', f.nspname, f.proname || '(' || pg_get_function_identity_arguments(f.oid) || ')',
+               'EXECUTE', 'medium'
+        FROM user_functions f
+        CROSS JOIN LATERAL aclexplode(coalesce(f.proacl, acldefault('f', f.proowner))) a
+        WHERE a.grantee = 0 AND NOT f.prosecdef
+
+        UNION ALL
+
+        SELECT 'security definer function', f.nspname,
+               f.proname || '(' || pg_get_function_identity_arguments(f.oid) || ')',
+               coalesce(
+                   (SELECT c FROM unnest(f.proconfig) c WHERE c LIKE 'search_path=%'),
+                   'no search_path set'
+               ),
+               'high'
+        FROM user_functions f
+        WHERE f.prosecdef
+        AND NOT EXISTS (
+            SELECT 1 FROM unnest(f.proconfig) c
+            WHERE c LIKE 'search_path=%'
+            AND c NOT LIKE '%public%'
+            AND c NOT LIKE '%$user%'
+        )
+    )
+    SELECT severity, object_type, schema, name, detail
+    FROM exposure
+    ORDER BY severity, object_type, schema, name
+"#;
+
+/// Client connections by application, role and database, with how many are active, idle, idle in a transaction and
+/// waiting on a lock. Recorded over time with `--history`, it shows how many connections an application really uses at
+/// once, see `pool_advice`.
+const CONNECTION_STATES_QUERY: &str = r#"
+    SELECT coalesce(nullif(application_name, ''), 'unnamed') AS application,
+           usename AS user,
+           datname AS database,
+           count(*) AS connections,
+           count(*) FILTER (WHERE state = 'active') AS active,
+           count(*) FILTER (WHERE state = 'idle') AS idle,
+           count(*) FILTER (WHERE state LIKE 'idle in transaction%') AS idle_in_transaction,
+           count(*) FILTER (WHERE wait_event_type = 'Lock') AS waiting_on_locks
+    FROM pg_stat_activity
+    WHERE backend_type = 'client backend'
+      AND pid <> pg_backend_pid()
+    GROUP BY 1, 2, 3
+    ORDER BY connections DESC, 1, 2, 3
+"#;
+
+/// Autovacuum workers currently running, with what they are doing to which table from their query text, the phase
+/// of a vacuum, how long they have been at it and whether they run to prevent transaction ID wraparound, which
+/// cannot be cancelled. <br>
+/// Every row carries how many workers `autovacuum_max_workers` allows and how many are in use, a single row without
+/// a worker is returned when none are running.
+const AUTOVACUUM_QUERY: &str = r#"
+    WITH workers AS (
+        SELECT a.pid,
+               a.datname,
+               regexp_replace(a.query, '^autovacuum: ', '') AS task,
+               p.phase,
+               date_trunc('second', now() - a.xact_start) AS running_for,
+               a.query LIKE '%(to prevent wraparound)' AS wraparound
+        FROM pg_stat_activity a
+        LEFT JOIN pg_stat_progress_vacuum p ON p.pid = a.pid
+        WHERE a.backend_type = 'autovacuum worker'
+    )
+    SELECT current_setting('autovacuum_max_workers')::int AS workers_configured,
+           (SELECT count(*) FROM workers) AS workers_in_use,
+           w.pid,
+           w.datname,
+           w.task,
+           w.phase,
+           w.running_for,
+           w.wraparound
+    FROM (SELECT 1) AS settings
+    LEFT JOIN workers w ON true
+    ORDER BY w.running_for DESC NULLS LAST
+"#;
+
+/// Inserted, updated and deleted rows per table, with the average rate since statistics were last reset
+const WRITE_CHURN_QUERY: &str = r#"
+    SELECT s.schemaname AS schema,
+           s.relname AS table_name,
+           s.n_tup_ins AS inserts,
+           s.n_tup_upd AS updates,
+           s.n_tup_del AS deletes,
+           s.n_tup_ins + s.n_tup_upd + s.n_tup_del AS writes,
+           round(
+               (s.n_tup_ins + s.n_tup_upd + s.n_tup_del) / greatest(
+                   extract(epoch FROM now() - coalesce(d.stats_reset, pg_postmaster_start_time()))::numeric,
+                   1
+               ),
+               2
+           ) AS writes_per_sec_since_reset
+    FROM pg_stat_user_tables s
+    JOIN pg_stat_database d ON d.datname = current_database()
+    ORDER BY writes DESC, s.schemaname, s.relname
+"#;
+
+/// Blocks read from the tables with a TOAST table, from the table itself and from its TOAST table and index, with
+/// the scans of the table since statistics were last reset. `toast_percent` is the share of the blocks read from TOAST,
+/// and tables reading mostly TOAST, over half of at least 1000 blocks, are flagged: their rows have large values that
+/// queries fetch, often through a `SELECT *` that does not need them.
+const TOAST_READS_QUERY: &str = r#"
+    WITH reads AS (
+        SELECT s.schemaname AS schema,
+               s.relname AS table_name,
+               pg_total_relation_size(c.reltoastrelid) AS toast_bytes,
+               coalesce(t.seq_scan, 0) + coalesce(t.idx_scan, 0) AS scans,
+               coalesce(s.heap_blks_read, 0) + coalesce(s.heap_blks_hit, 0) AS heap_blocks,
+               coalesce(s.toast_blks_read, 0) + coalesce(s.toast_blks_hit, 0)
+                   + coalesce(s.tidx_blks_read, 0) + coalesce(s.tidx_blks_hit, 0) AS toast_blocks,
+               coalesce(s.toast_blks_read, 0) + coalesce(s.tidx_blks_read, 0) AS toast_blocks_from_disk
+        FROM pg_statio_user_tables s
+        JOIN pg_stat_user_tables t ON t.relid = s.relid
+        JOIN pg_class c ON c.oid = s.relid
+        WHERE c.reltoastrelid <> 0
+    )
+    SELECT schema,
+           table_name,
+           toast_bytes,
+           scans,
+           heap_blocks,
+           toast_blocks,
+           toast_blocks_from_disk,
+           round(100.0 * toast_blocks / nullif(heap_blocks + toast_blocks, 0), 1) AS toast_percent,
+           CASE WHEN toast_blocks >= 1000 AND toast_blocks > heap_blocks THEN 'mostly reads TOAST' END AS finding
+    FROM reads
+    ORDER BY toast_blocks DESC, schema, table_name
+"#;
+
+/// Cumulative activity counters of the user tables: scans and the rows they read, rows written and updated in place
+/// (HOT), and how often the tables were vacuumed and analyzed, by hand or by autovacuum. Single readings only tell
+/// what happened since the statistics were reset, `delta` and `snapshot diff` turn them into rates and changes.
+const TABLE_ACTIVITY_QUERY: &str = r#"
+    SELECT schemaname AS schema,
+           relname AS table_name,
+           seq_scan AS seq_scans,
+           seq_tup_read AS seq_rows_read,
+           coalesce(idx_scan, 0) AS index_scans,
+           coalesce(idx_tup_fetch, 0) AS index_rows_fetched,
+           n_tup_ins AS inserts,
+           n_tup_upd AS updates,
+           n_tup_hot_upd AS hot_updates,
+           n_tup_del AS deletes,
+           vacuum_count + autovacuum_count AS vacuums,
+           analyze_count + autoanalyze_count AS analyzes
+    FROM pg_stat_user_tables
+    ORDER BY seq_tup_read + coalesce(idx_tup_fetch, 0) DESC, schemaname, relname
+"#;
+
+/// Counters of the background writer from `pg_stat_bgwriter`: buffers it cleaned, how often it stopped for having
+/// written `bgwriter_lru_maxpages`, buffers allocated, and the checkpoints and buffers written by checkpoints and by
+/// backends. <br>
+/// The columns Postgres 17 moved to `pg_stat_checkpointer` and `pg_stat_io` are read through `to_jsonb` and left
+/// empty there, see `wal` for them.
+const BGWRITER_QUERY: &str = r#"
+    SELECT (to_jsonb(b) ->> 'checkpoints_timed')::bigint AS checkpoints_timed,
+           (to_jsonb(b) ->> 'checkpoints_req')::bigint AS checkpoints_requested,
+           (to_jsonb(b) ->> 'buffers_checkpoint')::bigint AS buffers_checkpoint,
+           b.buffers_clean,
+           b.maxwritten_clean,
+           (to_jsonb(b) ->> 'buffers_backend')::bigint AS buffers_backend,
+           (to_jsonb(b) ->> 'buffers_backend_fsync')::bigint AS buffers_backend_fsync,
+           b.buffers_alloc,
+           b.stats_reset
+    FROM pg_stat_bgwriter b
+"#;
+
+/// Tables, views and their columns in user schemas that have no comment. <br>
+/// Rows without a `column_name` are the tables themselves.
+const UNDOCUMENTED_QUERY: &str = r#"
+    SELECT n.nspname AS schema,
+           c.relname AS table_name,
+           NULL::name AS column_name
+    FROM pg_class c
+    JOIN pg_namespace n ON n.oid = c.relnamespace
+    WHERE c.relkind IN ('r', 'p', 'v', 'm', 'f')
+    AND n.nspname NOT IN ('pg_catalog', 'information_schema')
+    AND n.nspname NOT LIKE 'pg\_%'
+    AND obj_description(c.oid, 'pg_class') IS NULL
+    UNION ALL
+    SELECT n.nspname, c.relname, a.attname
+    FROM pg_class c
+    JOIN pg_namespace n ON n.oid = c.relnamespace
+    JOIN pg_attribute a ON a.attrelid = c.oid AND a.attnum > 0 AND NOT a.attisdropped
+    WHERE c.relkind IN ('r', 'p', 'v', 'm', 'f')
+    AND n.nspname NOT IN ('pg_catalog', 'information_schema')
+    AND n.nspname NOT LIKE 'pg\_%'
+    AND col_description(c.oid, a.attnum) IS NULL
+    ORDER BY 1, 2, 3 NULLS FIRST
+"#;
+
+/// Columns of user tables with data types that commonly cause trouble, counted per schema and issue with up to
+/// five example columns each
+const TYPE_AUDIT_QUERY: &str = r#"
+    WITH user_columns AS (
+        SELECT c.*
+        FROM information_schema.columns c
+        JOIN information_schema.tables t
+          ON t.table_schema = c.table_schema AND t.table_name = c.table_name
+        WHERE t.table_type = 'BASE TABLE'
+        AND c.table_schema NOT IN ('pg_catalog', 'information_schema')
+        AND c.table_schema NOT LIKE 'pg\_%'
+    ),
+    findings AS (
+        SELECT table_schema, table_name, column_name,
+               CASE
+                    WHEN data_type = 'money' THEN 'money, use numeric'
+                    WHEN data_type = 'timestamp without time zone' THEN 'timestamp without time zone, use timestamptz'
+                    WHEN data_type = 'character' THEN 'char(n), use text or varchar'
+                    WHEN column_default LIKE 'nextval(%' THEN 'serial, use an identity column'
+               END AS issue
+        FROM user_columns
+        UNION ALL
+        SELECT DISTINCT c.table_schema, c.table_name, c.column_name, 'unbounded varchar in a key'
+        FROM user_columns c
+        JOIN information_schema.key_column_usage k
+          ON k.table_schema = c.table_schema AND k.table_name = c.table_name AND k.column_name = c.column_name
+        WHERE c.data_type = 'character varying' AND c.character_maximum_length IS NULL
+    )
+    SELECT table_schema::text AS schema,
+           issue,
+           count(*) AS columns,
+           array_to_string(
+               (array_agg(table_name || '.' || column_name ORDER BY table_name, column_name))[1:5],
+               ', '
+           ) AS examples
+    FROM findings
+    WHERE issue IS NOT NULL
+    GROUP BY table_schema, issue
+    ORDER BY table_schema, columns DESC, issue
+"#;
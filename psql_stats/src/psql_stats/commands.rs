@@ -0,0 +1,530 @@
+use std::io::{self, IsTerminal, Write};
+use std::time::Instant;
+
+use clap::Subcommand;
+use colored::Colorize;
+use tracing::error;
+
+use super::capture::{parse_capture_duration, render_capture};
+use super::collectors::{find_collector, recorded_sample, run_collector, COLLECTORS};
+use super::compare::{compare_profiles, diff_query_profiles, highlight_differences};
+use super::describe::render_description;
+use super::history::History;
+use super::metrics::{metric_trends, render_trends};
+use super::monitor_role::{monitor_role_sql, DEFAULT_MONITOR_ROLE};
+use super::queries::TextParam;
+use super::script::read_script;
+use super::output::OutputFormatter;
+use super::pager::print_paged;
+use super::plans::render_explain;
+use super::pools::render_pool_advice;
+use super::profiles::{delete_profile, edit_profile, list_profiles, rename_profile};
+use super::resets::{StatsReset, RESET_TARGETS};
+use super::snapshots::{diff_snapshot, save_snapshot};
+use super::state::{backup_state, restore_state};
+use super::style::warning_sign;
+use super::{Config, Connection, PGError, TableScope};
+
+/// Commands run once without starting the interactive menu, for shell scripts and cron jobs, e.g.
+/// `psql_stats -U postgres tables` or `psql_stats query "SELECT ..."`
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// Print how long the server has been running
+    Uptime,
+    /// Print the server version
+    Version,
+    /// List the tables in the schemas of the search_path, or of another schema, or of every schema
+    Tables {
+        /// List the tables of this schema instead
+        #[arg(long, conflicts_with = "all_schemas")]
+        schema: Option<String>,
+        /// List the tables of every schema but the system ones, grouped by schema
+        #[arg(long)]
+        all_schemas: bool,
+    },
+    /// List the schemas with their owner, tables, views and size
+    Schemas,
+    /// Describe a table like psql's \d: its owner, columns, indexes, constraints and the foreign keys referencing it
+    Describe {
+        /// The table, optionally schema qualified
+        table: String,
+    },
+    /// List the installed extensions
+    Extensions,
+    /// Show which statistics extensions, e.g. pg_stat_statements, the database has and how to enable the others
+    Capabilities,
+    /// List the tables by size, with their estimated rows and scans
+    TableStats,
+    /// Run a collector by name, any of those listed in the menu, e.g. activity or replication
+    Collect { name: String },
+    /// Print the JSON Schema of what collectors print with --format json, or of one collector
+    Schema { collector: Option<String> },
+    /// Print the SQL creating a login role that can run every collector without superuser, or run it with --execute
+    GenerateMonitorRole {
+        /// Name of the role
+        #[arg(long, default_value = DEFAULT_MONITOR_ROLE)]
+        name: String,
+        /// Create the role, or update its grants, instead of printing the SQL
+        #[arg(long)]
+        execute: bool,
+    },
+    /// List the saved connections with their server, database, TLS mode and collectors
+    Connections,
+    /// Delete a saved connection, and its password if it was kept in the keyring
+    DeleteConnection { name: String },
+    /// Rename a saved connection
+    RenameConnection { name: String, new_name: String },
+    /// Change a field of a saved connection, e.g. edit-connection prod port 5433
+    EditConnection {
+        name: String,
+        /// One of host, port, user, dbname, sslmode, sslrootcert, sslcert, sslkey, auth, aws_region,
+        /// target_session_attrs, ssh_host, ssh_user, ssh_key or collectors (separated by commas)
+        field: String,
+        /// The new value, empty for the default
+        value: String,
+    },
+    /// Compare the table sizes, row counts, extensions and settings of two saved connections, e.g. staging and production
+    Compare {
+        /// Name of a saved connection, e.g. staging
+        first: String,
+        /// Name of the saved connection to compare it with, e.g. production
+        second: String,
+        /// List what is the same on both as well, not only what differs
+        #[arg(long)]
+        all: bool,
+    },
+    /// Run the same query, or a saved query, on two saved connections and show the rows that were added, removed or changed
+    DiffQuery {
+        /// Name of a saved connection, e.g. the primary or the source of a migration
+        first: String,
+        /// Name of the saved connection to compare its rows with, e.g. a replica or the migrated database
+        second: String,
+        /// Column pairing the rows of both sides, the first column by default
+        #[arg(long)]
+        key: Option<String>,
+        /// List the rows that are the same on both as well
+        #[arg(long)]
+        all: bool,
+        /// The statement, or the name of a saved query
+        sql: String,
+    },
+    /// Write the configuration, saved queries and saved connections to one file, or restore them from it
+    State {
+        #[command(subcommand)]
+        action: StateAction,
+    },
+    /// Write the cumulative counters of the statistics views to a file, or show how much they grew since it was written
+    Snapshot {
+        #[command(subcommand)]
+        action: SnapshotAction,
+    },
+    /// Reset the cumulative statistics of the database, the background writer or pg_stat_statements, after confirming
+    ResetStats {
+        /// Which statistics to reset
+        #[arg(value_parser = RESET_TARGETS.to_vec())]
+        target: String,
+        /// Do not ask for confirmation, needed when not running in a terminal
+        #[arg(long)]
+        yes: bool,
+    },
+    /// Summarize the workload over a window: top query shapes, reads against writes, busiest users and tables
+    Capture {
+        /// How long to watch, e.g. 90, 30s, 5m or 1h
+        #[arg(long, default_value = "1m", value_parser = parse_capture_duration)]
+        duration: u64,
+    },
+    /// Write every statistic and health check to a timestamped JSON report file, for support tickets or later comparison
+    Report {
+        /// Replace database, table, host and user names with salted hashes and literals in queries with ?
+        #[arg(long)]
+        anonymize: bool,
+        /// Write YAML instead, as files named .yaml or .yml are
+        #[arg(long)]
+        yaml: bool,
+        /// The file to write, or a directory to write a timestamped file in, the current directory by default
+        file: Option<String>,
+    },
+    /// Run the health checks once, print a one line summary and exit with 0 (OK), 1 (WARNING) or 2 (CRITICAL), for monitoring
+    Healthcheck,
+    /// Recommend pool sizes from the connection-states samples in the --history file, flagging apps needing a pooler
+    PoolAdvice,
+    /// Show the trends of the key metrics recorded by report in the --metrics-db file, as sparklines
+    History {
+        /// Name of a saved connection, this connection by default
+        name: Option<String>,
+        /// How many of the latest reports to show
+        #[arg(long, default_value_t = 20)]
+        samples: usize,
+    },
+    /// Compare the plan of a query, or a saved query, with the one recorded in the --history file last time, and record it
+    PlanDiff {
+        /// Compare with the plan recorded this many captures ago instead of the latest
+        #[arg(long, default_value_t = 1)]
+        back: usize,
+        /// The statement, or the name of a saved query
+        sql: String,
+    },
+    /// Run a query, or a saved query, under EXPLAIN ANALYZE, rolled back, and show its plan with the time and rows of every node
+    Explain {
+        /// Only plan the query, without running it
+        #[arg(long)]
+        no_analyze: bool,
+        /// The statement, or the name of a saved query
+        sql: String,
+    },
+    /// Show how many partitions of the partitioned tables a query, or a saved query, scans and how many are pruned
+    Pruning {
+        /// The statement, or the name of a saved query
+        sql: String,
+    },
+    /// Run a query, or a query saved with `query --save` in the menu, binding the --param values to $1, $2, ...
+    Query {
+        /// Value for the next placeholder, can be given several times
+        #[arg(long = "param")]
+        params: Vec<String>,
+        /// Stream the rows to this file instead, as Parquet when it ends in .parquet and CSV otherwise
+        #[arg(long)]
+        export: Option<String>,
+        /// Do not ask to confirm a destructive statement, needed when not running in a terminal
+        #[arg(long)]
+        yes: bool,
+        /// The statement, or the name of a saved query
+        sql: String,
+    },
+    /// Run the statements of a SQL file one after the other, showing how long each took and how many rows it returned or changed
+    RunFile {
+        /// The SQL file, its statements ended by semicolons
+        path: String,
+        /// Go on with the next statements when one fails, instead of stopping
+        #[arg(long)]
+        continue_on_error: bool,
+    },
+}
+
+/// What `state` does with the state archive, see `backup_state`
+#[derive(Subcommand, Debug)]
+pub enum StateAction {
+    /// Write the archive, without passwords
+    Backup { file: String },
+    /// Replace the configuration with the archived one and add the archived connections
+    Restore { file: String },
+}
+
+/// What `snapshot` does, see `save_snapshot` and `diff_snapshot`
+#[derive(Subcommand, Debug)]
+pub enum SnapshotAction {
+    /// Write the counters of every collector with cumulative counters to the file
+    Save { file: String },
+    /// Show how much the counters grew since the snapshot in the file was written
+    Diff {
+        file: String,
+        /// Only this collector, e.g. table-activity
+        collector: Option<String>,
+        /// Show the average per-second rates instead of the totals
+        #[arg(long)]
+        rates: bool,
+    },
+}
+
+impl Command {
+    /// Whether the command needs a connection to the server, those managing the saved connections only need the file,
+    /// `compare` and `diff-query` connect to the saved connections they compare and `history` reads the metrics
+    /// database
+    pub fn needs_connection(&self) -> bool {
+        !matches!(
+            self,
+            Command::Connections
+                | Command::DeleteConnection { .. }
+                | Command::RenameConnection { .. }
+                | Command::EditConnection { .. }
+                | Command::Compare { .. }
+                | Command::DiffQuery { .. }
+                | Command::State { .. }
+                | Command::History { .. }
+        )
+    }
+}
+
+/// Runs `command` and prints its result in the configured output format, see `OutputFormatter`. <br>
+/// With `offline`, collectors show their most recent sample in `history` instead, and with `use_keyring` the saved
+/// connections compared read their passwords from the keyring. <br>
+/// Returns `MatchNotFound` for an unknown collector, after listing the known ones.
+pub fn run_command(
+    command: &Command,
+    connection: &mut Connection,
+    config: &Config,
+    all_databases: bool,
+    history: Option<&mut History>,
+    offline: bool,
+    use_keyring: bool,
+) -> Result<(), PGError> {
+    let name = match command {
+        Command::Uptime => "uptime",
+        Command::Version => "version",
+        Command::Tables { schema: None, all_schemas: false } => "tables",
+        Command::Tables { schema, all_schemas } => {
+            let scope = match schema {
+                Some(schema) => TableScope::Schema(schema),
+                None => TableScope::All,
+            };
+            let heading = match schema {
+                Some(schema) => format!("Tables in {}", schema),
+                None => "Tables in".to_string(),
+            };
+            let rows = connection.get_tables(scope)?;
+            let formatter = OutputFormatter::new(config);
+            match all_schemas {
+                true => println!("{}", formatter.grouped(&heading, &rows, "schema")),
+                false => println!("{}", formatter.titled(&heading, formatter.rows(&rows))),
+            }
+            return Ok(());
+        }
+        Command::Schemas => "schemas",
+        Command::Describe { table } => {
+            println!("{}", render_description(&connection.describe_table(table)?, config));
+            return Ok(());
+        }
+        Command::Extensions => "extensions",
+        Command::TableStats => "table-stats",
+        Command::Collect { name } => name.as_str(),
+        Command::Schema { collector } => {
+            let schema = connection.json_schema(collector.as_deref())?;
+            println!("{}", serde_json::to_string_pretty(&schema).expect("Failed to serialize schema"));
+            return Ok(());
+        }
+        Command::GenerateMonitorRole { name, execute: false } => {
+            print!("{}", monitor_role_sql(name, &connection.databases()?));
+            return Ok(());
+        }
+        Command::GenerateMonitorRole { name, execute: true } => {
+            connection.create_monitor_role(name)?;
+            println!("Role {} is ready, set its password with \\password {} in psql before logging in.", name, name);
+            return Ok(());
+        }
+        Command::Capabilities => {
+            println!("{}", connection.capabilities_summary()?);
+            return Ok(());
+        }
+        Command::Connections => {
+            let formatter = OutputFormatter::new(config);
+            println!("{}", formatter.titled("Saved connections", formatter.rows(&list_profiles()?)));
+            return Ok(());
+        }
+        Command::DeleteConnection { name } => {
+            delete_profile(name)?;
+            println!("Deleted the saved connection {}", name);
+            return Ok(());
+        }
+        Command::RenameConnection { name, new_name } => {
+            rename_profile(name, new_name)?;
+            println!("Renamed the saved connection {} to {}", name, new_name);
+            return Ok(());
+        }
+        Command::EditConnection { name, field, value } => {
+            edit_profile(name, field, value)?;
+            println!("Set {} of the saved connection {} to \"{}\"", field, name, value.trim());
+            return Ok(());
+        }
+        Command::Compare { first, second, all } => {
+            let mut rows = compare_profiles(first, second, use_keyring, *all)?;
+            if config.output_format == "table" {
+                highlight_differences(&mut rows);
+            }
+            let formatter = OutputFormatter::new(config);
+            let heading = format!("Differences between {} and {}", first, second);
+            println!("{}", formatter.titled(&heading, formatter.rows(&rows)));
+            return Ok(());
+        }
+        Command::DiffQuery { first, second, key, all, sql } => {
+            let query = config.queries.get(sql).unwrap_or(sql);
+            let query = query.trim().trim_end_matches(';');
+            let mut rows = diff_query_profiles(first, second, query, key.as_deref(), use_keyring, *all)?;
+            if config.output_format == "table" {
+                highlight_differences(&mut rows);
+            }
+            let formatter = OutputFormatter::new(config);
+            let heading = format!("Rows differing between {} and {}", first, second);
+            println!("{}", formatter.titled(&heading, formatter.rows(&rows)));
+            return Ok(());
+        }
+        Command::State { action: StateAction::Backup { file } } => {
+            let count = backup_state(file)?;
+            println!("Wrote the configuration and {} saved connection(s) to {}", count, file);
+            return Ok(());
+        }
+        Command::State { action: StateAction::Restore { file } } => {
+            let count = restore_state(file)?;
+            println!("Restored the configuration and {} saved connection(s) from {}", count, file);
+            return Ok(());
+        }
+        Command::Snapshot { action: SnapshotAction::Save { file } } => {
+            let count = save_snapshot(connection, all_databases, file)?;
+            println!("Wrote the counters of {} collector(s) to {}", count, file);
+            return Ok(());
+        }
+        Command::Snapshot { action: SnapshotAction::Diff { file, collector, rates } } => {
+            let collector = match collector {
+                Some(name) => match find_collector(name) {
+                    Some(c) if c.delta.is_some() => Some(c),
+                    _ => return Err(PGError::MatchNotFound),
+                },
+                None => None,
+            };
+            let formatter = OutputFormatter::new(config);
+            for (heading, rows) in diff_snapshot(connection, file, collector, *rates)? {
+                println!("{}", formatter.titled(&heading, formatter.rows(&rows)));
+            }
+            return Ok(());
+        }
+        Command::ResetStats { target, yes } => {
+            let reset = StatsReset::from_name(target).ok_or(PGError::MatchNotFound)?;
+            if !yes && !confirmed(reset.question())? {
+                println!("Nothing was done.");
+                return Ok(());
+            }
+            connection.reset_stats(reset)?;
+            println!("{}", reset.done());
+            return Ok(());
+        }
+        Command::Capture { duration } => {
+            let report = connection.capture_workload(*duration, config.activity_query_width)?;
+            println!("{}", render_capture(&report, config));
+            return Ok(());
+        }
+        // Run by main, which has the healthcheck thresholds, see `write_report`
+        Command::Report { .. } => unreachable!("The report is written by main"),
+        // Run by main, which has the thresholds and exits with the status
+        Command::Healthcheck => unreachable!("The healthcheck is run by main"),
+        Command::PoolAdvice => {
+            let history = history.ok_or(PGError::NoRecordedSample)?;
+            println!("{}", render_pool_advice(&connection.pool_advice(history)?, config));
+            return Ok(());
+        }
+        Command::History { name, samples } => {
+            let label = match name {
+                Some(name) => Connection::read_from_json(name, String::new(), false)?.label(),
+                None => connection.label(),
+            };
+            let path = config.metrics_db.as_deref().ok_or(PGError::NoRecordedMetrics)?;
+            println!("{}", render_trends(&metric_trends(path, &label, *samples)?, config));
+            return Ok(());
+        }
+        Command::PlanDiff { back, sql } => {
+            let history = history.ok_or(PGError::HistoryRequired)?;
+            let query = config.queries.get(sql).unwrap_or(sql);
+            println!("{}", connection.plan_diff(history, query, *back)?);
+            return Ok(());
+        }
+        Command::Explain { no_analyze, sql } => {
+            let query = config.queries.get(sql).unwrap_or(sql);
+            let plan = match no_analyze {
+                true => connection.explain(query)?,
+                false => connection.explain_analyze(query)?,
+            };
+            println!("{}", render_explain(&plan));
+            return Ok(());
+        }
+        Command::Pruning { sql } => {
+            let query = config.queries.get(sql).unwrap_or(sql);
+            let formatter = OutputFormatter::new(config);
+            println!("{}", formatter.titled("Partition pruning", formatter.rows(&connection.partition_pruning(query)?)));
+            return Ok(());
+        }
+        Command::Query { params, export, yes, sql } => {
+            let query = config.queries.get(sql).unwrap_or(sql);
+            let params: Vec<TextParam> = params.iter().map(|p| TextParam(Some(p.clone()))).collect();
+            // Destructive statements are confirmed as in the menu, see `Config::confirm_destructive`
+            if config.confirm_destructive && !yes {
+                let statements = connection.destructive_statements(query).unwrap_or_default();
+                for statement in &statements {
+                    println!("{}", format!("{} {}", warning_sign(), statement.describe()).red().bold());
+                }
+                if !statements.is_empty() && !confirmed("Run it anyway?")? {
+                    println!("Nothing was done.");
+                    return Ok(());
+                }
+            }
+            if let Some(path) = export {
+                let format = match path.ends_with(".parquet") {
+                    true => "parquet",
+                    false => "csv",
+                };
+                let rows = connection.export_query(query.trim().trim_end_matches(';'), &params, format, path)?;
+                eprintln!("Exported {} rows to {}.", rows, path);
+                return Ok(());
+            }
+            let result = connection.custom_query(query.trim().trim_end_matches(';'), &params, config.table.max_rows)?;
+            print_paged(&OutputFormatter::new(config).query_result(&result), &config.table);
+            return Ok(());
+        }
+        Command::RunFile { path, continue_on_error } => {
+            let statements = read_script(path)?;
+            let summary = connection.run_script(&statements, *continue_on_error, config);
+            eprintln!("{}", summary.describe(config.table.human));
+            return match summary.failed {
+                0 => Ok(()),
+                _ => Err(PGError::QueryFailed),
+            };
+        }
+    };
+
+    let collector = match find_collector(name) {
+        Some(c) => c,
+        None => {
+            let names: Vec<&str> = COLLECTORS.iter().map(|c| c.name).collect();
+            error!("unknown collector {}, expected one of: {}", name, names.join(", "));
+            return Err(PGError::MatchNotFound);
+        }
+    };
+
+    let formatter = OutputFormatter::new(config);
+    let (heading, output) = match (offline, history) {
+        (true, Some(h)) => {
+            let (heading, rows) = recorded_sample(h, &connection.label(), collector)?;
+            let output = formatter.collector_rows(collector.name, &heading, &rows, None);
+            (heading, output)
+        }
+        (_, history) => {
+            let start = Instant::now();
+            let (heading, rows) = run_collector(connection, collector, all_databases, history)?;
+            let output = formatter.collector_rows(collector.name, &heading, &rows, Some(start.elapsed()));
+            (heading, output)
+        }
+    };
+    print_paged(&formatter.titled(&heading, output), &config.table);
+    Ok(())
+}
+
+/// Runs the collectors `connection` was saved with, see `Connection::collectors`, one after the other as
+/// `collect <name>` would, so loading e.g. a standby's profile shows its replication status right away. <br>
+/// A collector that fails, or no longer exists, is reported without stopping the others.
+pub fn run_profile_collectors(
+    connection: &mut Connection,
+    config: &Config,
+    all_databases: bool,
+    mut history: Option<&mut History>,
+    offline: bool,
+) {
+    for name in connection.collectors.clone() {
+        let command = Command::Collect { name };
+        let history = history.as_deref_mut();
+        if let Err(e) = run_command(&command, connection, config, all_databases, history, offline, false) {
+            error!("{}", e);
+        }
+    }
+}
+
+/// Asks `question` on the terminal and returns whether the answer was yes. <br>
+/// Returns `NotConfirmed` when there is no terminal to ask on, e.g. in a cron job, where `--yes` has to be given.
+fn confirmed(question: &str) -> Result<bool, PGError> {
+    if !io::stdin().is_terminal() {
+        return Err(PGError::NotConfirmed);
+    }
+    print!("{} [y/N] ", question);
+    let _ = io::stdout().flush();
+    let mut answer = String::new();
+    if io::stdin().read_line(&mut answer).is_err() {
+        return Ok(false);
+    }
+    Ok(["y", "yes"].contains(&answer.trim().to_lowercase().as_str()))
+}
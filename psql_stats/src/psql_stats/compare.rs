@@ -0,0 +1,291 @@
+use std::collections::BTreeMap;
+
+use colored::Colorize;
+use serde_json::{json, Map, Value};
+
+use super::humanize::{human_bytes, thousands};
+use super::{Connection, PGError};
+
+/// Sections of a comparison, in the order they are listed
+const SECTIONS: &[&str] = &["table size", "row count", "extension", "setting"];
+
+/// Share of the larger value table sizes and row counts may differ by and still count as the same, as they change
+/// with every write and never match exactly between two servers
+const SIZE_TOLERANCE: f64 = 0.1;
+
+/// Query listing what `compare` compares: the size and estimated row count of every table, the installed extensions
+/// with their versions and every setting, as `current_setting` shows it. <br>
+/// Settings naming paths of the server itself are left out, as they differ between any two servers.
+const COMPARE_QUERY: &str = r#"
+    SELECT 'table size' AS section,
+           schemaname || '.' || relname AS name,
+           pg_total_relation_size(relid) AS amount,
+           NULL::text AS value
+    FROM pg_stat_user_tables
+    UNION ALL
+    SELECT 'row count',
+           s.schemaname || '.' || s.relname,
+           CASE WHEN c.reltuples < 0 THEN s.n_live_tup ELSE c.reltuples::bigint END,
+           NULL
+    FROM pg_stat_user_tables s
+    JOIN pg_class c ON c.oid = s.relid
+    UNION ALL
+    SELECT 'extension', extname, NULL, extversion
+    FROM pg_extension
+    UNION ALL
+    SELECT 'setting', name, NULL, current_setting(name)
+    FROM pg_settings
+    WHERE name NOT IN ('data_directory', 'config_file', 'hba_file', 'ident_file', 'external_pid_file')
+"#;
+
+impl Connection {
+    /// Runs `COMPARE_QUERY`, keyed by section and name, with sizes and counts as numbers and the rest as text
+    fn comparison_values(&mut self) -> Result<BTreeMap<(usize, String), Value>, PGError> {
+        let rows = match self.query_json(COMPARE_QUERY)? {
+            Value::Array(rows) => rows,
+            _ => Vec::new(),
+        };
+        Ok(rows
+            .into_iter()
+            .filter_map(|row| {
+                let section = SECTIONS.iter().position(|s| row["section"] == *s)?;
+                let name = row["name"].as_str()?.to_string();
+                let value = match &row["amount"] {
+                    Value::Null => row["value"].clone(),
+                    amount => amount.clone(),
+                };
+                Some(((section, name), value))
+            })
+            .collect())
+    }
+}
+
+/// Whether two values of a comparison count as the same, sizes and counts within `SIZE_TOLERANCE` of each other
+fn same(first: &Value, second: &Value) -> bool {
+    match (first.as_f64(), second.as_f64()) {
+        (Some(a), Some(b)) => (a - b).abs() <= SIZE_TOLERANCE * a.abs().max(b.abs()),
+        _ => first == second,
+    }
+}
+
+/// Writes a value of `section` for reading, sizes in bytes as e.g. `12 MB` and row counts with thousands separators
+fn display(section: &str, value: &Value) -> Value {
+    match (section, value.as_i64()) {
+        ("table size", Some(bytes)) => json!(human_bytes(bytes)),
+        ("row count", Some(rows)) => json!(thousands(&rows.to_string())),
+        _ => value.clone(),
+    }
+}
+
+/// Compares the table sizes, row counts, extensions and settings of the servers `first` and `second` are connected
+/// to, one row per entry with its value on each side, in columns named `first_name` and `second_name`. <br>
+/// The `difference` of a row is `missing` when only one side has it, `differs` when their values differ, and `same`
+/// otherwise; rows that are the same are only included with `all`.
+pub fn compare(
+    first_name: &str,
+    first: &mut Connection,
+    second_name: &str,
+    second: &mut Connection,
+    all: bool,
+) -> Result<Value, PGError> {
+    let first_values = first.comparison_values()?;
+    let second_values = second.comparison_values()?;
+
+    let mut keys: Vec<&(usize, String)> = first_values.keys().chain(second_values.keys()).collect();
+    keys.sort();
+    keys.dedup();
+
+    let mut rows = Vec::new();
+    for key in keys {
+        let section = SECTIONS[key.0];
+        let (a, b) = (first_values.get(key), second_values.get(key));
+        let difference = match (a, b) {
+            (Some(a), Some(b)) if same(a, b) => "same",
+            (Some(_), Some(_)) => "differs",
+            _ => "missing",
+        };
+        if difference == "same" && !all {
+            continue;
+        }
+        let mut row = Map::new();
+        row.insert("section".to_string(), json!(section));
+        row.insert("name".to_string(), json!(key.1));
+        row.insert(first_name.to_string(), a.map(|v| display(section, v)).unwrap_or(Value::Null));
+        row.insert(second_name.to_string(), b.map(|v| display(section, v)).unwrap_or(Value::Null));
+        row.insert("difference".to_string(), json!(difference));
+        rows.push(Value::Object(row));
+    }
+    Ok(Value::Array(rows))
+}
+
+/// A row of a query result, by column
+type Row = Map<String, Value>;
+
+/// Compares the rows `first` and `second` returned for the same query, pairing them by the value of their `key`
+/// column, the first column by default, one row per difference with the `key` and the values on each side, in columns
+/// named `first_name` and `second_name`. <br>
+/// The `difference` of a row is `removed` when only `first` has it, `added` when only `second` has it, and `changed`
+/// when their values differ, listing the `columns` that do and only their values; rows that are the same are only
+/// included with `all`. Rows sharing a key are paired in the order they were returned. <br>
+/// Returns `NoSuchKeyColumn` when a row has no `key` column.
+pub fn diff_rows(
+    first_name: &str,
+    first: &Value,
+    second_name: &str,
+    second: &Value,
+    key: Option<&str>,
+    all: bool,
+) -> Result<Value, PGError> {
+    let rows = |value: &Value| -> Vec<Row> {
+        value.as_array().into_iter().flatten().filter_map(|r| r.as_object().cloned()).collect()
+    };
+    let (first, second) = (rows(first), rows(second));
+    let key = match key {
+        Some(key) => key.to_string(),
+        None => match first.iter().chain(&second).next().and_then(|r| r.keys().next()) {
+            Some(column) => column.clone(),
+            None => return Ok(json!([])),
+        },
+    };
+    if first.iter().chain(&second).any(|r| !r.contains_key(&key)) {
+        return Err(PGError::NoSuchKeyColumn);
+    }
+
+    // Group both sides by key, in the order the keys are first seen
+    let mut order: Vec<String> = Vec::new();
+    let mut groups: BTreeMap<String, (Vec<&Row>, Vec<&Row>)> = BTreeMap::new();
+    for (side, row) in first.iter().map(|r| (0, r)).chain(second.iter().map(|r| (1, r))) {
+        let value = text(&row[&key]);
+        let group = groups.entry(value.clone()).or_insert_with(|| {
+            order.push(value);
+            (Vec::new(), Vec::new())
+        });
+        match side {
+            0 => group.0.push(row),
+            _ => group.1.push(row),
+        }
+    }
+
+    let mut differences = Vec::new();
+    for value in &order {
+        let (a, b) = &groups[value];
+        for i in 0..a.len().max(b.len()) {
+            let (a, b) = (a.get(i).copied(), b.get(i).copied());
+            let mut columns: Vec<&String> = Vec::new();
+            for column in a.into_iter().chain(b).flat_map(|r| r.keys()) {
+                if *column != key && !columns.contains(&column) {
+                    columns.push(column);
+                }
+            }
+            let difference = match (a, b) {
+                (Some(a), Some(b)) if columns.iter().all(|c| a.get(*c) == b.get(*c)) => "same",
+                (Some(a), Some(b)) => {
+                    columns.retain(|c| a.get(*c) != b.get(*c));
+                    "changed"
+                }
+                (Some(_), None) => "removed",
+                _ => "added",
+            };
+            if difference == "same" && !all {
+                continue;
+            }
+            let values = |row: Option<&Row>| match row {
+                Some(row) => json!(columns
+                    .iter()
+                    .map(|c| format!("{}={}", c, row.get(*c).map(text).unwrap_or_else(|| "NULL".to_string())))
+                    .collect::<Vec<String>>()
+                    .join(", ")),
+                None => Value::Null,
+            };
+            let mut row = Map::new();
+            row.insert(key.clone(), a.or(b).map(|r| r[&key].clone()).unwrap_or(Value::Null));
+            row.insert("difference".to_string(), json!(difference));
+            row.insert(
+                "columns".to_string(),
+                match difference {
+                    "changed" => json!(columns.iter().map(|c| c.as_str()).collect::<Vec<&str>>().join(", ")),
+                    _ => Value::Null,
+                },
+            );
+            row.insert(first_name.to_string(), values(a));
+            row.insert(second_name.to_string(), values(b));
+            differences.push(Value::Object(row));
+        }
+    }
+    Ok(Value::Array(differences))
+}
+
+/// Writes a value of a query result as text, `NULL` for null and strings without their quotes
+fn text(value: &Value) -> String {
+    match value {
+        Value::Null => "NULL".to_string(),
+        Value::String(s) => s.clone(),
+        value => value.to_string(),
+    }
+}
+
+/// Loads the saved connections `first` and `second` and connects to both. Their passwords are read from the keyring
+/// with `use_keyring`, or asked for when the servers want one. <br>
+/// Returns `SameConnection` when both names are the same, `MatchNotFound` if either is not saved and
+/// `ConnectionError` if either cannot be connected to.
+fn connect_profiles(first: &str, second: &str, use_keyring: bool) -> Result<Vec<Connection>, PGError> {
+    if first == second {
+        return Err(PGError::SameConnection);
+    }
+    let mut connections = Vec::new();
+    for name in [first, second] {
+        let mut connection = Connection::read_from_json(name, String::new(), use_keyring)?;
+        connection.connect_or_prompt();
+        if connection.client.is_none() {
+            return Err(PGError::ConnectionError);
+        }
+        connections.push(connection);
+    }
+    Ok(connections)
+}
+
+/// Connects to the saved connections `first` and `second`, see `connect_profiles`, and `compare`s them
+pub fn compare_profiles(first: &str, second: &str, use_keyring: bool, all: bool) -> Result<Value, PGError> {
+    let mut connections = connect_profiles(first, second, use_keyring)?;
+    let (a, b) = connections.split_at_mut(1);
+    let result = compare(first, &mut a[0], second, &mut b[0], all);
+    for connection in &mut connections {
+        connection.close();
+    }
+    result
+}
+
+/// Connects to the saved connections `first` and `second`, see `connect_profiles`, runs `query` on both and compares
+/// the rows they return with `diff_rows`, to check that a replica or a migrated database matches its source
+pub fn diff_query_profiles(
+    first: &str,
+    second: &str,
+    query: &str,
+    key: Option<&str>,
+    use_keyring: bool,
+    all: bool,
+) -> Result<Value, PGError> {
+    let mut connections = connect_profiles(first, second, use_keyring)?;
+    let results: Vec<Result<Value, PGError>> = connections.iter_mut().map(|c| c.query_json(query)).collect();
+    for connection in &mut connections {
+        connection.close();
+    }
+    let mut results = results.into_iter();
+    match (results.next(), results.next()) {
+        (Some(a), Some(b)) => diff_rows(first, &a?, second, &b?, key, all),
+        _ => Err(PGError::UnexpectedResult),
+    }
+}
+
+/// Colors the `difference` of the rows of `compare` and `diff_rows` for the terminal: entries only one side has in red
+/// and values that differ in yellow, so what staging lacks compared to production stands out
+pub fn highlight_differences(rows: &mut Value) {
+    for row in rows.as_array_mut().into_iter().flatten() {
+        let styled = match row["difference"].as_str() {
+            Some(text @ ("missing" | "removed" | "added")) => text.red().bold(),
+            Some(text @ ("differs" | "changed")) => text.yellow(),
+            _ => continue,
+        };
+        row["difference"] = Value::String(styled.to_string());
+    }
+}
@@ -0,0 +1,221 @@
+use std::cell::RefCell;
+use std::str::FromStr;
+use std::time::Duration;
+
+use postgres::{Client, Config};
+use rustyline::completion::{Completer, Pair};
+use rustyline::highlight::Highlighter;
+use rustyline::hint::Hinter;
+use rustyline::validate::Validator;
+use rustyline::{Context, Helper};
+
+use super::collectors::COLLECTORS;
+use super::monitor::Target;
+use super::profiles::{profiles, EDITABLE_FIELDS};
+use super::resets::RESET_TARGETS;
+use super::{connections_file, Connection, HELP_MENU};
+
+/// How long completing table names may wait for the server before giving up on them
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// Query listing the tables, views and foreign tables of the database, by their name alone when the search_path finds
+/// them and with their schema otherwise
+const TABLES_QUERY: &str = r#"
+    SELECT CASE WHEN pg_table_is_visible(c.oid)
+                THEN quote_ident(c.relname)
+                ELSE quote_ident(n.nspname) || '.' || quote_ident(c.relname)
+           END AS name
+    FROM pg_class c
+    JOIN pg_namespace n ON n.oid = c.relnamespace
+    WHERE c.relkind IN ('r', 'p', 'v', 'm', 'f')
+      AND n.nspname NOT IN ('pg_catalog', 'information_schema')
+      AND n.nspname NOT LIKE 'pg_toast%'
+    ORDER BY 1
+"#;
+
+/// What an argument of a menu command names, see `argument`
+enum Argument {
+    /// A saved connection
+    Saved,
+    /// One of the connections open in the menu
+    Open,
+    /// A table, or view, of the active connection's database
+    Table,
+    /// A field of a saved connection `edit-connection` changes
+    Field,
+    /// A collector
+    Collector,
+    /// The statistics `reset-stats` resets
+    ResetTarget,
+}
+
+/// What the argument at `index`, counting from 0, of the menu command `command` names, if it is completed at all
+fn argument(command: &str, index: usize) -> Option<Argument> {
+    match (command, index) {
+        ("open" | "delete-connection" | "rename-connection" | "edit-connection" | "history", 0) => {
+            Some(Argument::Saved)
+        }
+        ("compare" | "diff-query", 0 | 1) | ("dashboard", _) => Some(Argument::Saved),
+        ("switch" | "close", 0) => Some(Argument::Open),
+        ("columns" | "describe" | "\\d" | "show-create" | "count" | "peek" | "export-table", 0)
+        | ("import-table", 1) => Some(Argument::Table),
+        ("edit-connection", 1) => Some(Argument::Field),
+        ("delta", 0) | ("since", 1) => Some(Argument::Collector),
+        ("reset-stats", 0) => Some(Argument::ResetTarget),
+        _ => None,
+    }
+}
+
+/// The menu commands as `HELP_MENU` lists them, with their aliases, e.g. `\c` for `connect`, and the collectors. The
+/// numbered options are left out, they are quicker typed than completed.
+fn menu_commands() -> Vec<String> {
+    let mut commands = vec!["help".to_string()];
+    for line in HELP_MENU.lines() {
+        let usage = match line.trim_start().strip_prefix('=') {
+            Some(entry) => entry.split(" - ").next().unwrap_or(""),
+            None => continue,
+        };
+        let words: Vec<&str> = usage.split_whitespace().collect();
+        for (i, word) in words.iter().enumerate() {
+            // Aliases follow "(or", and commands listed together are separated by "/"
+            if i == 0 || matches!(words[i - 1], "(or" | "/") {
+                commands.push(word.trim_end_matches(')').replace("\\\\", "\\"));
+            }
+        }
+    }
+    commands.extend(COLLECTORS.iter().map(|c| c.name.to_string()));
+    commands.retain(|c| !c.chars().all(|ch| ch.is_ascii_digit()));
+    commands.sort();
+    commands.dedup();
+    commands
+}
+
+/// The names of the saved connections, none when the connections file cannot be read
+fn saved_connections() -> Vec<String> {
+    connections_file::load_or_new(&connections_file::path())
+        .ok()
+        .and_then(|file| {
+            profiles(&file).ok().map(|profiles| {
+                profiles
+                    .iter()
+                    .filter_map(|p| p["connection_name"].as_str().map(str::to_string))
+                    .collect()
+            })
+        })
+        .unwrap_or_default()
+}
+
+/// Completes what is typed at the menu prompt with Tab: the menu commands and collectors, then, depending on the
+/// command, the names of saved or open connections, of tables of the active connection's database, of the fields of
+/// a saved connection or of collectors. <br>
+/// Table names are fetched once a prompt needs them, over a connection of its own since the `Client` of the active
+/// connection cannot be lent to the prompt, and kept until the next prompt so tables created in between are found.
+pub struct MenuHelper {
+    commands: Vec<String>,
+    /// Names of the connections open in the menu, see `follow`
+    open: Vec<String>,
+    /// The database table names are fetched from, none while the active connection is not connected
+    target: Option<Target>,
+    client: RefCell<Option<Client>>,
+    tables: RefCell<Option<Vec<String>>>,
+}
+
+impl MenuHelper {
+    pub fn new() -> MenuHelper {
+        MenuHelper {
+            commands: menu_commands(),
+            open: Vec::new(),
+            target: None,
+            client: RefCell::new(None),
+            tables: RefCell::new(None),
+        }
+    }
+
+    /// Points completion at the database `connection` uses, the active one, and the connections named `open`. <br>
+    /// Cheap to call before every prompt, it only reconnects when the connection parameters changed.
+    pub fn follow(&mut self, connection: &Connection, open: Vec<String>) {
+        let wanted = connection.client.as_ref().map(|_| connection.connection_string());
+        if self.target.as_ref().map(|t| &t.0) != wanted.as_ref() {
+            self.target = wanted.and_then(|w| connection.tls_connector().ok().map(|c| (w, c)));
+            *self.client.get_mut() = None;
+        }
+        *self.tables.get_mut() = None;
+        self.open = open;
+    }
+
+    /// The table names of the database, fetched the first time they are needed since the last `follow`
+    fn tables(&self) -> Vec<String> {
+        let mut tables = self.tables.borrow_mut();
+        if tables.is_none() {
+            *tables = Some(self.fetch_tables().unwrap_or_default());
+        }
+        tables.clone().unwrap_or_default()
+    }
+
+    /// Reads the table names with `TABLES_QUERY`, connecting first unless still connected. None when the server
+    /// cannot be reached.
+    fn fetch_tables(&self) -> Option<Vec<String>> {
+        let (connection_string, connector) = self.target.as_ref()?;
+        let mut client = self.client.borrow_mut();
+        if client.is_none() {
+            *client = Config::from_str(connection_string)
+                .ok()
+                .and_then(|mut config| config.connect_timeout(CONNECT_TIMEOUT).connect(connector.clone()).ok());
+        }
+        match client.as_mut()?.query(TABLES_QUERY, &[]) {
+            Ok(rows) => Some(rows.iter().filter_map(|row| row.try_get(0).ok()).collect()),
+            Err(_) => {
+                *client = None;
+                None
+            }
+        }
+    }
+}
+
+impl Default for MenuHelper {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Completer for MenuHelper {
+    type Candidate = Pair;
+
+    fn complete(&self, line: &str, pos: usize, _ctx: &Context<'_>) -> rustyline::Result<(usize, Vec<Pair>)> {
+        let line = &line[..pos];
+        let start = line.rfind(|c: char| c.is_ascii_whitespace()).map(|i| i + 1).unwrap_or(0);
+        let word = &line[start..];
+        let before: Vec<&str> = line[..start].split_whitespace().collect();
+        let candidates = match before.split_first() {
+            None => self.commands.clone(),
+            Some((command, arguments)) => match argument(command, arguments.len()) {
+                Some(Argument::Saved) => saved_connections(),
+                Some(Argument::Open) => self.open.clone(),
+                Some(Argument::Table) => self.tables(),
+                Some(Argument::Field) => EDITABLE_FIELDS.iter().map(|f| f.to_string()).collect(),
+                Some(Argument::Collector) => COLLECTORS.iter().map(|c| c.name.to_string()).collect(),
+                Some(Argument::ResetTarget) => RESET_TARGETS.iter().map(|t| t.to_string()).collect(),
+                None => Vec::new(),
+            },
+        };
+        let matches = candidates
+            .into_iter()
+            .filter(|c| c.starts_with(word))
+            .map(|c| Pair {
+                replacement: format!("{} ", c),
+                display: c,
+            })
+            .collect();
+        Ok((start, matches))
+    }
+}
+
+impl Hinter for MenuHelper {
+    type Hint = String;
+}
+
+impl Highlighter for MenuHelper {}
+
+impl Validator for MenuHelper {}
+
+impl Helper for MenuHelper {}
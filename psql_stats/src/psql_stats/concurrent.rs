@@ -0,0 +1,172 @@
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+use postgres_native_tls::MakeTlsConnector;
+use serde_json::Value;
+use tokio::runtime::{Builder, Runtime};
+use tokio::task::JoinHandle;
+use tokio_postgres::Client;
+use tracing::error;
+
+use super::logging::log_query;
+use super::{json_query, json_rows, rows_returned, timeouts, timezone, transaction, Connection, PGError};
+
+/// Most sessions opened to run queries side by side, enough to overlap the slow ones without taking up many of the
+/// server's connection slots
+pub(crate) const MAX_SESSIONS: usize = 4;
+
+/// Where `ConcurrentSessions` connect to and how each session is set up, taken from a `Connection` so sessions can be
+/// opened again later, e.g. from another thread, without it
+#[derive(Clone)]
+pub(crate) struct SessionTarget {
+    connection_string: String,
+    connector: MakeTlsConnector,
+    /// Statements run on every new session: the time zone, statement timeout and read-only mode the `Connection`'s
+    /// own session is set up with
+    setup: Vec<String>,
+}
+
+impl SessionTarget {
+    /// The database `connection` is connected to, `ClientEmpty` when it is not connected
+    pub(crate) fn of(connection: &Connection) -> Result<SessionTarget, PGError> {
+        if connection.client.is_none() {
+            return Err(PGError::ClientEmpty);
+        }
+        let setup = [
+            timezone::session_statement(),
+            timeouts::session_statement(),
+            transaction::read_only_statement().map(str::to_string),
+        ];
+        Ok(SessionTarget {
+            connection_string: connection.connection_string(),
+            connector: connection.tls_connector()?,
+            setup: setup.into_iter().flatten().collect(),
+        })
+    }
+}
+
+/// Sessions to one database opened with `tokio-postgres`, over which several queries run at the same time instead of
+/// one after another, so the slowest query, not the sum of them all, is what a caller waits for. <br>
+/// The sessions are driven by a runtime of their own that only runs while `query_json` waits, so callers stay
+/// synchronous like the rest of the program. <br>
+/// They serve the work that fans out over many queries: `report`, the dashboard and the TUI. The menu's own session
+/// stays on the `postgres` client, which already drives `tokio-postgres` the same way, one query at a time.
+pub(crate) struct ConcurrentSessions {
+    runtime: Runtime,
+    clients: Vec<Arc<Client>>,
+    /// Tasks reading and writing the sockets of the sessions, which end once their `Client` is dropped
+    connections: Vec<JoinHandle<()>>,
+}
+
+impl ConcurrentSessions {
+    /// Opens `sessions` sessions to `target`, at least one. Fails when the runtime cannot be started or none of them
+    /// can connect, while sessions that fail besides one that connects are simply left out.
+    pub(crate) fn open(target: &SessionTarget, sessions: usize) -> Result<ConcurrentSessions, PGError> {
+        let runtime = Builder::new_current_thread().enable_all().build().map_err(|e| {
+            error!("{}", e);
+            PGError::ConnectionError
+        })?;
+        let mut clients = Vec::new();
+        let mut connections = Vec::new();
+        runtime.block_on(async {
+            let connects: Vec<_> = (0..sessions.max(1))
+                .map(|_| tokio::spawn(connect(target.clone())))
+                .collect();
+            for connect in connects {
+                if let Ok(Some((client, connection))) = connect.await {
+                    clients.push(Arc::new(client));
+                    connections.push(connection);
+                }
+            }
+        });
+        match clients.is_empty() {
+            true => Err(PGError::ConnectionError),
+            false => Ok(ConcurrentSessions {
+                runtime,
+                clients,
+                connections,
+            }),
+        }
+    }
+
+    /// Whether every session was closed, e.g. by the server restarting, so new ones must be opened to run queries
+    pub(crate) fn is_closed(&self) -> bool {
+        self.clients.iter().all(|client| client.is_closed())
+    }
+
+    /// Runs each of `queries` as `Connection::query_json` does, each session taking the next one waiting as soon as
+    /// it is done with its previous one, and returns their rows in the order of `queries`
+    pub(crate) fn query_json(&self, queries: &[&str]) -> Vec<Result<Value, PGError>> {
+        let waiting: VecDeque<(usize, (String, String))> =
+            queries.iter().map(|q| (q.to_string(), json_query(q))).enumerate().collect();
+        let waiting = Arc::new(Mutex::new(waiting));
+        let results: Vec<Option<Result<Value, PGError>>> = queries.iter().map(|_| None).collect();
+        let results = Arc::new(Mutex::new(results));
+
+        self.runtime.block_on(async {
+            let workers: Vec<_> = self
+                .clients
+                .iter()
+                .map(|client| {
+                    let (client, waiting, results) = (Arc::clone(client), Arc::clone(&waiting), Arc::clone(&results));
+                    tokio::spawn(async move {
+                        loop {
+                            let next = waiting.lock().ok().and_then(|mut w| w.pop_front());
+                            let (index, (query, json)) = match next {
+                                Some(next) => next,
+                                None => break,
+                            };
+                            let start = Instant::now();
+                            let rows = json_rows(&query, client.query_one(json.as_str(), &[]).await);
+                            log_query(&query, start.elapsed(), rows_returned(&rows));
+                            if let Ok(mut results) = results.lock() {
+                                results[index] = Some(rows);
+                            }
+                        }
+                    })
+                })
+                .collect();
+            for worker in workers {
+                let _ = worker.await;
+            }
+        });
+
+        let results = results.lock().map(|mut r| std::mem::take(&mut *r)).unwrap_or_default();
+        results.into_iter().map(|r| r.unwrap_or(Err(PGError::UnexpectedResult))).collect()
+    }
+}
+
+impl Drop for ConcurrentSessions {
+    /// Lets every session say goodbye to the server, which otherwise logs that the client went away unexpectedly
+    fn drop(&mut self) {
+        self.clients.clear();
+        let connections = std::mem::take(&mut self.connections);
+        self.runtime.block_on(async {
+            for connection in connections {
+                let _ = connection.await;
+            }
+        });
+    }
+}
+
+/// Opens one session to `target` and sets it up, or none when it cannot connect. The task driving its socket is
+/// spawned on the runtime this runs on.
+async fn connect(target: SessionTarget) -> Option<(Client, JoinHandle<()>)> {
+    let (client, connection) = match tokio_postgres::connect(&target.connection_string, target.connector).await {
+        Ok(connected) => connected,
+        Err(e) => {
+            error!("{}", e);
+            return None;
+        }
+    };
+    let connection = tokio::spawn(async move {
+        let _ = connection.await;
+    });
+    if !target.setup.is_empty() {
+        if let Err(e) = client.batch_execute(&target.setup.join("; ")).await {
+            error!("{}", e);
+        }
+    }
+    Some((client, connection))
+}
@@ -0,0 +1,391 @@
+use std::collections::BTreeMap;
+use std::env;
+use std::ffi::OsString;
+use std::fs;
+use std::io::{self, Write};
+use std::path::PathBuf;
+
+use directories::{BaseDirs, ProjectDirs};
+use serde_json::{json, Value};
+use tracing::error;
+
+use super::dashboard::{self, Panel};
+use super::output::OUTPUT_FORMATS;
+use super::table::TableOptions;
+use super::{connections_file, Connection, PGError};
+
+/// User preferences, stored as JSON in `config.json` in the config directory
+pub struct Config {
+    /// Whether to color the output
+    pub color: bool,
+    /// Default format results are printed in, one of `OUTPUT_FORMATS`
+    pub output_format: String,
+    /// Column width, truncation and `NULL` display used by tables, see `\pset`
+    pub table: TableOptions,
+    /// Panels shown by `dashboard`, in order, see `dashboard::panels_from_json`
+    pub dashboard: Vec<Panel>,
+    /// Whether to warn, before running a custom query, about the relations it names that the role cannot use, see
+    /// `Connection::precheck_query`
+    pub precheck_queries: bool,
+    /// Whether a custom query that drops objects, truncates a table, or deletes or updates without `WHERE` must be
+    /// confirmed before it is run, see `Connection::destructive_statements`
+    pub confirm_destructive: bool,
+    /// Statements saved with `query --save`, by name, run with `query <name>`
+    pub queries: BTreeMap<String, String>,
+    /// Whether to show how long every collector and custom query took, toggled for the session with `\timing`
+    pub timing: bool,
+    /// Characters of each session's query shown by option 9, see `Connection::get_activity`. 0 shows them whole
+    pub activity_query_width: usize,
+    /// Whether a custom query failing inside a transaction only undoes itself, see `Connection::protected_query`
+    pub on_error_rollback: bool,
+    /// Whether custom queries run in a read only transaction that is rolled back, and sessions default to read only
+    /// transactions, so nothing typed at the prompt writes, see `Connection::custom_query`
+    pub read_only: bool,
+    /// How many times the menu tries to reconnect, waiting longer each time, when it finds the connection dropped
+    /// before running a command, see `Connection::reconnect_if_dropped`. 0 leaves reconnecting to option 7
+    pub reconnect_attempts: u32,
+    /// Collectors `--serve` exposes, by name. Empty exposes every collector
+    pub serve_collectors: Vec<String>,
+    /// Seconds `dashboard`, `index-build` and `--tui` wait between refreshes when none is given
+    pub watch_interval: u64,
+    /// SQLite file every report records its key metrics in, for `history` to show their trends, see `metrics`. None
+    /// records nothing
+    pub metrics_db: Option<String>,
+    /// Webhook, e.g. a Slack incoming webhook, alerts are posted to while watching, see `Notifier`. None posts nothing
+    pub alert_webhook: Option<String>,
+    /// Seconds before an alert still not resolved is posted again
+    pub alert_cooldown: u64,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            color: true,
+            output_format: "table".to_string(),
+            table: TableOptions::default(),
+            dashboard: dashboard::panels_from_json(&Value::Null),
+            precheck_queries: true,
+            confirm_destructive: true,
+            queries: BTreeMap::new(),
+            timing: true,
+            activity_query_width: 60,
+            on_error_rollback: true,
+            read_only: true,
+            reconnect_attempts: 5,
+            serve_collectors: Vec::new(),
+            watch_interval: 2,
+            metrics_db: None,
+            alert_webhook: None,
+            alert_cooldown: 900,
+        }
+    }
+}
+
+impl Config {
+    /// Directory the configuration, saved connections and command history are kept in: `$XDG_CONFIG_HOME/psql_stats`
+    /// when set, otherwise the platform's own, see `config_directory`
+    pub fn directory() -> PathBuf {
+        let home = BaseDirs::new().map(|d| d.home_dir().to_path_buf());
+        let platform = ProjectDirs::from("", "", "psql_stats").map(|d| d.config_dir().to_path_buf());
+        config_directory(env::var_os("XDG_CONFIG_HOME"), home, platform)
+    }
+
+    /// Path of the configuration file
+    pub fn path() -> PathBuf {
+        Self::directory().join("config.json")
+    }
+
+    /// Reads the configuration file, returning `None` if there is none yet. <br>
+    /// Unknown or invalid values fall back to their defaults.
+    pub fn load() -> Option<Config> {
+        let text = fs::read_to_string(Self::path()).ok()?;
+        let value = serde_json::from_str::<Value>(&text).unwrap_or(Value::Null);
+        Some(Self::from_json(&value))
+    }
+
+    /// Reads the configuration from `value`, as `to_json` writes it. Unknown or invalid values fall back to their
+    /// defaults.
+    pub fn from_json(value: &Value) -> Config {
+        let defaults = Config::default();
+
+        Config {
+            color: value["color"].as_bool().unwrap_or(defaults.color),
+            output_format: value["output_format"]
+                .as_str()
+                .filter(|f| OUTPUT_FORMATS.contains(f))
+                .map(|f| f.to_string())
+                .unwrap_or(defaults.output_format),
+            table: TableOptions::from_json(&value["table"]),
+            dashboard: dashboard::panels_from_json(&value["dashboard"]),
+            precheck_queries: value["precheck_queries"].as_bool().unwrap_or(defaults.precheck_queries),
+            confirm_destructive: value["confirm_destructive"].as_bool().unwrap_or(defaults.confirm_destructive),
+            queries: value["queries"]
+                .as_object()
+                .map(|queries| {
+                    queries
+                        .iter()
+                        .filter_map(|(name, sql)| Some((name.clone(), sql.as_str()?.to_string())))
+                        .collect()
+                })
+                .unwrap_or(defaults.queries),
+            timing: value["timing"].as_bool().unwrap_or(defaults.timing),
+            activity_query_width: value["activity_query_width"]
+                .as_u64()
+                .map(|w| w as usize)
+                .unwrap_or(defaults.activity_query_width),
+            on_error_rollback: value["on_error_rollback"].as_bool().unwrap_or(defaults.on_error_rollback),
+            read_only: value["read_only"].as_bool().unwrap_or(defaults.read_only),
+            reconnect_attempts: value["reconnect_attempts"]
+                .as_u64()
+                .map(|n| n.min(u32::MAX as u64) as u32)
+                .unwrap_or(defaults.reconnect_attempts),
+            serve_collectors: value["serve_collectors"]
+                .as_array()
+                .map(|names| names.iter().filter_map(|n| Some(n.as_str()?.to_string())).collect())
+                .unwrap_or(defaults.serve_collectors),
+            watch_interval: value["watch_interval"]
+                .as_u64()
+                .filter(|i| *i > 0)
+                .unwrap_or(defaults.watch_interval),
+            metrics_db: value["metrics_db"].as_str().filter(|p| !p.is_empty()).map(|p| p.to_string()),
+            alert_webhook: value["alert_webhook"].as_str().filter(|w| !w.is_empty()).map(|w| w.to_string()),
+            alert_cooldown: value["alert_cooldown"].as_u64().unwrap_or(defaults.alert_cooldown),
+        }
+    }
+
+    /// The configuration as it is written to the configuration file
+    pub fn to_json(&self) -> Value {
+        json!({
+            "color": self.color,
+            "output_format": self.output_format,
+            "table": self.table.to_json(),
+            "dashboard": dashboard::panels_to_json(&self.dashboard),
+            "precheck_queries": self.precheck_queries,
+            "confirm_destructive": self.confirm_destructive,
+            "queries": self.queries,
+            "timing": self.timing,
+            "activity_query_width": self.activity_query_width,
+            "on_error_rollback": self.on_error_rollback,
+            "read_only": self.read_only,
+            "reconnect_attempts": self.reconnect_attempts,
+            "serve_collectors": self.serve_collectors,
+            "watch_interval": self.watch_interval,
+            "metrics_db": self.metrics_db,
+            "alert_webhook": self.alert_webhook,
+            "alert_cooldown": self.alert_cooldown,
+        })
+    }
+
+    /// Reads the configuration file again, for `--serve` to pick up changes made while it runs. Unlike `load`, a file
+    /// that cannot be read or is not valid JSON gives `None` rather than the defaults, so a file caught half written
+    /// leaves the running configuration as it was. A missing one gives the defaults.
+    pub fn reload() -> Option<Config> {
+        let text = match fs::read_to_string(Self::path()) {
+            Ok(text) => text,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Some(Config::default()),
+            Err(_) => return None,
+        };
+        let value = serde_json::from_str::<Value>(&text).ok().filter(|v| v.is_object())?;
+        Some(Self::from_json(&value))
+    }
+
+    /// Writes the configuration file, creating the config directory if needed
+    pub fn save(&self) -> Result<(), PGError> {
+        let value = self.to_json();
+
+        let written = fs::create_dir_all(Self::directory()).and_then(|_| {
+            fs::write(
+                Self::path(),
+                serde_json::to_string_pretty(&value).expect("Failed to serialize config"),
+            )
+        });
+        match written {
+            Ok(_) => Ok(()),
+            Err(e) => {
+                error!("{}", e);
+                Err(PGError::JSONWriteFileError)
+            }
+        }
+    }
+
+    /// Path of the file the commands entered at the prompt are kept in, so they can be recalled and searched with
+    /// Ctrl-R in later sessions
+    pub fn history_path() -> PathBuf {
+        Self::directory().join("command_history")
+    }
+
+    /// Whether this is the first launch: there is neither a configuration file nor a connections file
+    pub fn first_run() -> bool {
+        !Self::path().exists() && !connections_file::path().exists()
+    }
+
+    /// Guides the user through choosing their preferences and, optionally, creating a first connection profile,
+    /// then saves everything. <br>
+    /// Returns the new configuration, and the connection the user entered if they created a profile. The
+    /// connection has no password and is not connected yet.
+    pub fn setup() -> (Config, Option<Connection>) {
+        println!("Welcome! No configuration was found, so let's set a few things up.");
+        println!("Press enter to accept the default shown in brackets.\n");
+
+        let defaults = Config::default();
+        let config = Config {
+            color: ask_yes_no("Use colors in the output?", defaults.color),
+            output_format: loop {
+                let format = ask("Default output format, table, json or csv?", &defaults.output_format);
+                if OUTPUT_FORMATS.contains(&format.as_str()) {
+                    break format;
+                }
+                println!("Please enter one of: {}", OUTPUT_FORMATS.join(", "));
+            },
+            table: defaults.table.clone(),
+            dashboard: defaults.dashboard.clone(),
+            precheck_queries: defaults.precheck_queries,
+            confirm_destructive: defaults.confirm_destructive,
+            queries: defaults.queries.clone(),
+            timing: defaults.timing,
+            activity_query_width: defaults.activity_query_width,
+            on_error_rollback: defaults.on_error_rollback,
+            read_only: defaults.read_only,
+            reconnect_attempts: defaults.reconnect_attempts,
+            serve_collectors: defaults.serve_collectors.clone(),
+            watch_interval: defaults.watch_interval,
+            metrics_db: defaults.metrics_db.clone(),
+            alert_webhook: defaults.alert_webhook.clone(),
+            alert_cooldown: defaults.alert_cooldown,
+        };
+
+        match config.save() {
+            Ok(_) => println!("Saved your preferences to {}\n", Self::path().display()),
+            Err(e) => error!("{}", e),
+        }
+
+        if !ask_yes_no("Create a connection profile now?", true) {
+            return (config, None);
+        }
+
+        let name = ask("Connection name", "default");
+        let host = ask("Host", "localhost");
+        let port = ask("Port", "5432");
+        let user = ask("User", "postgres");
+        let dbname = ask("Database", &user);
+
+        let connection = Connection {
+            host,
+            port,
+            user,
+            dbname,
+            ..Default::default()
+        };
+
+        let file = json!({
+            "connections": [connection.profile(&name).to_value()],
+            "version": connections_file::CURRENT_VERSION,
+        });
+        match connections_file::save(&connections_file::path(), &file) {
+            Ok(_) => println!("Saved connection \"{}\", load it next time with -l {}\n", name, name),
+            Err(e) => error!("{}", e),
+        }
+
+        (config, Some(connection))
+    }
+}
+
+/// Picks the config directory from `$XDG_CONFIG_HOME`, the home directory and the platform's config directory:
+/// `~/.config/psql_stats` on Linux, `~/Library/Application Support/psql_stats` on macOS and
+/// `%APPDATA%\psql_stats\config` on Windows. <br>
+/// `~/.config/psql_stats` is kept wherever it already exists, as earlier releases used it on every platform.
+fn config_directory(xdg_config_home: Option<OsString>, home: Option<PathBuf>, platform: Option<PathBuf>) -> PathBuf {
+    if let Some(dir) = xdg_config_home.filter(|d| !d.is_empty()) {
+        return PathBuf::from(dir).join("psql_stats");
+    }
+    let legacy = home.unwrap_or_else(|| PathBuf::from(".")).join(".config").join("psql_stats");
+    match platform {
+        Some(platform) if !legacy.is_dir() => platform,
+        _ => legacy,
+    }
+}
+
+/// Asks `question` and returns the trimmed answer, or `default` if the answer is empty
+fn ask(question: &str, default: &str) -> String {
+    print!("{} [{}]: ", question, default);
+    io::stdout().flush().expect("Could not flush");
+
+    let mut answer = String::new();
+    io::stdin().read_line(&mut answer).expect("Could not read input.");
+    match answer.trim() {
+        "" => default.to_string(),
+        a => a.to_string(),
+    }
+}
+
+/// Asks a yes or no `question`, returning `default` if the answer is empty
+fn ask_yes_no(question: &str, default: bool) -> bool {
+    let answer = ask(question, if default { "Y/n" } else { "y/N" });
+    match answer.to_ascii_lowercase().as_str() {
+        "y" | "yes" => true,
+        "n" | "no" => false,
+        _ => default,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// An empty directory under the system's temporary directory, standing in for a home directory
+    fn scratch_home(name: &str) -> PathBuf {
+        let home = env::temp_dir().join(format!("psql_stats-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&home);
+        fs::create_dir_all(&home).expect("Could not create scratch directory");
+        home
+    }
+
+    #[test]
+    fn xdg_config_home_wins() {
+        let xdg = env::temp_dir().join("xdg");
+        let dir = config_directory(Some(xdg.clone().into_os_string()), None, Some(PathBuf::from("platform")));
+        assert_eq!(dir, xdg.join("psql_stats"));
+    }
+
+    #[test]
+    fn empty_xdg_config_home_is_ignored() {
+        let home = scratch_home("empty-xdg");
+        let platform = home.join("platform").join("psql_stats");
+        let dir = config_directory(Some(OsString::new()), Some(home.clone()), Some(platform.clone()));
+        assert_eq!(dir, platform);
+        fs::remove_dir_all(home).unwrap();
+    }
+
+    #[test]
+    fn platform_directory_is_used_without_a_legacy_one() {
+        let home = scratch_home("platform");
+        let platform = home.join("AppData").join("Roaming").join("psql_stats").join("config");
+        assert_eq!(config_directory(None, Some(home.clone()), Some(platform.clone())), platform);
+        fs::remove_dir_all(home).unwrap();
+    }
+
+    #[test]
+    fn legacy_directory_is_kept_where_it_exists() {
+        let home = scratch_home("legacy");
+        let legacy = home.join(".config").join("psql_stats");
+        fs::create_dir_all(&legacy).unwrap();
+        let platform = home.join("Library").join("Application Support").join("psql_stats");
+        assert_eq!(config_directory(None, Some(home.clone()), Some(platform)), legacy);
+        fs::remove_dir_all(home).unwrap();
+    }
+
+    #[test]
+    fn falls_back_to_the_working_directory_without_a_home() {
+        let dir = config_directory(None, None, None);
+        assert_eq!(dir, PathBuf::from(".").join(".config").join("psql_stats"));
+    }
+
+    #[test]
+    fn files_are_joined_with_the_platform_separator() {
+        let platform = PathBuf::from("roaming").join("psql_stats").join("config");
+        let dir = config_directory(None, Some(PathBuf::from("missing-home")), Some(platform.clone()));
+        let path = dir.join("config.json");
+        assert_eq!(path.parent(), Some(platform.as_path()));
+        assert_eq!(path.components().count(), 4);
+    }
+}
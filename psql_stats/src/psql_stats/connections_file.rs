@@ -0,0 +1,348 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use serde_json::{json, Value};
+use tracing::{error, warn};
+
+use super::history::{rotation_of, unix_now};
+use super::{one_line, Config, PGError};
+
+/// Name of the file saved connections are stored in, in the config directory
+const CONNECTIONS_FILE: &str = "connections.toml";
+
+/// Name of the JSON file earlier releases stored saved connections in, in the config directory
+const LEGACY_CONNECTIONS_FILE: &str = "db_connections.json";
+
+/// Version of the connections file format written by this program. <br>
+/// 1: `{"connections": [...]}` without a version field, as written by the first releases <br>
+/// 2: adds `version`, and every connection has all of `auth`, `aws_region` and `target_session_attrs` <br>
+/// 3: every connection has all of `sslmode`, `sslrootcert`, `sslcert` and `sslkey` <br>
+/// 4: every connection has `collectors`, the names of the collectors run when it is loaded <br>
+/// 5: every connection has `keyring_entry`, the keyring entry holding its password when saved with `--use-keyring` <br>
+/// 6: stored as TOML in `connections.toml` in the config directory, which has no `null`, rather than as JSON in
+/// `db_connections.json` <br>
+/// 7: every connection has all of `ssh_host`, `ssh_user` and `ssh_key`, the bastion host it is reached through
+pub(crate) const CURRENT_VERSION: u64 = 7;
+
+/// Number of backups of the connections file kept next to it, older ones are removed
+const BACKUPS_KEPT: usize = 5;
+
+/// Path of the connections file: `connections.toml` in the config directory, see `Config::directory`
+pub(crate) fn path() -> PathBuf {
+    Config::directory().join(CONNECTIONS_FILE)
+}
+
+/// Converts the `db_connections.json` earlier releases kept in the config directory, their default location, to
+/// `connections.toml` next to it, once, when there is no `connections.toml` yet. Run at startup, before anything
+/// reads the saved connections. The old file is renamed with a `.migrated` suffix rather than removed, and the
+/// connections moved are logged. <br>
+/// A `db_connections.json` in the current directory is left alone, it may not be a connections file of this program.
+/// A file that cannot be converted, e.g. one written by a newer release, is left alone with a warning.
+pub fn migrate_legacy_connections() {
+    let path = path();
+    let legacy = Config::directory().join(LEGACY_CONNECTIONS_FILE);
+    if path.exists() || !legacy.is_file() {
+        return;
+    }
+
+    let file = match read_legacy(&legacy).and_then(|file| save(&path, &file).map(|_| file)) {
+        Ok(f) => f,
+        Err(e) => {
+            warn!("could not move the saved connections of {} to {}: {}", legacy.display(), path.display(), e);
+            return;
+        }
+    };
+    let names: Vec<&str> = file["connections"]
+        .as_array()
+        .map(|c| c.iter().filter_map(|c| c["connection_name"].as_str()).collect())
+        .unwrap_or_default();
+    warn!("moved the saved connections of {} to {}: {}", legacy.display(), path.display(), names.join(", "));
+    if let Err(e) = fs::rename(&legacy, sibling(&legacy, ".migrated")) {
+        warn!("could not rename {}, remove it yourself: {}", legacy.display(), e);
+    }
+}
+
+/// Reads a connections file of version 5 or earlier, which were JSON, and migrates it to `CURRENT_VERSION`
+fn read_legacy(path: &Path) -> Result<Value, PGError> {
+    let text = fs::read_to_string(path).map_err(|_| PGError::JSONOpenFileError)?;
+    let file = serde_json::from_str::<Value>(&text).map_err(|e| PGError::MalformedConnectionsFile {
+        reason: format!("{} is not valid JSON, {}", path.display(), e),
+    })?;
+    migrate(file)
+}
+
+/// Reads the connections file at `path` and migrates it to `CURRENT_VERSION` in memory. <br>
+/// Returns `JSONOpenFileError` if the file cannot be read, `MalformedConnectionsFile` if it is not valid TOML, and
+/// `UnsupportedFileVersion` if it was written by a newer version of this program, so it is never rewritten in a format
+/// that would lose information.
+pub(crate) fn load(path: &Path) -> Result<Value, PGError> {
+    let text = fs::read_to_string(path).map_err(|_| PGError::JSONOpenFileError)?;
+    let file = toml::from_str::<Value>(&text).map_err(|e| {
+        let line = e.span().map_or(1, |span| text[..span.start].matches('\n').count() + 1);
+        PGError::MalformedConnectionsFile {
+            reason: format!("{} is not valid TOML, line {}: {}", path.display(), line, one_line(e.message(), 0)),
+        }
+    })?;
+    migrate(file)
+}
+
+/// Brings a connections file of any earlier version up to `CURRENT_VERSION`, one version at a time
+pub(crate) fn migrate(mut file: Value) -> Result<Value, PGError> {
+    if !file.is_object() {
+        return Err(PGError::MalformedConnectionsFile {
+            reason: "it does not hold a table of settings".to_string(),
+        });
+    }
+
+    let version = file["version"].as_u64().unwrap_or(1);
+    if version > CURRENT_VERSION {
+        return Err(PGError::UnsupportedFileVersion);
+    }
+    if version < 2 {
+        migrate_v1_to_v2(&mut file);
+    }
+    if version < 3 {
+        migrate_v2_to_v3(&mut file);
+    }
+    if version < 4 {
+        migrate_v3_to_v4(&mut file);
+    }
+    if version < 5 {
+        migrate_v4_to_v5(&mut file);
+    }
+    if version < 6 {
+        migrate_v5_to_v6(&mut file);
+    }
+    if version < 7 {
+        migrate_v6_to_v7(&mut file);
+    }
+    Ok(file)
+}
+
+/// Same as `load`, but returns an empty file of the current version when there is none at `path` yet, for the first
+/// connection to be saved in
+pub(crate) fn load_or_new(path: &Path) -> Result<Value, PGError> {
+    match path.exists() {
+        true => load(path),
+        false => Ok(json!({"connections": [], "version": CURRENT_VERSION})),
+    }
+}
+
+/// Writes `file` to `path`, first copying the current file to a timestamped backup, see `backup_path`. <br>
+/// The new contents are written to a temporary file that then replaces the old one, so an interrupted write never
+/// leaves a truncated connections file behind. The directory it is in is created if needed.
+pub(crate) fn save(path: &Path, file: &Value) -> Result<(), PGError> {
+    if path.exists() {
+        let backup = backup_path(path, unix_now());
+        if let Err(e) = fs::copy(path, &backup) {
+            error!("could not back up {}: {}", path.display(), e);
+            return Err(PGError::JSONWriteFileError);
+        }
+        prune_backups(path);
+    }
+
+    let text = match toml::to_string_pretty(file) {
+        Ok(t) => t,
+        Err(e) => {
+            error!("could not write {}: {}", path.display(), e);
+            return Err(PGError::JSONWriteFileError);
+        }
+    };
+    let temporary = sibling(path, ".tmp");
+    let created = match path.parent() {
+        Some(directory) if !directory.as_os_str().is_empty() => fs::create_dir_all(directory),
+        _ => Ok(()),
+    };
+    match created.and_then(|_| fs::write(&temporary, text)).and_then(|_| fs::rename(&temporary, path)) {
+        Ok(_) => Ok(()),
+        Err(e) => {
+            error!("could not write {}: {}", path.display(), e);
+            Err(PGError::JSONWriteFileError)
+        }
+    }
+}
+
+/// Adds the version field and fills in the connection options added since the first release
+fn migrate_v1_to_v2(file: &mut Value) {
+    let object = file.as_object_mut().expect("connections file is an object");
+    let connections = object.entry("connections").or_insert_with(|| json!([]));
+
+    if let Some(connections) = connections.as_array_mut() {
+        for connection in connections.iter_mut().filter_map(|c| c.as_object_mut()) {
+            for key in ["auth", "aws_region", "target_session_attrs"] {
+                connection.entry(key).or_insert_with(|| json!(""));
+            }
+        }
+    }
+    object.insert("version".to_string(), json!(2));
+}
+
+/// Fills in the TLS options, empty meaning the defaults the connections were made with before
+fn migrate_v2_to_v3(file: &mut Value) {
+    let object = file.as_object_mut().expect("connections file is an object");
+    if let Some(connections) = object.get_mut("connections").and_then(|c| c.as_array_mut()) {
+        for connection in connections.iter_mut().filter_map(|c| c.as_object_mut()) {
+            for key in ["sslmode", "sslrootcert", "sslcert", "sslkey"] {
+                connection.entry(key).or_insert_with(|| json!(""));
+            }
+        }
+    }
+    object.insert("version".to_string(), json!(3));
+}
+
+/// Adds an empty list of collectors to run on load, so loading a connection shows nothing more than before
+fn migrate_v3_to_v4(file: &mut Value) {
+    let object = file.as_object_mut().expect("connections file is an object");
+    if let Some(connections) = object.get_mut("connections").and_then(|c| c.as_array_mut()) {
+        for connection in connections.iter_mut().filter_map(|c| c.as_object_mut()) {
+            connection.entry("collectors").or_insert_with(|| json!([]));
+        }
+    }
+    object.insert("version".to_string(), json!(4));
+}
+
+/// Adds an empty keyring entry, as passwords were never saved before
+fn migrate_v4_to_v5(file: &mut Value) {
+    let object = file.as_object_mut().expect("connections file is an object");
+    if let Some(connections) = object.get_mut("connections").and_then(|c| c.as_array_mut()) {
+        for connection in connections.iter_mut().filter_map(|c| c.as_object_mut()) {
+            connection.entry("keyring_entry").or_insert_with(|| json!(""));
+        }
+    }
+    object.insert("version".to_string(), json!(5));
+}
+
+/// Drops the fields set to `null`, which TOML has no way of writing, the same as leaving them out
+fn migrate_v5_to_v6(file: &mut Value) {
+    let object = file.as_object_mut().expect("connections file is an object");
+    if let Some(connections) = object.get_mut("connections").and_then(|c| c.as_array_mut()) {
+        for connection in connections.iter_mut().filter_map(|c| c.as_object_mut()) {
+            connection.retain(|_, value| !value.is_null());
+        }
+    }
+    object.insert("version".to_string(), json!(6));
+}
+
+/// Fills in the SSH tunnel options, empty meaning the connections are made directly as before
+fn migrate_v6_to_v7(file: &mut Value) {
+    let object = file.as_object_mut().expect("connections file is an object");
+    if let Some(connections) = object.get_mut("connections").and_then(|c| c.as_array_mut()) {
+        for connection in connections.iter_mut().filter_map(|c| c.as_object_mut()) {
+            for key in ["ssh_host", "ssh_user", "ssh_key"] {
+                connection.entry(key).or_insert_with(|| json!(""));
+            }
+        }
+    }
+    object.insert("version".to_string(), json!(7));
+}
+
+/// Path of the file next to `path` named after it with `suffix` appended, e.g. `connections.toml.tmp`
+fn sibling(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(suffix);
+    path.with_file_name(name)
+}
+
+/// Path the file at `path` is backed up to when saved at `saved_at`, e.g. `connections.toml.1700000000.bak`,
+/// numbered after the backups already taken in the same second so none is overwritten
+fn backup_path(path: &Path, saved_at: u64) -> PathBuf {
+    let mut backup = sibling(path, &format!(".{}.bak", saved_at));
+    let mut n = 1;
+    while backup.exists() {
+        backup = sibling(path, &format!(".{}-{}.bak", saved_at, n));
+        n += 1;
+    }
+    backup
+}
+
+/// Removes all but the newest `BACKUPS_KEPT` backups of the file at `path`
+fn prune_backups(path: &Path) {
+    let directory = match path.parent() {
+        Some(d) if !d.as_os_str().is_empty() => d,
+        _ => Path::new("."),
+    };
+    let prefix = format!("{}.", path.file_name().and_then(|n| n.to_str()).unwrap_or(""));
+
+    let mut backups: Vec<_> = match fs::read_dir(directory) {
+        Ok(entries) => entries
+            .filter_map(|e| e.ok())
+            .map(|e| e.path())
+            .filter_map(|p| {
+                let name = p.file_name().and_then(|n| n.to_str()).unwrap_or("");
+                let taken = name.strip_prefix(&prefix)?.strip_suffix(".bak").and_then(rotation_of)?;
+                Some((taken, p))
+            })
+            .collect(),
+        Err(_) => return,
+    };
+
+    // Sorted by the time they were taken, then by their number within that second, oldest first
+    backups.sort();
+    let excess = backups.len().saturating_sub(BACKUPS_KEPT);
+    for (_, old) in &backups[..excess] {
+        if let Err(e) = fs::remove_file(old) {
+            error!("could not remove old backup {}: {}", old.display(), e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Writes `text` to a file of its own under the system's temporary directory
+    fn scratch_file(name: &str, text: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("psql_stats-{}-{}", std::process::id(), name));
+        fs::write(&path, text).expect("Could not write scratch file");
+        path
+    }
+
+    #[test]
+    fn corrupt_files_are_reported_as_malformed() {
+        let toml = scratch_file("corrupt.toml", "version = 6\n[[connections]\nhost = \"db\"\n");
+        match load(&toml) {
+            Err(PGError::MalformedConnectionsFile { reason }) => assert!(reason.contains("line 2"), "{}", reason),
+            other => panic!("expected MalformedConnectionsFile, got {:?}", other),
+        }
+        let json = scratch_file("corrupt.json", "{\"connections\": [{\"host\": \"db\"");
+        assert!(matches!(read_legacy(&json), Err(PGError::MalformedConnectionsFile { .. })));
+        assert!(matches!(migrate(json!([1, 2])), Err(PGError::MalformedConnectionsFile { .. })));
+        fs::remove_file(toml).unwrap();
+        fs::remove_file(json).unwrap();
+    }
+
+    #[test]
+    fn first_release_files_are_migrated_to_the_current_version() {
+        let file = json!({"connections": [{"connection_name": "prod", "host": "db"}]});
+        let file = migrate(file).unwrap();
+        assert_eq!(file["version"], CURRENT_VERSION);
+        let connection = &file["connections"][0];
+        assert_eq!(connection["host"], "db");
+        assert_eq!(connection["ssh_host"], "");
+    }
+
+    #[test]
+    fn saves_in_the_same_second_keep_a_backup_each() {
+        let directory = std::env::temp_dir().join(format!("psql_stats-{}-backups", std::process::id()));
+        let path = directory.join(CONNECTIONS_FILE);
+        let _ = fs::remove_dir_all(&directory);
+        for host in ["db1", "db2", "db3"] {
+            save(&path, &json!({"connections": [{"host": host}], "version": CURRENT_VERSION})).unwrap();
+        }
+
+        let backups = fs::read_dir(&directory).unwrap().filter_map(|e| e.ok()).map(|e| e.path());
+        let mut backed_up: Vec<_> = backups
+            .filter(|p| p.extension().is_some_and(|e| e == "bak"))
+            .map(|p| load(&p).unwrap()["connections"][0]["host"].clone())
+            .collect();
+        backed_up.sort_by_key(|h| h.to_string());
+        assert_eq!(backed_up, [json!("db1"), json!("db2")]);
+        fs::remove_dir_all(directory).unwrap();
+    }
+
+    #[test]
+    fn missing_files_cannot_be_opened() {
+        let path = std::env::temp_dir().join(format!("psql_stats-{}-missing.toml", std::process::id()));
+        assert!(matches!(load(&path), Err(PGError::JSONOpenFileError)));
+    }
+}
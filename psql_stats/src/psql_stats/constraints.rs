@@ -0,0 +1,211 @@
+use colored::Colorize;
+use serde_json::{json, Value};
+
+use super::healthcheck::{Check, Status};
+use super::output::OutputFormatter;
+use super::versions::{pick, QueryVariant};
+use super::style::warning_sign;
+use super::{Config, Connection, PGError};
+
+/// Query listing the check, foreign key and not-null constraints added `NOT VALID` and never validated, which the
+/// rows already in their table may break. A migration that validates them later, or a dump restored elsewhere,
+/// fails on such a row, and the planner cannot rely on them meanwhile.
+pub const NOT_VALID_CONSTRAINTS_QUERY: &str = r#"
+    SELECT n.nspname AS schema,
+           c.conrelid::regclass::text AS table,
+           c.conname AS constraint,
+           CASE c.contype WHEN 'c' THEN 'check'
+                          WHEN 'f' THEN 'foreign key'
+                          WHEN 'n' THEN 'not null'
+                          ELSE c.contype::text
+           END AS type,
+           pg_get_constraintdef(c.oid) AS definition,
+           format('ALTER TABLE %s VALIDATE CONSTRAINT %I', c.conrelid::regclass, c.conname) AS validate_with
+    FROM pg_constraint c
+    JOIN pg_namespace n ON n.oid = c.connamespace
+    WHERE NOT c.convalidated
+      AND c.conrelid <> 0
+      AND n.nspname NOT IN ('pg_catalog', 'information_schema')
+      AND n.nspname NOT LIKE 'pg_toast%'
+    ORDER BY n.nspname, c.conrelid::regclass::text, c.conname
+"#;
+
+/// Query listing the foreign keys with no index on the referencing columns, largest table first. Every update or
+/// delete of a referenced row then scans the whole referencing table to check for rows pointing at it, under a lock.
+/// <br>
+/// An index counts when the foreign key's columns are its leading columns, in any order, and it is not partial.
+pub const UNINDEXED_FOREIGN_KEYS_QUERY: &str = r#"
+    WITH foreign_keys AS (
+        SELECT n.nspname AS schema,
+               c.conrelid,
+               c.confrelid,
+               c.conname,
+               c.conkey,
+               (SELECT string_agg(quote_ident(a.attname), ', ' ORDER BY k.position)
+                FROM unnest(c.conkey) WITH ORDINALITY k(attnum, position)
+                JOIN pg_attribute a ON a.attrelid = c.conrelid AND a.attnum = k.attnum) AS columns
+        FROM pg_constraint c
+        JOIN pg_namespace n ON n.oid = c.connamespace
+        WHERE c.contype = 'f'
+          AND c.conparentid = 0
+          AND n.nspname NOT IN ('pg_catalog', 'information_schema')
+          AND n.nspname NOT LIKE 'pg_toast%'
+    )
+    SELECT f.schema,
+           f.conrelid::regclass::text AS table,
+           f.conname AS constraint,
+           f.columns,
+           f.confrelid::regclass::text AS referenced_table,
+           pg_relation_size(f.conrelid) AS size_bytes,
+           format('CREATE INDEX CONCURRENTLY ON %s (%s)', f.conrelid::regclass, f.columns) AS index_with
+    FROM foreign_keys f
+    WHERE NOT EXISTS (
+        SELECT FROM pg_index i
+        WHERE i.indrelid = f.conrelid
+          AND i.indpred IS NULL
+          AND (i.indkey::int2[])[0:cardinality(f.conkey) - 1] @> f.conkey
+    )
+    ORDER BY size_bytes DESC, f.schema, f.conrelid::regclass::text, f.conname
+"#;
+
+/// Query listing the indexes marked invalid, which are kept up to date on every write but never used by queries.
+/// They are left behind by a `CREATE INDEX CONCURRENTLY` or `REINDEX CONCURRENTLY` that failed or was cancelled, or
+/// are still being built, as `building` says (read from `pg_stat_progress_create_index`, Postgres 12).
+pub const INVALID_INDEXES_QUERY: &str = r#"
+    SELECT n.nspname AS schema,
+           t.relname AS table,
+           c.relname AS index,
+           pg_relation_size(i.indexrelid) AS size_bytes,
+           EXISTS (SELECT FROM pg_stat_progress_create_index p WHERE p.index_relid = i.indexrelid) AS building,
+           CASE WHEN c.relname ~ '_cc(new|old)[0-9]*$'
+                THEN format('left by a failed REINDEX CONCURRENTLY, DROP INDEX CONCURRENTLY %s', i.indexrelid::regclass)
+                ELSE format('DROP INDEX CONCURRENTLY %s and create it again, or REINDEX INDEX CONCURRENTLY %1$s',
+                            i.indexrelid::regclass)
+           END AS note
+    FROM pg_index i
+    JOIN pg_class c ON c.oid = i.indexrelid
+    JOIN pg_class t ON t.oid = i.indrelid
+    JOIN pg_namespace n ON n.oid = c.relnamespace
+    WHERE NOT i.indisvalid
+    ORDER BY n.nspname, t.relname, c.relname
+"#;
+
+/// `INVALID_INDEXES_QUERY` before Postgres 12, which reports no progress of index builds and cannot `REINDEX
+/// CONCURRENTLY`
+const INVALID_INDEXES_BEFORE_12_QUERY: &str = r#"
+    SELECT n.nspname AS schema,
+           t.relname AS table,
+           c.relname AS index,
+           pg_relation_size(i.indexrelid) AS size_bytes,
+           NULL::boolean AS building,
+           format('DROP INDEX CONCURRENTLY %s and create it again', i.indexrelid::regclass) AS note
+    FROM pg_index i
+    JOIN pg_class c ON c.oid = i.indexrelid
+    JOIN pg_class t ON t.oid = i.indrelid
+    JOIN pg_namespace n ON n.oid = c.relnamespace
+    WHERE NOT i.indisvalid
+    ORDER BY n.nspname, t.relname, c.relname
+"#;
+
+/// Forms of `INVALID_INDEXES_QUERY` for older servers
+pub const INVALID_INDEXES_VARIANTS: &[QueryVariant] = &[QueryVariant {
+    before: 120000,
+    query: INVALID_INDEXES_BEFORE_12_QUERY,
+}];
+
+impl Connection {
+    /// Lists the indexes marked invalid, see `INVALID_INDEXES_QUERY`
+    pub fn get_invalid_indexes(&mut self) -> Result<Value, PGError> {
+        self.query_json(pick(self.server_version, INVALID_INDEXES_QUERY, INVALID_INDEXES_VARIANTS))
+    }
+
+    /// Reports what makes writes and migrations slower, or fail, than they look like they should: the constraints
+    /// never validated under `not_valid`, the foreign keys without an index under `unindexed_foreign_keys` and the
+    /// invalid indexes under `invalid_indexes`
+    pub fn constraint_report(&mut self) -> Result<Value, PGError> {
+        let not_valid = self.query_json(NOT_VALID_CONSTRAINTS_QUERY)?;
+        let unindexed = self.query_json(UNINDEXED_FOREIGN_KEYS_QUERY)?;
+        let invalid = self.get_invalid_indexes()?;
+        Ok(json!({ "not_valid": not_valid, "unindexed_foreign_keys": unindexed, "invalid_indexes": invalid }))
+    }
+}
+
+/// Warns about the constraints never validated and the invalid indexes, except those still being built, of a
+/// `Connection::constraint_report`. Foreign keys without an index are left to `render_constraint_report`, as small
+/// tables rarely need one.
+pub(crate) fn constraints_check(report: &Value) -> Check {
+    let not_valid = report["not_valid"].as_array().map_or(0, |r| r.len());
+    let invalid = report["invalid_indexes"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter(|r| r["building"] != true)
+        .count();
+    let mut problems = Vec::new();
+    if invalid > 0 {
+        problems.push(format!("{} invalid index(es)", invalid));
+    }
+    if not_valid > 0 {
+        problems.push(format!("{} constraint(s) never validated", not_valid));
+    }
+    match problems.is_empty() {
+        true => Check {
+            name: "constraints",
+            status: Status::Ok,
+            message: "no invalid indexes or constraints left NOT VALID".to_string(),
+        },
+        false => Check {
+            name: "constraints",
+            status: Status::Warn,
+            message: problems.join(", "),
+        },
+    }
+}
+
+/// Renders what `Connection::constraint_report` returns: in JSON as one object, otherwise the constraints never
+/// validated, the foreign keys without an index and the invalid indexes each in its own table, followed by what to do
+/// about those found
+pub fn render_constraint_report(report: &Value, config: &Config) -> String {
+    if config.output_format == "json" {
+        return serde_json::to_string_pretty(report).expect("Failed to serialize the report");
+    }
+    let formatter = OutputFormatter::new(config);
+    let mut sections = vec![
+        formatter.titled("Constraints never validated", formatter.rows(&report["not_valid"])),
+        formatter.titled("Foreign keys without an index", formatter.rows(&report["unindexed_foreign_keys"])),
+        formatter.titled("Invalid indexes", formatter.rows(&report["invalid_indexes"])),
+    ];
+    let found = |key: &str| report[key].as_array().is_some_and(|r| !r.is_empty());
+    if found("not_valid") {
+        sections.push(
+            format!(
+                "{} Validate the constraints with validate_with, it only takes a SHARE UPDATE EXCLUSIVE lock",
+                warning_sign()
+            )
+            .yellow()
+            .to_string(),
+        );
+    }
+    if found("unindexed_foreign_keys") {
+        sections.push(
+            format!(
+                "{} Updates and deletes of referenced rows scan the whole table of a foreign key without an index, \
+                 create one with index_with when the table is large",
+                warning_sign()
+            )
+            .yellow()
+            .to_string(),
+        );
+    }
+    if found("invalid_indexes") {
+        sections.push(
+            format!(
+                "{} Invalid indexes slow down every write without serving any query, see their note",
+                warning_sign()
+            )
+            .yellow()
+            .to_string(),
+        );
+    }
+    sections.join("\n")
+}
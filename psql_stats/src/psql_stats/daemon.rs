@@ -0,0 +1,158 @@
+use std::env;
+use std::fs;
+#[cfg(unix)]
+use std::os::unix::net::UnixDatagram;
+use std::path::PathBuf;
+use std::sync::atomic::AtomicBool;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use signal_hook::consts::{SIGINT, SIGTERM};
+use tracing::error;
+
+/// Sends a state update such as `READY=1` or `STOPPING=1` to systemd. <br>
+/// Does nothing when the program was not started by systemd with `Type=notify` (no `NOTIFY_SOCKET` set).
+#[cfg(unix)]
+pub fn notify(state: &str) {
+    let socket_path = match env::var("NOTIFY_SOCKET") {
+        Ok(p) => p,
+        Err(_) => return,
+    };
+
+    let socket = match UnixDatagram::unbound() {
+        Ok(s) => s,
+        Err(e) => {
+            error!("could not notify systemd: {}", e);
+            return;
+        }
+    };
+
+    // Paths starting with '@' refer to the Linux abstract socket namespace
+    let sent = match socket_path.strip_prefix('@') {
+        Some(name) => send_abstract(&socket, name, state),
+        None => socket.send_to(state.as_bytes(), &socket_path).map(|_| ()),
+    };
+
+    if let Err(e) = sent {
+        error!("could not notify systemd: {}", e);
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn send_abstract(socket: &UnixDatagram, name: &str, state: &str) -> std::io::Result<()> {
+    use std::os::linux::net::SocketAddrExt;
+    use std::os::unix::net::SocketAddr;
+
+    let addr = SocketAddr::from_abstract_name(name.as_bytes())?;
+    socket.send_to_addr(state.as_bytes(), &addr).map(|_| ())
+}
+
+#[cfg(all(unix, not(target_os = "linux")))]
+fn send_abstract(_socket: &UnixDatagram, _name: &str, _state: &str) -> std::io::Result<()> {
+    Err(std::io::Error::new(
+        std::io::ErrorKind::Unsupported,
+        "abstract sockets are only available on Linux",
+    ))
+}
+
+/// There is no systemd outside Unix, so nothing to notify
+#[cfg(not(unix))]
+pub fn notify(_state: &str) {}
+
+/// Systemd's watchdog (`WatchdogSec=` in the unit), pinged from the loop it guards rather than on a timer, so a loop
+/// that hangs stops pinging and systemd restarts the service. Does nothing when the watchdog is not enabled.
+pub(crate) struct Watchdog {
+    /// Half the interval systemd expects pings within, `None` without a watchdog
+    interval: Option<Duration>,
+    pinged: Option<Instant>,
+}
+
+impl Watchdog {
+    /// The watchdog systemd enabled through `WATCHDOG_USEC`, if any
+    pub(crate) fn from_env() -> Watchdog {
+        let usec = env::var("WATCHDOG_USEC").ok().and_then(|u| u.parse::<u64>().ok());
+        Watchdog {
+            interval: usec.filter(|u| *u > 0).map(|u| Duration::from_micros(u / 2)),
+            pinged: None,
+        }
+    }
+
+    /// Tells systemd the loop made progress, unless it was told less than half the interval ago
+    pub(crate) fn ping(&mut self) {
+        if self.due() {
+            notify("WATCHDOG=1");
+        }
+    }
+
+    /// Whether a ping is due, noting it was sent when it is
+    fn due(&mut self) -> bool {
+        let interval = match self.interval {
+            Some(interval) => interval,
+            None => return false,
+        };
+        if self.pinged.is_some_and(|p| p.elapsed() < interval) {
+            return false;
+        }
+        self.pinged = Some(Instant::now());
+        true
+    }
+}
+
+/// Returns a flag that is set once the program receives SIGTERM or SIGINT, so long running modes can finish
+/// the current request and shut down cleanly.
+pub fn shutdown_flag() -> Arc<AtomicBool> {
+    let flag = Arc::new(AtomicBool::new(false));
+    for signal in [SIGTERM, SIGINT] {
+        if let Err(e) = signal_hook::flag::register(signal, Arc::clone(&flag)) {
+            error!("could not register signal handler: {}", e);
+        }
+    }
+    flag
+}
+
+/// A file containing the id of the running process, removed again when dropped
+pub struct PidFile {
+    path: PathBuf,
+}
+
+impl PidFile {
+    /// Writes the current process id to `path`
+    pub fn create(path: &str) -> std::io::Result<PidFile> {
+        fs::write(path, format!("{}\n", std::process::id()))?;
+        Ok(PidFile {
+            path: PathBuf::from(path),
+        })
+    }
+}
+
+impl Drop for PidFile {
+    fn drop(&mut self) {
+        if let Err(e) = fs::remove_file(&self.path) {
+            error!("could not remove pid file: {}", e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn watchdog_pings_at_most_every_half_interval() {
+        let mut watchdog = Watchdog {
+            interval: Some(Duration::from_secs(60)),
+            pinged: None,
+        };
+        assert!(watchdog.due());
+        assert!(!watchdog.due());
+
+        watchdog.pinged = Some(Instant::now() - Duration::from_secs(61));
+        assert!(watchdog.due());
+
+        let mut disabled = Watchdog {
+            interval: None,
+            pinged: None,
+        };
+        assert!(!disabled.due());
+    }
+}
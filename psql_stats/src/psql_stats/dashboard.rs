@@ -0,0 +1,297 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+use colored::Colorize;
+use ratatui::crossterm::terminal;
+use serde_json::{json, Value};
+use signal_hook::consts::SIGINT;
+
+use super::alerts::Notifier;
+use super::collectors::{find_collector, Collector};
+use super::concurrent::{ConcurrentSessions, SessionTarget, MAX_SESSIONS};
+use super::style::glyph;
+use super::table::{fit_width, render_json_rows, TableOptions};
+use super::{Connection, PGError};
+
+/// Built-in layouts that can be picked by name in the config file, each a list of collectors with the number of rows
+/// they may take up. The first one is the default.
+pub const LAYOUTS: &[(&str, &[(&str, usize)])] = &[
+    ("overview", &[("uptime", 1), ("database-stats", 6), ("activity", 10)]),
+    ("replication", &[("uptime", 1), ("replication", 8), ("activity", 6)]),
+    ("application", &[("activity", 12), ("write-churn", 8), ("database-stats", 4)]),
+];
+
+/// A dashboard panel: a collector and the number of rows it may take up, longer results are cut off
+#[derive(Clone)]
+pub struct Panel {
+    pub collector: String,
+    pub rows: usize,
+}
+
+/// Returns the panels of the built-in layout called `name`
+pub fn layout(name: &str) -> Option<Vec<Panel>> {
+    let (_, panels) = LAYOUTS.iter().find(|(n, _)| *n == name)?;
+    Some(
+        panels
+            .iter()
+            .map(|(collector, rows)| Panel {
+                collector: collector.to_string(),
+                rows: *rows,
+            })
+            .collect(),
+    )
+}
+
+/// Reads the dashboard panels from the `dashboard` object of the configuration file, in the order they are shown:
+/// either `{"panels": [{"collector": "activity", "rows": 10}, ...]}`, or `{"layout": "replication"}` for one of the
+/// `LAYOUTS`. Anything else falls back to the default layout.
+pub(crate) fn panels_from_json(value: &Value) -> Vec<Panel> {
+    if let Some(panels) = value["panels"].as_array() {
+        return panels
+            .iter()
+            .filter_map(|p| {
+                Some(Panel {
+                    collector: p["collector"].as_str()?.to_string(),
+                    rows: p["rows"].as_u64().unwrap_or(5) as usize,
+                })
+            })
+            .collect();
+    }
+    value["layout"]
+        .as_str()
+        .and_then(layout)
+        .unwrap_or_else(|| layout(LAYOUTS[0].0).unwrap_or_default())
+}
+
+pub(crate) fn panels_to_json(panels: &[Panel]) -> Value {
+    let panels: Vec<Value> = panels
+        .iter()
+        .map(|p| json!({"collector": p.collector, "rows": p.rows}))
+        .collect();
+    json!({ "panels": panels })
+}
+
+/// Width assumed when the terminal cannot tell us its size
+const DEFAULT_WIDTH: usize = 160;
+
+impl Connection {
+    /// Opens another connection to show on the dashboard next to this one: the saved connection named `target`, or
+    /// else the server at `target`, given as `host` or `host:port`, with this connection's credentials
+    pub fn dashboard_peer(&self, target: &str) -> Connection {
+        if let Ok(mut saved) = Connection::read_from_json(target, self.password.clone(), false) {
+            saved.connect();
+            return saved;
+        }
+
+        let (host, port) = match target.split_once(':') {
+            Some((h, p)) => (h.to_string(), p.to_string()),
+            None => (target.to_string(), self.port.clone()),
+        };
+        let mut peer = Connection {
+            client: None,
+            host,
+            dbname: self.dbname.clone(),
+            user: self.user.clone(),
+            port,
+            password: self.password.clone(),
+            sslmode: self.sslmode.clone(),
+            sslrootcert: self.sslrootcert.clone(),
+            sslcert: self.sslcert.clone(),
+            sslkey: self.sslkey.clone(),
+            auth: self.auth.clone(),
+            aws_region: self.aws_region.clone(),
+            target_session_attrs: String::new(),
+            ssh_host: self.ssh_host.clone(),
+            ssh_user: self.ssh_user.clone(),
+            ssh_key: self.ssh_key.clone(),
+            tunnel: None,
+            connected_to: None,
+            collectors: Vec::new(),
+            privileges: Default::default(),
+            capabilities: Default::default(),
+            server_version: 0,
+            transaction: Default::default(),
+            schema: self.schema.clone(),
+            search_path: String::new(),
+            timezone: String::new(),
+            queries: 0,
+        };
+        peer.connect();
+        peer
+    }
+}
+
+/// Shows the same `panels` for every connection in `connections`, side by side, refreshing every `interval` until the
+/// user presses Ctrl-C. <br>
+/// Every refresh queries all connections at the same time, one thread each, so the columns always show samples taken
+/// at the same moment and a slow server does not hold up the others. The panels of a column are collected at the same
+/// time as well, over sessions of its own kept open between refreshes, so a slow panel does not hold up the others,
+/// see `collect_panels`. Connections that are not connected show the error in their column instead. <br>
+/// With a `notifier`, the thresholds it watches are checked on every connection after each refresh, see
+/// `Notifier::check`.
+pub fn watch_dashboard(
+    connections: &mut [Connection],
+    panels: &[Panel],
+    interval: Duration,
+    mut notifier: Option<&mut Notifier>,
+) -> Result<(), PGError> {
+    // Ctrl-C stops the dashboard instead of the whole program
+    let stop = Arc::new(AtomicBool::new(false));
+    let signal = signal_hook::flag::register(SIGINT, Arc::clone(&stop)).ok();
+
+    let options = TableOptions {
+        max_width: 40,
+        ..TableOptions::default()
+    };
+
+    let mut sessions: Vec<Option<ConcurrentSessions>> = connections.iter().map(|_| None).collect();
+    while !stop.load(Ordering::Relaxed) {
+        let columns: Vec<Vec<String>> = thread::scope(|scope| {
+            let workers: Vec<_> = connections
+                .iter_mut()
+                .zip(sessions.iter_mut())
+                .map(|(connection, sessions)| scope.spawn(|| render_column(connection, sessions, panels, &options)))
+                .collect();
+            workers
+                .into_iter()
+                .map(|w| w.join().unwrap_or_else(|_| vec!["(failed to collect)".to_string()]))
+                .collect()
+        });
+
+        // Clear the screen and move the cursor to the top left
+        print!("\x1B[2J\x1B[H");
+        println!(
+            "Dashboard, refreshing every {}s (Ctrl-C to stop watching):\n",
+            interval.as_secs()
+        );
+        println!("{}", side_by_side(&columns, terminal_width()));
+        if let Some(notifier) = notifier.as_deref_mut() {
+            for connection in connections.iter_mut() {
+                notifier.check(connection);
+            }
+        }
+
+        thread::sleep(interval);
+    }
+
+    if let Some(id) = signal {
+        signal_hook::low_level::unregister(id);
+    }
+    Ok(())
+}
+
+/// Runs every panel's collector on `connection`, see `collect_panels`, and renders them one under the other, headed by
+/// the connection
+fn render_column(
+    connection: &mut Connection,
+    sessions: &mut Option<ConcurrentSessions>,
+    panels: &[Panel],
+    options: &TableOptions,
+) -> Vec<String> {
+    let mut lines = vec![connection.label().bold().to_string(), String::new()];
+    if connection.client.is_none() {
+        lines.push(format!("Error: {}", PGError::ClientEmpty).red().to_string());
+        return lines;
+    }
+
+    let collectors: Vec<Option<&Collector>> = panels.iter().map(|p| find_collector(&p.collector)).collect();
+    let found: Vec<&Collector> = collectors.iter().flatten().copied().collect();
+    let mut results = collect_panels(connection, sessions, &found).into_iter();
+
+    for (panel, collector) in panels.iter().zip(collectors) {
+        let height = &panel.rows;
+        let collector = match collector {
+            Some(c) => c,
+            None => {
+                lines.push(format!("Error: no collector called {}", panel.collector).red().to_string());
+                lines.push(String::new());
+                continue;
+            }
+        };
+        lines.push(collector.title.bold().to_string());
+        match results.next().unwrap_or(Err(PGError::UnexpectedResult)) {
+            Ok(Value::Array(rows)) => {
+                let shown: Vec<Value> = rows.iter().take(*height).cloned().collect();
+                lines.extend(render_json_rows(&Value::Array(shown), options).lines().map(|l| l.replace('\t', "  ")));
+                if rows.len() > *height {
+                    lines.push(format!("  ... {} more", rows.len() - height));
+                }
+            }
+            Ok(other) => lines.push(format!("  {}", other)),
+            Err(e) => lines.push(format!("  Error: {}", e).red().to_string()),
+        }
+        lines.push(String::new());
+    }
+    lines
+}
+
+/// Runs `collectors` on `connection` as `Connection::collect` does and returns their rows in the same order. <br>
+/// They run at the same time over `sessions`, opened on first use and again once the server closed them, or one after
+/// another on `connection` itself when those cannot be opened.
+fn collect_panels(
+    connection: &mut Connection,
+    sessions: &mut Option<ConcurrentSessions>,
+    collectors: &[&Collector],
+) -> Vec<Result<Value, PGError>> {
+    if sessions.as_ref().is_none_or(ConcurrentSessions::is_closed) {
+        *sessions = SessionTarget::of(connection)
+            .and_then(|target| ConcurrentSessions::open(&target, collectors.len().min(MAX_SESSIONS)))
+            .ok();
+    }
+    let sessions = match sessions {
+        Some(sessions) => sessions,
+        None => return collectors.iter().map(|c| connection.collect(c, false)).collect(),
+    };
+
+    let available: Vec<Result<(), PGError>> = collectors
+        .iter()
+        .map(|c| connection.privileges.check(c.requires).and_then(|_| connection.capabilities.check(c.extension)))
+        .collect();
+    let queries: Vec<&str> = collectors
+        .iter()
+        .zip(&available)
+        .filter(|(_, available)| available.is_ok())
+        .map(|(c, _)| c.query_for(connection.server_version))
+        .collect();
+    connection.queries += queries.len() as u64;
+    let mut rows = sessions.query_json(&queries).into_iter();
+
+    collectors
+        .iter()
+        .zip(available)
+        .map(|(c, available)| {
+            available?;
+            let rows = rows.next().unwrap_or(Err(PGError::UnexpectedResult));
+            rows.map_err(|e| e.with_context(c.context()))
+        })
+        .collect()
+}
+
+/// Lays `columns` of lines out next to each other within `width` characters, cutting off lines that are too long
+fn side_by_side(columns: &[Vec<String>], width: usize) -> String {
+    let separator = glyph(" \u{2502} ", " | ");
+    let count = columns.len().max(1);
+    let column_width = width.saturating_sub(separator.chars().count() * (count - 1)) / count;
+    let height = columns.iter().map(|c| c.len()).max().unwrap_or(0);
+
+    (0..height)
+        .map(|i| {
+            let cells: Vec<String> = columns
+                .iter()
+                .map(|c| fit_width(c.get(i).map(|l| l.as_str()).unwrap_or(""), column_width))
+                .collect();
+            cells.join(separator).trim_end().to_string()
+        })
+        .collect::<Vec<String>>()
+        .join("\n")
+}
+
+/// Width of the terminal, or `DEFAULT_WIDTH` when the output is not one
+fn terminal_width() -> usize {
+    match terminal::size() {
+        Ok((columns, _)) if columns > 0 => columns as usize,
+        _ => DEFAULT_WIDTH,
+    }
+}
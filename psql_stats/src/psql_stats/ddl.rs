@@ -0,0 +1,218 @@
+use postgres::types::Oid;
+use postgres::Client;
+
+use super::{query_context, Connection, PGError};
+
+/// Query resolving `$1` to a relation: its oid, kind and quoted, schema qualified name
+const RELATION_QUERY: &str = r#"
+    SELECT c.oid, c.relkind::text, c.oid::regclass::text
+    FROM pg_class c
+    WHERE c.oid = to_regclass($1)
+"#;
+
+/// Query listing the columns of the relation `$1` with everything their definition needs
+const TABLE_COLUMNS_QUERY: &str = r#"
+    SELECT quote_ident(a.attname),
+           format_type(a.atttypid, a.atttypmod),
+           CASE WHEN a.attcollation <> t.typcollation THEN quote_ident(co.collname) END,
+           pg_get_expr(d.adbin, d.adrelid),
+           a.attnotnull,
+           a.attidentity::text,
+           a.attgenerated::text
+    FROM pg_attribute a
+    JOIN pg_type t ON t.oid = a.atttypid
+    LEFT JOIN pg_collation co ON co.oid = a.attcollation
+    LEFT JOIN pg_attrdef d ON d.adrelid = a.attrelid AND d.adnum = a.attnum
+    WHERE a.attrelid = $1
+    AND a.attnum > 0
+    AND NOT a.attisdropped
+    ORDER BY a.attnum
+"#;
+
+/// Query listing the constraints of the table `$1`, primary key first
+const TABLE_CONSTRAINTS_QUERY: &str = r#"
+    SELECT quote_ident(conname), pg_get_constraintdef(oid, true)
+    FROM pg_constraint
+    WHERE conrelid = $1
+    AND contype IN ('p', 'u', 'c', 'f', 'x')
+    ORDER BY contype <> 'p', contype <> 'u', conname
+"#;
+
+/// Query listing the definitions of the indexes of the table `$1` not already created by one of its constraints
+const TABLE_INDEXES_QUERY: &str = r#"
+    SELECT pg_get_indexdef(i.indexrelid)
+    FROM pg_index i
+    WHERE i.indrelid = $1
+    AND NOT EXISTS (SELECT 1 FROM pg_constraint c WHERE c.conindid = i.indexrelid AND c.conrelid = i.indrelid)
+    ORDER BY i.indexrelid::regclass::text
+"#;
+
+/// Query returning the partition key, parent and bounds of the table `$1`, NULL for what does not apply
+const TABLE_PARTITIONING_QUERY: &str = r#"
+    SELECT CASE WHEN c.relkind = 'p' THEN pg_get_partkeydef(c.oid) END,
+           (SELECT i.inhparent::regclass::text FROM pg_inherits i WHERE i.inhrelid = c.oid AND c.relispartition),
+           CASE WHEN c.relispartition THEN pg_get_expr(c.relpartbound, c.oid) END,
+           obj_description(c.oid, 'pg_class')
+    FROM pg_class c
+    WHERE c.oid = $1
+"#;
+
+/// Query returning the options of the sequence `$1`
+const SEQUENCE_QUERY: &str = r#"
+    SELECT format_type(s.seqtypid, NULL), s.seqstart, s.seqincrement, s.seqmin, s.seqmax, s.seqcache, s.seqcycle
+    FROM pg_sequence s
+    WHERE s.seqrelid = $1
+"#;
+
+/// Query finding the functions and procedures named `$1`, optionally schema qualified, among the overloads visible
+/// on the search path when it is not
+const FUNCTIONS_QUERY: &str = r#"
+    SELECT pg_get_functiondef(p.oid)
+    FROM pg_proc p
+    JOIN pg_namespace n ON n.oid = p.pronamespace,
+    parse_ident($1) AS name
+    WHERE p.proname = name[array_length(name, 1)]
+    AND CASE array_length(name, 1)
+            WHEN 1 THEN pg_function_is_visible(p.oid)
+            WHEN 2 THEN n.nspname = name[1]
+            ELSE false
+        END
+    AND p.prokind IN ('f', 'p')
+    ORDER BY p.oid::regprocedure::text
+"#;
+
+impl Connection {
+    /// Reconstructs the statements creating `object` from the catalogs: a table (with its constraints, indexes and
+    /// comment), a view or materialized view, an index, a sequence, or a function or procedure. <br>
+    /// `object` may be schema qualified. A function name gives every overload of it, or a single one when written
+    /// with its argument types, e.g. `add(integer, integer)`. Returns `MatchNotFound` when nothing has that name and
+    /// `UnsupportedObject` for other kinds of relations, such as foreign tables.
+    pub fn show_create(&mut self, object: &str) -> Result<Vec<String>, PGError> {
+        let client = match &mut self.client {
+            Some(c) => c,
+            None => return Err(PGError::ClientEmpty),
+        };
+
+        // to_regclass refuses names such as function signatures outright, which are looked up below
+        let relation = client.query_opt(RELATION_QUERY, &[&object]).unwrap_or(None);
+        if let Some(row) = relation {
+            let (oid, kind, name): (Oid, String, String) = (row.get(0), row.get(1), row.get(2));
+            let statement = match kind.as_str() {
+                "r" | "p" => create_table(client, oid, &name),
+                "v" => create_view(client, oid, &name, "VIEW"),
+                "m" => create_view(client, oid, &name, "MATERIALIZED VIEW"),
+                "i" => query_text(client, "SELECT pg_get_indexdef($1::oid) || ';'", oid),
+                "S" => create_sequence(client, oid, &name),
+                _ => Err(PGError::UnsupportedObject),
+            }?;
+            return Ok(vec![statement]);
+        }
+
+        // An argument list picks a single overload, which regprocedure resolves
+        let functions = if object.contains('(') {
+            client.query(
+                "SELECT pg_get_functiondef(p) FROM to_regprocedure($1) AS p WHERE p IS NOT NULL",
+                &[&object],
+            )
+        } else {
+            client.query(FUNCTIONS_QUERY, &[&object])
+        };
+        match functions {
+            Ok(rows) if !rows.is_empty() => Ok(rows
+                .iter()
+                .map(|r| r.get::<_, String>(0).trim_end().to_string() + ";")
+                .collect()),
+            // parse_ident refuses names that cannot be an identifier, which cannot be an object either
+            _ => Err(PGError::MatchNotFound),
+        }
+    }
+}
+
+fn query_text(client: &mut Client, query: &str, oid: Oid) -> Result<String, PGError> {
+    match client.query_one(query, &[&oid]) {
+        Ok(row) => Ok(row.get(0)),
+        Err(e) => Err(PGError::query(query_context(query), e)),
+    }
+}
+
+fn create_view(client: &mut Client, oid: Oid, name: &str, kind: &str) -> Result<String, PGError> {
+    let definition = query_text(client, "SELECT pg_get_viewdef($1::oid, true)", oid)?;
+    Ok(format!("CREATE {} {} AS\n{}", kind, name, definition.trim_end()))
+}
+
+fn create_sequence(client: &mut Client, oid: Oid, name: &str) -> Result<String, PGError> {
+    let row = match client.query_one(SEQUENCE_QUERY, &[&oid]) {
+        Ok(r) => r,
+        Err(e) => return Err(PGError::query(format!("reading the sequence {}", name), e)),
+    };
+    let cycle = if row.get::<_, bool>(6) { "CYCLE" } else { "NO CYCLE" };
+    Ok(format!(
+        "CREATE SEQUENCE {} AS {} START WITH {} INCREMENT BY {} MINVALUE {} MAXVALUE {} CACHE {} {};",
+        name,
+        row.get::<_, String>(0),
+        row.get::<_, i64>(1),
+        row.get::<_, i64>(2),
+        row.get::<_, i64>(3),
+        row.get::<_, i64>(4),
+        row.get::<_, i64>(5),
+        cycle
+    ))
+}
+
+/// Builds `CREATE TABLE` from the columns and constraints of the table, followed by its other indexes and comment
+fn create_table(client: &mut Client, oid: Oid, name: &str) -> Result<String, PGError> {
+    let columns = client.query(TABLE_COLUMNS_QUERY, &[&oid]).map_err(|e| PGError::query(format!("reading the table {}", name), e))?;
+    let constraints = client.query(TABLE_CONSTRAINTS_QUERY, &[&oid]).map_err(|e| PGError::query(format!("reading the table {}", name), e))?;
+    let indexes = client.query(TABLE_INDEXES_QUERY, &[&oid]).map_err(|e| PGError::query(format!("reading the table {}", name), e))?;
+    let partitioning = client.query_one(TABLE_PARTITIONING_QUERY, &[&oid]).map_err(|e| PGError::query(format!("reading the table {}", name), e))?;
+    let (partition_key, parent, bounds, comment): (Option<String>, Option<String>, Option<String>, Option<String>) =
+        (partitioning.get(0), partitioning.get(1), partitioning.get(2), partitioning.get(3));
+
+    let mut lines = Vec::new();
+    // A partition takes its columns from its parent, so only constraints of its own are listed
+    if parent.is_none() {
+        for column in &columns {
+            let (column_name, data_type): (String, String) = (column.get(0), column.get(1));
+            let (collation, default): (Option<String>, Option<String>) = (column.get(2), column.get(3));
+            let (not_null, identity, generated): (bool, String, String) = (column.get(4), column.get(5), column.get(6));
+
+            let mut line = format!("    {} {}", column_name, data_type);
+            if let Some(c) = collation {
+                line += &format!(" COLLATE {}", c);
+            }
+            match (identity.as_str(), generated.as_str(), default) {
+                ("a", _, _) => line += " GENERATED ALWAYS AS IDENTITY",
+                ("d", _, _) => line += " GENERATED BY DEFAULT AS IDENTITY",
+                (_, "s", Some(expression)) => line += &format!(" GENERATED ALWAYS AS ({}) STORED", expression),
+                (_, _, Some(expression)) => line += &format!(" DEFAULT {}", expression),
+                _ => {}
+            }
+            if not_null {
+                line += " NOT NULL";
+            }
+            lines.push(line);
+        }
+    }
+    for constraint in &constraints {
+        let (constraint_name, definition): (String, String) = (constraint.get(0), constraint.get(1));
+        lines.push(format!("    CONSTRAINT {} {}", constraint_name, definition));
+    }
+
+    let mut statement = match (&parent, &bounds) {
+        (Some(p), Some(b)) if lines.is_empty() => format!("CREATE TABLE {} PARTITION OF {} {}", name, p, b),
+        (Some(p), Some(b)) => format!("CREATE TABLE {} PARTITION OF {} (\n{}\n) {}", name, p, lines.join(",\n"), b),
+        _ => format!("CREATE TABLE {} (\n{}\n)", name, lines.join(",\n")),
+    };
+    if let Some(key) = partition_key {
+        statement += &format!(" PARTITION BY {}", key);
+    }
+    statement += ";";
+
+    for index in &indexes {
+        statement += &format!("\n{};", index.get::<_, String>(0));
+    }
+    if let Some(c) = comment {
+        statement += &format!("\nCOMMENT ON TABLE {} IS '{}';", name, c.replace('\'', "''"));
+    }
+    Ok(statement)
+}
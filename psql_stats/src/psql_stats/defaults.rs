@@ -0,0 +1,212 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::PathBuf;
+
+use toml::Value;
+use tracing::warn;
+
+use super::healthcheck::Thresholds;
+use super::output::OUTPUT_FORMATS;
+use super::{one_line, Config};
+
+/// Name of the defaults file in the config directory
+const DEFAULTS_FILE: &str = "psql_stats.toml";
+
+/// Defaults read from `psql_stats.toml` in the config directory, for preferences kept by hand rather than through
+/// the menu. <br>
+/// Its top level keys apply to every connection, and those under `[connections.<name>]` to the saved connection
+/// loaded with `-l <name>`, over the top level ones. Keys that are not set leave `config.json`'s values, and flags
+/// given on the command line override them all, e.g.
+///
+/// ```toml
+/// output_format = "table"
+/// color = true
+/// watch_interval = 5
+///
+/// [thresholds]
+/// cache_hit_warn_percent = 97
+///
+/// [connections.reporting]
+/// output_format = "json"
+/// schema = "analytics"
+/// thresholds = { replication_lag_warn_secs = 600 }
+/// ```
+#[derive(Default)]
+pub struct Defaults {
+    /// Format results are printed in, one of `OUTPUT_FORMATS`
+    pub output_format: Option<String>,
+    /// Whether to color the output
+    pub color: Option<bool>,
+    /// Seconds `dashboard`, `index-build` and `--tui` wait between refreshes when none is given
+    pub watch_interval: Option<u64>,
+    /// Schema put first in the `search_path` on connect, see `Connection::schema`
+    pub schema: Option<String>,
+    /// Webhook alerts are posted to while watching, see `Notifier`
+    pub alert_webhook: Option<String>,
+    /// Seconds before an alert still not resolved is posted again
+    pub alert_cooldown: Option<u64>,
+    /// Healthcheck thresholds by the name of their `Thresholds` field, see `Thresholds::set`
+    pub thresholds: BTreeMap<String, f64>,
+}
+
+impl Defaults {
+    /// Path of the defaults file
+    pub fn path() -> PathBuf {
+        Config::directory().join(DEFAULTS_FILE)
+    }
+
+    /// Reads the defaults file, with the overrides of the saved connection named `connection` applied over the top
+    /// level values. <br>
+    /// A missing file sets nothing. One that is not valid TOML is ignored with a warning, as are the values that
+    /// are not valid, so a mistake in it never keeps the program from starting.
+    pub fn load(connection: Option<&str>) -> Defaults {
+        let path = Self::path();
+        let text = match fs::read_to_string(&path) {
+            Ok(text) => text,
+            Err(_) => return Defaults::default(),
+        };
+        let value = match toml::from_str::<Value>(&text) {
+            Ok(value) => value,
+            Err(e) => {
+                let line = e.span().map_or(1, |span| text[..span.start].matches('\n').count() + 1);
+                warn!("ignoring {}, line {}: {}", path.display(), line, one_line(e.message(), 0));
+                return Defaults::default();
+            }
+        };
+
+        let mut defaults = Self::from_toml(&value);
+        if let Some(overrides) = connection.and_then(|name| value.get("connections")?.get(name)) {
+            defaults.merge(Self::from_toml(overrides));
+        }
+        defaults
+    }
+
+    /// The defaults set in a table of the defaults file
+    fn from_toml(value: &Value) -> Defaults {
+        let output_format = value.get("output_format").and_then(|f| f.as_str());
+        if let Some(format) = output_format.filter(|f| !OUTPUT_FORMATS.contains(f)) {
+            warn!("ignoring output_format = {:?} in {}, expected one of {:?}", format, DEFAULTS_FILE, OUTPUT_FORMATS);
+        }
+
+        let mut thresholds = BTreeMap::new();
+        for (name, threshold) in value.get("thresholds").and_then(|t| t.as_table()).into_iter().flatten() {
+            // TOML tells integers from floats, `95` is as good a threshold as `95.0`
+            match threshold.as_float().or(threshold.as_integer().map(|t| t as f64)) {
+                Some(threshold) if Thresholds::default().set(name, threshold) => {
+                    thresholds.insert(name.clone(), threshold);
+                }
+                _ => warn!("ignoring the threshold {} in {}", name, DEFAULTS_FILE),
+            }
+        }
+
+        Defaults {
+            output_format: output_format.filter(|f| OUTPUT_FORMATS.contains(f)).map(|f| f.to_string()),
+            color: value.get("color").and_then(|c| c.as_bool()),
+            watch_interval: value
+                .get("watch_interval")
+                .and_then(|i| i.as_integer())
+                .filter(|i| *i > 0)
+                .map(|i| i as u64),
+            schema: value
+                .get("schema")
+                .and_then(|s| s.as_str())
+                .filter(|s| !s.trim().is_empty())
+                .map(|s| s.to_string()),
+            alert_webhook: value
+                .get("alert_webhook")
+                .and_then(|w| w.as_str())
+                .filter(|w| !w.trim().is_empty())
+                .map(|w| w.to_string()),
+            alert_cooldown: value
+                .get("alert_cooldown")
+                .and_then(|c| c.as_integer())
+                .filter(|c| *c >= 0)
+                .map(|c| c as u64),
+            thresholds,
+        }
+    }
+
+    /// Sets the values `overrides` sets over those of `self`
+    fn merge(&mut self, overrides: Defaults) {
+        if overrides.output_format.is_some() {
+            self.output_format = overrides.output_format;
+        }
+        if overrides.color.is_some() {
+            self.color = overrides.color;
+        }
+        if overrides.watch_interval.is_some() {
+            self.watch_interval = overrides.watch_interval;
+        }
+        if overrides.schema.is_some() {
+            self.schema = overrides.schema;
+        }
+        if overrides.alert_webhook.is_some() {
+            self.alert_webhook = overrides.alert_webhook;
+        }
+        if overrides.alert_cooldown.is_some() {
+            self.alert_cooldown = overrides.alert_cooldown;
+        }
+        self.thresholds.extend(overrides.thresholds);
+    }
+
+    /// Applies the defaults set to `config` and `thresholds`, before the command line flags are, see `Overrides`
+    pub fn apply(&self, config: &mut Config, thresholds: &mut Thresholds) {
+        if let Some(format) = &self.output_format {
+            config.output_format = format.clone();
+        }
+        if let Some(color) = self.color {
+            config.color = color;
+        }
+        if let Some(interval) = self.watch_interval {
+            config.watch_interval = interval;
+        }
+        if let Some(webhook) = &self.alert_webhook {
+            config.alert_webhook = Some(webhook.clone());
+        }
+        if let Some(cooldown) = self.alert_cooldown {
+            config.alert_cooldown = cooldown;
+        }
+        for (name, threshold) in &self.thresholds {
+            thresholds.set(name, *threshold);
+        }
+    }
+}
+
+/// The values given on the command line that override those of `config.json` and `psql_stats.toml`, kept so they
+/// still do when `--serve` reads the files again
+#[derive(Default)]
+pub struct Overrides {
+    /// Saved connection loaded with `-l`, whose section of `psql_stats.toml` applies
+    pub connection: Option<String>,
+    /// Webhook alerts are posted to, an empty one turning alerts off
+    pub alert_webhook: Option<String>,
+    /// Seconds before an alert still not resolved is posted again
+    pub alert_cooldown: Option<u64>,
+    /// Healthcheck thresholds by the name of their `Thresholds` field, see `Thresholds::set`
+    pub thresholds: Vec<(&'static str, f64)>,
+}
+
+impl Overrides {
+    /// Applies the values given to `config` and `thresholds`, after the defaults files' are
+    pub fn apply(&self, config: &mut Config, thresholds: &mut Thresholds) {
+        if let Some(webhook) = &self.alert_webhook {
+            config.alert_webhook = Some(webhook.clone()).filter(|w| !w.is_empty());
+        }
+        if let Some(cooldown) = self.alert_cooldown {
+            config.alert_cooldown = cooldown;
+        }
+        for (name, threshold) in &self.thresholds {
+            thresholds.set(name, *threshold);
+        }
+    }
+
+    /// Reads `config.json` and `psql_stats.toml` again and applies these values over them, or `None` when
+    /// `config.json` cannot be read, see `Config::reload`
+    pub fn reload(&self) -> Option<(Config, Thresholds)> {
+        let mut config = Config::reload()?;
+        let mut thresholds = Thresholds::default();
+        Defaults::load(self.connection.as_deref()).apply(&mut config, &mut thresholds);
+        self.apply(&mut config, &mut thresholds);
+        Some((config, thresholds))
+    }
+}
@@ -0,0 +1,115 @@
+use serde_json::{Map, Number, Value};
+
+/// Describes how to turn two samples of a collector with cumulative counters into per-second rates
+pub struct Delta {
+    /// Columns identifying a row across samples, e.g. schema and table name
+    pub keys: &'static [&'static str],
+    /// Cumulative counter columns to compute rates for
+    pub counters: &'static [&'static str],
+    /// Counter whose rate the result is sorted by, highest first
+    pub sort_by: &'static str,
+}
+
+/// Computes the per-second rate of every counter between two samples taken `seconds` apart. <br>
+/// Each row of the result holds the key columns plus a `<counter>_per_sec` column per counter. A `database` column,
+/// added when collecting from every database, is always treated as a key as well. Rows only present in
+/// `current` are treated as if their counters were zero in `previous`, and a counter that went backwards (the
+/// statistics were reset in between) is measured from zero.
+pub fn rates(previous: &Value, current: &Value, delta: &Delta, seconds: f64) -> Value {
+    let seconds = seconds.max(1.0);
+    let empty = Vec::new();
+    let previous_rows = previous.as_array().unwrap_or(&empty);
+
+    let mut result: Vec<Value> = current
+        .as_array()
+        .unwrap_or(&empty)
+        .iter()
+        .map(|row| {
+            let (mut output, before) = key_columns(previous_rows, row, delta);
+            for counter in delta.counters {
+                let rate = (growth(row, before, counter) / seconds * 100.0).round() / 100.0;
+                output.insert(
+                    format!("{}_per_sec", counter),
+                    Number::from_f64(rate).map(Value::Number).unwrap_or(Value::Null),
+                );
+            }
+            Value::Object(output)
+        })
+        .collect();
+
+    let sort_column = format!("{}_per_sec", delta.sort_by);
+    result.sort_by(|a, b| as_number(&b[&sort_column]).total_cmp(&as_number(&a[&sort_column])));
+    Value::Array(result)
+}
+
+/// Computes how much every counter grew between two samples, as `rates` does but in total rather than per second,
+/// e.g. the rows written since a `mark`. Each row holds the key columns plus the counters, and rows where no counter
+/// changed are left out.
+pub fn changes(previous: &Value, current: &Value, delta: &Delta) -> Value {
+    let empty = Vec::new();
+    let previous_rows = previous.as_array().unwrap_or(&empty);
+
+    let mut result: Vec<Value> = current
+        .as_array()
+        .unwrap_or(&empty)
+        .iter()
+        .filter_map(|row| {
+            let (mut output, before) = key_columns(previous_rows, row, delta);
+            let grown: Vec<f64> = delta.counters.iter().map(|c| growth(row, before, c)).collect();
+            if grown.iter().all(|g| *g == 0.0) {
+                return None;
+            }
+            for (counter, grown) in delta.counters.iter().zip(grown) {
+                // Counters are whole numbers, apart from the odd numeric one
+                let number = match grown.fract() == 0.0 {
+                    true => Some(Number::from(grown as i64)),
+                    false => Number::from_f64(grown),
+                };
+                output.insert(counter.to_string(), number.map(Value::Number).unwrap_or(Value::Null));
+            }
+            Some(Value::Object(output))
+        })
+        .collect();
+
+    result.sort_by(|a, b| as_number(&b[delta.sort_by]).total_cmp(&as_number(&a[delta.sort_by])));
+    Value::Array(result)
+}
+
+/// The key columns of `row`, with the `database` column when collecting from every database, and the row of
+/// `previous_rows` with the same keys if there is one
+fn key_columns<'a>(previous_rows: &'a [Value], row: &Value, delta: &Delta) -> (Map<String, Value>, Option<&'a Value>) {
+    let mut keys: Vec<&str> = delta.keys.to_vec();
+    if row.get("database").is_some() {
+        keys.insert(0, "database");
+    }
+
+    let before = previous_rows
+        .iter()
+        .find(|p| keys.iter().all(|k| p[*k] == row[*k]));
+
+    let mut output = Map::new();
+    for key in keys {
+        output.insert(key.to_string(), row[key].clone());
+    }
+    (output, before)
+}
+
+/// How much `counter` grew from `before` to `row`, measured from zero when `row` is new or the counter went backwards
+fn growth(row: &Value, before: Option<&Value>, counter: &str) -> f64 {
+    let now = as_number(&row[counter]);
+    let then = before.map(|b| as_number(&b[counter])).unwrap_or(0.0);
+    if now >= then {
+        now - then
+    } else {
+        now
+    }
+}
+
+/// Reads a counter, which Postgres may send as a JSON number or, for `numeric` columns, a string
+fn as_number(value: &Value) -> f64 {
+    match value {
+        Value::Number(n) => n.as_f64().unwrap_or(0.0),
+        Value::String(s) => s.parse().unwrap_or(0.0),
+        _ => 0.0,
+    }
+}
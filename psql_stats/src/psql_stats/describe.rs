@@ -0,0 +1,135 @@
+use serde_json::Value;
+
+use super::humanize::thousands;
+use super::output::OutputFormatter;
+use super::{Config, Connection, PGError};
+
+/// Query resolving `$1` to a table, view or materialized view: its quoted, schema qualified name, kind, owner,
+/// estimated rows and comment, along with the schema and name `information_schema` and `pg_indexes` know it by
+const DESCRIBED_RELATION_QUERY: &str = r#"
+    SELECT c.oid::regclass::text AS table_name,
+           CASE c.relkind
+                WHEN 'r' THEN 'table'
+                WHEN 'p' THEN 'partitioned table'
+                WHEN 'v' THEN 'view'
+                WHEN 'm' THEN 'materialized view'
+                WHEN 'f' THEN 'foreign table'
+           END AS kind,
+           pg_get_userbyid(c.relowner) AS owner,
+           CASE WHEN c.reltuples >= 0 THEN c.reltuples::bigint END AS estimated_rows,
+           obj_description(c.oid, 'pg_class') AS comment,
+           n.nspname AS schema,
+           c.relname AS name
+    FROM pg_class c
+    JOIN pg_namespace n ON n.oid = c.relnamespace
+    WHERE c.oid = to_regclass($1)
+    AND c.relkind IN ('r', 'p', 'v', 'm', 'f')
+"#;
+
+/// Query listing the columns of the table named `$2` in the schema `$1`, as `information_schema.columns` gives them,
+/// with their full type, nullability, default and comment
+const DESCRIBE_COLUMNS_QUERY: &str = r#"
+    SELECT c.column_name::text AS column_name,
+           format_type(a.atttypid, a.atttypmod) AS data_type,
+           c.is_nullable = 'YES' AS nullable,
+           coalesce(c.column_default, c.generation_expression)::text AS default,
+           col_description(a.attrelid, a.attnum) AS comment
+    FROM information_schema.columns c
+    JOIN pg_attribute a ON a.attrelid = format('%I.%I', c.table_schema, c.table_name)::regclass
+                       AND a.attname = c.column_name
+    WHERE c.table_schema = $1
+    AND c.table_name = $2
+    ORDER BY c.ordinal_position
+"#;
+
+/// Query listing the indexes of the table named `$2` in the schema `$1` from `pg_indexes`, with their size
+const DESCRIBE_INDEXES_QUERY: &str = r#"
+    SELECT indexname AS index_name,
+           pg_relation_size(format('%I.%I', schemaname, indexname)::regclass) AS index_bytes,
+           indexdef AS definition
+    FROM pg_indexes
+    WHERE schemaname = $1
+    AND tablename = $2
+    ORDER BY indexname
+"#;
+
+/// Query listing the constraints of the table `$1`, primary key first, followed by the foreign keys of other tables
+/// referencing it, as `\d` shows them under "Referenced by"
+const DESCRIBE_CONSTRAINTS_QUERY: &str = r#"
+    SELECT conname AS constraint_name,
+           CASE WHEN referencing = 1 THEN 'referenced by ' || conrelid::regclass::text
+                WHEN contype = 'p' THEN 'primary key'
+                WHEN contype = 'u' THEN 'unique'
+                WHEN contype = 'f' THEN 'foreign key'
+                WHEN contype = 'c' THEN 'check'
+                WHEN contype = 'x' THEN 'exclusion'
+                WHEN contype = 'n' THEN 'not null'
+                WHEN contype = 't' THEN 'trigger'
+           END AS type,
+           pg_get_constraintdef(oid, true) AS definition
+    FROM (
+        SELECT *, 0 AS referencing FROM pg_constraint WHERE conrelid = to_regclass($1)
+        UNION ALL
+        SELECT *, 1 FROM pg_constraint WHERE confrelid = to_regclass($1) AND conrelid <> confrelid
+    ) c
+    ORDER BY referencing, contype <> 'p', contype <> 'u', contype <> 'f', conname
+"#;
+
+impl Connection {
+    /// Describes the table, view or materialized view `table`, which may be schema qualified, the way psql's `\d`
+    /// does: its owner, kind and comment, its columns with their types, nullability and defaults, its indexes, and
+    /// its constraints, followed by the foreign keys of other tables referencing it. <br>
+    /// Returns `MatchNotFound` when there is no such relation.
+    pub fn describe_table(&mut self, table: &str) -> Result<Value, PGError> {
+        let mut description = match self.query_json_with(DESCRIBED_RELATION_QUERY, &[&table])? {
+            Value::Array(mut rows) if !rows.is_empty() => rows.remove(0),
+            _ => return Err(PGError::MatchNotFound),
+        };
+        let schema = description["schema"].as_str().unwrap_or("").to_string();
+        let name = description["name"].as_str().unwrap_or("").to_string();
+        let qualified = description["table_name"].as_str().unwrap_or(table).to_string();
+
+        let columns = self.query_json_with(DESCRIBE_COLUMNS_QUERY, &[&schema, &name])?;
+        let indexes = self.query_json_with(DESCRIBE_INDEXES_QUERY, &[&schema, &name])?;
+        let constraints = self.query_json_with(DESCRIBE_CONSTRAINTS_QUERY, &[&qualified])?;
+        if let Some(fields) = description.as_object_mut() {
+            fields.shift_remove("schema");
+            fields.shift_remove("name");
+        }
+        description["columns"] = columns;
+        description["indexes"] = indexes;
+        description["constraints"] = constraints;
+        Ok(description)
+    }
+}
+
+/// Renders what `Connection::describe_table` returns: in JSON as one object, otherwise as a line with the table, its
+/// kind, owner and comment followed by its columns, indexes and constraints, each in its own table
+pub fn render_description(description: &Value, config: &Config) -> String {
+    if config.output_format == "json" {
+        return serde_json::to_string_pretty(description).expect("Failed to serialize the description");
+    }
+    let formatter = OutputFormatter::new(config);
+    let text = |key: &str| description[key].as_str().unwrap_or("").to_string();
+    let kind = text("kind");
+    let (first, rest) = kind.split_at(kind.len().min(1));
+    let mut heading = format!("{}{} {} (owner {})", first.to_uppercase(), rest, text("table_name"), text("owner"));
+    if let Some(rows) = description["estimated_rows"].as_i64() {
+        heading += &format!(", about {} rows", thousands(&rows.to_string()));
+    }
+    if let Some(comment) = description["comment"].as_str() {
+        heading += &format!(" - {}", comment);
+    }
+
+    let mut sections = Vec::new();
+    if config.output_format == "table" {
+        sections.push(heading);
+    }
+    for (key, title) in [("columns", "Columns"), ("indexes", "Indexes"), ("constraints", "Constraints")] {
+        let empty = description[key].as_array().map(|rows| rows.is_empty()).unwrap_or(true);
+        if key == "columns" || !empty {
+            sections.push(formatter.titled(title, formatter.rows(&description[key])));
+        }
+    }
+    sections.join("\n")
+}
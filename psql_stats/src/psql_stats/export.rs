@@ -0,0 +1,491 @@
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Read, Write};
+use std::sync::Arc;
+use std::time::Instant;
+
+use parquet::basic::{LogicalType, Repetition, Type as PhysicalType};
+use parquet::data_type::{BoolType, ByteArray, ByteArrayType, DoubleType, FloatType, Int32Type, Int64Type};
+use parquet::file::properties::WriterProperties;
+use parquet::file::writer::SerializedFileWriter;
+use parquet::schema::types::Type;
+use postgres::fallible_iterator::FallibleIterator;
+use postgres::types::{ToSql, Type as PgType};
+use postgres::GenericClient;
+use serde_json::Value;
+use tracing::error;
+
+use super::logging::log_query;
+use super::output::{csv_field, csv_record};
+use super::queries::TextParam;
+use super::query_result::{column_names, describe_query_error, row_to_json};
+use super::redact::redact_json;
+use super::transaction::{self, TransactionState};
+use super::{reason, Connection, PGError};
+
+/// Formats tables can be exported in
+pub const EXPORT_FORMATS: &[&str] = &["csv", "parquet"];
+
+/// Rows buffered before they are written out as one Parquet row group
+const ROW_GROUP_SIZE: usize = 65_536;
+
+/// Name and type of every column of the relation `$1`, in the order `SELECT *` returns them
+const EXPORT_COLUMNS_QUERY: &str = r#"
+    SELECT a.attname::text, a.atttypid::regtype::text
+    FROM pg_attribute a
+    WHERE a.attrelid = $1::text::regclass
+    AND a.attnum > 0
+    AND NOT a.attisdropped
+    ORDER BY a.attnum
+"#;
+
+impl Connection {
+    /// Streams the contents of the table or view `table` (optionally schema qualified) into the file at `path`, as
+    /// CSV with a header row or as Parquet, and returns the number of rows written. <br>
+    /// Rows are read with `COPY ... TO STDOUT`, so the table is never held in memory: CSV is copied through as the
+    /// server produces it, and Parquet is written in row groups of `ROW_GROUP_SIZE` rows. Parquet columns keep the
+    /// booleans, integers and floating point numbers of the table, every other type is written as text.
+    pub fn export_table(&mut self, table: &str, format: &str, path: &str) -> Result<u64, PGError> {
+        let client = match &mut self.client {
+            Some(c) => c,
+            None => return Err(PGError::ClientEmpty),
+        };
+
+        // Resolving the name through regclass checks it exists and gives it back safely quoted
+        let relation: String = match client.query_one("SELECT $1::text::regclass::text", &[&table]) {
+            Ok(row) => row.get(0),
+            Err(_) => return Err(PGError::MatchNotFound),
+        };
+        let columns: Vec<(String, String)> = match client.query(EXPORT_COLUMNS_QUERY, &[&table]) {
+            Ok(rows) => rows.iter().map(|r| (r.get(0), r.get(1))).collect(),
+            Err(e) => return Err(PGError::query(format!("reading the columns of {}", relation), e)),
+        };
+
+        let file = File::create(path).map_err(|e| export_error(path, e))?;
+        match format {
+            "parquet" => {
+                let copy = format!("COPY (SELECT * FROM {}) TO STDOUT", relation);
+                let reader = client.copy_out(copy.as_str()).map_err(|e| PGError::query(format!("exporting {}", relation), e))?;
+                write_parquet(reader, &columns, file).map_err(|e| export_error(path, e))
+            }
+            _ => {
+                let copy = format!("COPY (SELECT * FROM {}) TO STDOUT WITH (FORMAT csv, HEADER)", relation);
+                let mut reader = client
+                    .copy_out(copy.as_str())
+                    .map_err(|e| PGError::query(format!("exporting {}", relation), e))?;
+                let mut writer = CsvRowCounter {
+                    inner: BufWriter::new(file),
+                    rows: 0,
+                    quoted: false,
+                };
+                io::copy(&mut reader, &mut writer).map_err(|e| export_error(path, e))?;
+                writer.flush().map_err(|e| export_error(path, e))?;
+
+                // The header is not a row
+                Ok(writer.rows.saturating_sub(1))
+            }
+        }
+    }
+
+    /// Runs `query`, with `params` bound to its `$1`, `$2`, ... placeholders, and streams the rows it returns into
+    /// the file at `path`, as CSV with a header row or as Parquet, returning the number of rows written. <br>
+    /// Rows are read one at a time with `query_raw` and written as they come, so results far larger than memory can
+    /// be exported, and are converted and masked as `custom_query` shows them. Parquet columns keep the booleans,
+    /// integers and floating point numbers of the result, every other type is written as text. <br>
+    /// In read-only mode the query runs in a read only transaction that is rolled back, unless one is open already.
+    pub fn export_query(
+        &mut self,
+        query: &str,
+        params: &[TextParam],
+        format: &str,
+        path: &str,
+    ) -> Result<u64, PGError> {
+        self.queries += 1;
+        let read_only = transaction::read_only() && self.transaction == TransactionState::Idle;
+        let client = match &mut self.client {
+            Some(c) => c,
+            None => return Err(PGError::ClientEmpty),
+        };
+        let rows = match read_only {
+            true => {
+                let failed = |e: postgres::Error| {
+                    error!("{}", e);
+                    PGError::QueryFailed
+                };
+                // Dropping the transaction rolls it back
+                let mut transaction = client.build_transaction().read_only(true).start().map_err(failed)?;
+                stream_query(&mut transaction, query, params, format, path)?
+            }
+            false => stream_query(client, query, params, format, path)?,
+        };
+        if !read_only {
+            self.track_transaction(query);
+        }
+        Ok(rows)
+    }
+}
+
+/// Runs `query` on `client` and writes its rows to `path` as `Connection::export_query` describes
+fn stream_query(
+    client: &mut impl GenericClient,
+    query: &str,
+    params: &[TextParam],
+    format: &str,
+    path: &str,
+) -> Result<u64, PGError> {
+    let start = Instant::now();
+    let failed = |e: postgres::Error| {
+        log_query(query, start.elapsed(), Err(reason(&e)));
+        error!("{}", describe_query_error(query, &e));
+        PGError::QueryFailed
+    };
+
+    let statement = client.prepare(query).map_err(failed)?;
+    if statement.columns().is_empty() {
+        error!("the statement returns no rows to export");
+        return Err(PGError::QueryFailed);
+    }
+    if statement.params().len() != params.len() {
+        error!(
+            "the query has {} parameters ($1, $2, ...) but {} values were given",
+            statement.params().len(),
+            params.len()
+        );
+        return Err(PGError::QueryFailed);
+    }
+    let columns = column_names(statement.columns().iter().map(|c| c.name()));
+    let types: Vec<&PgType> = statement.columns().iter().map(|c| c.type_()).collect();
+
+    let file = File::create(path).map_err(|e| export_error(path, e))?;
+    let mut results = client
+        .query_raw(&statement, params.iter().map(|p| p as &dyn ToSql))
+        .map_err(failed)?;
+    let mut rows = 0;
+    match format {
+        "parquet" => {
+            let schema: Vec<(String, String)> = columns
+                .iter()
+                .zip(&types)
+                .map(|(name, ty)| (name.clone(), regtype_name(ty).to_string()))
+                .collect();
+            let mut writer = ParquetWriter::new(&schema, file).map_err(|e| export_error(path, e))?;
+            while let Some(row) = results.next().map_err(failed)? {
+                let mut row = row_to_json(&row, &columns);
+                redact_json(&mut row);
+                let fields = columns.iter().map(|c| parquet_field(&row[c])).collect();
+                writer.push(fields).map_err(|e| export_error(path, e))?;
+                rows += 1;
+            }
+            writer.close().map_err(|e| export_error(path, e))?;
+        }
+        _ => {
+            let mut writer = BufWriter::new(file);
+            let header: Vec<String> = columns.iter().map(|c| csv_field(c)).collect();
+            writeln!(writer, "{}", header.join(",")).map_err(|e| export_error(path, e))?;
+            while let Some(row) = results.next().map_err(failed)? {
+                let mut row = row_to_json(&row, &columns);
+                redact_json(&mut row);
+                writeln!(writer, "{}", csv_record(&columns, &row)).map_err(|e| export_error(path, e))?;
+                rows += 1;
+            }
+            writer.flush().map_err(|e| export_error(path, e))?;
+        }
+    }
+    log_query(query, start.elapsed(), Ok::<u64, PGError>(rows));
+    Ok(rows)
+}
+
+/// The name `regtype` gives `ty`, which `ColumnBuffer::new` picks the Parquet type of a column by
+fn regtype_name(ty: &PgType) -> &str {
+    match *ty {
+        PgType::BOOL => "boolean",
+        PgType::INT2 => "smallint",
+        PgType::INT4 => "integer",
+        PgType::INT8 => "bigint",
+        PgType::FLOAT4 => "real",
+        PgType::FLOAT8 => "double precision",
+        _ => ty.name(),
+    }
+}
+
+/// A value of a row from `row_to_json` as `ColumnBuffer::push` takes it, booleans written as `COPY` does
+fn parquet_field(value: &Value) -> Option<String> {
+    match value {
+        Value::Null => None,
+        Value::Bool(b) => Some(if *b { "t" } else { "f" }.to_string()),
+        Value::String(s) => Some(s.clone()),
+        other => Some(other.to_string()),
+    }
+}
+
+fn export_error(path: &str, error: impl std::fmt::Display) -> PGError {
+    error!("could not export to {}: {}", path, error);
+    PGError::ExportError
+}
+
+/// Passes CSV through to `inner`, counting the records written: line breaks outside of quoted fields
+struct CsvRowCounter<W: Write> {
+    inner: W,
+    rows: u64,
+    quoted: bool,
+}
+
+impl<W: Write> Write for CsvRowCounter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        for b in &buf[..written] {
+            match b {
+                // A doubled quote inside a quoted field toggles twice, leaving the state unchanged
+                b'"' => self.quoted = !self.quoted,
+                b'\n' if !self.quoted => self.rows += 1,
+                _ => {}
+            }
+        }
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Values of one Parquet column for the rows of the current row group, NULLs left out
+enum ColumnValues {
+    Bool(Vec<bool>),
+    Int32(Vec<i32>),
+    Int64(Vec<i64>),
+    Float(Vec<f32>),
+    Double(Vec<f64>),
+    Text(Vec<ByteArray>),
+}
+
+/// A Parquet column being filled: its values and, per row, whether the value is present (1) or NULL (0)
+struct ColumnBuffer {
+    values: ColumnValues,
+    definition_levels: Vec<i16>,
+}
+
+impl ColumnBuffer {
+    fn new(pg_type: &str) -> Self {
+        let values = match pg_type {
+            "boolean" => ColumnValues::Bool(Vec::new()),
+            "smallint" | "integer" => ColumnValues::Int32(Vec::new()),
+            "bigint" => ColumnValues::Int64(Vec::new()),
+            "real" => ColumnValues::Float(Vec::new()),
+            "double precision" => ColumnValues::Double(Vec::new()),
+            _ => ColumnValues::Text(Vec::new()),
+        };
+        ColumnBuffer {
+            values,
+            definition_levels: Vec::new(),
+        }
+    }
+
+    fn parquet_type(&self, name: &str) -> parquet::errors::Result<Type> {
+        let (physical, logical) = match self.values {
+            ColumnValues::Bool(_) => (PhysicalType::BOOLEAN, None),
+            ColumnValues::Int32(_) => (PhysicalType::INT32, None),
+            ColumnValues::Int64(_) => (PhysicalType::INT64, None),
+            ColumnValues::Float(_) => (PhysicalType::FLOAT, None),
+            ColumnValues::Double(_) => (PhysicalType::DOUBLE, None),
+            ColumnValues::Text(_) => (PhysicalType::BYTE_ARRAY, Some(LogicalType::String)),
+        };
+        Type::primitive_type_builder(name, physical)
+            .with_repetition(Repetition::OPTIONAL)
+            .with_logical_type(logical)
+            .build()
+    }
+
+    /// Adds the value of a `COPY` text format field, `None` being NULL
+    fn push(&mut self, field: Option<String>) -> Result<(), String> {
+        let field = match field {
+            Some(f) => f,
+            None => {
+                self.definition_levels.push(0);
+                return Ok(());
+            }
+        };
+
+        let invalid = || format!("unexpected value \"{}\"", field);
+        match &mut self.values {
+            ColumnValues::Bool(v) => v.push(field == "t"),
+            ColumnValues::Int32(v) => v.push(field.parse().map_err(|_| invalid())?),
+            ColumnValues::Int64(v) => v.push(field.parse().map_err(|_| invalid())?),
+            ColumnValues::Float(v) => v.push(field.parse().map_err(|_| invalid())?),
+            ColumnValues::Double(v) => v.push(field.parse().map_err(|_| invalid())?),
+            ColumnValues::Text(v) => v.push(ByteArray::from(field.into_bytes())),
+        }
+        self.definition_levels.push(1);
+        Ok(())
+    }
+
+    fn clear(&mut self) {
+        self.definition_levels.clear();
+        match &mut self.values {
+            ColumnValues::Bool(v) => v.clear(),
+            ColumnValues::Int32(v) => v.clear(),
+            ColumnValues::Int64(v) => v.clear(),
+            ColumnValues::Float(v) => v.clear(),
+            ColumnValues::Double(v) => v.clear(),
+            ColumnValues::Text(v) => v.clear(),
+        }
+    }
+}
+
+/// Reads rows in the `COPY` text format from `reader` and writes them to `file` as Parquet, returning the number of
+/// rows written
+fn write_parquet(reader: impl Read, columns: &[(String, String)], file: File) -> Result<u64, String> {
+    let mut writer = ParquetWriter::new(columns, file)?;
+    for line in BufReader::new(reader).lines() {
+        let line = line.map_err(|e| e.to_string())?;
+        writer.push(line.split('\t').map(unescape_copy_field).collect())?;
+    }
+    writer.close()
+}
+
+/// A Parquet file being written, its rows buffered per column and flushed every `ROW_GROUP_SIZE` rows
+struct ParquetWriter {
+    writer: SerializedFileWriter<File>,
+    buffers: Vec<ColumnBuffer>,
+    buffered: usize,
+    rows: u64,
+}
+
+impl ParquetWriter {
+    /// Starts a file with one optional column per name and Postgres type, as `regtype` names it, of `columns`
+    fn new(columns: &[(String, String)], file: File) -> Result<Self, String> {
+        let buffers: Vec<ColumnBuffer> = columns.iter().map(|(_, pg_type)| ColumnBuffer::new(pg_type)).collect();
+        let fields = columns
+            .iter()
+            .zip(&buffers)
+            .map(|((name, _), buffer)| buffer.parquet_type(name).map(Arc::new))
+            .collect::<parquet::errors::Result<Vec<_>>>()
+            .map_err(|e| e.to_string())?;
+        let schema = Type::group_type_builder("schema")
+            .with_fields(fields)
+            .build()
+            .map_err(|e| e.to_string())?;
+
+        let properties = Arc::new(WriterProperties::builder().build());
+        let writer = SerializedFileWriter::new(file, Arc::new(schema), properties).map_err(|e| e.to_string())?;
+        Ok(ParquetWriter {
+            writer,
+            buffers,
+            buffered: 0,
+            rows: 0,
+        })
+    }
+
+    /// Adds a row, one field per column with `None` for NULL
+    fn push(&mut self, fields: Vec<Option<String>>) -> Result<(), String> {
+        if fields.len() != self.buffers.len() {
+            return Err(format!(
+                "expected {} fields in row {}, got {}",
+                self.buffers.len(),
+                self.rows + 1,
+                fields.len()
+            ));
+        }
+        for (buffer, field) in self.buffers.iter_mut().zip(fields) {
+            buffer.push(field)?;
+        }
+
+        self.rows += 1;
+        self.buffered += 1;
+        if self.buffered == ROW_GROUP_SIZE {
+            write_row_group(&mut self.writer, &mut self.buffers)?;
+            self.buffered = 0;
+        }
+        Ok(())
+    }
+
+    /// Writes the rows still buffered and the footer, returning the number of rows written
+    fn close(mut self) -> Result<u64, String> {
+        if self.buffered > 0 || self.rows == 0 {
+            write_row_group(&mut self.writer, &mut self.buffers)?;
+        }
+        self.writer.close().map_err(|e| e.to_string())?;
+        Ok(self.rows)
+    }
+}
+
+/// Writes the buffered values of every column as one row group and empties the buffers
+fn write_row_group(writer: &mut SerializedFileWriter<File>, buffers: &mut [ColumnBuffer]) -> Result<(), String> {
+    let mut group = writer.next_row_group().map_err(|e| e.to_string())?;
+    for buffer in buffers.iter_mut() {
+        let mut column = match group.next_column().map_err(|e| e.to_string())? {
+            Some(c) => c,
+            None => return Err("more columns than in the schema".to_string()),
+        };
+        let levels = Some(buffer.definition_levels.as_slice());
+        let written = match &buffer.values {
+            ColumnValues::Bool(v) => column.typed::<BoolType>().write_batch(v, levels, None),
+            ColumnValues::Int32(v) => column.typed::<Int32Type>().write_batch(v, levels, None),
+            ColumnValues::Int64(v) => column.typed::<Int64Type>().write_batch(v, levels, None),
+            ColumnValues::Float(v) => column.typed::<FloatType>().write_batch(v, levels, None),
+            ColumnValues::Double(v) => column.typed::<DoubleType>().write_batch(v, levels, None),
+            ColumnValues::Text(v) => column.typed::<ByteArrayType>().write_batch(v, levels, None),
+        };
+        written.map_err(|e| e.to_string())?;
+        column.close().map_err(|e| e.to_string())?;
+        buffer.clear();
+    }
+    group.close().map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+/// Decodes a field of the `COPY` text format: `\N` is NULL, and backslash escapes such as `\t`, `\n`, `\\` and octal
+/// `\123` stand for the characters they replace
+fn unescape_copy_field(field: &str) -> Option<String> {
+    if field == "\\N" {
+        return None;
+    }
+    if !field.contains('\\') {
+        return Some(field.to_string());
+    }
+
+    let mut bytes = Vec::with_capacity(field.len());
+    let mut chars = field.bytes().peekable();
+    while let Some(b) = chars.next() {
+        if b != b'\\' {
+            bytes.push(b);
+            continue;
+        }
+        match chars.next() {
+            Some(b'b') => bytes.push(0x08),
+            Some(b'f') => bytes.push(0x0C),
+            Some(b'n') => bytes.push(b'\n'),
+            Some(b'r') => bytes.push(b'\r'),
+            Some(b't') => bytes.push(b'\t'),
+            Some(b'v') => bytes.push(0x0B),
+            Some(d @ b'0'..=b'7') => {
+                let mut value = (d - b'0') as u32;
+                for _ in 0..2 {
+                    match chars.peek() {
+                        Some(d @ b'0'..=b'7') => {
+                            value = value * 8 + (d - b'0') as u32;
+                            chars.next();
+                        }
+                        _ => break,
+                    }
+                }
+                bytes.push(value as u8);
+            }
+            Some(b'x') => {
+                let mut value = 0u32;
+                for _ in 0..2 {
+                    match chars.peek().and_then(|c| (*c as char).to_digit(16)) {
+                        Some(d) => {
+                            value = value * 16 + d;
+                            chars.next();
+                        }
+                        None => break,
+                    }
+                }
+                bytes.push(value as u8);
+            }
+            Some(other) => bytes.push(other),
+            None => bytes.push(b'\\'),
+        }
+    }
+    Some(String::from_utf8_lossy(&bytes).to_string())
+}
@@ -0,0 +1,49 @@
+use postgres::Client;
+
+/// Values accepted for `target_session_attrs`, with the same meaning as in libpq
+pub const TARGET_SESSION_ATTRS: &[&str] = &["any", "read-write", "read-only", "primary", "standby", "prefer-standby"];
+
+/// Pairs every host of a comma separated `host` list with its port. <br>
+/// `port` is either a single port used for every host, or a comma separated list with one port per host. Empty
+/// ports default to 5432, as do missing ones when the lists differ in length.
+pub(crate) fn candidates(host: &str, port: &str) -> Vec<(String, String)> {
+    let ports: Vec<&str> = port.split(',').map(|p| p.trim()).collect();
+    host.split(',')
+        .map(|h| h.trim())
+        .enumerate()
+        .map(|(i, h)| {
+            let port = match ports.len() {
+                1 => ports[0],
+                _ => ports.get(i).copied().unwrap_or(""),
+            };
+            let port = if port.is_empty() { "5432" } else { port };
+            (h.to_string(), port.to_string())
+        })
+        .collect()
+}
+
+/// Returns the passes to make over the host list for `attrs`, each naming the session kind accepted in that pass. <br>
+/// `prefer-standby` first looks for a standby and then settles for any server, every other value needs one pass.
+pub(crate) fn passes(attrs: &str) -> Vec<&str> {
+    match attrs {
+        "prefer-standby" => vec!["standby", "any"],
+        "" => vec!["any"],
+        other => vec![other],
+    }
+}
+
+/// Checks whether the session on `client` is of the kind `attrs` asks for. Servers that cannot tell us are rejected.
+pub(crate) fn session_matches(client: &mut Client, attrs: &str) -> bool {
+    let query = match attrs {
+        "read-write" => "SELECT current_setting('transaction_read_only') = 'off'",
+        "read-only" => "SELECT current_setting('transaction_read_only') = 'on'",
+        "primary" => "SELECT NOT pg_is_in_recovery()",
+        "standby" => "SELECT pg_is_in_recovery()",
+        _ => return true,
+    };
+
+    match client.query_one(query, &[]) {
+        Ok(row) => row.try_get::<_, bool>(0).unwrap_or(false),
+        Err(_) => false,
+    }
+}
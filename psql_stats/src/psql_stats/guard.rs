@@ -0,0 +1,218 @@
+use super::humanize::thousands;
+use super::precheck::{qualified_name, tokenize};
+use super::{Connection, PGError};
+
+/// Query estimating the rows of the relation `$1` from the planner statistics, those of all its partitions for a
+/// partitioned table, or the live rows counted by the statistics collector when it was never analyzed. <br>
+/// Returns no row when the relation does not exist, and a `NULL` estimate when it holds no rows of its own, as views
+/// and indexes do.
+const RELATION_ROWS_QUERY: &str = r#"
+    SELECT CASE WHEN c.relkind IN ('r', 'm', 'p') THEN (
+               SELECT sum(CASE WHEN l.reltuples < 0 THEN coalesce(s.n_live_tup, 0) ELSE l.reltuples END)::bigint
+               FROM pg_class l
+               LEFT JOIN pg_stat_all_tables s ON s.relid = l.oid
+               WHERE l.oid = c.oid
+                  OR l.oid IN (SELECT relid FROM pg_partition_tree(c.oid) WHERE isleaf)
+           ) END
+    FROM pg_class c
+    WHERE c.oid = to_regclass($1)
+"#;
+
+/// Query estimating the rows of all the tables and materialized views of the schema `$1`, as `RELATION_ROWS_QUERY`
+const SCHEMA_ROWS_QUERY: &str = r#"
+    SELECT sum(CASE WHEN c.reltuples < 0 THEN coalesce(s.n_live_tup, 0) ELSE c.reltuples END)::bigint
+    FROM pg_class c
+    JOIN pg_namespace n ON n.oid = c.relnamespace
+    LEFT JOIN pg_stat_all_tables s ON s.relid = c.oid
+    WHERE n.nspname = $1
+      AND c.relkind IN ('r', 'm')
+"#;
+
+/// Kinds of objects `DROP` is followed by, e.g. `MATERIALIZED VIEW`
+const DROP_OBJECT_WORDS: &[&str] = &[
+    "materialized", "foreign", "table", "index", "view", "schema", "database", "sequence", "function", "procedure",
+    "routine", "aggregate", "type", "domain", "extension", "role", "user", "trigger", "policy", "rule",
+    "tablespace", "publication", "subscription", "server", "owned", "by",
+];
+
+/// A statement of a custom query that drops objects or removes or changes every row of a table, which is only run
+/// once confirmed
+pub struct DestructiveStatement {
+    /// What the statement does, e.g. `DROP TABLE` or `DELETE without WHERE from`
+    pub action: String,
+    /// The objects it drops, empties or rewrites, as the statement names them
+    pub targets: Vec<String>,
+    /// Rows the statement affects, estimated from the planner statistics, when its targets hold rows at all
+    pub estimated_rows: Option<i64>,
+}
+
+impl DestructiveStatement {
+    pub fn describe(&self) -> String {
+        let rows = match self.estimated_rows {
+            Some(rows) => format!(", about {} rows affected", thousands(&rows.to_string())),
+            None => String::new(),
+        };
+        format!("{} {}{}", self.action, self.targets.join(", "), rows)
+    }
+
+    /// Whether the targets are tables, whose rows `Connection::destructive_statements` estimates
+    fn holds_rows(&self) -> bool {
+        ["DROP TABLE", "DROP MATERIALIZED VIEW", "TRUNCATE"].contains(&self.action.as_str())
+            || self.action.starts_with("DELETE")
+            || self.action.starts_with("UPDATE")
+    }
+}
+
+impl Connection {
+    /// Finds the destructive statements of `query` (see `destructive_statements`) and estimates how many rows each
+    /// affects, from the planner statistics of the tables it names or of every table in the schemas it drops. <br>
+    /// Targets that do not exist, or that the statement was misread for, are left out of the estimate.
+    pub fn destructive_statements(&mut self, query: &str) -> Result<Vec<DestructiveStatement>, PGError> {
+        let client = match &mut self.client {
+            Some(c) => c,
+            None => return Err(PGError::ClientEmpty),
+        };
+
+        let mut statements = destructive_statements(query);
+        for statement in &mut statements {
+            let schemas = statement.action == "DROP SCHEMA";
+            if !schemas && !statement.holds_rows() {
+                continue;
+            }
+            for target in &statement.targets {
+                let rows = match schemas {
+                    true => client.query_opt(SCHEMA_ROWS_QUERY, &[&unquote(target)]),
+                    false => client.query_opt(RELATION_ROWS_QUERY, &[target]),
+                };
+                if let Ok(Some(row)) = rows {
+                    if let Some(rows) = row.get::<_, Option<i64>>(0) {
+                        *statement.estimated_rows.get_or_insert(0) += rows;
+                    }
+                }
+            }
+        }
+        Ok(statements)
+    }
+}
+
+/// Lists the statements of `query` that destroy data: `DROP`, `TRUNCATE`, and `DELETE` or `UPDATE` without a `WHERE`
+/// clause, also when they are part of a `WITH` query, each with the objects it targets and no row estimate. <br>
+/// The statements are tokenized as `Connection::precheck_query` does, so keywords inside string literals and
+/// comments are ignored. `ALTER TABLE ... DROP COLUMN` and statements run inside functions are not looked at.
+pub(crate) fn destructive_statements(query: &str) -> Vec<DestructiveStatement> {
+    let tokens = tokenize(query);
+    let mut statements = Vec::new();
+    for statement in tokens.split(|t| t == ";") {
+        let keyword = |i: usize, word: &str| statement.get(i).map(|t| t.eq_ignore_ascii_case(word)).unwrap_or(false);
+
+        if keyword(0, "drop") {
+            let mut i = 1;
+            let mut words = Vec::new();
+            while statement.get(i).map(|t| DROP_OBJECT_WORDS.contains(&t.to_lowercase().as_str())).unwrap_or(false) {
+                words.push(statement[i].to_uppercase());
+                i += 1;
+            }
+            statements.push(DestructiveStatement {
+                action: ["DROP".to_string()].into_iter().chain(words).collect::<Vec<_>>().join(" "),
+                targets: names(statement, i),
+                estimated_rows: None,
+            });
+            continue;
+        }
+        if keyword(0, "truncate") {
+            statements.push(DestructiveStatement {
+                action: "TRUNCATE".to_string(),
+                targets: names(statement, 1),
+                estimated_rows: None,
+            });
+            continue;
+        }
+
+        for i in 0..statement.len() {
+            // A statement of its own, or of a WITH query, rather than e.g. GRANT UPDATE or ON CONFLICT DO UPDATE
+            if i > 0 && !keyword(i - 1, "(") && !keyword(i - 1, ")") {
+                continue;
+            }
+            let (action, start) = if keyword(i, "delete") && keyword(i + 1, "from") {
+                ("DELETE without WHERE from", i + 2)
+            } else if keyword(i, "update") {
+                ("UPDATE without WHERE of", i + 1)
+            } else {
+                continue;
+            };
+            if !has_where(statement, start) {
+                statements.push(DestructiveStatement {
+                    action: action.to_string(),
+                    targets: names(statement, start).into_iter().take(1).collect(),
+                    estimated_rows: None,
+                });
+            }
+        }
+    }
+    statements
+}
+
+/// Whether a `WHERE` follows `tokens[start]` before the statement, or the parenthesis it is written in, ends.
+/// Conditions of subqueries do not count.
+fn has_where(tokens: &[String], start: usize) -> bool {
+    let mut depth = 0;
+    for token in &tokens[start..] {
+        match token.as_str() {
+            "(" => depth += 1,
+            ")" if depth == 0 => return false,
+            ")" => depth -= 1,
+            t if depth == 0 && t.eq_ignore_ascii_case("where") => return true,
+            _ => {}
+        }
+    }
+    false
+}
+
+/// Reads the comma separated names starting at `tokens[i]`, skipping `IF EXISTS`, `ONLY`, `CONCURRENTLY`, the
+/// argument lists of functions and the `*` asking for child tables
+fn names(tokens: &[String], mut i: usize) -> Vec<String> {
+    let keyword = |i: usize, word: &str| tokens.get(i).map(|t| t.eq_ignore_ascii_case(word)).unwrap_or(false);
+    let mut names = Vec::new();
+    loop {
+        while ["if", "exists", "only", "table", "concurrently"].iter().any(|w| keyword(i, w)) {
+            i += 1;
+        }
+        let (name, next) = match qualified_name(tokens, i) {
+            Some(n) => n,
+            None => break,
+        };
+        names.push(name);
+        i = next;
+        if keyword(i, "(") {
+            let mut depth = 0;
+            while let Some(token) = tokens.get(i) {
+                depth += match token.as_str() {
+                    "(" => 1,
+                    ")" => -1,
+                    _ => 0,
+                };
+                i += 1;
+                if depth == 0 {
+                    break;
+                }
+            }
+        }
+        if keyword(i, "*") {
+            i += 1;
+        }
+        if !keyword(i, ",") {
+            break;
+        }
+        i += 1;
+    }
+    names
+}
+
+/// The name of a schema as the server stores it: a quoted name as written, without the quotes, and any other folded
+/// to lower case
+fn unquote(name: &str) -> String {
+    match name.strip_prefix('"').and_then(|n| n.strip_suffix('"')) {
+        Some(quoted) => quoted.replace("\"\"", "\""),
+        None => name.to_lowercase(),
+    }
+}
@@ -0,0 +1,470 @@
+use std::fmt;
+
+use serde_json::Value;
+
+use super::constraints::constraints_check;
+use super::saturation::saturation_check;
+use super::sequences::sequences_check;
+use super::versions::{pick, QueryVariant};
+use super::Connection;
+
+/// Checksum failures per database, the average time spent reading a block from disk when track_io_timing is on, and
+/// how many blocks were found in shared buffers instead
+const IO_HEALTH_QUERY: &str = r#"
+    SELECT current_setting('data_checksums') AS data_checksums,
+           current_setting('track_io_timing') AS track_io_timing,
+           current_setting('track_counts') AS track_counts,
+           coalesce(sum(checksum_failures), 0)::bigint AS checksum_failures,
+           max(checksum_last_failure)::text AS checksum_last_failure,
+           coalesce(sum(blk_read_time), 0)::float8 AS read_time_ms,
+           coalesce(sum(blks_read), 0)::bigint AS blocks_read,
+           coalesce(sum(blks_hit), 0)::bigint AS blocks_hit
+    FROM pg_stat_database
+"#;
+
+/// `IO_HEALTH_QUERY` before Postgres 12, which did not count checksum failures in `pg_stat_database`, so none are
+/// reported
+const IO_HEALTH_BEFORE_12_QUERY: &str = r#"
+    SELECT current_setting('data_checksums') AS data_checksums,
+           current_setting('track_io_timing') AS track_io_timing,
+           current_setting('track_counts') AS track_counts,
+           0::bigint AS checksum_failures,
+           NULL::text AS checksum_last_failure,
+           coalesce(sum(blk_read_time), 0)::float8 AS read_time_ms,
+           coalesce(sum(blks_read), 0)::bigint AS blocks_read,
+           coalesce(sum(blks_hit), 0)::bigint AS blocks_hit
+    FROM pg_stat_database
+"#;
+
+/// The forms of `IO_HEALTH_QUERY` for older servers, see `versions::pick`
+const IO_HEALTH_VARIANTS: &[QueryVariant] = &[QueryVariant {
+    before: 120000,
+    query: IO_HEALTH_BEFORE_12_QUERY,
+}];
+
+/// How far behind replication is: on a primary how long the slowest standby takes to replay, null without standbys,
+/// and on a standby how long ago the last replayed transaction committed, 0 once it replayed all it received since
+/// a primary without writes commits nothing to replay
+pub const REPLICATION_HEALTH_QUERY: &str = r#"
+    SELECT pg_is_in_recovery() AS standby,
+           CASE WHEN NOT pg_is_in_recovery()
+                THEN (SELECT extract(epoch FROM max(replay_lag)) FROM pg_stat_replication)
+                WHEN pg_last_wal_receive_lsn() = pg_last_wal_replay_lsn() THEN 0
+                ELSE extract(epoch FROM now() - pg_last_xact_replay_timestamp())
+           END::float8 AS lag_seconds,
+           (SELECT count(*) FROM pg_stat_replication) AS standbys
+"#;
+
+/// The database closest to transaction ID, or multixact ID, wraparound: the age of its oldest unfrozen ID, and which
+/// share of the 2^31 IDs in use at most that is
+const WRAPAROUND_HEALTH_QUERY: &str = r#"
+    SELECT datname,
+           greatest(age(datfrozenxid), mxid_age(datminmxid))::bigint AS age,
+           round(100.0 * greatest(age(datfrozenxid), mxid_age(datminmxid)) / 2147483648, 1)::float8 AS used_percent
+    FROM pg_database
+    ORDER BY 2 DESC
+    LIMIT 1
+"#;
+
+/// Blocks that must have been read, from shared buffers or disk, before the cache hit ratio says anything
+const CACHE_MIN_BLOCKS: f64 = 10_000.0;
+
+/// Outcome of a single check, ordered from best to worst
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Status {
+    Ok,
+    Warn,
+    Crit,
+}
+
+impl Status {
+    /// Exit code following the Nagios plugin convention: 0 OK, 1 WARNING, 2 CRITICAL
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            Status::Ok => 0,
+            Status::Warn => 1,
+            Status::Crit => 2,
+        }
+    }
+}
+
+impl fmt::Display for Status {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.pad(match self {
+            Status::Ok => "OK",
+            Status::Warn => "WARNING",
+            Status::Crit => "CRITICAL",
+        })
+    }
+}
+
+/// Result of one health check
+pub struct Check {
+    pub name: &'static str,
+    pub status: Status,
+    pub message: String,
+}
+
+/// Limits above which the healthcheck warns or fails
+#[derive(Clone)]
+pub struct Thresholds {
+    /// Average milliseconds per block read from disk before warning
+    pub io_read_warn_ms: f64,
+    /// Average milliseconds per block read from disk before failing
+    pub io_read_crit_ms: f64,
+    /// Percentage of the connections available to roles that are not superusers in use before warning
+    pub connections_warn_percent: f64,
+    /// Percentage of the connections available to roles that are not superusers in use before failing
+    pub connections_crit_percent: f64,
+    /// Percentage of the blocks read found in shared buffers below which to warn
+    pub cache_hit_warn_percent: f64,
+    /// Percentage of the blocks read found in shared buffers below which to fail
+    pub cache_hit_crit_percent: f64,
+    /// Seconds the slowest standby, or this one, may lag behind before warning
+    pub replication_lag_warn_secs: f64,
+    /// Seconds the slowest standby, or this one, may lag behind before failing
+    pub replication_lag_crit_secs: f64,
+    /// Percentage of the transaction IDs used up before wraparound before warning
+    pub wraparound_warn_percent: f64,
+    /// Percentage of the transaction IDs used up before wraparound before failing
+    pub wraparound_crit_percent: f64,
+    /// Percentage of the values of the most used up sequence of an integer column used before warning
+    pub sequence_warn_percent: f64,
+    /// Percentage of the values of the most used up sequence of an integer column used before failing
+    pub sequence_crit_percent: f64,
+    /// Seconds a query may run before alerts warn about it, see `Notifier`
+    pub long_query_warn_secs: f64,
+    /// Seconds a query may run before alerts report it as critical
+    pub long_query_crit_secs: f64,
+}
+
+impl Default for Thresholds {
+    fn default() -> Self {
+        Thresholds {
+            io_read_warn_ms: 10.0,
+            io_read_crit_ms: 50.0,
+            connections_warn_percent: 80.0,
+            connections_crit_percent: 100.0,
+            cache_hit_warn_percent: 95.0,
+            cache_hit_crit_percent: 90.0,
+            replication_lag_warn_secs: 60.0,
+            replication_lag_crit_secs: 300.0,
+            wraparound_warn_percent: 50.0,
+            wraparound_crit_percent: 75.0,
+            sequence_warn_percent: 75.0,
+            sequence_crit_percent: 90.0,
+            long_query_warn_secs: 300.0,
+            long_query_crit_secs: 1800.0,
+        }
+    }
+}
+
+impl Thresholds {
+    /// Sets the threshold named as its field, e.g. `cache_hit_warn_percent`, returning false for an unknown name
+    pub fn set(&mut self, name: &str, value: f64) -> bool {
+        let threshold = match name {
+            "io_read_warn_ms" => &mut self.io_read_warn_ms,
+            "io_read_crit_ms" => &mut self.io_read_crit_ms,
+            "connections_warn_percent" => &mut self.connections_warn_percent,
+            "connections_crit_percent" => &mut self.connections_crit_percent,
+            "cache_hit_warn_percent" => &mut self.cache_hit_warn_percent,
+            "cache_hit_crit_percent" => &mut self.cache_hit_crit_percent,
+            "replication_lag_warn_secs" => &mut self.replication_lag_warn_secs,
+            "replication_lag_crit_secs" => &mut self.replication_lag_crit_secs,
+            "wraparound_warn_percent" => &mut self.wraparound_warn_percent,
+            "wraparound_crit_percent" => &mut self.wraparound_crit_percent,
+            "sequence_warn_percent" => &mut self.sequence_warn_percent,
+            "sequence_crit_percent" => &mut self.sequence_crit_percent,
+            "long_query_warn_secs" => &mut self.long_query_warn_secs,
+            "long_query_crit_secs" => &mut self.long_query_crit_secs,
+            _ => return false,
+        };
+        *threshold = value;
+        true
+    }
+}
+
+impl Connection {
+    /// Runs every health check against the database. <br>
+    /// Checks never fail outright, a check that could not be evaluated reports `Status::Crit` with the reason.
+    pub fn healthcheck(&mut self, thresholds: &Thresholds) -> Vec<Check> {
+        if self.client.is_none() {
+            return vec![Check {
+                name: "connection",
+                status: Status::Crit,
+                message: "could not connect to the database".to_string(),
+            }];
+        }
+
+        let mut checks = vec![Check {
+            name: "connection",
+            status: Status::Ok,
+            message: "connected".to_string(),
+        }];
+
+        match self.query_json(pick(self.server_version, IO_HEALTH_QUERY, IO_HEALTH_VARIANTS)) {
+            Ok(rows) => {
+                let row = &rows[0];
+                checks.push(statistics_check(row));
+                checks.push(checksum_check(row));
+                checks.push(io_timing_check(row, thresholds));
+                checks.push(cache_hit_check(row, thresholds));
+            }
+            Err(e) => checks.push(Check {
+                name: "statistics",
+                status: Status::Crit,
+                message: format!("could not read pg_stat_database: {}", e),
+            }),
+        }
+
+        checks.push(match self.connection_limits() {
+            Ok(limits) => saturation_check(&limits, thresholds),
+            Err(e) => Check {
+                name: "connections",
+                status: Status::Crit,
+                message: format!("could not read pg_stat_activity: {}", e),
+            },
+        });
+
+        checks.push(match self.query_json(REPLICATION_HEALTH_QUERY) {
+            Ok(rows) => replication_check(&rows[0], thresholds),
+            Err(e) => Check {
+                name: "replication",
+                status: Status::Crit,
+                message: format!("could not read the replication lag: {}", e),
+            },
+        });
+
+        checks.push(match self.query_json(WRAPAROUND_HEALTH_QUERY) {
+            Ok(rows) => wraparound_check(&rows[0], thresholds),
+            Err(e) => Check {
+                name: "wraparound",
+                status: Status::Crit,
+                message: format!("could not read pg_database: {}", e),
+            },
+        });
+
+        checks.push(match self.get_sequence_usage() {
+            Ok(rows) => sequences_check(&rows, thresholds),
+            Err(e) => Check {
+                name: "sequences",
+                status: Status::Crit,
+                message: format!("could not read pg_sequences: {}", e),
+            },
+        });
+
+        checks.push(match self.constraint_report() {
+            Ok(report) => constraints_check(&report),
+            Err(e) => Check {
+                name: "constraints",
+                status: Status::Crit,
+                message: format!("could not read pg_constraint and pg_index: {}", e),
+            },
+        });
+
+        checks.push(match self.pending_restart() {
+            Ok(pending) => pending_restart_check(&pending),
+            Err(e) => Check {
+                name: "restart",
+                status: Status::Crit,
+                message: format!("could not read pg_settings: {}", e),
+            },
+        });
+
+        checks
+    }
+}
+
+/// Returns the worst status of all `checks`
+pub fn overall_status(checks: &[Check]) -> Status {
+    checks.iter().map(|c| c.status).max().unwrap_or(Status::Ok)
+}
+
+/// Builds the one line summary printed by the healthcheck, e.g.
+/// `CRITICAL - checksums: 3 checksum failures; io: 12.5ms per block read`. Only problems are listed unless
+/// everything is fine.
+pub fn summary(checks: &[Check]) -> String {
+    let status = overall_status(checks);
+    let problems: Vec<String> = checks
+        .iter()
+        .filter(|c| c.status != Status::Ok)
+        .map(|c| format!("{}: {}", c.name, c.message))
+        .collect();
+
+    if problems.is_empty() {
+        format!("{} - all {} checks passed", status, checks.len())
+    } else {
+        format!("{} - {}", status, problems.join("; "))
+    }
+}
+
+/// Statistics must be collected for the other checks, and autovacuum, to work at all
+fn statistics_check(row: &Value) -> Check {
+    if row["track_counts"] == "on" {
+        Check {
+            name: "statistics",
+            status: Status::Ok,
+            message: "statistics are being collected".to_string(),
+        }
+    } else {
+        Check {
+            name: "statistics",
+            status: Status::Warn,
+            message: "track_counts is off, statistics and autovacuum are unavailable".to_string(),
+        }
+    }
+}
+
+/// Any checksum failure means data on disk is corrupt
+fn checksum_check(row: &Value) -> Check {
+    let failures = row["checksum_failures"].as_i64().unwrap_or(0);
+    if failures > 0 {
+        Check {
+            name: "checksums",
+            status: Status::Crit,
+            message: format!(
+                "{} checksum failures, last at {}",
+                failures,
+                row["checksum_last_failure"].as_str().unwrap_or("unknown")
+            ),
+        }
+    } else if row["data_checksums"] == "on" {
+        Check {
+            name: "checksums",
+            status: Status::Ok,
+            message: "no checksum failures".to_string(),
+        }
+    } else {
+        Check {
+            name: "checksums",
+            status: Status::Ok,
+            message: "data checksums are disabled, corruption cannot be detected".to_string(),
+        }
+    }
+}
+
+/// Slow block reads point at a struggling disk or storage network
+fn io_timing_check(row: &Value, thresholds: &Thresholds) -> Check {
+    if row["track_io_timing"] != "on" {
+        return Check {
+            name: "io",
+            status: Status::Ok,
+            message: "track_io_timing is off, read latency is not measured".to_string(),
+        };
+    }
+
+    let blocks = row["blocks_read"].as_f64().unwrap_or(0.0);
+    let read_ms = row["read_time_ms"].as_f64().unwrap_or(0.0);
+    let per_block = if blocks > 0.0 { read_ms / blocks } else { 0.0 };
+
+    Check {
+        name: "io",
+        status: above(per_block, thresholds.io_read_warn_ms, thresholds.io_read_crit_ms),
+        message: format!("{:.2}ms per block read", per_block),
+    }
+}
+
+/// Status of `value` against a `warn` and a `crit` limit, which it must stay below
+pub(crate) fn above(value: f64, warn: f64, crit: f64) -> Status {
+    if value >= crit {
+        Status::Crit
+    } else if value >= warn {
+        Status::Warn
+    } else {
+        Status::Ok
+    }
+}
+
+/// Blocks read from disk rather than shared buffers slow every query down, shared_buffers may be too small for the
+/// data queried. Not judged until `CACHE_MIN_BLOCKS` were read, as right after a restart most reads miss.
+fn cache_hit_check(row: &Value, thresholds: &Thresholds) -> Check {
+    let hit = row["blocks_hit"].as_f64().unwrap_or(0.0);
+    let total = hit + row["blocks_read"].as_f64().unwrap_or(0.0);
+    if total < CACHE_MIN_BLOCKS {
+        return Check {
+            name: "cache",
+            status: Status::Ok,
+            message: "too few blocks read yet to judge the cache hit ratio".to_string(),
+        };
+    }
+
+    let percent = (1000.0 * hit / total).round() / 10.0;
+    let status = if percent < thresholds.cache_hit_crit_percent {
+        Status::Crit
+    } else if percent < thresholds.cache_hit_warn_percent {
+        Status::Warn
+    } else {
+        Status::Ok
+    };
+    let mut message = format!("{}% of blocks read found in shared buffers", percent);
+    if status != Status::Ok {
+        message.push_str(", cache-stats lists the tables and indexes read from disk");
+    }
+    Check {
+        name: "cache",
+        status,
+        message,
+    }
+}
+
+/// A standby lagging behind serves stale reads and, promoted, loses what it did not replay
+pub(crate) fn replication_check(row: &Value, thresholds: &Thresholds) -> Check {
+    let lag = match row["lag_seconds"].as_f64() {
+        Some(lag) => lag,
+        None => {
+            let message = match row["standby"].as_bool() {
+                Some(true) => "no transaction replayed yet",
+                _ if row["standbys"].as_i64().unwrap_or(0) > 0 => "no replay lag reported by the standbys",
+                _ => "no standbys",
+            };
+            return Check {
+                name: "replication",
+                status: Status::Ok,
+                message: message.to_string(),
+            };
+        }
+    };
+
+    let message = match row["standby"].as_bool() {
+        Some(true) => format!("replaying {:.1}s behind the primary", lag),
+        _ => format!("slowest standby replaying {:.1}s behind", lag),
+    };
+    Check {
+        name: "replication",
+        status: above(lag, thresholds.replication_lag_warn_secs, thresholds.replication_lag_crit_secs),
+        message,
+    }
+}
+
+/// The server stops accepting writes to avoid transaction ID wraparound, once a database used up its IDs, until it is
+/// vacuumed. Autovacuum should freeze the oldest rows long before.
+fn wraparound_check(row: &Value, thresholds: &Thresholds) -> Check {
+    let used = row["used_percent"].as_f64().unwrap_or(0.0);
+    Check {
+        name: "wraparound",
+        status: above(used, thresholds.wraparound_warn_percent, thresholds.wraparound_crit_percent),
+        message: format!(
+            "{}% of transaction IDs used up in {}, the oldest unfrozen is {} transactions old",
+            used,
+            row["datname"].as_str().unwrap_or("?"),
+            row["age"]
+        ),
+    }
+}
+
+/// Configuration changes that are waiting for a restart will surprise whoever restarts the server next
+fn pending_restart_check(pending: &[String]) -> Check {
+    if pending.is_empty() {
+        Check {
+            name: "restart",
+            status: Status::Ok,
+            message: "no settings waiting for a restart".to_string(),
+        }
+    } else {
+        Check {
+            name: "restart",
+            status: Status::Warn,
+            message: format!("settings changed but waiting for a restart: {}", pending.join(", ")),
+        }
+    }
+}
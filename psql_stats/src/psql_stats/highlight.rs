@@ -0,0 +1,203 @@
+use std::str::FromStr;
+
+use colored::{Color, Colorize};
+use serde_json::{Map, Value};
+use tracing::warn;
+
+use super::humanize::parse_interval;
+
+/// Multipliers of the size suffixes accepted in rule values, matched case-insensitively
+const SIZE_SUFFIXES: &[(&str, f64)] = &[
+    ("kb", 1024.0),
+    ("mb", 1024.0 * 1024.0),
+    ("gb", 1024.0 * 1024.0 * 1024.0),
+    ("tb", 1024.0 * 1024.0 * 1024.0 * 1024.0),
+];
+
+/// Multipliers of the duration suffixes accepted in rule values, compared against interval cells
+const DURATION_SUFFIXES: &[(&str, f64)] = &[("ms", 0.001), ("s", 1.0), ("min", 60.0), ("h", 3600.0), ("d", 86_400.0)];
+
+#[derive(Clone, Copy, PartialEq)]
+enum Operator {
+    Greater,
+    GreaterOrEqual,
+    Less,
+    LessOrEqual,
+    Equal,
+    NotEqual,
+}
+
+#[derive(Clone)]
+enum Threshold {
+    /// A number, possibly given with a size suffix such as `100MB`
+    Number(f64),
+    /// A duration in seconds, given with a suffix such as `5min`, compared against intervals
+    Duration(f64),
+    /// Anything else, only compared with `=` and `!=`
+    Text(String),
+}
+
+/// A rule coloring the rows of any table whose `column` compares to a threshold, written as
+/// `<column> <operator> <value> => <color>`, e.g. `dead_ratio > 0.2 => yellow` or `lag_bytes > 100MB => red`. <br>
+/// Operators are `>`, `>=`, `<`, `<=`, `=` and `!=`. Values are numbers, sizes (`KB`, `MB`, `GB`, `TB`), durations
+/// (`ms`, `s`, `min`, `h`, `d`, compared with intervals such as replication lag) or text (`state = idle in
+/// transaction => yellow`). Colors are the names known to `colored`, such as red, yellow or bright blue.
+#[derive(Clone)]
+pub struct HighlightRule {
+    /// The rule as written, to save it back to the config file
+    pub text: String,
+    column: String,
+    operator: Operator,
+    threshold: Threshold,
+    color: Color,
+}
+
+impl FromStr for HighlightRule {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let (condition, color) = text
+            .split_once("=>")
+            .ok_or_else(|| "expected <column> <operator> <value> => <color>".to_string())?;
+        let color = Color::from_str(color.trim()).map_err(|_| format!("unknown color \"{}\"", color.trim()))?;
+
+        // Two character operators first, so ">=" is not read as ">"
+        let operators = [
+            (">=", Operator::GreaterOrEqual),
+            ("<=", Operator::LessOrEqual),
+            ("!=", Operator::NotEqual),
+            (">", Operator::Greater),
+            ("<", Operator::Less),
+            ("=", Operator::Equal),
+        ];
+        let (column, operator, value) = operators
+            .iter()
+            .find_map(|(symbol, operator)| {
+                let (column, value) = condition.split_once(symbol)?;
+                Some((column.trim(), *operator, value.trim()))
+            })
+            .ok_or_else(|| "expected one of >, >=, <, <=, = or !=".to_string())?;
+        if column.is_empty() || value.is_empty() {
+            return Err("expected <column> <operator> <value> => <color>".to_string());
+        }
+
+        let threshold = parse_threshold(value);
+        if let Threshold::Text(_) = threshold {
+            if !matches!(operator, Operator::Equal | Operator::NotEqual) {
+                return Err(format!("\"{}\" is not a number, size or duration to compare with", value));
+            }
+        }
+
+        Ok(HighlightRule {
+            text: text.trim().to_string(),
+            column: column.to_string(),
+            operator,
+            threshold,
+            color,
+        })
+    }
+}
+
+impl HighlightRule {
+    /// Whether `row` has the rule's column and its value matches
+    fn matches(&self, row: &Map<String, Value>) -> bool {
+        let value = match row.get(&self.column) {
+            Some(Value::Null) | None => return false,
+            Some(v) => v,
+        };
+
+        match &self.threshold {
+            Threshold::Number(threshold) => match number_of(value) {
+                Some(n) => compare(n, self.operator, *threshold),
+                None => false,
+            },
+            Threshold::Duration(threshold) => match value.as_str().and_then(parse_interval) {
+                Some(seconds) => compare(seconds, self.operator, *threshold),
+                None => false,
+            },
+            Threshold::Text(threshold) => {
+                let text = match value {
+                    Value::String(s) => s.clone(),
+                    v => v.to_string(),
+                };
+                (text == *threshold) == (self.operator == Operator::Equal)
+            }
+        }
+    }
+}
+
+/// Parses the rule strings of the config file, printing a warning for, and skipping, any that are invalid
+pub(crate) fn parse_rules(value: &Value) -> Vec<HighlightRule> {
+    let rules = match value.as_array() {
+        Some(r) => r,
+        None => return Vec::new(),
+    };
+    rules
+        .iter()
+        .filter_map(|rule| {
+            let text = rule.as_str()?;
+            match text.parse() {
+                Ok(r) => Some(r),
+                Err(e) => {
+                    warn!("ignoring highlight rule \"{}\": {}", text, e);
+                    None
+                }
+            }
+        })
+        .collect()
+}
+
+/// Returns the color of the last rule in `rules` matching `row`, so later rules can override earlier ones, e.g. a red
+/// rule for a high threshold listed after a yellow one for a lower threshold
+pub(crate) fn row_color(rules: &[HighlightRule], row: &Value) -> Option<Color> {
+    let row = row.as_object()?;
+    rules.iter().rev().find(|r| r.matches(row)).map(|r| r.color)
+}
+
+/// Colors every line of every cell of a row
+pub(crate) fn paint(cells: &mut [Vec<String>], color: Color) {
+    for line in cells.iter_mut().flatten() {
+        *line = line.color(color).to_string();
+    }
+}
+
+fn parse_threshold(value: &str) -> Threshold {
+    if let Ok(n) = value.parse::<f64>() {
+        return Threshold::Number(n);
+    }
+
+    let lower = value.to_ascii_lowercase();
+    let with_suffix = |suffixes: &[(&str, f64)]| {
+        suffixes.iter().find_map(|(suffix, multiplier)| {
+            let number: f64 = lower.strip_suffix(suffix)?.trim().parse().ok()?;
+            Some(number * multiplier)
+        })
+    };
+    if let Some(bytes) = with_suffix(SIZE_SUFFIXES) {
+        return Threshold::Number(bytes);
+    }
+    if let Some(seconds) = with_suffix(DURATION_SUFFIXES) {
+        return Threshold::Duration(seconds);
+    }
+    Threshold::Text(value.to_string())
+}
+
+/// Reads a cell as a number, including numbers the server returned as text such as `numeric` values
+fn number_of(value: &Value) -> Option<f64> {
+    match value {
+        Value::Number(n) => n.as_f64(),
+        Value::String(s) => s.parse().ok(),
+        _ => None,
+    }
+}
+
+fn compare(value: f64, operator: Operator, threshold: f64) -> bool {
+    match operator {
+        Operator::Greater => value > threshold,
+        Operator::GreaterOrEqual => value >= threshold,
+        Operator::Less => value < threshold,
+        Operator::LessOrEqual => value <= threshold,
+        Operator::Equal => value == threshold,
+        Operator::NotEqual => value != threshold,
+    }
+}
@@ -0,0 +1,244 @@
+use std::fs::{self, OpenOptions};
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use serde_json::{json, Value};
+
+/// The active history file is rotated once its first record is older than this, even if it is still small
+const ROTATE_AFTER: Duration = Duration::from_secs(24 * 60 * 60);
+
+const MEGABYTE: u64 = 1024 * 1024;
+
+/// Append-only JSONL history of collected statistics. <br>
+/// Each line is one record: `{"timestamp": <unix seconds>, "connection": "user@host:port/db", "collector": "uptime", "data": [...]}`. <br>
+/// The active file is rotated to `<path>.<unix seconds>`, or `<path>.<unix seconds>-<n>` when one was already rotated
+/// that second, once it grows past `max_file_bytes` or is older than a day,
+/// and rotated files are deleted once they are older than `retention` or all files together exceed `max_total_bytes`,
+/// so unattended runs cannot fill the disk.
+pub struct History {
+    path: PathBuf,
+    max_file_bytes: u64,
+    retention: Duration,
+    max_total_bytes: u64,
+    /// Timestamp of the first record in the active file, read lazily
+    started_at: Option<u64>,
+}
+
+impl History {
+    /// Creates a history writing to `path`, rotating at `max_file_mb` megabytes and keeping rotated files for
+    /// `retention_days` days, up to `max_total_mb` megabytes in total
+    pub fn new(path: &str, max_file_mb: u64, retention_days: u64, max_total_mb: u64) -> Self {
+        History {
+            path: PathBuf::from(path),
+            max_file_bytes: max_file_mb * MEGABYTE,
+            retention: Duration::from_secs(retention_days * 24 * 60 * 60),
+            max_total_bytes: max_total_mb * MEGABYTE,
+            started_at: None,
+        }
+    }
+
+    /// Appends a record for `collector` to the history, rotating the file first if needed
+    pub fn append(&mut self, connection: &str, collector: &str, data: &Value) -> io::Result<()> {
+        self.rotate_if_needed()?;
+
+        let now = unix_now();
+        let record = json!({
+            "timestamp": now,
+            "connection": connection,
+            "collector": collector,
+            "data": data,
+        });
+
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        writeln!(file, "{}", record)?;
+
+        if self.started_at.is_none() {
+            self.started_at = Some(now);
+        }
+        Ok(())
+    }
+
+    /// Makes sure every record appended so far is written to disk, not only handed to the operating system, so none
+    /// are lost when the machine goes down right after the program exits
+    pub fn sync(&self) -> io::Result<()> {
+        match fs::File::open(&self.path) {
+            Ok(file) => file.sync_all(),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Returns the timestamp and data of the most recent record for `collector` on `connection` in the active file
+    pub fn latest(&self, connection: &str, collector: &str) -> Option<(u64, Value)> {
+        let file = fs::File::open(&self.path).ok()?;
+        BufReader::new(file)
+            .lines()
+            .map_while(Result::ok)
+            .filter_map(|line| serde_json::from_str::<Value>(&line).ok())
+            .filter(|r| r["connection"] == connection && r["collector"] == collector)
+            .last()
+            .and_then(|mut r| Some((r["timestamp"].as_u64()?, r["data"].take())))
+    }
+
+    /// Returns the timestamp and data of every record for `collector` on `connection`, oldest first, including those
+    /// in rotated files not deleted yet
+    pub fn records(&self, connection: &str, collector: &str) -> Vec<(u64, Value)> {
+        let mut paths: Vec<PathBuf> = self.rotated_files().unwrap_or_default().into_iter().map(|f| f.0).rev().collect();
+        paths.push(self.path.clone());
+
+        paths
+            .iter()
+            .filter_map(|path| fs::File::open(path).ok())
+            .flat_map(|file| BufReader::new(file).lines().map_while(Result::ok))
+            .filter_map(|line| serde_json::from_str::<Value>(&line).ok())
+            .filter(|r| r["connection"] == connection && r["collector"] == collector)
+            .filter_map(|mut r| Some((r["timestamp"].as_u64()?, r["data"].take())))
+            .collect()
+    }
+
+    /// Moves the active file aside if it is too big or too old, then applies the retention policy
+    fn rotate_if_needed(&mut self) -> io::Result<()> {
+        let size = match fs::metadata(&self.path) {
+            Ok(m) => m.len(),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(()),
+            Err(e) => return Err(e),
+        };
+
+        if self.started_at.is_none() {
+            self.started_at = first_timestamp(&self.path);
+        }
+        let too_old = match self.started_at {
+            Some(started) => unix_now().saturating_sub(started) >= ROTATE_AFTER.as_secs(),
+            None => false,
+        };
+
+        if size >= self.max_file_bytes || too_old {
+            fs::rename(&self.path, self.rotated_path(unix_now()))?;
+            self.started_at = None;
+            self.enforce_retention()?;
+        }
+        Ok(())
+    }
+
+    /// Path the active file is moved to when rotated at `rotated_at`, numbered after the files already rotated in the
+    /// same second so none is overwritten
+    fn rotated_path(&self, rotated_at: u64) -> PathBuf {
+        let mut rotated = PathBuf::from(format!("{}.{}", self.path.display(), rotated_at));
+        let mut n = 1;
+        while rotated.exists() {
+            rotated = PathBuf::from(format!("{}.{}-{}", self.path.display(), rotated_at, n));
+            n += 1;
+        }
+        rotated
+    }
+
+    /// Deletes rotated files older than the retention period, then the oldest ones until the total size fits
+    fn enforce_retention(&self) -> io::Result<()> {
+        let now = SystemTime::now();
+        let mut kept: Vec<(PathBuf, u64)> = Vec::new();
+
+        for (path, rotated_at, size) in self.rotated_files()? {
+            let age = now
+                .duration_since(UNIX_EPOCH + Duration::from_secs(rotated_at))
+                .unwrap_or_default();
+            if age > self.retention {
+                fs::remove_file(&path)?;
+            } else {
+                kept.push((path, size));
+            }
+        }
+
+        // rotated_files is sorted newest first, so everything past the budget is the oldest data
+        let mut total = fs::metadata(&self.path).map(|m| m.len()).unwrap_or(0);
+        for (path, size) in kept {
+            total += size;
+            if total > self.max_total_bytes {
+                fs::remove_file(&path)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Lists rotated history files as `(path, rotation time, size)`, newest first
+    fn rotated_files(&self) -> io::Result<Vec<(PathBuf, u64, u64)>> {
+        let dir = match self.path.parent() {
+            Some(p) if !p.as_os_str().is_empty() => p.to_path_buf(),
+            _ => PathBuf::from("."),
+        };
+        let prefix = match self.path.file_name() {
+            Some(n) => format!("{}.", n.to_string_lossy()),
+            None => return Ok(Vec::new()),
+        };
+
+        let mut files = Vec::new();
+        for entry in fs::read_dir(dir)? {
+            let entry = entry?;
+            let name = entry.file_name().to_string_lossy().to_string();
+            if let Some((rotated_at, n)) = name.strip_prefix(&prefix).and_then(rotation_of) {
+                files.push((entry.path(), rotated_at, n, entry.metadata()?.len()));
+            }
+        }
+        files.sort_by_key(|f| std::cmp::Reverse((f.1, f.2)));
+        Ok(files.into_iter().map(|(path, rotated_at, _, size)| (path, rotated_at, size)).collect())
+    }
+}
+
+/// The rotation time and number of a rotated file from the suffix of its name, `<unix seconds>` or
+/// `<unix seconds>-<n>`, see `History::rotated_path`
+pub(crate) fn rotation_of(suffix: &str) -> Option<(u64, u64)> {
+    let (seconds, n) = suffix.split_once('-').unwrap_or((suffix, "0"));
+    Some((seconds.parse().ok()?, n.parse().ok()?))
+}
+
+/// Reads the timestamp of the first record in a history file
+fn first_timestamp(path: &Path) -> Option<u64> {
+    let file = fs::File::open(path).ok()?;
+    let mut line = String::new();
+    BufReader::new(file).read_line(&mut line).ok()?;
+    serde_json::from_str::<Value>(&line).ok()?["timestamp"].as_u64()
+}
+
+/// Returns the current time in seconds since the unix epoch
+pub fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rotations_in_the_same_second_keep_every_file() {
+        let dir = std::env::temp_dir().join(format!("psql_stats-history-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("history.jsonl");
+        // Rotates before every record but the first, as each one outgrows the limit
+        let mut history = History::new(path.to_str().unwrap(), 0, 30, 500);
+
+        for n in 0..3 {
+            history.append("postgres@localhost:5432/postgres", "uptime", &json!([{"n": n}])).unwrap();
+        }
+        let rotated = history.rotated_files().unwrap();
+        assert_eq!(rotated.len(), 2);
+        // In the order they were written, though the files were rotated within the same second
+        let records = history.records("postgres@localhost:5432/postgres", "uptime");
+        let order: Vec<i64> = records.iter().map(|(_, data)| data[0]["n"].as_i64().unwrap()).collect();
+        assert_eq!(order, vec![0, 1, 2]);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn rotation_suffixes_are_read_with_their_number() {
+        assert_eq!(rotation_of("1760000000"), Some((1760000000, 0)));
+        assert_eq!(rotation_of("1760000000-2"), Some((1760000000, 2)));
+        assert_eq!(rotation_of("bak"), None);
+    }
+}
@@ -0,0 +1,143 @@
+/// Units byte counts are shown in, each 1024 times the previous one
+const BYTE_UNITS: &[&str] = &["B", "KB", "MB", "GB", "TB", "PB"];
+
+/// Formats a byte count in the largest unit it reaches, e.g. `1536` as `1.5 KB`
+pub fn human_bytes(bytes: i64) -> String {
+    let mut value = bytes.unsigned_abs() as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < BYTE_UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+
+    let sign = if bytes < 0 { "-" } else { "" };
+    match unit {
+        0 => format!("{}{} B", sign, value),
+        _ => format!("{}{:.1} {}", sign, value, BYTE_UNITS[unit]),
+    }
+}
+
+/// Formats a number of seconds as days, hours, minutes and seconds, e.g. `3d 4h 12m`. <br>
+/// Only the largest units are kept: seconds are left out from an hour on, and durations under a second are shown in
+/// milliseconds.
+pub fn human_duration(seconds: f64) -> String {
+    if seconds < 0.0 {
+        return format!("-{}", human_duration(-seconds));
+    }
+    if seconds < 1.0 {
+        return format!("{}ms", (seconds * 1000.0).round());
+    }
+
+    let total = seconds as u64;
+    let (days, hours, minutes, seconds) = (total / 86_400, total % 86_400 / 3600, total % 3600 / 60, total % 60);
+    let parts = match (days, hours) {
+        (0, 0) => vec![(minutes, "m"), (seconds, "s")],
+        (0, _) => vec![(hours, "h"), (minutes, "m")],
+        _ => vec![(days, "d"), (hours, "h"), (minutes, "m")],
+    };
+
+    let shown: Vec<String> = parts
+        .iter()
+        .filter(|(value, _)| *value > 0)
+        .map(|(value, unit)| format!("{}{}", value, unit))
+        .collect();
+    shown.join(" ")
+}
+
+/// Inserts thousands separators into the integer part of a number written out in decimal, e.g. `1234567.5` as
+/// `1,234,567.5`
+pub fn thousands(number: &str) -> String {
+    let (sign, unsigned) = match number.strip_prefix('-') {
+        Some(rest) => ("-", rest),
+        None => ("", number),
+    };
+    let (integer, fraction) = match unsigned.find(|c: char| !c.is_ascii_digit()) {
+        Some(i) => unsigned.split_at(i),
+        None => (unsigned, ""),
+    };
+
+    let mut grouped = String::new();
+    for (i, digit) in integer.chars().enumerate() {
+        if i > 0 && (integer.len() - i) % 3 == 0 {
+            grouped.push(',');
+        }
+        grouped.push(digit);
+    }
+    format!("{}{}{}", sign, grouped, fraction)
+}
+
+/// Parses a duration given on the command line into seconds: a number of seconds, or a number followed by `s`, `m`,
+/// `min` or `h`, e.g. `90`, `30s` or `5m`. Returns `None` for anything else, and for zero.
+pub fn parse_duration(text: &str) -> Option<u64> {
+    let text = text.trim();
+    let split = text.find(|c: char| !c.is_ascii_digit()).unwrap_or(text.len());
+    let (amount, unit) = text.split_at(split);
+    let unit_seconds = match unit {
+        "" | "s" => 1,
+        "m" | "min" => 60,
+        "h" => 3600,
+        _ => return None,
+    };
+    match amount.parse::<u64>() {
+        Ok(amount) if amount > 0 => amount.checked_mul(unit_seconds),
+        _ => None,
+    }
+}
+
+/// Parses an interval as Postgres prints it, e.g. `3 days 04:12:00.5` or `1 year 2 mons`, into seconds. <br>
+/// Months count as 30 days and years as 365.25, as in Postgres' own interval arithmetic. Returns `None` for anything
+/// else, so it can be used to tell intervals apart from other text.
+pub fn parse_interval(text: &str) -> Option<f64> {
+    let mut words = text.split_whitespace().peekable();
+    let mut seconds = 0.0;
+    let mut parsed = false;
+
+    while let Some(word) = words.next() {
+        if word.contains(':') {
+            // The time part always comes last
+            seconds += parse_clock(word)?;
+            parsed = true;
+            if words.peek().is_some() {
+                return None;
+            }
+            break;
+        }
+
+        let amount: f64 = word.parse().ok()?;
+        let unit_seconds = match words.next()? {
+            "year" | "years" => 365.25 * 86_400.0,
+            "mon" | "mons" => 30.0 * 86_400.0,
+            "day" | "days" => 86_400.0,
+            _ => return None,
+        };
+        seconds += amount * unit_seconds;
+        parsed = true;
+    }
+
+    if parsed {
+        Some(seconds)
+    } else {
+        None
+    }
+}
+
+/// Parses the `[+-]HH:MM:SS[.ffffff]` time part of an interval into seconds
+fn parse_clock(clock: &str) -> Option<f64> {
+    let (sign, clock) = match clock.chars().next()? {
+        '-' => (-1.0, &clock[1..]),
+        '+' => (1.0, &clock[1..]),
+        _ => (1.0, clock),
+    };
+
+    let parts: Vec<&str> = clock.split(':').collect();
+    if parts.len() != 3 || parts.iter().any(|p| p.is_empty()) {
+        return None;
+    }
+    let hours: u64 = parts[0].parse().ok()?;
+    let minutes: u64 = parts[1].parse().ok()?;
+    let seconds: f64 = parts[2].parse().ok()?;
+    if minutes >= 60 || !(0.0..60.0).contains(&seconds) {
+        return None;
+    }
+    Some(sign * ((hours * 3600 + minutes * 60) as f64 + seconds))
+}
@@ -0,0 +1,217 @@
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+
+use postgres::Client;
+use tracing::error;
+
+use super::transaction::read_only;
+use super::{Connection, PGError};
+
+/// Records sent in one `COPY`, so a bad row only sends its own batch back to be retried
+const IMPORT_BATCH_ROWS: usize = 10_000;
+
+/// A CSV record, as the bytes read from the file, and the line of the file it starts on
+struct Record {
+    line: u64,
+    bytes: Vec<u8>,
+}
+
+/// A record the server refused, and why
+pub struct RejectedRow {
+    pub line: u64,
+    pub error: String,
+}
+
+/// Outcome of an import: how many rows were loaded and which were refused
+pub struct ImportSummary {
+    pub imported: u64,
+    pub rejected: Vec<RejectedRow>,
+}
+
+impl Connection {
+    /// Loads the CSV file at `path` into the table `table` (optionally schema qualified) with `COPY ... FROM STDIN`,
+    /// printing the progress through the file as it goes. <br>
+    /// The first record of the file is the header, naming the table columns the values go to, as `export-table`
+    /// writes it. Records are sent in batches of `IMPORT_BATCH_ROWS`: when the server refuses a batch it is split in
+    /// halves and retried until the rows at fault are found, so every other row is still loaded. Refused rows are
+    /// written, with the header, to `<path>.rejected` so they can be fixed and imported again.
+    pub fn import_table(&mut self, path: &str, table: &str) -> Result<ImportSummary, PGError> {
+        if read_only() {
+            return Err(PGError::ReadOnly);
+        }
+        let client = match &mut self.client {
+            Some(c) => c,
+            None => return Err(PGError::ClientEmpty),
+        };
+
+        // Resolving the name through regclass checks it exists and gives it back safely quoted
+        let relation: String = match client.query_one("SELECT $1::text::regclass::text", &[&table]) {
+            Ok(row) => row.get(0),
+            Err(_) => return Err(PGError::MatchNotFound),
+        };
+
+        let file = File::open(path).map_err(|e| import_error(path, e))?;
+        let total_bytes = file.metadata().map(|m| m.len()).unwrap_or(0);
+        let mut reader = BufReader::new(file);
+        let mut line = 1;
+
+        let header = match read_record(&mut reader, &mut line).map_err(|e| import_error(path, e))? {
+            Some(h) => h,
+            None => return Err(import_error(path, "the file is empty")),
+        };
+        let columns: Vec<String> = csv_fields(&header.bytes).iter().map(|c| quote_ident(c)).collect();
+        let copy = format!("COPY {} ({}) FROM STDIN WITH (FORMAT csv)", relation, columns.join(", "));
+
+        let mut summary = ImportSummary {
+            imported: 0,
+            rejected: Vec::new(),
+        };
+        let mut rejected_records = Vec::new();
+        let mut read_bytes = header.bytes.len() as u64;
+        loop {
+            let mut batch = Vec::new();
+            while batch.len() < IMPORT_BATCH_ROWS {
+                match read_record(&mut reader, &mut line).map_err(|e| import_error(path, e))? {
+                    Some(r) => {
+                        read_bytes += r.bytes.len() as u64;
+                        batch.push(r);
+                    }
+                    None => break,
+                }
+            }
+            if batch.is_empty() {
+                break;
+            }
+
+            copy_records(client, &copy, &batch, &mut summary, &mut rejected_records);
+            print!(
+                "\rImported {} rows, {} rejected ({}% of the file)",
+                summary.imported,
+                summary.rejected.len(),
+                read_bytes * 100 / total_bytes.max(1)
+            );
+            io::stdout().flush().ok();
+        }
+        println!();
+
+        if !rejected_records.is_empty() {
+            let rejected_path = format!("{}.rejected", path);
+            write_rejected(&rejected_path, &header, &rejected_records).map_err(|e| import_error(&rejected_path, e))?;
+        }
+        Ok(summary)
+    }
+}
+
+fn import_error(path: &str, error: impl std::fmt::Display) -> PGError {
+    error!("could not import {}: {}", path, error);
+    PGError::ImportError
+}
+
+/// Copies `records` in one `COPY`, or when the server refuses them, each half on its own, down to single rows which
+/// are then recorded as rejected
+fn copy_records(
+    client: &mut Client,
+    copy: &str,
+    records: &[Record],
+    summary: &mut ImportSummary,
+    rejected: &mut Vec<Vec<u8>>,
+) {
+    let result = client.copy_in(copy).and_then(|mut writer| {
+        for record in records {
+            // A failed write is reported again by finish, with the server's reason
+            if writer.write_all(&record.bytes).is_err() {
+                break;
+            }
+        }
+        writer.finish()
+    });
+
+    match result {
+        Ok(rows) => summary.imported += rows,
+        Err(e) if records.len() == 1 => {
+            let error = match e.as_db_error() {
+                Some(db) => db.message().to_string(),
+                None => e.to_string(),
+            };
+            summary.rejected.push(RejectedRow {
+                line: records[0].line,
+                error,
+            });
+            rejected.push(records[0].bytes.clone());
+        }
+        Err(_) => {
+            let (first, second) = records.split_at(records.len() / 2);
+            copy_records(client, copy, first, summary, rejected);
+            copy_records(client, copy, second, summary, rejected);
+        }
+    }
+}
+
+/// Reads the next CSV record, which spans several lines when a quoted field holds line breaks, and advances `line`
+/// past it. Returns `None` at the end of the file.
+fn read_record(reader: &mut impl BufRead, line: &mut u64) -> io::Result<Option<Record>> {
+    let mut record = Record {
+        line: *line,
+        bytes: Vec::new(),
+    };
+    let mut quoted = false;
+    loop {
+        let start = record.bytes.len();
+        if reader.read_until(b'\n', &mut record.bytes)? == 0 {
+            break;
+        }
+        *line += 1;
+
+        // A doubled quote inside a quoted field toggles twice, leaving the state unchanged
+        quoted ^= record.bytes[start..].iter().filter(|b| **b == b'"').count() % 2 == 1;
+        if !quoted {
+            break;
+        }
+    }
+
+    if record.bytes.is_empty() {
+        return Ok(None);
+    }
+    if !record.bytes.ends_with(b"\n") {
+        record.bytes.push(b'\n');
+    }
+    Ok(Some(record))
+}
+
+/// Splits a CSV record into its fields, unquoting them
+fn csv_fields(record: &[u8]) -> Vec<String> {
+    let text = String::from_utf8_lossy(record);
+    let text = text.trim_end_matches(['\r', '\n']);
+
+    let mut fields = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                field.push('"');
+                chars.next();
+            }
+            '"' => quoted = !quoted,
+            ',' if !quoted => fields.push(std::mem::take(&mut field)),
+            c => field.push(c),
+        }
+    }
+    fields.push(field);
+    fields
+}
+
+/// Quotes a column name from the header as an identifier, so it is matched exactly as written
+pub(crate) fn quote_ident(name: &str) -> String {
+    format!("\"{}\"", name.replace('"', "\"\""))
+}
+
+fn write_rejected(path: &str, header: &Record, records: &[Vec<u8>]) -> io::Result<()> {
+    let mut writer = BufWriter::new(File::create(path)?);
+    writer.write_all(&header.bytes)?;
+    for record in records {
+        writer.write_all(record)?;
+    }
+    writer.flush()
+}
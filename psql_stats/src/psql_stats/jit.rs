@@ -0,0 +1,54 @@
+/// Query summarizing JIT compilation: the settings deciding when queries are compiled, the share of all statement
+/// time spent compiling, and the 20 statements of `pg_stat_statements` spending the most time on it, with the time of
+/// each step and the share of the statement's own time it takes. <br>
+/// The settings are repeated on every row, and given on a row of their own when no statement was compiled. JIT
+/// timings are only kept from `pg_stat_statements` 1.10 (Postgres 15) on, and read through `to_jsonb` so that older
+/// versions list no statements rather than failing. Times are in milliseconds.
+pub const JIT_QUERY: &str = r#"
+    WITH statements AS (
+        SELECT s.queryid AS query_id,
+               s.calls,
+               coalesce((j ->> 'total_exec_time')::float8, (j ->> 'total_time')::float8) AS total_exec_time,
+               (j ->> 'jit_functions')::bigint AS jit_functions,
+               (j ->> 'jit_generation_time')::float8 AS generation_ms,
+               (j ->> 'jit_inlining_time')::float8 AS inlining_ms,
+               (j ->> 'jit_optimization_time')::float8 AS optimization_ms,
+               (j ->> 'jit_emission_time')::float8 AS emission_ms,
+               coalesce((j ->> 'jit_deform_time')::float8, 0) AS deform_ms,
+               s.query
+        FROM pg_stat_statements s, to_jsonb(s) AS j
+    ),
+    jit AS (
+        SELECT *,
+               generation_ms + inlining_ms + optimization_ms + emission_ms + deform_ms AS jit_ms
+        FROM statements
+    ),
+    totals AS (
+        SELECT round((100.0 * sum(jit_ms) / nullif(sum(total_exec_time), 0))::numeric, 1) AS total_jit_percent
+        FROM jit
+    ),
+    top AS (
+        SELECT *
+        FROM jit
+        WHERE jit_functions > 0
+        ORDER BY jit_ms DESC
+        LIMIT 20
+    )
+    SELECT current_setting('jit') AS jit,
+           current_setting('jit_above_cost') AS jit_above_cost,
+           current_setting('jit_inline_above_cost') AS jit_inline_above_cost,
+           current_setting('jit_optimize_above_cost') AS jit_optimize_above_cost,
+           t.total_jit_percent,
+           s.query_id,
+           s.calls,
+           s.jit_functions,
+           round(s.generation_ms::numeric, 2) AS generation_ms,
+           round(s.inlining_ms::numeric, 2) AS inlining_ms,
+           round(s.optimization_ms::numeric, 2) AS optimization_ms,
+           round(s.jit_ms::numeric, 2) AS jit_ms,
+           round((100.0 * s.jit_ms / nullif(s.total_exec_time, 0))::numeric, 1) AS jit_percent,
+           left(regexp_replace(s.query, '\s+', ' ', 'g'), 80) AS query
+    FROM totals t
+    LEFT JOIN top s ON true
+    ORDER BY s.jit_ms DESC NULLS LAST
+"#;
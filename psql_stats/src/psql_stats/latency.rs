@@ -0,0 +1,170 @@
+use std::thread;
+use std::time::{Duration, Instant};
+
+use super::{Connection, PGError};
+
+/// Number of linear sub-buckets each power of two is split into. <br>
+/// Four sub-buckets keeps the relative error of a bucket under 25% while the histogram stays short enough
+/// to fit on a terminal.
+const SUB_BUCKETS: u64 = 4;
+
+/// Width, in characters, of the longest bar drawn by `LatencyHistogram::render`
+const BAR_WIDTH: u64 = 40;
+
+impl Connection {
+    /// Runs `SELECT 1` against the database `samples` times, waiting `interval` between each query, and returns
+    /// the round-trip time of every query. <br>
+    /// Returns a `PGError` if the `client` is `None` or any of the queries fail.
+    pub fn sample_latency(&mut self, samples: usize, interval: Duration) -> Result<Vec<Duration>, PGError> {
+        match &mut self.client {
+            Some(ref mut c) => {
+                let mut timings = Vec::with_capacity(samples);
+                for sample in 0..samples {
+                    if sample > 0 {
+                        thread::sleep(interval);
+                    }
+                    let start = Instant::now();
+                    match c.simple_query("SELECT 1") {
+                        Ok(_) => timings.push(start.elapsed()),
+                        Err(e) => return Err(PGError::query("measuring the latency", e)),
+                    }
+                }
+                Ok(timings)
+            }
+
+            // Client is empty, cannot run a query
+            None => Err(PGError::ClientEmpty),
+        }
+    }
+}
+
+/// HDR-style histogram of query round-trip times. <br>
+/// Values are recorded in microseconds into logarithmic buckets (one per power of two), each split into
+/// `SUB_BUCKETS` linear sub-buckets, so both sub-millisecond and multi-second latencies keep useful precision.
+#[derive(Default)]
+pub struct LatencyHistogram {
+    counts: Vec<u64>,
+    samples: Vec<u64>,
+}
+
+impl LatencyHistogram {
+    /// Builds a histogram from a set of round-trip times
+    pub fn from_durations(durations: &[Duration]) -> Self {
+        let mut histogram = LatencyHistogram::default();
+        for d in durations {
+            histogram.record(*d);
+        }
+        histogram
+    }
+
+    /// Records a single round-trip time
+    pub fn record(&mut self, duration: Duration) {
+        let micros = duration.as_micros().min(u64::MAX as u128) as u64;
+        let index = bucket_index(micros);
+        if self.counts.len() <= index {
+            self.counts.resize(index + 1, 0);
+        }
+        self.counts[index] += 1;
+        self.samples.push(micros);
+    }
+
+    /// Returns the value in microseconds below which `percentile` percent of the samples fall
+    pub fn percentile(&self, percentile: f64) -> u64 {
+        if self.samples.is_empty() {
+            return 0;
+        }
+        let mut sorted = self.samples.clone();
+        sorted.sort_unstable();
+        let rank = ((percentile / 100.0) * (sorted.len() - 1) as f64).round() as usize;
+        sorted[rank.min(sorted.len() - 1)]
+    }
+
+    /// Returns the mean and the standard deviation (the jitter) of the samples in microseconds
+    pub fn mean_and_jitter(&self) -> (f64, f64) {
+        if self.samples.is_empty() {
+            return (0.0, 0.0);
+        }
+        let n = self.samples.len() as f64;
+        let mean = self.samples.iter().sum::<u64>() as f64 / n;
+        let variance = self
+            .samples
+            .iter()
+            .map(|s| (*s as f64 - mean).powi(2))
+            .sum::<f64>()
+            / n;
+        (mean, variance.sqrt())
+    }
+
+    /// Renders the histogram as ASCII bars, one line per bucket between the fastest and slowest sample,
+    /// followed by a summary line with the percentiles and jitter.
+    pub fn render(&self) -> String {
+        let mut output = String::new();
+        let first = self.counts.iter().position(|c| *c > 0);
+        let last = self.counts.iter().rposition(|c| *c > 0);
+
+        if let (Some(first), Some(last)) = (first, last) {
+            let max_count = self.counts[first..=last].iter().max().copied().unwrap_or(1);
+            for index in first..=last {
+                let (low, high) = bucket_bounds(index);
+                let count = self.counts[index];
+                let bar = "#".repeat((count * BAR_WIDTH).div_ceil(max_count) as usize);
+                output.push_str(&format!(
+                    "\t{:>9} - {:<9} | {:<width$} {}\n",
+                    format_micros(low as f64),
+                    format_micros(high as f64),
+                    bar,
+                    count,
+                    width = BAR_WIDTH as usize
+                ));
+            }
+        }
+
+        let (mean, jitter) = self.mean_and_jitter();
+        output.push_str(&format!(
+            "\tsamples: {}  min: {}  p50: {}  p90: {}  p99: {}  max: {}  mean: {}  jitter: {}",
+            self.samples.len(),
+            format_micros(self.percentile(0.0) as f64),
+            format_micros(self.percentile(50.0) as f64),
+            format_micros(self.percentile(90.0) as f64),
+            format_micros(self.percentile(99.0) as f64),
+            format_micros(self.percentile(100.0) as f64),
+            format_micros(mean),
+            format_micros(jitter),
+        ));
+        output
+    }
+}
+
+/// Returns the index of the bucket `micros` falls into
+fn bucket_index(micros: u64) -> usize {
+    if micros < SUB_BUCKETS {
+        return micros as usize;
+    }
+    let exponent = 63 - micros.leading_zeros() as u64;
+    let shift = exponent - 2;
+    let sub_bucket = (micros >> shift) - SUB_BUCKETS;
+    (SUB_BUCKETS + shift * SUB_BUCKETS + sub_bucket) as usize
+}
+
+/// Returns the inclusive lower and exclusive upper bound, in microseconds, of a bucket
+fn bucket_bounds(index: usize) -> (u64, u64) {
+    let index = index as u64;
+    if index < SUB_BUCKETS {
+        return (index, index + 1);
+    }
+    let shift = (index - SUB_BUCKETS) / SUB_BUCKETS;
+    let sub_bucket = (index - SUB_BUCKETS) % SUB_BUCKETS;
+    let low = (SUB_BUCKETS + sub_bucket) << shift;
+    (low, low + (1 << shift))
+}
+
+/// Formats a number of microseconds with the most readable unit
+fn format_micros(micros: f64) -> String {
+    if micros >= 1_000_000.0 {
+        format!("{:.2}s", micros / 1_000_000.0)
+    } else if micros >= 1_000.0 {
+        format!("{:.2}ms", micros / 1_000.0)
+    } else {
+        format!("{:.0}us", micros)
+    }
+}
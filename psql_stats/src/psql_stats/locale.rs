@@ -0,0 +1,151 @@
+use chrono::{DateTime, NaiveDate, NaiveDateTime};
+
+/// How a region writes numbers, dates and times, used for tables when `human` is on. JSON, CSV, reports and the
+/// HTTP API always keep plain numbers and ISO 8601 dates.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Locale {
+    /// Name the locale is chosen by, a language and region such as `de-DE`
+    pub name: &'static str,
+    /// Put between groups of three digits
+    pub group_separator: &'static str,
+    /// Put between the integer and the fraction
+    pub decimal_separator: &'static str,
+    /// `chrono` format of a date
+    pub date: &'static str,
+    /// `chrono` format of a time of day
+    pub time: &'static str,
+}
+
+/// Every locale `--locale` accepts
+pub const LOCALES: &[Locale] = &[
+    Locale {
+        name: "en-US",
+        group_separator: ",",
+        decimal_separator: ".",
+        date: "%m/%d/%Y",
+        time: "%-I:%M:%S %p",
+    },
+    Locale {
+        name: "en-GB",
+        group_separator: ",",
+        decimal_separator: ".",
+        date: "%d/%m/%Y",
+        time: "%H:%M:%S",
+    },
+    Locale {
+        name: "de-DE",
+        group_separator: ".",
+        decimal_separator: ",",
+        date: "%d.%m.%Y",
+        time: "%H:%M:%S",
+    },
+    Locale {
+        name: "de-CH",
+        group_separator: "\u{2019}",
+        decimal_separator: ".",
+        date: "%d.%m.%Y",
+        time: "%H:%M:%S",
+    },
+    Locale {
+        name: "fr-FR",
+        group_separator: "\u{202F}",
+        decimal_separator: ",",
+        date: "%d/%m/%Y",
+        time: "%H:%M:%S",
+    },
+    Locale {
+        name: "es-ES",
+        group_separator: ".",
+        decimal_separator: ",",
+        date: "%d/%m/%Y",
+        time: "%H:%M:%S",
+    },
+    Locale {
+        name: "it-IT",
+        group_separator: ".",
+        decimal_separator: ",",
+        date: "%d/%m/%Y",
+        time: "%H:%M:%S",
+    },
+    Locale {
+        name: "nl-NL",
+        group_separator: ".",
+        decimal_separator: ",",
+        date: "%d-%m-%Y",
+        time: "%H:%M:%S",
+    },
+    Locale {
+        name: "pt-BR",
+        group_separator: ".",
+        decimal_separator: ",",
+        date: "%d/%m/%Y",
+        time: "%H:%M:%S",
+    },
+    Locale {
+        name: "sv-SE",
+        group_separator: "\u{A0}",
+        decimal_separator: ",",
+        date: "%Y-%m-%d",
+        time: "%H:%M:%S",
+    },
+    Locale {
+        name: "ja-JP",
+        group_separator: ",",
+        decimal_separator: ".",
+        date: "%Y/%m/%d",
+        time: "%H:%M:%S",
+    },
+];
+
+impl Locale {
+    /// Finds the locale called `name`, ignoring case, accepting `_` for `-` and any encoding after a dot as in
+    /// `de_DE.UTF-8`, or the first locale of a language given alone, such as `fr`
+    pub fn find(name: &str) -> Option<Locale> {
+        let name = name.split('.').next().unwrap_or("").replace('_', "-");
+        LOCALES
+            .iter()
+            .find(|l| l.name.eq_ignore_ascii_case(&name))
+            .or_else(|| {
+                LOCALES
+                    .iter()
+                    .find(|l| l.name.split('-').next().is_some_and(|language| language.eq_ignore_ascii_case(&name)))
+            })
+            .copied()
+    }
+
+    /// Rewrites a number written with `,` between groups of digits and `.` before the fraction, as `thousands`
+    /// and `human_bytes` write them, with the separators of this locale
+    pub fn number(&self, text: &str) -> String {
+        text.chars()
+            .map(|c| match c {
+                ',' => self.group_separator.to_string(),
+                '.' => self.decimal_separator.to_string(),
+                c => c.to_string(),
+            })
+            .collect()
+    }
+
+    /// Rewrites a date, or a timestamp with or without a time zone, as `query_json` returns them in ISO 8601, the
+    /// way this locale writes them. A time zone is kept as an offset after the time. <br>
+    /// Returns `None` for anything else.
+    pub fn date_time(&self, text: &str) -> Option<String> {
+        if let Ok(timestamp) = DateTime::parse_from_rfc3339(text) {
+            let (date, time) = (timestamp.format(self.date), timestamp.format(self.time));
+            return Some(format!("{} {} {}", date, time, timestamp.offset()));
+        }
+        if let Ok(timestamp) = NaiveDateTime::parse_from_str(text, "%Y-%m-%dT%H:%M:%S%.f") {
+            return Some(format!("{} {}", timestamp.format(self.date), timestamp.format(self.time)));
+        }
+        NaiveDate::parse_from_str(text, "%Y-%m-%d")
+            .ok()
+            .map(|date| date.format(self.date).to_string())
+    }
+}
+
+/// Parses the value of `--locale`, see `Locale::find`
+pub fn parse_locale(name: &str) -> Result<Locale, String> {
+    Locale::find(name).ok_or_else(|| {
+        let names: Vec<&str> = LOCALES.iter().map(|l| l.name).collect();
+        format!("unknown locale, expected one of: {}", names.join(", "))
+    })
+}
@@ -0,0 +1,252 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use serde_json::{json, Value};
+
+use super::humanize::{human_duration, parse_interval};
+use super::style::glyph;
+use super::{shorten_queries, Connection, PGError};
+
+/// Query listing the sessions waiting on a lock and the sessions holding them up, with `blocked_by` the pids a
+/// session waits for, the lock mode it waits for and what on: a relation, a row of one, or the transaction that last
+/// changed the row. `waiting` is how long its query has been running, `transaction_age` how long its transaction has
+/// been open. Relations of other databases cannot be named and show as their oid.
+pub const LOCKS_QUERY: &str = r#"
+    WITH blocked AS (
+        SELECT pid, blocked_by
+        FROM (SELECT pid, pg_blocking_pids(pid) AS blocked_by FROM pg_stat_activity) p
+        WHERE cardinality(blocked_by) > 0
+    )
+    SELECT a.pid,
+           coalesce(b.blocked_by, '{}') AS blocked_by,
+           a.usename,
+           a.datname,
+           a.state,
+           l.mode AS waiting_for,
+           CASE
+                WHEN l.locktype = 'transactionid' THEN 'transaction ' || l.transactionid
+                WHEN l.relation IS NULL THEN l.locktype
+                WHEN l.locktype = 'tuple' THEN 'a row of ' || rel.name
+                ELSE rel.name
+           END AS lock_on,
+           CASE WHEN l.pid IS NOT NULL THEN date_trunc('second', now() - a.query_start) END AS waiting,
+           date_trunc('second', now() - a.xact_start) AS transaction_age,
+           a.query
+    FROM pg_stat_activity a
+    LEFT JOIN blocked b ON b.pid = a.pid
+    LEFT JOIN pg_locks l ON l.pid = a.pid AND NOT l.granted
+    LEFT JOIN LATERAL (
+        SELECT CASE
+                    WHEN l.database = (SELECT oid FROM pg_database WHERE datname = current_database())
+                    THEN l.relation::regclass::text
+                    ELSE l.relation::text
+               END AS name
+    ) rel ON true
+    WHERE a.pid IN (SELECT pid FROM blocked UNION SELECT unnest(blocked_by) FROM blocked)
+    ORDER BY a.pid
+"#;
+
+/// Query listing the relation and row locks of the current database sessions are waiting for, with the modes the
+/// sessions ahead of them hold or are waiting for on the same relation, polled by `Connection::sample_locks`
+const LOCK_WAITS_QUERY: &str = r#"
+    SELECT w.relation::regclass::text,
+           w.pid,
+           w.mode,
+           array(SELECT DISTINCT h.mode
+                 FROM pg_locks h
+                 WHERE h.locktype = w.locktype
+                 AND h.database = w.database
+                 AND h.relation = w.relation
+                 AND h.pid = ANY(pg_blocking_pids(w.pid)))
+    FROM pg_locks w
+    WHERE NOT w.granted
+    AND w.locktype IN ('relation', 'tuple')
+    AND w.database = (SELECT oid FROM pg_database WHERE datname = current_database())
+"#;
+
+/// What was seen waiting on one relation while sampling, see `Connection::sample_locks`
+#[derive(Default)]
+struct Contention {
+    /// Polls in which at least one session waited on the relation
+    polls: usize,
+    /// Sessions seen waiting on it
+    waiters: BTreeSet<i32>,
+    /// How often each lock mode was waited for behind each mode held, or queued, ahead of it
+    conflicts: BTreeMap<(String, String), usize>,
+}
+
+impl Connection {
+    /// Polls the lock waits of the current database every `interval` for `window`, and ranks the relations sessions
+    /// waited on by the share of polls they were contended in: how many sessions waited, and which lock modes waited
+    /// behind which, e.g. `RowExclusiveLock behind AccessExclusiveLock` for writes held up by DDL. <br>
+    /// Waits shorter than `interval` can slip between polls, so the counts are a sample rather than every wait. <br>
+    /// Returns the number of polls taken with the rows, most contended relation first.
+    pub fn sample_locks(&mut self, window: Duration, interval: Duration) -> Result<(usize, Value), PGError> {
+        let client = match &mut self.client {
+            Some(c) => c,
+            None => return Err(PGError::ClientEmpty),
+        };
+
+        let mut relations: BTreeMap<String, Contention> = BTreeMap::new();
+        let mut polls = 0;
+        let start = Instant::now();
+        while polls == 0 || start.elapsed() < window {
+            if polls > 0 {
+                thread::sleep(interval);
+            }
+            let rows = client.query(LOCK_WAITS_QUERY, &[]).map_err(|e| PGError::query("sampling the lock waits", e))?;
+            polls += 1;
+
+            let mut seen = BTreeSet::new();
+            for row in rows {
+                let (relation, pid, mode, held): (String, i32, String, Vec<String>) =
+                    (row.get(0), row.get(1), row.get(2), row.get(3));
+                let contention = relations.entry(relation.clone()).or_default();
+                if seen.insert(relation) {
+                    contention.polls += 1;
+                }
+                contention.waiters.insert(pid);
+                for held in held {
+                    *contention.conflicts.entry((mode.clone(), held)).or_default() += 1;
+                }
+            }
+        }
+
+        let mut ranked: Vec<(String, Contention)> = relations.into_iter().collect();
+        ranked.sort_by(|(_, a), (_, b)| b.polls.cmp(&a.polls).then(b.waiters.len().cmp(&a.waiters.len())));
+        let rows = ranked
+            .iter()
+            .map(|(relation, contention)| {
+                let mut conflicts: Vec<(&(String, String), &usize)> = contention.conflicts.iter().collect();
+                conflicts.sort_by(|a, b| b.1.cmp(a.1));
+                let conflicts: Vec<String> = conflicts
+                    .iter()
+                    .map(|((waiting, held), count)| format!("{} behind {} ({})", waiting, held, count))
+                    .collect();
+                json!({
+                    "relation": relation,
+                    "contended_polls": contention.polls,
+                    "contended_percent": (contention.polls as f64 * 1000.0 / polls as f64).round() / 10.0,
+                    "waiting_sessions": contention.waiters.len(),
+                    "conflicts": conflicts.join(", "),
+                })
+            })
+            .collect();
+        Ok((polls, Value::Array(rows)))
+    }
+
+    /// Lists the sessions waiting on a lock and those blocking them, see `LOCKS_QUERY`. Empty when no session waits.
+    /// <br>
+    /// Queries are put on one line and cut off at `query_width` characters, 0 keeps them whole. <br>
+    /// Roles without `pg_read_all_stats` only see the queries of their own sessions.
+    pub fn get_locks(&mut self, query_width: usize) -> Result<Value, PGError> {
+        let mut rows = self.query_json(LOCKS_QUERY)?;
+        shorten_queries(&mut rows, query_width);
+        Ok(rows)
+    }
+}
+
+/// Draws the rows of `get_locks` as a tree, one per session holding others up without waiting itself, with the
+/// sessions waiting on it below it, and those waiting on them below them, e.g.
+///
+/// ```text
+/// 4242 alice@shop idle in transaction, transaction open 12m 3s: ALTER TABLE orders ADD COLUMN note text
+/// └─ 4250 bob@shop waiting 11m 40s for AccessShareLock on orders: SELECT * FROM orders WHERE id = $1
+///    └─ 4251 carol@shop waiting 2m 5s for RowExclusiveLock on orders: UPDATE orders SET ...
+/// ```
+///
+/// A session waiting on several others is drawn below each of them. Sessions waiting on each other in a circle,
+/// which the deadlock detector soon breaks up, are drawn from the lowest pid.
+pub fn blocking_tree(rows: &Value) -> Vec<String> {
+    let sessions: BTreeMap<i64, &Value> = rows
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|row| Some((row["pid"].as_i64()?, row)))
+        .collect();
+
+    let mut waiters: BTreeMap<i64, Vec<i64>> = BTreeMap::new();
+    for (pid, row) in &sessions {
+        for blocker in row["blocked_by"].as_array().into_iter().flatten().filter_map(|b| b.as_i64()) {
+            waiters.entry(blocker).or_default().push(*pid);
+        }
+    }
+
+    let waiting = |pid: &i64| {
+        sessions
+            .get(pid)
+            .is_some_and(|row| row["blocked_by"].as_array().is_some_and(|b| !b.is_empty()))
+    };
+    let mut roots: Vec<i64> = waiters.keys().filter(|pid| !waiting(pid)).copied().collect();
+    if roots.is_empty() {
+        roots.extend(waiters.keys().next());
+    }
+
+    let mut lines = vec![];
+    let mut drawn = BTreeSet::new();
+    for root in roots {
+        lines.push(describe_session(root, sessions.get(&root).copied()));
+        draw_waiters(root, "", &sessions, &waiters, &mut drawn, &mut lines);
+    }
+    lines
+}
+
+/// Draws the sessions waiting on `pid` below it, indented by `indent`, skipping those already drawn above them so
+/// sessions waiting on each other in a circle end
+fn draw_waiters(
+    pid: i64,
+    indent: &str,
+    sessions: &BTreeMap<i64, &Value>,
+    waiters: &BTreeMap<i64, Vec<i64>>,
+    drawn: &mut BTreeSet<i64>,
+    lines: &mut Vec<String>,
+) {
+    drawn.insert(pid);
+    let children = waiters.get(&pid).map(|w| w.as_slice()).unwrap_or_default();
+    for (i, child) in children.iter().enumerate() {
+        let last = i + 1 == children.len();
+        let (branch, continuation) = match last {
+            true => (glyph("└─ ", "`- "), "   "),
+            false => (glyph("├─ ", "|- "), glyph("│  ", "|  ")),
+        };
+        lines.push(format!("{}{}{}", indent, branch, describe_session(*child, sessions.get(child).copied())));
+        if !drawn.contains(child) {
+            draw_waiters(*child, &format!("{}{}", indent, continuation), sessions, waiters, drawn, lines);
+        }
+    }
+    drawn.remove(&pid);
+}
+
+/// Describes a session of the tree on one line: who it is, what it waits for and how long, or how long its
+/// transaction has been open when it does not wait, and its query. A session not in `pg_stat_activity`, such as a
+/// prepared transaction, only shows its pid.
+fn describe_session(pid: i64, row: Option<&Value>) -> String {
+    let row = match row {
+        Some(row) => row,
+        None => return format!("{} (not a visible session, perhaps a prepared transaction)", pid),
+    };
+    let text = |key: &str| row[key].as_str().unwrap_or("?").to_string();
+    let duration = |key: &str| row[key].as_str().and_then(parse_interval).map(human_duration);
+
+    let mut line = format!("{} {}@{}", pid, text("usename"), text("datname"));
+    match (row["waiting_for"].as_str(), duration("waiting")) {
+        (Some(mode), waited) => {
+            line += " waiting";
+            if let Some(waited) = waited {
+                line += &format!(" {}", waited);
+            }
+            line += &format!(" for {} on {}", mode, text("lock_on"));
+        }
+        (None, _) => {
+            line += &format!(" {}", text("state"));
+            if let Some(age) = duration("transaction_age") {
+                line += &format!(", transaction open {}", age);
+            }
+        }
+    }
+    if let Some(query) = row["query"].as_str().filter(|q| !q.is_empty()) {
+        line += &format!(": {}", query);
+    }
+    line
+}
@@ -0,0 +1,142 @@
+use std::fmt;
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
+
+use chrono::Utc;
+use colored::Colorize;
+use tracing::field::{Field, Visit};
+use tracing::span::{Attributes, Id, Record};
+use tracing::{debug, Event, Level, Metadata, Subscriber};
+
+use super::redact::redact;
+use super::{one_line, PGError};
+
+/// Most detailed level written to the log file, whatever the terminal shows: every query run and connection made
+const FILE_LEVEL: Level = Level::DEBUG;
+
+/// Sends what the program logs with `tracing` to the terminal and, optionally, a log file. <br>
+/// Errors and warnings are printed on stderr the way the program always printed them, `Error: ...` and
+/// `Warning: ...`, the more detailed levels dimmed and prefixed with their level. Only events of this program are
+/// handled, those of the libraries it uses are left out.
+struct Logger {
+    /// Most detailed level shown on the terminal
+    console: Level,
+    file: Option<Mutex<File>>,
+    next_span: AtomicU64,
+}
+
+/// The fields of an event: its message, and the others as ` name=value`
+#[derive(Default)]
+struct Fields {
+    message: String,
+    rest: String,
+}
+
+impl Visit for Fields {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        match field.name() {
+            "message" => self.message = value.to_string(),
+            name => self.rest += &format!(" {}={:?}", name, value),
+        }
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        match field.name() {
+            "message" => self.message = format!("{:?}", value),
+            name => self.rest += &format!(" {}={:?}", name, value),
+        }
+    }
+}
+
+impl Subscriber for Logger {
+    fn enabled(&self, metadata: &Metadata<'_>) -> bool {
+        let level = *metadata.level();
+        let logged = level <= self.console || (self.file.is_some() && level <= FILE_LEVEL);
+        logged && metadata.target().starts_with("psql_stats")
+    }
+
+    fn new_span(&self, _: &Attributes<'_>) -> Id {
+        Id::from_u64(self.next_span.fetch_add(1, Ordering::Relaxed))
+    }
+
+    fn record(&self, _: &Id, _: &Record<'_>) {}
+
+    fn record_follows_from(&self, _: &Id, _: &Id) {}
+
+    fn event(&self, event: &Event<'_>) {
+        let mut fields = Fields::default();
+        event.record(&mut fields);
+        let level = *event.metadata().level();
+
+        if level <= self.console {
+            let line = match level {
+                Level::ERROR => format!("Error: {}{}", fields.message, fields.rest),
+                Level::WARN => format!("Warning: {}{}", fields.message, fields.rest),
+                _ => format!("{} {}{}", level, fields.message, fields.rest).dimmed().to_string(),
+            };
+            eprintln!("{}", line);
+        }
+        if let Some(file) = &self.file {
+            if level <= FILE_LEVEL {
+                if let Ok(mut file) = file.lock() {
+                    let timestamp = Utc::now().format("%Y-%m-%dT%H:%M:%S%.3fZ");
+                    // A log that cannot be written must not stop the program, nor flood the terminal with errors
+                    // Lines of the message after the first, e.g. the query an error points at, are indented under it
+                    let message = fields.message.replace('\n', "\n    ");
+                    let _ = writeln!(file, "{} {:5} {}{}", timestamp, level, message, fields.rest);
+                }
+            }
+        }
+    }
+
+    fn enter(&self, _: &Id) {}
+
+    fn exit(&self, _: &Id) {}
+}
+
+/// Sets up where what the program logs goes, before anything is logged. <br>
+/// The terminal shows errors and warnings, only errors with `quiet`, and with a `verbosity` of 1 (`-v`) the
+/// connections opened and closed as well, with 2 (`-vv`) every query run with its duration, and with 3 or more
+/// (`-vvv`) everything. <br>
+/// With `log_file`, errors, warnings, connections and queries are appended to the file too, each line starting with
+/// its UTC time and level, whatever the terminal shows, so problems can be looked into after the fact.
+pub fn init_logging(verbosity: u8, quiet: bool, log_file: Option<&str>) -> Result<(), PGError> {
+    let console = match (quiet, verbosity) {
+        (true, _) => Level::ERROR,
+        (false, 0) => Level::WARN,
+        (false, 1) => Level::INFO,
+        (false, 2) => Level::DEBUG,
+        _ => Level::TRACE,
+    };
+    let file = match log_file {
+        Some(path) => match OpenOptions::new().create(true).append(true).open(path) {
+            Ok(f) => Some(Mutex::new(f)),
+            Err(e) => {
+                eprintln!("Error: could not open the log file {}: {}", path, e);
+                return Err(PGError::LogFileError);
+            }
+        },
+        None => None,
+    };
+
+    let logger = Logger {
+        console,
+        file,
+        next_span: AtomicU64::new(1),
+    };
+    tracing::subscriber::set_global_default(logger).map_err(|_| PGError::LogFileError)
+}
+
+/// Logs at debug level that `query` ran for `elapsed` and returned or changed the `Ok` number of rows, or failed with
+/// the `Err` error. The query is put on one line, with the credentials in it masked.
+pub(crate) fn log_query<E: fmt::Display>(query: &str, elapsed: Duration, outcome: Result<u64, E>) {
+    let duration_ms = (elapsed.as_secs_f64() * 1_000_000.0).round() / 1000.0;
+    let query = redact(&one_line(query, 0));
+    match outcome {
+        Ok(rows) => debug!(duration_ms, rows, "ran {}", query),
+        Err(e) => debug!(duration_ms, error = %e, "failed {}", query),
+    }
+}
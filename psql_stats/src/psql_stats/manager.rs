@@ -0,0 +1,148 @@
+use serde_json::{json, Value};
+
+use super::transaction::TransactionState;
+use super::{Connection, PGError};
+
+/// The connections open in the interactive menu, each under a name, e.g. a primary and its replica or staging and
+/// production. Options and commands run against the active one, see `switch`.
+pub struct ConnectionManager {
+    connections: Vec<(String, Connection)>,
+    active: usize,
+    /// Queries run on the connections closed or replaced so far, see `queries`
+    closed_queries: u64,
+}
+
+impl ConnectionManager {
+    /// Starts with `connection`, named `name`, as the only and active connection
+    pub fn new(name: &str, connection: Connection) -> ConnectionManager {
+        ConnectionManager {
+            connections: vec![(name.to_string(), connection)],
+            active: 0,
+            closed_queries: 0,
+        }
+    }
+
+    /// The connection options and commands run against
+    pub fn active(&self) -> &Connection {
+        &self.connections[self.active].1
+    }
+
+    pub fn active_mut(&mut self) -> &mut Connection {
+        &mut self.connections[self.active].1
+    }
+
+    /// Name of the active connection
+    pub fn active_name(&self) -> &str {
+        &self.connections[self.active].0
+    }
+
+    /// Names of the open connections, in the order they were opened
+    pub fn names(&self) -> Vec<String> {
+        self.connections.iter().map(|(name, _)| name.clone()).collect()
+    }
+
+    /// Whether more than one connection is open, in which case the prompt names the active one
+    pub fn several_open(&self) -> bool {
+        self.connections.len() > 1
+    }
+
+    fn position(&self, name: &str) -> Result<usize, PGError> {
+        self.connections
+            .iter()
+            .position(|(n, _)| n == name)
+            .ok_or(PGError::MatchNotFound)
+    }
+
+    /// Adds `connection` under `name` and makes it the active one, returning `DuplicateConnection` if the name is
+    /// taken by another open connection
+    pub fn open(&mut self, name: &str, connection: Connection) -> Result<(), PGError> {
+        if self.position(name).is_ok() {
+            return Err(PGError::DuplicateConnection);
+        }
+        self.connections.push((name.to_string(), connection));
+        self.active = self.connections.len() - 1;
+        Ok(())
+    }
+
+    /// Replaces the active connection with `connection`, e.g. a saved connection loaded with option 8, naming it
+    /// `name` unless another open connection already has that name
+    pub fn replace_active(&mut self, name: &str, connection: Connection) {
+        let mut previous = std::mem::replace(&mut self.connections[self.active].1, connection);
+        self.closed_queries += previous.queries;
+        previous.close();
+        if self.position(name).is_err() {
+            self.connections[self.active].0 = name.to_string();
+        }
+    }
+
+    /// Makes the connection named `name` the active one, or returns `MatchNotFound`
+    pub fn switch(&mut self, name: &str) -> Result<(), PGError> {
+        self.active = self.position(name)?;
+        Ok(())
+    }
+
+    /// Closes the connection named `name`, rolling back a transaction it has open. Closing the active connection
+    /// makes the one opened before it active. <br>
+    /// Returns `MatchNotFound` if there is no such connection and `LastOpenConnection` for the only one left.
+    pub fn close(&mut self, name: &str) -> Result<(), PGError> {
+        let index = self.position(name)?;
+        if self.connections.len() == 1 {
+            return Err(PGError::LastOpenConnection);
+        }
+        let (_, mut closed) = self.connections.remove(index);
+        self.closed_queries += closed.queries;
+        closed.close();
+        if self.active >= index && self.active > 0 {
+            self.active -= 1;
+        }
+        Ok(())
+    }
+
+    /// Lists the open connections, one row each with its name, whether it is the active one, what it is connected to
+    /// and whether it is connected or has a transaction open
+    pub fn list(&self) -> Value {
+        let rows: Vec<Value> = self
+            .connections
+            .iter()
+            .enumerate()
+            .map(|(i, (name, c))| {
+                let status = match (&c.client, c.transaction) {
+                    (Some(client), _) if client.is_closed() => "not connected",
+                    (None, _) => "not connected",
+                    (Some(_), TransactionState::Idle) => "connected",
+                    (Some(_), TransactionState::Open) => "in transaction",
+                    (Some(_), TransactionState::Failed) => "in failed transaction",
+                };
+                json!({
+                    "name": name,
+                    "active": if i == self.active { "*" } else { "" },
+                    "connection": c.label(),
+                    "status": status,
+                })
+            })
+            .collect();
+        Value::Array(rows)
+    }
+
+    /// Queries run on every connection opened in the menu, whether or not it is still open
+    pub fn queries(&self) -> u64 {
+        self.closed_queries + self.connections.iter().map(|(_, c)| c.queries).sum::<u64>()
+    }
+
+    /// Closes every open connection, telling the server the session ends rather than leaving it to notice the
+    /// connection dropped, which rolls back any transaction still open
+    pub fn close_all(&mut self) {
+        for (_, connection) in &mut self.connections {
+            connection.close();
+        }
+    }
+
+    /// Names of the open connections with a transaction open, which is rolled back when they are closed
+    pub fn open_transactions(&self) -> Vec<&str> {
+        self.connections
+            .iter()
+            .filter(|(_, c)| c.transaction != TransactionState::Idle)
+            .map(|(name, _)| name.as_str())
+            .collect()
+    }
+}
@@ -0,0 +1,118 @@
+use std::time::Instant;
+
+use chrono::Local;
+use serde_json::{json, Value};
+
+use super::collectors::{Collector, COLLECTORS};
+use super::delta::changes;
+use super::humanize::human_duration;
+use super::{Connection, PGError};
+
+/// A bookmark set with `mark <label>`: when and on which connection it was set, and a sample of every collector with
+/// cumulative counters, which `since <label>` measures the counters against
+struct Mark {
+    label: String,
+    /// `Connection::label` of the connection the samples were taken on
+    connection: String,
+    all_databases: bool,
+    taken_at: Instant,
+    /// Local time the mark was set, for listing the marks
+    set_at: String,
+    samples: Vec<(&'static Collector, Value)>,
+}
+
+/// The marks set in this session, e.g. before starting a batch job, kept until the program exits
+#[derive(Default)]
+pub struct Marks {
+    marks: Vec<Mark>,
+}
+
+impl Marks {
+    /// Samples the collectors with cumulative counters, see `sample_counters`, and keeps them as `label`, replacing
+    /// an earlier mark with the same label. <br>
+    /// Returns how many collectors were sampled.
+    pub fn set(&mut self, label: &str, connection: &mut Connection, all_databases: bool) -> Result<usize, PGError> {
+        let samples = sample_counters(connection, all_databases)?;
+        let count = samples.len();
+        self.marks.retain(|m| m.label != label);
+        self.marks.push(Mark {
+            label: label.to_string(),
+            connection: connection.label(),
+            all_databases,
+            taken_at: Instant::now(),
+            set_at: Local::now().format("%Y-%m-%d %H:%M:%S").to_string(),
+            samples,
+        });
+        Ok(count)
+    }
+
+    /// Samples the collectors of the mark `label` again, or only `collector`, and returns a heading along with how
+    /// much their counters grew since the mark, for each of them. <br>
+    /// Returns `MatchNotFound` if there is no such mark and `MarkOnOtherConnection` if it was set on another
+    /// connection than `connection`.
+    pub fn since(
+        &self,
+        label: &str,
+        connection: &mut Connection,
+        collector: Option<&Collector>,
+    ) -> Result<Vec<(String, Value)>, PGError> {
+        let mark = self.marks.iter().find(|m| m.label == label).ok_or(PGError::MatchNotFound)?;
+        if mark.connection != connection.label() {
+            return Err(PGError::MarkOnOtherConnection);
+        }
+
+        let elapsed = human_duration(mark.taken_at.elapsed().as_secs_f64());
+        let mut results = Vec::new();
+        for (marked, before) in &mark.samples {
+            if collector.is_some_and(|c| c.name != marked.name) {
+                continue;
+            }
+            let delta = match marked.delta.as_ref() {
+                Some(delta) => delta,
+                None => continue,
+            };
+            let now = connection.collect(marked, mark.all_databases)?;
+            let heading = format!("{} since {} ({} ago)", marked.name, mark.label, elapsed);
+            results.push((heading, changes(before, &now, delta)));
+        }
+        Ok(results)
+    }
+
+    /// Lists the marks, one row each with its label, the connection it was set on and when
+    pub fn list(&self) -> Value {
+        self.marks
+            .iter()
+            .map(|m| {
+                json!({
+                    "label": m.label,
+                    "connection": m.connection,
+                    "set_at": m.set_at,
+                    "age": human_duration(m.taken_at.elapsed().as_secs_f64()),
+                })
+            })
+            .collect()
+    }
+}
+
+/// Samples every collector with cumulative counters (see `Delta`), in every database with `all_databases`. Collectors
+/// the role may not run are left out. <br>
+/// Returns the error of the first collector when none could be sampled.
+pub(crate) fn sample_counters(
+    connection: &mut Connection,
+    all_databases: bool,
+) -> Result<Vec<(&'static Collector, Value)>, PGError> {
+    let mut samples = Vec::new();
+    let mut first_error = None;
+    for collector in COLLECTORS.iter().filter(|c| c.delta.is_some()) {
+        match connection.collect(collector, all_databases) {
+            Ok(rows) => samples.push((collector, rows)),
+            Err(e) => {
+                first_error.get_or_insert(e);
+            }
+        }
+    }
+    match (samples.is_empty(), first_error) {
+        (true, Some(e)) => Err(e),
+        _ => Ok(samples),
+    }
+}
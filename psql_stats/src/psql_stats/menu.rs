@@ -0,0 +1,1960 @@
+use std::io;
+use std::io::Write;
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+use colored::Colorize;
+use rustyline::error::ReadlineError;
+use rustyline::history::DefaultHistory;
+use rustyline::{CompletionType, Editor};
+use serde_json::{json, Value};
+use tracing::error;
+
+use super::alerts::Notifier;
+use super::backends::Signal;
+use super::capture::render_capture;
+use super::collectors::{find_collector, recorded_sample, run_collector, sample_rates};
+use super::commands::run_profile_collectors;
+use super::compare::{compare_profiles, diff_query_profiles, highlight_differences};
+use super::completion::MenuHelper;
+use super::config::Config;
+use super::constraints::render_constraint_report;
+use super::dashboard::watch_dashboard;
+use super::describe::render_description;
+use super::export::EXPORT_FORMATS;
+use super::healthcheck::Thresholds;
+use super::history::History;
+use super::humanize::parse_duration;
+use super::latency::LatencyHistogram;
+use super::locks::blocking_tree;
+use super::manager::ConnectionManager;
+use super::marks::Marks;
+use super::metrics::{metric_trends, record_report_metrics, render_trends};
+use super::monitor::{HealthMonitor, LinkStatus};
+use super::monitor_role::{monitor_role_sql, DEFAULT_MONITOR_ROLE};
+use super::output::OutputFormatter;
+use super::pager::print_paged;
+use super::plans::render_explain;
+use super::pools::render_pool_advice;
+use super::privileges::{monitor_hint, Privilege};
+use super::profiles::{delete_profile, edit_profile, list_profiles, rename_profile, EDITABLE_FIELDS};
+use super::queries::QueryArgs;
+use super::report::{render_report, write_report, Anonymizer};
+use super::resets::{StatsReset, RESET_TARGETS};
+use super::rows::{format_count, format_elapsed};
+use super::saturation::render_saturation;
+use super::script::read_script;
+use super::sequences::render_sequences;
+use super::session::Session;
+use super::settings::{highlight_changed_settings, highlight_file_settings};
+use super::snapshots::{diff_snapshot, save_snapshot};
+use super::spills::render_spill_report;
+use super::state::{backup_state, restore_state};
+use super::style::warning_sign;
+use super::transaction::TransactionState;
+use super::{help_menu, highlight_cache_hit_ratio, Connection, PGError, TableScope};
+
+/// What the command line chose for the interactive menu besides the configuration, see `Menu::new`
+#[derive(Default, Clone, Debug)]
+pub struct MenuOptions {
+    /// Run per-database collectors in every database, as `--all-databases` does
+    pub all_databases: bool,
+    /// Show the samples saved in the history instead of connecting, as `--offline` does
+    pub offline: bool,
+    /// Keep the passwords of saved connections in the system keyring, as `--use-keyring` does
+    pub use_keyring: bool,
+    /// Standbys `replicas` compares the server with besides the ones given after it, as `--replica` does
+    pub replicas: Vec<String>,
+}
+
+/// The interactive menu the program starts when not given a command: a prompt reading options and commands, with
+/// line editing and completion, that runs them on the active one of the connections open in it. <br>
+/// `run` shows the prompt until the user exits, while `execute` runs a single line, so another program can drive the
+/// menu with lines of its own.
+pub struct Menu {
+    /// Several connections can be open at once, options and commands run against the active one
+    manager: ConnectionManager,
+    config: Config,
+    thresholds: Thresholds,
+    history: Option<History>,
+    options: MenuOptions,
+    editor: Editor<MenuHelper, DefaultHistory>,
+    /// File the lines entered are kept in, shared across sessions
+    history_path: PathBuf,
+    marks: Marks,
+    /// Counts what is done in the menu for the summary shown on exit, and ends the menu on SIGTERM
+    session: Session,
+}
+
+impl Menu {
+    /// Sets up the menu on `connection`, called `name` among the connections opened next to it later, with the
+    /// `thresholds` health checks and alerts use. With a `history`, the samples of the collectors run are recorded in
+    /// it, and shown from it when `options` asks for `offline`.
+    pub fn new(
+        name: &str,
+        connection: Connection,
+        config: Config,
+        thresholds: Thresholds,
+        history: Option<History>,
+        options: MenuOptions,
+    ) -> Menu {
+        // Line editing with history shared across sessions, Ctrl-R searches it, and Tab completes commands and names
+        let editor_config = rustyline::Config::builder().completion_type(CompletionType::List).build();
+        let mut editor: Editor<MenuHelper, DefaultHistory> =
+            Editor::with_config(editor_config).expect("Could not initialize the prompt");
+        editor.set_helper(Some(MenuHelper::new()));
+        let history_path = Config::history_path();
+        if history_path.exists() {
+            if let Err(e) = editor.load_history(&history_path) {
+                error!("could not load command history: {}", e);
+            }
+        }
+
+        Menu {
+            manager: ConnectionManager::new(name, connection),
+            config,
+            thresholds,
+            history,
+            options,
+            editor,
+            history_path,
+            marks: Marks::default(),
+            session: Session::start(),
+        }
+    }
+
+    /// Runs the menu until the user exits: shows a summary of the server and what the connection was saved to show,
+    /// then reads lines at the prompt and runs them, see `execute`. <br>
+    /// On exit, rolls back the open transactions, closes every connection and shows a summary of the session.
+    pub fn run(mut self) {
+        // Start with a one screen summary of the server, the options and commands are a help away
+        let connection = self.manager.active_mut();
+        match connection.startup_summary(&self.thresholds) {
+            Ok(summary) => {
+                println!("\n{}\n", summary);
+                println!("Type help for the options and commands, or summary to see this again.\n");
+            }
+            Err(_) => help_menu(&connection.privileges, &connection.capabilities),
+        }
+
+        // Show what the loaded connection was saved to show, e.g. replication for a standby
+        let (all_databases, offline) = (self.options.all_databases, self.options.offline);
+        run_profile_collectors(connection, &self.config, all_databases, self.history.as_mut(), offline);
+
+        // Check the connection in the background so the prompt never waits on the network
+        let monitor = HealthMonitor::spawn(Duration::from_secs(5));
+        let mut interrupted = false;
+
+        loop {
+            if self.session.terminated() {
+                println!("Terminated, exiting...");
+                break;
+            }
+            let label = match self.manager.several_open() {
+                true => format!("{} ", format!("[{}]", self.manager.active_name()).cyan().bold()),
+                false => String::new(),
+            };
+            let open = self.manager.names();
+            let connection = self.manager.active_mut();
+            monitor.watch(connection);
+            if let Some(helper) = self.editor.helper_mut() {
+                helper.follow(connection, open);
+            }
+            print!("Connection status: ");
+            match connection.client {
+                None if self.options.offline => {
+                    println!("{}", "Offline (showing saved data)".yellow().bold());
+                }
+                Some(ref c) if !c.is_closed() => match monitor.status() {
+                    LinkStatus::Down => {
+                        println!("{}", "Not Connected (server unreachable)".red().bold());
+                    }
+                    LinkStatus::Up | LinkStatus::Unknown => {
+                        let session = [("search_path", &connection.search_path), ("time zone", &connection.timezone)];
+                        let details: Vec<String> = session
+                            .iter()
+                            .filter(|(_, value)| !value.is_empty())
+                            .map(|(name, value)| format!("{}: {}", name, value))
+                            .collect();
+                        match details.is_empty() {
+                            true => println!("{}", "Connected".green().bold()),
+                            false => println!("{} ({})", "Connected".green().bold(), details.join(", ")),
+                        }
+                    }
+                },
+                Some(_) => {
+                    println!("{}", "Not Connected".red().bold());
+                }
+                None => {
+                    println!("{}", "Not Connected".red());
+                }
+            }
+
+            // Like psql's =*> and =!>, the prompt shows a transaction is open, or that a statement in it failed
+            connection.refresh_transaction();
+            let prompt = match connection.transaction {
+                TransactionState::Idle => format!("{}Please enter an option: ", label),
+                TransactionState::Open => format!("{}{} Please enter an option: ", label, "[transaction]".yellow().bold()),
+                TransactionState::Failed => {
+                    format!("{}{} Please enter an option: ", label, "[failed transaction]".red().bold())
+                }
+            };
+            self.session.prompting(true);
+            let read = self.editor.readline(&prompt);
+            self.session.prompting(false);
+            let input = match read {
+                Ok(line) => line,
+                // SIGTERM wakes the prompt up as Ctrl-C does
+                Err(ReadlineError::Interrupted) if self.session.terminated() => continue,
+                // Ctrl-C clears the line and exits when pressed twice in a row, Ctrl-D exits like option 0
+                Err(ReadlineError::Interrupted) if interrupted => {
+                    println!("Exiting...");
+                    break;
+                }
+                Err(ReadlineError::Interrupted) => {
+                    println!("(Press Ctrl-C again, Ctrl-D or enter 0 to exit)");
+                    interrupted = true;
+                    continue;
+                }
+                Err(ReadlineError::Eof) => {
+                    println!("Exiting...");
+                    break;
+                }
+                Err(e) => {
+                    error!("{}", e);
+                    break;
+                }
+            };
+            if !input.trim().is_empty() && self.editor.add_history_entry(input.as_str()).unwrap_or(false) {
+                let saved = std::fs::create_dir_all(Config::directory())
+                    .map_err(ReadlineError::from)
+                    .and_then(|_| self.editor.append_history(&self.history_path));
+                if let Err(e) = saved {
+                    error!("could not save command history: {}", e);
+                }
+            }
+
+            interrupted = false;
+            if !self.execute(&input) {
+                break;
+            }
+        }
+
+        // Like psql, quitting with an open transaction rolls it back, the server does so when the connection closes
+        for name in self.manager.open_transactions() {
+            match self.manager.several_open() {
+                true => {
+                    let warning = format!("{} The open transaction of {} was rolled back", warning_sign(), name);
+                    println!("{}", warning.yellow().bold())
+                }
+                false => println!("{}", format!("{} The open transaction was rolled back", warning_sign()).yellow().bold()),
+            }
+        }
+        self.manager.close_all();
+        if let Some(h) = self.history.as_ref() {
+            if let Err(e) = h.sync() {
+                error!("could not write the history file: {}", e);
+            }
+        }
+        println!("{}", self.session.summary(self.manager.queries()));
+    }
+
+    /// The connection options and commands run on, the one opened last or switched to
+    pub fn active(&self) -> &Connection {
+        self.manager.active()
+    }
+
+    /// Runs `input`, an option or command with its arguments as typed at the prompt, on the active connection and
+    /// prints what it shows, asking at the prompt for what is missing or for confirmation. <br>
+    /// Returns false when `input` asks to exit, e.g. `0` or `quit`.
+    pub fn execute(&mut self, input: &str) -> bool {
+        let Menu {
+            manager,
+            config,
+            thresholds,
+            history,
+            options,
+            editor,
+            marks,
+            session,
+            ..
+        } = self;
+        let connection = manager.active_mut();
+
+        let mut words = input.split_whitespace();
+        let command = words.next().unwrap_or("");
+        let arguments: Vec<&str> = words.collect();
+        if !matches!(command, "" | "0" | "quit" | "exit") {
+            session.command_run();
+        }
+
+        // Offline, collectors show their most recent sample in the history and everything else is unavailable
+        if let Some(h) = history.as_ref().filter(|_| options.offline) {
+            if !matches!(command, "0" | "quit" | "exit" | "\\pset" | "pset") {
+                let name = match command {
+                    "2" => "uptime",
+                    "3" => "version",
+                    "4" => "tables",
+                    "5" => "extensions",
+                    other => other,
+                };
+                match find_collector(name) {
+                    Some(collector) => match recorded_sample(h, &connection.label(), collector) {
+                        Ok((heading, rows)) => {
+                            let formatter = OutputFormatter::new(config);
+                            println!("{}", formatter.titled(&heading, formatter.rows(&rows)));
+                        }
+                        Err(e) => {
+                            error!("{}", e);
+                        }
+                    },
+                    None => println!(
+                        "{} is not available offline, type the name of a collector to see its saved data.",
+                        command
+                    ),
+                }
+                return true;
+            }
+        }
+
+        // A connection the server dropped, e.g. by restarting, is made again before the command runs on it
+        let local = matches!(command, "" | "0" | "quit" | "exit" | "help" | "7" | "reconnect" | "\\pset" | "pset");
+        if !options.offline && !local {
+            connection.reconnect_if_dropped(config.reconnect_attempts);
+        }
+
+        match command {
+            // Exit program
+            "0" | "quit" | "exit" => {
+                println!("Exiting...");
+                return false;
+            }
+
+            // Save connection, asking for the name and the collectors unless given after the option
+            "1" | "save" => {
+                let mut conn_name_input: String = String::new();
+                let mut collectors_input = String::new();
+                match arguments.split_first() {
+                    Some((name, collectors)) => {
+                        conn_name_input = name.to_string();
+                        collectors_input = collectors.join(" ");
+                    }
+                    None => {
+                        println!("Please enter the what you wish to name this connection.");
+                        io::stdin()
+                            .read_line(&mut conn_name_input)
+                            .expect("Could not read input");
+
+                        println!(
+                            "Which collectors should run whenever this connection is loaded? Separate them with commas,"
+                        );
+                        println!("e.g. replication, activity, or leave it empty for none.");
+                        io::stdin()
+                            .read_line(&mut collectors_input)
+                            .expect("Could not read input");
+                    }
+                }
+                let conn_name = conn_name_input.trim();
+                connection.collectors = collectors_input
+                    .split(',')
+                    .map(|name| name.trim())
+                    .filter(|name| !name.is_empty())
+                    .filter(|name| match find_collector(name) {
+                        Some(_) => true,
+                        None => {
+                            println!("Skipping {}, there is no collector by that name.", name);
+                            false
+                        }
+                    })
+                    .map(|name| name.to_string())
+                    .collect();
+
+                match connection.write_to_json(conn_name.to_string(), options.use_keyring) {
+                    Ok(b) => {
+                        if b {
+                            println!("Successfully saved your connection.")
+                        }
+                    }
+
+                    Err(e) => {
+                        error!("{}", e);
+                    }
+                }
+            }
+
+            // Display how long the server has been running, and whether it restarted since the last time we looked
+            "2" | "uptime" => match connection.get_uptime() {
+                Ok(uptime) => {
+                    let formatter = OutputFormatter::new(config);
+                    println!("{}", formatter.titled("Uptime", formatter.rows(&uptime.to_json())));
+                    if let Some(h) = history.as_mut() {
+                        if uptime.record(h, &connection.label()) == Some(true) {
+                            println!(
+                                "{}",
+                                format!(
+                                    "{} The server has restarted since the previous uptime sample in the history",
+                                    warning_sign()
+                                )
+                                .yellow()
+                                .bold()
+                            );
+                        }
+                    }
+                }
+                Err(e) => {
+                    error!("{}", e);
+                }
+            },
+
+            // Display current running version of postgres
+            "3" | "version" => match connection.version() {
+                Ok(row) => match row.try_get::<_, String>(0) {
+                    Ok(v) => {
+                        let formatter = OutputFormatter::new(config);
+                        println!("{}", formatter.titled("Current running version", formatter.rows(&json!([{ "version": v }]))));
+                    }
+                    Err(e) => {
+                        error!("{}", e);
+                    }
+                },
+                Err(e) => {
+                    error!("{}", e);
+                }
+            },
+
+            // Display the tables in the search_path, or in one schema, or in every schema grouped by schema
+            "4" | "tables" => {
+                let scope = match arguments.first() {
+                    Some(&"--all") => TableScope::All,
+                    Some(schema) => TableScope::Schema(schema),
+                    None => TableScope::SearchPath,
+                };
+                let heading = match scope {
+                    TableScope::SearchPath => format!("Tables (search_path: {})", connection.search_path),
+                    TableScope::Schema(schema) => format!("Tables in {}", schema),
+                    TableScope::All => "Tables in".to_string(),
+                };
+                let all = matches!(scope, TableScope::All);
+                match connection.get_tables(scope) {
+                    Ok(rows) => {
+                        let formatter = OutputFormatter::new(config);
+                        match all {
+                            true => println!("{}", formatter.grouped(&heading, &rows, "schema")),
+                            false => println!("{}", formatter.titled(&heading, formatter.rows(&rows))),
+                        }
+                    }
+                    Err(e) => {
+                        error!("{}", e);
+                    }
+                }
+            }
+
+            // List the schemas of the database with their owner, tables and size
+            "schemas" => match connection.get_schemas() {
+                Ok(rows) => {
+                    let formatter = OutputFormatter::new(config);
+                    println!("{}", formatter.titled("Schemas", formatter.rows(&rows)));
+                }
+                Err(e) => error!("{}", e),
+            },
+
+            // Display all extensions
+            "5" | "extensions" => match connection.get_extensions() {
+                Ok(rows) => {
+                    let extensions: Vec<Value> = rows
+                        .iter()
+                        .filter_map(|row| {
+                            Some(json!({
+                                "name": row.try_get::<_, String>("name").ok()?,
+                                "installed_version": row.try_get::<_, Option<String>>("installed_version").unwrap_or(None),
+                                "default_version": row.try_get::<_, Option<String>>("default_version").unwrap_or(None),
+                            }))
+                        })
+                        .collect();
+                    let formatter = OutputFormatter::new(config);
+                    println!("{}", formatter.titled("Installed extensions", formatter.rows(&Value::Array(extensions))));
+                }
+                Err(e) => {
+                    error!("{}", e);
+                }
+            },
+
+            // Run a query typed after the option, or else entered on the following lines up to a semicolon
+            "6" => {
+                let mut query = input.trim().trim_start_matches('6').trim().to_string();
+                let mut prompt = "query> ";
+                while prompt == "   ...> " && !query.ends_with(';') || query.is_empty() {
+                    match editor.readline(prompt) {
+                        // An empty line ends the query too, and cancels it when nothing was typed
+                        Ok(line) if line.trim().is_empty() => break,
+                        Ok(line) => {
+                            query += "\n";
+                            query += line.trim_end();
+                            prompt = "   ...> ";
+                        }
+                        Err(_) => break,
+                    }
+                }
+                let query = query.trim().trim_end_matches(';').trim();
+                if query.is_empty() {
+                    return true;
+                }
+                let _ = editor.add_history_entry(query);
+
+                if config.precheck_queries {
+                    match connection.precheck_query(query) {
+                        Ok(warnings) => {
+                            for warning in warnings {
+                                println!(
+                                    "{}",
+                                    format!("{} {}", warning_sign(), warning.describe(&connection.user)).yellow().bold()
+                                );
+                            }
+                        }
+                        Err(e) => error!("{}", e),
+                    }
+                }
+                if config.confirm_destructive {
+                    let statements = connection.destructive_statements(query).unwrap_or_default();
+                    for statement in &statements {
+                        println!("{}", format!("{} {}", warning_sign(), statement.describe()).red().bold());
+                    }
+                    let confirmed = statements.is_empty()
+                        || match editor.readline("Run it anyway? [y/N] ") {
+                            Ok(answer) => ["y", "yes"].contains(&answer.trim().to_lowercase().as_str()),
+                            Err(_) => false,
+                        };
+                    if !confirmed {
+                        println!("Nothing was done.");
+                        return true;
+                    }
+                }
+                let result = match config.on_error_rollback {
+                    true => connection.protected_query(query, &[], config.table.max_rows),
+                    false => connection.custom_query(query, &[], config.table.max_rows),
+                };
+                match result {
+                    Ok(result) => print_paged(&OutputFormatter::new(config).query_result(&result), &config.table),
+                    Err(e) => error!("{}", e),
+                }
+            }
+
+            // Run a query with values bound to its placeholders, or a saved query, or save one, or export its rows,
+            // `\export <file> ...` being short for `query --export <file> ...`
+            "query" | "\\export" => match QueryArgs::parse(&match command {
+                "\\export" => format!("--export {}", input.trim().trim_start_matches("\\export")),
+                _ => input.trim().trim_start_matches("query").to_string(),
+            }) {
+                Ok(QueryArgs {
+                    save: Some(name),
+                    sql,
+                    ..
+                }) => {
+                    config.queries.insert(name.clone(), sql);
+                    match config.save() {
+                        Ok(_) => println!("Saved the query as {}, run it with: query [--param <value> ...] {}", name, name),
+                        Err(e) => error!("{}", e),
+                    }
+                }
+                Ok(QueryArgs {
+                    params, export, sql, ..
+                }) => {
+                    let query = config.queries.get(&sql).cloned().unwrap_or(sql);
+                    if config.precheck_queries {
+                        if let Ok(warnings) = connection.precheck_query(&query) {
+                            for warning in warnings {
+                                println!(
+                                    "{}",
+                                    format!("{} {}", warning_sign(), warning.describe(&connection.user)).yellow().bold()
+                                );
+                            }
+                        }
+                    }
+                    if config.confirm_destructive {
+                        let statements = connection.destructive_statements(&query).unwrap_or_default();
+                        for statement in &statements {
+                            println!("{}", format!("{} {}", warning_sign(), statement.describe()).red().bold());
+                        }
+                        let confirmed = statements.is_empty()
+                            || match editor.readline("Run it anyway? [y/N] ") {
+                                Ok(answer) => ["y", "yes"].contains(&answer.trim().to_lowercase().as_str()),
+                                Err(_) => false,
+                            };
+                        if !confirmed {
+                            println!("Nothing was done.");
+                            return true;
+                        }
+                    }
+                    if let Some(path) = export {
+                        let format = match path.ends_with(".parquet") {
+                            true => "parquet",
+                            false => "csv",
+                        };
+                        match connection.export_query(&query, &params, format, &path) {
+                            Ok(rows) => println!("Exported {} rows to {}.", rows, path),
+                            Err(e) => error!("{}", e),
+                        }
+                        return true;
+                    }
+                    let result = match config.on_error_rollback {
+                        true => connection.protected_query(&query, &params, config.table.max_rows),
+                        false => connection.custom_query(&query, &params, config.table.max_rows),
+                    };
+                    match result {
+                        Ok(result) => print_paged(&OutputFormatter::new(config).query_result(&result), &config.table),
+                        Err(e) => error!("{}", e),
+                    }
+                }
+                Err(e) => {
+                    error!("{}", e);
+                    println!("Usage: query [--param <value> | --null ...] <sql | saved query name>");
+                    println!("       query --save <name> <sql>");
+                    println!("       \\export <file> [--param <value> | --null ...] <sql | saved query name>");
+                }
+            },
+
+            // Attempt to reestablish connection
+            "7" | "reconnect" => {
+                connection.connect_or_prompt();
+            }
+
+            // Load a connection, asking for its name unless given after the option
+            "8" | "load" => {
+                let mut connection_name = "".to_string();
+                match arguments.first() {
+                    Some(name) => connection_name = name.to_string(),
+                    None => {
+                        print!("Connection name: ");
+                        io::stdout().flush().expect("Could not flush");
+
+                        io::stdin()
+                            .read_line(&mut connection_name)
+                            .expect("Could not read input.");
+
+                        println!("Entered: {}", connection_name.trim());
+                    }
+                }
+
+                let name = connection_name.trim().to_string();
+                match Connection::read_from_json(&name, String::new(), options.use_keyring) {
+                    Ok(mut loaded) => {
+                        // Saved connections have no password unless kept in the keyring, otherwise it is taken from
+                        // PGPASSWORD or ~/.pgpass, or asked for without echoing it when the server wants one
+                        println!("Connection found.");
+                        loaded.connect_or_prompt();
+                        manager.replace_active(&name, loaded);
+                        let connection = manager.active_mut();
+                        run_profile_collectors(connection, config, options.all_databases, history.as_mut(), false);
+                    }
+                    Err(e) => {
+                        error!("{}", e);
+                    }
+                }
+            }
+
+            // List, delete, rename or edit the saved connections
+            "connections" => match list_profiles() {
+                Ok(rows) => {
+                    let formatter = OutputFormatter::new(config);
+                    println!("{}", formatter.titled("Saved connections", formatter.rows(&rows)));
+                }
+                Err(e) => error!("{}", e),
+            },
+
+            "delete-connection" => match arguments.first() {
+                Some(name) => {
+                    let confirmed = match editor.readline(&format!("Delete the saved connection {}? [y/N] ", name)) {
+                        Ok(answer) => ["y", "yes"].contains(&answer.trim().to_lowercase().as_str()),
+                        Err(_) => false,
+                    };
+                    match confirmed {
+                        true => match delete_profile(name) {
+                            Ok(_) => println!("Deleted the saved connection {}", name),
+                            Err(e) => error!("{}", e),
+                        },
+                        false => println!("Nothing was done."),
+                    }
+                }
+                None => println!("Usage: delete-connection <name> (connections lists them)"),
+            },
+
+            "rename-connection" => match (arguments.first(), arguments.get(1)) {
+                (Some(name), Some(new_name)) => match rename_profile(name, new_name) {
+                    Ok(_) => println!("Renamed the saved connection {} to {}", name, new_name),
+                    Err(e) => error!("{}", e),
+                },
+                _ => println!("Usage: rename-connection <name> <new name>"),
+            },
+
+            "edit-connection" => match (arguments.first(), arguments.get(1)) {
+                (Some(name), Some(field)) => {
+                    let value = arguments[2..].join(" ");
+                    match edit_profile(name, field, &value) {
+                        Ok(_) => println!("Set {} of the saved connection {} to \"{}\"", field, name, value),
+                        Err(e) => error!("{}", e),
+                    }
+                }
+                _ => {
+                    println!("Usage: edit-connection <name> <field> [value]");
+                    println!("Fields: {}", EDITABLE_FIELDS.join(", "));
+                }
+            },
+
+            // Open another connection next to this one and make it the active one
+            "open" => {
+                let (target, name) = match arguments.as_slice() {
+                    [target] => (*target, *target),
+                    [target, "as", name] => (*target, *name),
+                    _ => {
+                        println!("Usage: open <saved connection | host[:port]> [as <name>]");
+                        return true;
+                    }
+                };
+                let opened = match Connection::read_from_json(target, String::new(), options.use_keyring) {
+                    Ok(mut saved) => {
+                        saved.connect_or_prompt();
+                        saved
+                    }
+                    // Another server, with the credentials of the active connection
+                    Err(_) => connection.dashboard_peer(target),
+                };
+                if opened.client.is_none() {
+                    return true;
+                }
+                match manager.open(name, opened) {
+                    Ok(_) => println!("Opened {} ({}), switch back with switch <name>", name, manager.active().label()),
+                    Err(e) => error!("{}", e),
+                }
+            }
+
+            // Run the following options and commands against another open connection
+            "switch" => match arguments.first() {
+                Some(name) => match manager.switch(name) {
+                    Ok(_) => println!("Switched to {} ({})", name, manager.active().label()),
+                    Err(e) => error!("{}, list-open shows the open connections", e),
+                },
+                None => println!("Usage: switch <name>"),
+            },
+
+            // Close one of the open connections
+            "close" => match arguments.first() {
+                Some(name) => match manager.close(name) {
+                    Ok(_) => println!("Closed {}, {} is active", name, manager.active_name()),
+                    Err(e) => error!("{}", e),
+                },
+                None => println!("Usage: close <name>"),
+            },
+
+            // List the open connections, marking the active one
+            "list-open" => {
+                let formatter = OutputFormatter::new(config);
+                println!("{}", formatter.titled("Open connections", formatter.rows(&manager.list())));
+            }
+
+            // Compare two saved connections, e.g. staging with production
+            "compare" => {
+                let all = arguments.contains(&"--all");
+                let names: Vec<&str> = arguments.iter().copied().filter(|a| *a != "--all").collect();
+                let (first, second) = match names.as_slice() {
+                    [first, second] => (*first, *second),
+                    _ => {
+                        println!("Usage: compare <saved connection> <saved connection> [--all]");
+                        return true;
+                    }
+                };
+                match compare_profiles(first, second, options.use_keyring, all) {
+                    Ok(mut rows) => {
+                        if config.output_format == "table" {
+                            highlight_differences(&mut rows);
+                        }
+                        let formatter = OutputFormatter::new(config);
+                        let heading = format!("Differences between {} and {}", first, second);
+                        println!("{}", formatter.titled(&heading, formatter.rows(&rows)));
+                    }
+                    Err(e) => error!("{}", e),
+                }
+            }
+
+            // Run the same query on two saved connections and show the rows that differ
+            "diff-query" => {
+                let mut rest = input.trim().trim_start_matches("diff-query").trim();
+                let mut names = Vec::new();
+                let (mut key, mut all) = (None, false);
+                while let Some((word, tail)) = rest.split_once(char::is_whitespace) {
+                    match word {
+                        "--all" => all = true,
+                        "--key" => match tail.trim_start().split_once(char::is_whitespace) {
+                            Some((column, tail)) => {
+                                key = Some(column);
+                                rest = tail.trim_start();
+                                continue;
+                            }
+                            None => break,
+                        },
+                        _ if names.len() < 2 => names.push(word),
+                        _ => break,
+                    }
+                    rest = tail.trim_start();
+                }
+                let sql = rest.trim().trim_matches('"').trim();
+                let (first, second) = match names.as_slice() {
+                    [first, second] if !sql.is_empty() => (*first, *second),
+                    _ => {
+                        println!("Usage: diff-query <saved connection> <saved connection> [--key column] [--all] <sql>");
+                        return true;
+                    }
+                };
+                let query = config.queries.get(sql).map(|q| q.as_str()).unwrap_or(sql);
+                match diff_query_profiles(first, second, query.trim().trim_end_matches(';'), key, options.use_keyring, all) {
+                    Ok(mut rows) => {
+                        let count = rows.as_array().map(|r| r.len()).unwrap_or(0);
+                        if config.output_format == "table" {
+                            highlight_differences(&mut rows);
+                        }
+                        let formatter = OutputFormatter::new(config);
+                        let heading = format!("Rows differing between {} and {}", first, second);
+                        println!("{}", formatter.titled(&heading, formatter.rows(&rows)));
+                        if count == 0 && config.output_format == "table" && !all {
+                            println!("{}", "Both return the same rows.".green());
+                        }
+                    }
+                    Err(e) => error!("{}", e),
+                }
+            }
+
+            // Move the configuration and saved connections between workstations
+            "state" => match (arguments.first(), arguments.get(1..).unwrap_or_default().join(" ")) {
+                (Some(&"backup"), file) if !file.is_empty() => match backup_state(&file) {
+                    Ok(count) => println!("Wrote the configuration and {} saved connection(s) to {}", count, file),
+                    Err(e) => error!("{}", e),
+                },
+                (Some(&"restore"), file) if !file.is_empty() => {
+                    let question = "Replace the configuration and add the saved connections of the archive? [y/N] ";
+                    let confirmed = match editor.readline(question) {
+                        Ok(answer) => ["y", "yes"].contains(&answer.trim().to_lowercase().as_str()),
+                        Err(_) => false,
+                    };
+                    match confirmed {
+                        true => match restore_state(&file) {
+                            Ok(count) => println!(
+                                "Restored the configuration, which applies from the next start, and {} saved \
+                                 connection(s) from {}",
+                                count, file
+                            ),
+                            Err(e) => error!("{}", e),
+                        },
+                        false => println!("Nothing was done."),
+                    }
+                }
+                _ => println!("Usage: state backup <file> | state restore <file>"),
+            },
+
+            // Display the sessions connected to the server and what they are running
+            "9" => {
+                let width = match arguments.first().map(|w| w.parse::<usize>()) {
+                    None => config.activity_query_width,
+                    Some(Ok(width)) => width,
+                    Some(Err(_)) => {
+                        println!("Usage: 9 [query width, 0 for whole queries]");
+                        return true;
+                    }
+                };
+                match connection.get_activity(width) {
+                    Ok(rows) => {
+                        if connection.privileges.limited(Privilege::Monitor) {
+                            println!("{}", monitor_hint(&connection.user).yellow());
+                        }
+                        let formatter = OutputFormatter::new(config);
+                        println!("{}", formatter.titled("Sessions connected to the server", formatter.rows(&rows)));
+                    }
+                    Err(e) => {
+                        error!("{}", e);
+                    }
+                }
+            }
+
+            // Display the size, row estimate and scans of every table, largest first
+            "10" => match connection.get_table_stats() {
+                Ok(rows) => {
+                    let formatter = OutputFormatter::new(config);
+                    println!("{}", formatter.titled("Table statistics", formatter.rows(&rows)));
+                }
+                Err(e) => {
+                    error!("{}", e);
+                }
+            },
+
+            // Display the indexes worth cleaning up: unused, duplicate or bloated
+            "11" => match connection.get_index_stats() {
+                Ok(rows) => {
+                    let formatter = OutputFormatter::new(config);
+                    println!("{}", formatter.titled("Index statistics", formatter.rows(&rows)));
+                }
+                Err(e) => {
+                    error!("{}", e);
+                }
+            },
+
+            // Display when the tables were last vacuumed and analyzed, and their dead tuples
+            "13" => match connection.get_vacuum_stats() {
+                Ok(rows) => {
+                    let formatter = OutputFormatter::new(config);
+                    println!("{}", formatter.titled("Vacuum statistics", formatter.rows(&rows)));
+                }
+                Err(e) => {
+                    error!("{}", e);
+                }
+            },
+
+            // Browse the server settings, those whose name contains the filter given, as in show settings like wal
+            "14" | "show" => {
+                let filter = match (command, arguments.as_slice()) {
+                    ("14", []) | ("show", ["settings"]) => None,
+                    ("14", [filter]) | ("show", ["settings", "like", filter]) => Some(*filter),
+                    _ => {
+                        println!("Usage: 14 [text] | show settings [like <text>]");
+                        return true;
+                    }
+                };
+                match connection.get_settings(filter) {
+                    Ok(mut rows) => {
+                        if config.output_format == "table" {
+                            highlight_changed_settings(&mut rows);
+                        }
+                        let formatter = OutputFormatter::new(config);
+                        println!("{}", formatter.titled("Server settings", formatter.rows(&rows)));
+                    }
+                    Err(e) => {
+                        error!("{}", e);
+                    }
+                }
+            }
+
+            // Display the transactions, cache hit ratio, tuples, temp files and deadlocks of every database
+            "12" => match connection.get_database_stats() {
+                Ok(mut rows) => {
+                    if config.output_format == "table" {
+                        highlight_cache_hit_ratio(&mut rows);
+                    }
+                    let formatter = OutputFormatter::new(config);
+                    println!("{}", formatter.titled("Database statistics", formatter.rows(&rows)));
+                }
+                Err(e) => {
+                    error!("{}", e);
+                }
+            },
+
+            // Poll the lock waits for a while and rank the relations sessions kept waiting on
+            "locks" if arguments.first() == Some(&"--sample") => {
+                let seconds = arguments
+                    .get(1)
+                    .and_then(|s| s.parse::<u64>().ok())
+                    .unwrap_or(30);
+                let interval = arguments
+                    .get(2)
+                    .and_then(|s| s.parse::<u64>().ok())
+                    .unwrap_or(200);
+
+                println!("Sampling lock waits for {}s, every {}ms...", seconds, interval);
+                match connection.sample_locks(Duration::from_secs(seconds), Duration::from_millis(interval)) {
+                    Ok((_, rows)) if rows.as_array().is_some_and(|r| r.is_empty()) => {
+                        println!("No session waited on a relation or row lock while sampling.")
+                    }
+                    Ok((polls, rows)) => {
+                        let heading = format!("Relations sessions waited on, in {} polls", polls);
+                        let formatter = OutputFormatter::new(config);
+                        println!("{}", formatter.titled(&heading, formatter.rows(&rows)));
+                    }
+                    Err(e) => error!("{}", e),
+                }
+            }
+
+            // Show who blocks whom, as a tree of the sessions waiting on locks below the ones holding them
+            "locks" => match connection.get_locks(config.activity_query_width) {
+                Ok(rows) if rows.as_array().is_some_and(|r| r.is_empty()) => {
+                    println!("No session is waiting on a lock.")
+                }
+                Ok(rows) => {
+                    let formatter = OutputFormatter::new(config);
+                    if config.output_format == "table" {
+                        println!("Blocking tree:");
+                        for line in blocking_tree(&rows) {
+                            println!("  {}", line);
+                        }
+                        println!();
+                    }
+                    let heading = "Sessions waiting on locks and blocking them";
+                    println!("{}", formatter.titled(heading, formatter.rows(&rows)));
+                }
+                Err(e) => error!("{}", e),
+            },
+
+            // Stop the query of a session, or end the session, once the user confirms it is the right one
+            "cancel" | "terminate" => {
+                let signal = match command {
+                    "cancel" => Signal::Cancel,
+                    _ => Signal::Terminate,
+                };
+                let pid = match arguments.first().map(|p| p.parse::<i32>()) {
+                    Some(Ok(pid)) => pid,
+                    _ => {
+                        println!("Usage: {} <pid> (option 9 lists the sessions with their pid)", command);
+                        return true;
+                    }
+                };
+                match connection.describe_backend(pid, config.activity_query_width) {
+                    Ok(session) => {
+                        let formatter = OutputFormatter::new(config);
+                        println!("{}", formatter.rows(&session));
+                        let confirmed = match editor.readline(&format!("{} [y/N] ", signal.question())) {
+                            Ok(answer) => ["y", "yes"].contains(&answer.trim().to_lowercase().as_str()),
+                            Err(_) => false,
+                        };
+                        if !confirmed {
+                            println!("Nothing was done.");
+                            return true;
+                        }
+                        match connection.signal_backend(pid, signal) {
+                            Ok(_) => println!("{}", signal.done(pid)),
+                            Err(e) => error!("{}", e),
+                        }
+                    }
+                    Err(e) => error!("{}", e),
+                }
+            }
+
+            // Reconnect to another database on the same server
+            "connect" | "\\c" => match arguments.first() {
+                Some(dbname) => match connection.switch_database(dbname) {
+                    Ok(_) => println!("Now connected to database \"{}\".", dbname),
+                    Err(e) => error!("{}", e),
+                },
+                None => println!("Usage: connect <dbname>"),
+            },
+
+            // Drill down into the columns of a table
+            "columns" => match arguments.first() {
+                Some(table) => match connection.get_columns(table) {
+                    Ok((comment, columns)) => {
+                        let heading = match comment {
+                            Some(c) => format!("Table {} - {}", table, c),
+                            None => format!("Table {} (no comment)", table),
+                        };
+                        let formatter = OutputFormatter::new(config);
+                        println!("{}", formatter.titled(&heading, formatter.rows(&columns)));
+                    }
+                    Err(e) => error!("{}", e),
+                },
+                None => println!("Usage: columns <table>"),
+            },
+
+            // Describe a table with its columns, indexes and constraints
+            "describe" | "\\d" => match arguments.first() {
+                Some(table) => match connection.describe_table(table) {
+                    Ok(description) => println!("{}", render_description(&description, config)),
+                    Err(e) => error!("{}", e),
+                },
+                None => println!("Usage: describe <table>"),
+            },
+
+            // Print the statements that would recreate an object
+            "show-create" => match arguments.first() {
+                // Function signatures contain spaces, e.g. add(integer, integer)
+                Some(_) => match connection.show_create(&arguments.join(" ")) {
+                    Ok(statements) => {
+                        for statement in statements {
+                            println!("{}\n", statement);
+                        }
+                    }
+                    Err(e) => error!("{}", e),
+                },
+                None => println!("Usage: show-create <object>"),
+            },
+
+            // Show the statements that took the most time, with the WAL, JIT and I/O timings the server reports
+            "statements" => {
+                let limit = match arguments.first().map(|n| n.parse::<i64>()) {
+                    None => 20,
+                    Some(Ok(limit)) if limit > 0 => limit,
+                    Some(_) => {
+                        println!("Usage: statements [limit]");
+                        return true;
+                    }
+                };
+                match connection.get_statement_stats(limit, config.activity_query_width) {
+                    Ok((version, rows)) => {
+                        let heading = format!("Statements by total execution time (pg_stat_statements {})", version);
+                        let formatter = OutputFormatter::new(config);
+                        println!("{}", formatter.titled(&heading, formatter.rows(&rows)));
+                    }
+                    Err(e) => error!("{}", e),
+                }
+            }
+
+            // List the functions and procedures, of one schema if given
+            "functions" => match connection.get_routines(arguments.first().copied()) {
+                Ok(rows) => {
+                    let heading = match arguments.first() {
+                        Some(schema) => format!("Functions and procedures in {}", schema),
+                        None => "Functions and procedures".to_string(),
+                    };
+                    let formatter = OutputFormatter::new(config);
+                    println!("{}", formatter.titled(&heading, formatter.rows(&rows)));
+                }
+                Err(e) => error!("{}", e),
+            },
+
+            // Print the body of a function
+            "function-source" => match arguments.first() {
+                // Function signatures contain spaces, e.g. add(integer, integer)
+                Some(_) => match connection.routine_source(&arguments.join(" ")) {
+                    Ok(sources) => {
+                        for source in sources {
+                            println!("{}\n", source);
+                        }
+                    }
+                    Err(e) => error!("{}", e),
+                },
+                None => println!("Usage: function-source <function>"),
+            },
+
+            // Estimate the rows of a table, or count them when asked to
+            "count" => {
+                let exact = arguments.contains(&"--exact");
+                match arguments.iter().find(|a| !a.starts_with("--")) {
+                    Some(table) if exact => {
+                        println!(
+                            "{}",
+                            format!(
+                                "{} Counting every row of {} reads the whole table, which can take a long time on a big table",
+                                warning_sign(),
+                                table
+                            )
+                            .yellow()
+                            .bold()
+                        );
+                        match connection.count_rows(table) {
+                            Ok((rows, elapsed)) => println!(
+                                "{} rows in {} (exact, took {})",
+                                format_count(rows, config.table.human),
+                                table,
+                                format_elapsed(elapsed, config.table.human)
+                            ),
+                            Err(e) => error!("{}", e),
+                        }
+                    }
+                    Some(table) => match connection.estimate_rows(table) {
+                        Ok(estimate) => println!("{}", estimate.describe(table, config.table.human)),
+                        Err(e) => error!("{}", e),
+                    },
+                    None => println!("Usage: count <table> [--exact]"),
+                }
+            }
+
+            // Show a few rows of a table to see what its data looks like
+            "peek" => match arguments.first() {
+                Some(table) => {
+                    let limit = arguments
+                        .get(1)
+                        .and_then(|s| s.parse::<u32>().ok())
+                        .unwrap_or(10);
+                    match connection.peek(table, i64::from(limit)) {
+                        Ok((rows, sampled)) => {
+                            let formatter = OutputFormatter::new(config);
+                            match sampled {
+                                true => {
+                                    let heading = format!("Random sample of {} rows from {}", limit, table);
+                                    println!("{}", formatter.titled(&heading, formatter.rows(&rows)));
+                                }
+                                false => println!("{}", formatter.rows(&rows)),
+                            }
+                        }
+                        Err(e) => error!("{}", e),
+                    }
+                }
+                None => println!("Usage: peek <table> [n]"),
+            },
+
+            // List the roles with their attributes and memberships
+            "roles" => match connection.get_roles() {
+                Ok(rows) => {
+                    let formatter = OutputFormatter::new(config);
+                    println!("{}", formatter.titled("Roles", formatter.rows(&rows)));
+                }
+                Err(e) => error!("{}", e),
+            },
+
+            // List the privileges granted on tables, of one schema if given
+            "grants" => match connection.get_table_grants(arguments.first().copied()) {
+                Ok(rows) => {
+                    let heading = match arguments.first() {
+                        Some(schema) => format!("Table privileges in {}", schema),
+                        None => "Table privileges".to_string(),
+                    };
+                    let formatter = OutputFormatter::new(config);
+                    println!("{}", formatter.titled(&heading, formatter.rows(&rows)));
+                }
+                Err(e) => error!("{}", e),
+            },
+
+            // Preview what DROP ROLE would complain about
+            "role-deps" => match arguments.first() {
+                Some(role) => match connection.role_dependencies(role) {
+                    Ok(rows) => {
+                        let formatter = OutputFormatter::new(config);
+                        let heading = format!("Objects depending on role \"{}\"", role);
+                        println!("{}", formatter.titled(&heading, formatter.rows(&rows)));
+                        if rows.as_array().map(|r| !r.is_empty()).unwrap_or(false) {
+                            println!(
+                                "Run REASSIGN OWNED BY {} TO <new owner> and DROP OWNED BY {} in each database listed before dropping the role.",
+                                role, role
+                            );
+                        }
+                    }
+                    Err(e) => error!("{}", e),
+                },
+                None => println!("Usage: role-deps <role>"),
+            },
+
+            // Show which statistics extensions the database has, probing them again
+            "capabilities" => match connection.capabilities_summary() {
+                Ok(summary) => println!("{}", summary),
+                Err(e) => error!("{}", e),
+            },
+
+            // Print, or run with --execute, the SQL creating a role that runs every collector without superuser
+            "generate-monitor-role" => {
+                let name = arguments.iter().find(|a| !a.starts_with("--")).copied().unwrap_or(DEFAULT_MONITOR_ROLE);
+                if arguments.contains(&"--execute") {
+                    match connection.create_monitor_role(name) {
+                        Ok(_) => println!("Role {} is ready, set its password with \\password {} in psql.", name, name),
+                        Err(e) => error!("{}", e),
+                    }
+                } else {
+                    match connection.databases() {
+                        Ok(databases) => print!("{}", monitor_role_sql(name, &databases)),
+                        Err(e) => error!("{}", e),
+                    }
+                }
+            }
+
+            // Sample round-trip latency and render a histogram
+            "latency" => {
+                let samples = arguments
+                    .first()
+                    .and_then(|s| s.parse::<usize>().ok())
+                    .unwrap_or(50);
+                let interval = arguments
+                    .get(1)
+                    .and_then(|s| s.parse::<u64>().ok())
+                    .unwrap_or(100);
+
+                println!("Sampling {} round trips...", samples);
+                match connection.sample_latency(samples, Duration::from_millis(interval)) {
+                    Ok(timings) => {
+                        println!("Round-trip latency:");
+                        println!("{}", LatencyHistogram::from_durations(&timings).render());
+                    }
+                    Err(e) => {
+                        error!("{}", e);
+                    }
+                }
+            }
+
+            // Collect every statistic and health check into a single JSON or YAML report
+            "report" => {
+                let anonymize = arguments.contains(&"--anonymize");
+                let yaml = arguments.contains(&"--yaml");
+                let path = arguments.iter().find(|a| !a.starts_with("--"));
+
+                let mut report = connection.report(options.all_databases, thresholds);
+                if let Err(e) = record_report_metrics(config, &connection.label(), &report) {
+                    error!("{}", e);
+                }
+                if anonymize {
+                    Anonymizer::default().anonymize(&mut report);
+                }
+
+                match path {
+                    Some(p) => match write_report(&report, yaml, Some(p)) {
+                        Ok(p) => println!("Report written to {}", p),
+                        Err(e) => error!("{}", e),
+                    },
+                    None => print_paged(&render_report(&report, if yaml { "yaml" } else { "json" }), &config.table),
+                }
+            }
+
+            // Show the trends of the key metrics the reports recorded, of this or a saved connection
+            "history" => {
+                let samples = arguments.iter().find_map(|a| a.parse::<usize>().ok()).unwrap_or(20);
+                let label = match arguments.iter().find(|a| a.parse::<usize>().is_err()) {
+                    Some(name) => Connection::read_from_json(name, String::new(), false).map(|c| c.label()),
+                    None => Ok(connection.label()),
+                };
+                let trends = label.and_then(|label| match config.metrics_db.as_deref() {
+                    Some(path) => metric_trends(path, &label, samples),
+                    None => Err(PGError::NoRecordedMetrics),
+                });
+                match trends {
+                    Ok(trends) => println!("{}", render_trends(&trends, config)),
+                    Err(e) => error!("{}", e),
+                }
+            }
+
+            // Compare the plan of a query with the one recorded last time, and record it
+            "plan-diff" => {
+                let rest = input.trim().trim_start_matches("plan-diff").trim();
+                let (back, sql) = match rest.strip_prefix("--back") {
+                    Some(r) => {
+                        let (back, sql) = r.trim_start().split_once(char::is_whitespace).unwrap_or((r.trim(), ""));
+                        (back.parse::<usize>().ok(), sql.trim())
+                    }
+                    None => (Some(1), rest),
+                };
+                match (back, history.as_mut()) {
+                    (Some(back), Some(h)) if !sql.is_empty() => {
+                        let query = config.queries.get(sql).map(|q| q.as_str()).unwrap_or(sql);
+                        match connection.plan_diff(h, query, back) {
+                            Ok(diff) => println!("{}", diff),
+                            Err(e) => error!("{}", e),
+                        }
+                    }
+                    (Some(_), None) if !sql.is_empty() => error!("{}", PGError::HistoryRequired),
+                    _ => println!("Usage: plan-diff [--back <n>] <sql | saved query name>"),
+                }
+            }
+
+            // Show the plan of a query with what every node took
+            "explain" => {
+                let rest = input.trim().trim_start_matches("explain").trim();
+                let (analyze, sql) = match rest.strip_prefix("--no-analyze") {
+                    Some(sql) => (false, sql.trim()),
+                    None => (true, rest),
+                };
+                if sql.is_empty() {
+                    println!("Usage: explain [--no-analyze] <sql | saved query name>");
+                    return true;
+                }
+                let query = config.queries.get(sql).map(|q| q.as_str()).unwrap_or(sql);
+                let plan = match analyze {
+                    true => connection.explain_analyze(query),
+                    false => connection.explain(query),
+                };
+                match plan {
+                    Ok(plan) => println!("{}", render_explain(&plan)),
+                    Err(e) => error!("{}", e),
+                }
+            }
+
+            // Show how many partitions the plan of a query scans and how many it prunes
+            "pruning" => {
+                let sql = input.trim().trim_start_matches("pruning").trim();
+                if sql.is_empty() {
+                    println!("Usage: pruning <sql | saved query name>");
+                    return true;
+                }
+                let query = config.queries.get(sql).map(|q| q.as_str()).unwrap_or(sql);
+                match connection.partition_pruning(query) {
+                    Ok(rows) => {
+                        let formatter = OutputFormatter::new(config);
+                        println!("{}", formatter.titled("Partition pruning", formatter.rows(&rows)));
+                    }
+                    Err(e) => error!("{}", e),
+                }
+            }
+
+            // Display non-default settings, or the per role and per database overrides
+            "settings" => {
+                let option = |flag: &str| {
+                    arguments
+                        .iter()
+                        .position(|a| *a == flag)
+                        .and_then(|i| arguments.get(i + 1).copied())
+                };
+                let role = option("--role");
+                let database = option("--database");
+
+                let overrides = role.is_some() || database.is_some() || arguments.contains(&"--overrides");
+
+                // Changes that have not been applied yet are easy to miss in the table, call them out first
+                if !overrides {
+                    if let Ok(pending) = connection.pending_restart() {
+                        if !pending.is_empty() {
+                            println!(
+                                "{}",
+                                format!(
+                                    "{} Changed settings waiting for a server restart: {}",
+                                    warning_sign(),
+                                    pending.join(", ")
+                                )
+                                .yellow()
+                                .bold()
+                            );
+                        }
+                    }
+                }
+
+                let result = if overrides {
+                    connection
+                        .setting_overrides(role, database)
+                        .map(|rows| ("Settings set per role or per database".to_string(), rows))
+                } else {
+                    run_collector(
+                        connection,
+                        find_collector("settings").expect("settings collector is registered"),
+                        options.all_databases,
+                        history.as_mut(),
+                    )
+                };
+
+                match result {
+                    Ok((heading, rows)) => {
+                        let formatter = OutputFormatter::new(config);
+                        println!("{}", formatter.titled(&heading, formatter.rows(&rows)));
+                    }
+                    Err(e) => {
+                        error!("{}", e);
+                    }
+                }
+            }
+
+            // Display the configuration file entries, highlighting errors and overridden values
+            "config-file" => {
+                let start = Instant::now();
+                match run_collector(
+                    connection,
+                    find_collector("config-file").expect("config-file collector is registered"),
+                    false,
+                    history.as_mut(),
+                ) {
+                    Ok((heading, mut rows)) => {
+                        highlight_file_settings(&mut rows);
+                        let formatter = OutputFormatter::new(config);
+                        println!("{}", formatter.titled(&heading, formatter.timed_rows(&rows, start.elapsed())));
+                    }
+                    Err(e) => {
+                        error!("{}", e);
+                    }
+                }
+            }
+
+            // Compare the server with its replicas
+            "replicas" => {
+                let mut standbys = options.replicas.clone();
+                standbys.extend(arguments.iter().map(|a| a.to_string()));
+
+                match connection.replicas(&standbys) {
+                    Ok(nodes) => {
+                        if connection.privileges.limited(Privilege::Monitor) {
+                            println!("{}", monitor_hint(&connection.user).yellow());
+                        }
+                        let formatter = OutputFormatter::new(config);
+                        println!("{}", formatter.titled("Replicas", formatter.rows(&nodes)));
+                    }
+                    Err(e) => {
+                        error!("{}", e);
+                    }
+                }
+            }
+
+            // Show the size of every database and tablespace, largest first
+            "sizes" => {
+                let formatter = OutputFormatter::new(config);
+                match connection.get_database_sizes() {
+                    Ok(rows) => println!("{}", formatter.titled("Databases by size", formatter.rows(&rows))),
+                    Err(e) => error!("{}", e),
+                }
+                match connection.get_tablespace_sizes() {
+                    Ok(rows) => println!("{}", formatter.titled("Tablespaces by size", formatter.rows(&rows))),
+                    Err(e) => error!("{}", e),
+                }
+            }
+
+            // Compare the connections in use with max_connections, by state and by user and database
+            "saturation" => match connection.connection_saturation() {
+                Ok(report) => println!("{}", render_saturation(&report, config, thresholds)),
+                Err(e) => error!("{}", e),
+            },
+
+            // List the sequences of integer columns by how much of their values is used up
+            "sequences" => match connection.get_sequence_usage() {
+                Ok(rows) => println!("{}", render_sequences(&rows, config, thresholds)),
+                Err(e) => error!("{}", e),
+            },
+
+            // List the constraints never validated, the foreign keys without an index and the invalid indexes
+            "constraints" => match connection.constraint_report() {
+                Ok(report) => println!("{}", render_constraint_report(&report, config)),
+                Err(e) => error!("{}", e),
+            },
+
+            // Show the temporary files written by every database and the statements spilling the most
+            "spills" => match connection.spill_report() {
+                Ok(report) => println!("{}", render_spill_report(&report, config)),
+                Err(e) => error!("{}", e),
+            },
+
+            // Recommend pool sizes from the connection states recorded in the history
+            "pool-advice" => match history.as_ref().map(|h| connection.pool_advice(h)) {
+                Some(Ok(advice)) => println!("{}", render_pool_advice(&advice, config)),
+                Some(Err(e)) => error!("{}", e),
+                None => error!("{}", PGError::NoRecordedSample),
+            },
+
+            // List the replication slots with the WAL they retain
+            "slots" => match connection.get_replication_slots() {
+                Ok(rows) => {
+                    let formatter = OutputFormatter::new(config);
+                    println!("{}", formatter.titled("Replication slots", formatter.rows(&rows)));
+                    let inactive = rows.as_array().into_iter().flatten().any(|r| r["active"] == false);
+                    if inactive {
+                        println!("{}", "Drop an inactive slot no longer needed with drop-slot <slot_name>.".yellow());
+                    }
+                }
+                Err(e) => error!("{}", e),
+            },
+
+            // Drop an inactive replication slot, after showing it and confirming
+            "drop-slot" => match arguments.first() {
+                Some(name) => match connection.describe_slot(name) {
+                    Ok(slot) => {
+                        let formatter = OutputFormatter::new(config);
+                        println!("{}", formatter.rows(&slot));
+                        if slot[0]["active"] == true {
+                            error!("{}", PGError::SlotActive);
+                            return true;
+                        }
+                        println!(
+                            "{}",
+                            "Its consumer, a standby or a logical replication subscriber, will not be able to resume and \
+                             has to be rebuilt or resynchronized."
+                                .yellow()
+                                .bold()
+                        );
+                        let question = format!("Drop the replication slot {}? [y/N] ", name);
+                        let confirmed = match editor.readline(&question) {
+                            Ok(answer) => ["y", "yes"].contains(&answer.trim().to_lowercase().as_str()),
+                            Err(_) => false,
+                        };
+                        if !confirmed {
+                            println!("Nothing was done.");
+                            return true;
+                        }
+                        match connection.drop_slot(name) {
+                            Ok(_) => println!("Dropped the replication slot {}, the WAL it retained can be removed.", name),
+                            Err(e) => error!("{}", e),
+                        }
+                    }
+                    Err(e) => error!("{}", e),
+                },
+                None => println!("Usage: drop-slot <slot_name> (slots lists them)"),
+            },
+
+            // Sample a collector twice and show the per-second rates in between
+            "delta" => match arguments.first().and_then(|name| find_collector(name)) {
+                Some(collector) if collector.delta.is_none() => {
+                    println!("Collector {} has no cumulative counters to compute rates for.", collector.name)
+                }
+                Some(collector) => {
+                    let seconds = arguments
+                        .get(1)
+                        .and_then(|s| s.parse::<u64>().ok())
+                        .unwrap_or(5);
+
+                    println!("Sampling {} twice, {}s apart...", collector.name, seconds);
+                    match sample_rates(connection, collector, options.all_databases, seconds) {
+                        Some(Ok((heading, rows))) => {
+                            let formatter = OutputFormatter::new(config);
+                            println!("{}", formatter.titled(&heading, formatter.rows(&rows)));
+                        }
+                        Some(Err(e)) => {
+                            error!("{}", e);
+                        }
+                        None => {}
+                    }
+                }
+                None => println!("Usage: delta <collector> [seconds]"),
+            },
+
+            // Remember the cumulative counters, e.g. before starting a batch job, or list what was remembered
+            "mark" => match arguments.first() {
+                Some(label) => match marks.set(label, connection, options.all_databases) {
+                    Ok(count) => println!("Marked {} collector(s) as {}, see since {}", count, label, label),
+                    Err(e) => error!("{}", e),
+                },
+                None => {
+                    let formatter = OutputFormatter::new(config);
+                    println!("{}", formatter.titled("Marks", formatter.rows(&marks.list())));
+                }
+            },
+
+            // Show how much the counters grew since a mark
+            "since" => {
+                let collector = match arguments.get(1) {
+                    None => None,
+                    Some(name) => match find_collector(name) {
+                        Some(collector) if collector.delta.is_some() => Some(collector),
+                        _ => {
+                            println!("Collector {} has no cumulative counters to compare with a mark.", name);
+                            return true;
+                        }
+                    },
+                };
+                match arguments.first() {
+                    Some(label) => match marks.since(label, connection, collector) {
+                        Ok(results) => {
+                            let formatter = OutputFormatter::new(config);
+                            for (heading, rows) in results {
+                                println!("{}", formatter.titled(&heading, formatter.rows(&rows)));
+                            }
+                        }
+                        Err(e) => error!("{}, mark lists the marks", e),
+                    },
+                    None => println!("Usage: since <label> [collector]"),
+                }
+            }
+
+            // Zero the cumulative statistics before a measurement window, once the user confirms it
+            "reset-stats" => match arguments.first().and_then(|name| StatsReset::from_name(name)) {
+                Some(reset) => {
+                    let confirmed = match editor.readline(&format!("{} [y/N] ", reset.question())) {
+                        Ok(answer) => ["y", "yes"].contains(&answer.trim().to_lowercase().as_str()),
+                        Err(_) => false,
+                    };
+                    if !confirmed {
+                        println!("Nothing was done.");
+                        return true;
+                    }
+                    match connection.reset_stats(reset) {
+                        Ok(_) => println!("{}", reset.done()),
+                        Err(e) => error!("{}", e),
+                    }
+                }
+                None => println!("Usage: reset-stats <{}>", RESET_TARGETS.join("|")),
+            },
+
+            // Write the cumulative counters to a file, or show how much they grew since one was written
+            "snapshot" => {
+                let per_second = arguments.contains(&"--rates");
+                let rest: Vec<&str> = arguments.iter().copied().filter(|a| *a != "--rates").collect();
+                match rest.as_slice() {
+                    ["save", file] => match save_snapshot(connection, options.all_databases, file) {
+                        Ok(count) => println!("Wrote the counters of {} collector(s) to {}", count, file),
+                        Err(e) => error!("{}", e),
+                    },
+                    ["diff", file, names @ ..] if names.len() <= 1 => {
+                        let collector = match names.first() {
+                            None => None,
+                            Some(name) => match find_collector(name) {
+                                Some(collector) if collector.delta.is_some() => Some(collector),
+                                _ => {
+                                    println!("Collector {} has no cumulative counters to compare with a snapshot.", name);
+                                    return true;
+                                }
+                            },
+                        };
+                        match diff_snapshot(connection, file, collector, per_second) {
+                            Ok(results) => {
+                                let formatter = OutputFormatter::new(config);
+                                for (heading, rows) in results {
+                                    println!("{}", formatter.titled(&heading, formatter.rows(&rows)));
+                                }
+                            }
+                            Err(e) => error!("{}", e),
+                        }
+                    }
+                    _ => println!("Usage: snapshot save <file> | snapshot diff <file> [collector] [--rates]"),
+                }
+            }
+
+            // Show how often checkpoints happen, and warn when max_wal_size cuts them short
+            "checkpoints" => {
+                let seconds = match arguments.first().map(|s| s.parse::<u64>()) {
+                    None => None,
+                    Some(Ok(seconds)) if seconds > 0 => Some(seconds),
+                    Some(_) => {
+                        println!("Usage: checkpoints [seconds]");
+                        return true;
+                    }
+                };
+                if let Some(seconds) = seconds {
+                    println!("Sampling the checkpoint counters twice, {}s apart...", seconds);
+                }
+                match connection.get_checkpoint_stats(seconds) {
+                    Ok(stats) => {
+                        let heading = match seconds {
+                            Some(_) => "Checkpoints over the sampled period",
+                            None => "Checkpoints since the statistics were reset",
+                        };
+                        let formatter = OutputFormatter::new(config);
+                        println!("{}", formatter.titled(heading, formatter.rows(&stats.to_json())));
+                        for warning in stats.warnings() {
+                            println!("{}", format!("{} {}", warning_sign(), warning).yellow().bold());
+                        }
+                    }
+                    Err(e) => error!("{}", e),
+                }
+            }
+
+            // Summarize the workload over a window: top query shapes, reads against writes, busiest users and tables
+            "capture" => {
+                let seconds = match arguments.as_slice() {
+                    [] => Some(60),
+                    ["--duration", duration] => parse_duration(duration),
+                    _ => None,
+                };
+                let seconds = match seconds {
+                    Some(seconds) => seconds,
+                    None => {
+                        println!("Usage: capture [--duration 1m], e.g. --duration 90, 30s, 5m or 1h");
+                        return true;
+                    }
+                };
+                println!("Capturing the workload for {}s...", seconds);
+                match connection.capture_workload(seconds, config.activity_query_width) {
+                    Ok(report) => println!("{}", render_capture(&report, config)),
+                    Err(e) => error!("{}", e),
+                }
+            }
+
+            // Show the WAL generated and the buffers written by checkpoints, the background writer and backends
+            "wal" => {
+                let seconds = match arguments.first().map(|s| s.parse::<u64>()) {
+                    None => None,
+                    Some(Ok(seconds)) if seconds > 0 => Some(seconds),
+                    Some(_) => {
+                        println!("Usage: wal [seconds]");
+                        return true;
+                    }
+                };
+                let (heading, result) = match seconds {
+                    Some(seconds) => {
+                        println!("Sampling the WAL and checkpoint counters twice, {}s apart...", seconds);
+                        ("WAL and checkpoints per second", connection.get_wal_rates(seconds))
+                    }
+                    None => ("WAL and checkpoints since the statistics were reset", connection.get_wal_stats()),
+                };
+                match result {
+                    Ok(rows) => {
+                        let formatter = OutputFormatter::new(config);
+                        println!("{}", formatter.titled(heading, formatter.rows(&rows)));
+                    }
+                    Err(e) => error!("{}", e),
+                }
+            }
+
+            // Watch this connection next to saved connections or other servers, refreshing them together
+            "dashboard" => {
+                let mut interval = config.watch_interval;
+                let mut connections = vec![std::mem::take(connection)];
+                for argument in &arguments {
+                    match argument.parse::<u64>() {
+                        Ok(seconds) => interval = seconds,
+                        Err(_) => {
+                            let peer = connections[0].dashboard_peer(argument);
+                            connections.push(peer);
+                        }
+                    }
+                }
+
+                let mut notifier = Notifier::new(config, thresholds);
+                let interval = Duration::from_secs(interval);
+                if let Err(e) = watch_dashboard(&mut connections, &config.dashboard, interval, notifier.as_mut()) {
+                    error!("{}", e);
+                }
+                let mut connections = connections.into_iter();
+                *connection = connections.next().unwrap_or_default();
+                for mut peer in connections {
+                    peer.close();
+                }
+            }
+
+            // Stream a table out to a CSV or Parquet file
+            "export-table" => {
+                let mut format = "csv";
+                let mut positional = Vec::new();
+                let mut words = arguments.iter();
+                while let Some(word) = words.next() {
+                    match *word {
+                        "--format" => format = words.next().copied().unwrap_or(""),
+                        other => positional.push(other),
+                    }
+                }
+
+                match positional.as_slice() {
+                    [table, rest @ ..] if EXPORT_FORMATS.contains(&format) && rest.len() <= 1 => {
+                        let path = match rest.first() {
+                            Some(p) => p.to_string(),
+                            None => format!("{}.{}", table, format),
+                        };
+                        match connection.export_table(table, format, &path) {
+                            Ok(rows) => println!("Exported {} rows of {} to {}.", rows, table, path),
+                            Err(e) => error!("{}", e),
+                        }
+                    }
+                    _ => println!("Usage: export-table <schema.table> [--format csv|parquet] [file]"),
+                }
+            }
+
+            // Load a CSV file into a table
+            "import-table" => match arguments.as_slice() {
+                [file, table] => match connection.import_table(file, table) {
+                    Ok(summary) => {
+                        println!("Imported {} rows from {} into {}.", summary.imported, file, table);
+                        if !summary.rejected.is_empty() {
+                            println!(
+                                "{} rows were rejected and written to {}.rejected:",
+                                summary.rejected.len(),
+                                file
+                            );
+                            for row in summary.rejected.iter().take(10) {
+                                println!("    line {}: {}", row.line, row.error);
+                            }
+                            if summary.rejected.len() > 10 {
+                                println!("    ...");
+                            }
+                        }
+                    }
+                    Err(e) => error!("{}", e),
+                },
+                _ => println!("Usage: import-table <file> <schema.table>"),
+            },
+
+            // Run the statements of a SQL file one after the other
+            "run-file" => {
+                let continue_on_error = arguments.contains(&"--continue-on-error");
+                let files: Vec<&&str> = arguments.iter().filter(|a| **a != "--continue-on-error").collect();
+                let statements = match files.as_slice() {
+                    [file] => match read_script(file) {
+                        Ok(statements) => statements,
+                        Err(e) => {
+                            error!("{}", e);
+                            return true;
+                        }
+                    },
+                    _ => {
+                        println!("Usage: run-file <file.sql> [--continue-on-error]");
+                        return true;
+                    }
+                };
+                if config.confirm_destructive {
+                    let script: Vec<&str> = statements.iter().map(|s| s.sql.as_str()).collect();
+                    let destructive = connection.destructive_statements(&script.join(";\n")).unwrap_or_default();
+                    for statement in &destructive {
+                        println!("{}", format!("{} {}", warning_sign(), statement.describe()).red().bold());
+                    }
+                    let confirmed = destructive.is_empty()
+                        || match editor.readline("Run the file anyway? [y/N] ") {
+                            Ok(answer) => ["y", "yes"].contains(&answer.trim().to_lowercase().as_str()),
+                            Err(_) => false,
+                        };
+                    if !confirmed {
+                        println!("Nothing was done.");
+                        return true;
+                    }
+                }
+                let summary = connection.run_script(&statements, continue_on_error, config);
+                println!("{}", summary.describe(config.table.human));
+            }
+
+            // Watch running index builds until they finish
+            "index-build" => {
+                let interval = arguments
+                    .first()
+                    .and_then(|s| s.parse::<u64>().ok())
+                    .unwrap_or(config.watch_interval);
+                if connection.privileges.limited(Privilege::Monitor) {
+                    println!("{}", monitor_hint(&connection.user).yellow());
+                }
+                if let Err(e) = connection.watch_index_builds(Duration::from_secs(interval)) {
+                    error!("{}", e);
+                }
+            }
+
+            // Show or change the schemas unqualified table names are looked up in
+            "set" if arguments.first() == Some(&"search_path") => {
+                let schemas = arguments[1..].join(" ");
+                let schemas = schemas.trim_start_matches('=').trim_start();
+                let schemas = schemas.strip_prefix("to ").or(schemas.strip_prefix("TO ")).unwrap_or(schemas);
+                if schemas.is_empty() {
+                    println!("search_path: {}", connection.search_path);
+                    println!("Usage: set search_path <schema>[, <schema> ...] | default");
+                    return true;
+                }
+                match connection.set_search_path(schemas) {
+                    Ok(search_path) => println!("search_path is now {}", search_path),
+                    Err(e) => error!("{}", e),
+                }
+            }
+
+            // Group the following statements into a transaction, to check their effect before committing them
+            "begin" => match connection.begin() {
+                Ok(_) => println!("BEGIN"),
+                Err(e) => error!("{}", e),
+            },
+
+            // Keep the statements run since begin, or undo them
+            "commit" => match connection.commit() {
+                Ok(outcome) => println!("{}", outcome),
+                Err(e) => error!("{}", e),
+            },
+            "rollback" => match connection.rollback() {
+                Ok(_) => println!("ROLLBACK"),
+                Err(e) => error!("{}", e),
+            },
+
+            // Show the summary shown on connecting again
+            "summary" => match connection.startup_summary(thresholds) {
+                Ok(summary) => println!("{}", summary),
+                Err(e) => error!("{}", e),
+            },
+
+            // Show or change how tables are printed for this session
+            "\\pset" | "pset" => match arguments.as_slice() {
+                [] => println!("{}", config.table.describe()),
+                [name, value @ ..] => match config.table.set(name, &value.join(" ")) {
+                    Ok(_) => println!("{}", config.table.describe()),
+                    Err(e) => error!("{}", e),
+                },
+            },
+
+            // Show or change whether collectors and queries are timed, like psql's \timing
+            "\\timing" | "timing" => {
+                config.timing = match arguments.first() {
+                    Some(&"on") => true,
+                    Some(&"off") => false,
+                    None => !config.timing,
+                    Some(_) => {
+                        println!("Usage: \\timing [on|off]");
+                        return true;
+                    }
+                };
+                println!("Timing is {}.", if config.timing { "on" } else { "off" });
+            }
+
+            // Run a collector by name, or show the help menu for anything unknown
+            name => match find_collector(name) {
+                Some(collector) => {
+                    let start = Instant::now();
+                    match run_collector(connection, collector, options.all_databases, history.as_mut()) {
+                        Ok((heading, rows)) => {
+                            if connection.privileges.limited(collector.requires) {
+                                println!("{}", monitor_hint(&connection.user).yellow());
+                            }
+                            let formatter = OutputFormatter::new(config);
+                            let output = formatter.collector_rows(collector.name, &heading, &rows, Some(start.elapsed()));
+                            print_paged(&formatter.titled(&heading, output), &config.table);
+                        }
+                        Err(e) => {
+                            error!("{}", e);
+                        }
+                    }
+                }
+                None => {
+                    help_menu(&connection.privileges, &connection.capabilities);
+                }
+            },
+        }
+        true
+    }
+}
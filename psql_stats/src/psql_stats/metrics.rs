@@ -0,0 +1,206 @@
+use chrono::{Local, TimeZone};
+use rusqlite::params_from_iter;
+use serde_json::{json, Value};
+use tracing::error;
+
+use super::history::unix_now;
+use super::output::OutputFormatter;
+use super::style::glyph;
+use super::{Config, Connection, PGError};
+
+/// The key metrics every report records, the columns of the `samples` table besides the connection and time
+const KEY_METRICS: &[&str] = &[
+    "database_bytes",
+    "connections",
+    "active_connections",
+    "cache_hit_percent",
+    "replication_lag_seconds",
+];
+
+/// Levels a sparkline is drawn with, lowest first
+const SPARKS: &str = "▁▂▃▄▅▆▇█";
+
+/// `SPARKS` in ASCII, see `glyph`
+const ASCII_SPARKS: &str = "_.-~=+*#";
+
+/// Query reading the `KEY_METRICS`: the size of the current database, the client connections and how many are
+/// active, the share of its blocks found in shared buffers, and the replication lag, how far behind the slowest
+/// standby replays on a primary, and how long ago the last replayed transaction committed on a standby
+const KEY_METRICS_QUERY: &str = r#"
+    SELECT pg_database_size(current_database()) AS database_bytes,
+           (SELECT count(*) FROM pg_stat_activity WHERE backend_type = 'client backend') AS connections,
+           (SELECT count(*) FROM pg_stat_activity WHERE backend_type = 'client backend' AND state = 'active')
+               AS active_connections,
+           (SELECT round(100.0 * blks_hit / nullif(blks_hit + blks_read, 0), 2)::float8
+            FROM pg_stat_database
+            WHERE datname = current_database()) AS cache_hit_percent,
+           CASE WHEN pg_is_in_recovery()
+                THEN extract(epoch FROM now() - pg_last_xact_replay_timestamp())
+                ELSE (SELECT extract(epoch FROM max(replay_lag)) FROM pg_stat_replication)
+           END::float8 AS replication_lag_seconds
+"#;
+
+/// Statement creating the table the metrics are kept in, one row per report, unless it exists
+const CREATE_SAMPLES: &str = r#"
+    CREATE TABLE IF NOT EXISTS samples (
+        connection TEXT NOT NULL,
+        taken_at INTEGER NOT NULL,
+        database_bytes INTEGER,
+        connections INTEGER,
+        active_connections INTEGER,
+        cache_hit_percent REAL,
+        replication_lag_seconds REAL
+    );
+    CREATE INDEX IF NOT EXISTS samples_connection_taken_at ON samples (connection, taken_at);
+"#;
+
+impl Connection {
+    /// Reads the `KEY_METRICS` a report records, see `KEY_METRICS_QUERY`
+    pub fn key_metrics(&mut self) -> Result<Value, PGError> {
+        match self.query_json(KEY_METRICS_QUERY)? {
+            Value::Array(mut rows) if !rows.is_empty() => Ok(rows.remove(0)),
+            _ => Err(PGError::UnexpectedResult),
+        }
+    }
+}
+
+/// Prints what SQLite reported and returns `MetricsDbError`
+fn db_error(error: rusqlite::Error) -> PGError {
+    error!("{}", error);
+    PGError::MetricsDbError
+}
+
+/// Opens the metrics database at `path`, creating it and its table if needed
+fn open(path: &str) -> Result<rusqlite::Connection, PGError> {
+    let db = rusqlite::Connection::open(path).map_err(db_error)?;
+    db.execute_batch(CREATE_SAMPLES).map_err(db_error)?;
+    Ok(db)
+}
+
+/// Records `metrics`, as `Connection::key_metrics` reads them, for the connection labelled `label` in the metrics
+/// database at `path`, taken now
+fn record_metrics(path: &str, label: &str, metrics: &Value) -> Result<(), PGError> {
+    let db = open(path)?;
+    let placeholders: Vec<String> = (3..KEY_METRICS.len() + 3).map(|i| format!("?{}", i)).collect();
+    let statement = format!(
+        "INSERT INTO samples (connection, taken_at, {}) VALUES (?1, ?2, {})",
+        KEY_METRICS.join(", "),
+        placeholders.join(", ")
+    );
+    let mut values = vec![json!(label), json!(unix_now())];
+    values.extend(KEY_METRICS.iter().map(|m| metrics[*m].clone()));
+    let values = values.iter().map(|v| match v {
+        Value::String(s) => rusqlite::types::Value::Text(s.clone()),
+        Value::Number(n) if n.is_i64() => rusqlite::types::Value::Integer(n.as_i64().unwrap_or(0)),
+        Value::Number(n) => rusqlite::types::Value::Real(n.as_f64().unwrap_or(0.0)),
+        _ => rusqlite::types::Value::Null,
+    });
+    db.execute(&statement, params_from_iter(values)).map_err(db_error)?;
+    Ok(())
+}
+
+/// Records the `key_metrics` of `report`, see `Connection::report`, for the connection labelled `label` in the
+/// metrics database when `Config::metrics_db` names one. Nothing is recorded when they could not be read.
+pub fn record_report_metrics(config: &Config, label: &str, report: &Value) -> Result<(), PGError> {
+    match config.metrics_db.as_deref() {
+        Some(path) if report["key_metrics"].get("error").is_none() => {
+            record_metrics(path, label, &report["key_metrics"])
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Draws `values` as a sparkline, from the lowest of them to the highest, with a space where one is missing
+fn sparkline(values: &[Option<f64>]) -> String {
+    let known = values.iter().flatten();
+    let min = known.clone().copied().fold(f64::INFINITY, f64::min);
+    let max = known.copied().fold(f64::NEG_INFINITY, f64::max);
+    let sparks: Vec<char> = glyph(SPARKS, ASCII_SPARKS).chars().collect();
+    values
+        .iter()
+        .map(|value| match value {
+            Some(v) if max > min => sparks[((v - min) / (max - min) * (sparks.len() - 1) as f64).round() as usize],
+            Some(_) => sparks[0],
+            None => ' ',
+        })
+        .collect()
+}
+
+/// A metric as a JSON number, whole numbers without a fraction so that sizes and counts read as such
+fn number(value: f64) -> Value {
+    match value.fract() == 0.0 {
+        true => json!(value as i64),
+        false => json!(value),
+    }
+}
+
+/// Reads the last `samples` metrics recorded for the connection labelled `label` in the metrics database at `path`
+/// and sums up every one of the `KEY_METRICS`: its first and last value, lowest and highest, how much it changed and
+/// a sparkline of its trend. <br>
+/// Returns the time of the first and last sample along with the rows, or `NoRecordedMetrics` when nothing was
+/// recorded for the connection.
+pub fn metric_trends(path: &str, label: &str, samples: usize) -> Result<Value, PGError> {
+    let db = open(path)?;
+    let query = format!(
+        "SELECT taken_at, {} FROM samples WHERE connection = ?1 ORDER BY taken_at DESC LIMIT ?2",
+        KEY_METRICS.join(", ")
+    );
+    let mut statement = db.prepare(&query).map_err(db_error)?;
+    let mut rows: Vec<(i64, Vec<Option<f64>>)> = statement
+        .query_map(rusqlite::params![label, samples as i64], |row| {
+            let values = (1..=KEY_METRICS.len()).map(|i| row.get::<_, Option<f64>>(i)).collect::<Result<_, _>>()?;
+            Ok((row.get(0)?, values))
+        })
+        .and_then(|rows| rows.collect::<Result<_, _>>())
+        .map_err(db_error)?;
+    if rows.is_empty() {
+        return Err(PGError::NoRecordedMetrics);
+    }
+    rows.reverse();
+
+    let trends: Vec<Value> = KEY_METRICS
+        .iter()
+        .enumerate()
+        .map(|(i, metric)| {
+            let values: Vec<Option<f64>> = rows.iter().map(|(_, values)| values[i]).collect();
+            let known: Vec<f64> = values.iter().flatten().copied().collect();
+            let first = known.first().copied();
+            let last = known.last().copied();
+            json!({
+                "metric": metric,
+                "first": first.map(number),
+                "last": last.map(number),
+                "min": known.iter().copied().reduce(f64::min).map(number),
+                "max": known.iter().copied().reduce(f64::max).map(number),
+                "change": first.zip(last).map(|(first, last)| number(((last - first) * 100.0).round() / 100.0)),
+                "trend": sparkline(&values),
+            })
+        })
+        .collect();
+
+    let time = |at: i64| Local.timestamp_opt(at, 0).single().map(|t| t.format("%Y-%m-%d %H:%M").to_string());
+    Ok(json!({
+        "connection": label,
+        "samples": rows.len(),
+        "from": time(rows[0].0),
+        "to": time(rows[rows.len() - 1].0),
+        "metrics": trends,
+    }))
+}
+
+/// Renders what `metric_trends` returns: in JSON as one object, otherwise the metrics in a table under a heading
+/// naming the connection and the period
+pub fn render_trends(trends: &Value, config: &Config) -> String {
+    if config.output_format == "json" {
+        return serde_json::to_string_pretty(trends).expect("Failed to serialize the trends");
+    }
+    let heading = format!(
+        "Trends of {} over the last {} reports, {} to {}",
+        trends["connection"].as_str().unwrap_or(""),
+        trends["samples"],
+        trends["from"].as_str().unwrap_or("?"),
+        trends["to"].as_str().unwrap_or("?")
+    );
+    let formatter = OutputFormatter::new(config);
+    formatter.titled(&heading, formatter.rows(&trends["metrics"]))
+}
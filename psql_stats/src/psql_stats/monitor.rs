@@ -0,0 +1,154 @@
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, AtomicU8, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use postgres::{Client, Config};
+use postgres_native_tls::MakeTlsConnector;
+
+use super::Connection;
+
+/// How long a single check may take before the server is considered unreachable
+const CHECK_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// How often the monitor thread wakes up to see whether it was stopped or its target changed
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// The server the monitor checks: the connection string, which tells targets apart, and the TLS connector to connect
+/// with
+pub(crate) type Target = (String, MakeTlsConnector);
+
+/// Reachability of the database server as last seen by the `HealthMonitor`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LinkStatus {
+    /// No check has finished yet since the monitor was started or pointed at another database
+    Unknown,
+    Up,
+    Down,
+}
+
+impl LinkStatus {
+    fn from_u8(value: u8) -> LinkStatus {
+        match value {
+            1 => LinkStatus::Up,
+            2 => LinkStatus::Down,
+            _ => LinkStatus::Unknown,
+        }
+    }
+
+    fn as_u8(self) -> u8 {
+        match self {
+            LinkStatus::Unknown => 0,
+            LinkStatus::Up => 1,
+            LinkStatus::Down => 2,
+        }
+    }
+}
+
+/// Checks in a background thread whether the database server can be reached, so the interactive prompt can show
+/// the connection status without waiting on the network. <br>
+/// The monitor keeps its own connection, since a `Client` cannot be shared with the thread running the menu.
+pub struct HealthMonitor {
+    status: Arc<AtomicU8>,
+    stop: Arc<AtomicBool>,
+    target: Arc<Mutex<Option<Target>>>,
+}
+
+impl HealthMonitor {
+    /// Starts checking the server every `interval`. Nothing is checked until `watch` is called.
+    pub fn spawn(interval: Duration) -> HealthMonitor {
+        let monitor = HealthMonitor {
+            status: Arc::new(AtomicU8::new(LinkStatus::Unknown.as_u8())),
+            stop: Arc::new(AtomicBool::new(false)),
+            target: Arc::new(Mutex::new(None)),
+        };
+
+        let status = Arc::clone(&monitor.status);
+        let stop = Arc::clone(&monitor.stop);
+        let target = Arc::clone(&monitor.target);
+        thread::spawn(move || check_loop(interval, &status, &stop, &target));
+
+        monitor
+    }
+
+    /// Points the monitor at the server and database `connection` uses. <br>
+    /// Cheap to call before every prompt, the monitor only reconnects when the connection parameters changed.
+    pub fn watch(&self, connection: &Connection) {
+        let wanted = connection.client.as_ref().map(|_| connection.connection_string());
+
+        let mut target = self.target.lock().expect("Health monitor lock poisoned");
+        if target.as_ref().map(|t| &t.0) != wanted.as_ref() {
+            // The connection was just made with the same TLS settings, so the connector can be built again
+            *target = wanted.and_then(|w| connection.tls_connector().ok().map(|c| (w, c)));
+            self.status.store(LinkStatus::Unknown.as_u8(), Ordering::Relaxed);
+        }
+    }
+
+    /// Returns the result of the most recent check
+    pub fn status(&self) -> LinkStatus {
+        LinkStatus::from_u8(self.status.load(Ordering::Relaxed))
+    }
+}
+
+impl Drop for HealthMonitor {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+    }
+}
+
+/// Body of the monitor thread: checks the current target every `interval` until `stop` is set
+fn check_loop(
+    interval: Duration,
+    status: &AtomicU8,
+    stop: &AtomicBool,
+    target: &Mutex<Option<Target>>,
+) {
+    let mut checked: Option<String> = None;
+    let mut client: Option<Client> = None;
+    let mut last_check: Option<Instant> = None;
+
+    while !stop.load(Ordering::Relaxed) {
+        let current = target.lock().map(|t| t.clone()).unwrap_or(None);
+
+        // Start over whenever the menu switches to another database
+        if current.as_ref().map(|c| &c.0) != checked.as_ref() {
+            checked = current.as_ref().map(|c| c.0.clone());
+            client = None;
+            last_check = None;
+        }
+
+        let due = last_check.map(|at| at.elapsed() >= interval).unwrap_or(true);
+        if let (Some((connection_string, connector)), true) = (current, due) {
+            let up = check(&connection_string, connector, &mut client);
+            last_check = Some(Instant::now());
+
+            // The target may have changed while checking, only report results for the one still wanted
+            if target.lock().map(|t| t.as_ref().map(|t| &t.0) == checked.as_ref()).unwrap_or(false) {
+                let result = if up { LinkStatus::Up } else { LinkStatus::Down };
+                status.store(result.as_u8(), Ordering::Relaxed);
+            }
+        }
+
+        thread::sleep(POLL_INTERVAL);
+    }
+}
+
+/// Checks whether the server behind `connection_string` answers, reusing `client` when it is still open and
+/// connecting again otherwise
+fn check(connection_string: &str, connector: MakeTlsConnector, client: &mut Option<Client>) -> bool {
+    if client.is_none() {
+        *client = Config::from_str(connection_string)
+            .ok()
+            .and_then(|mut config| config.connect_timeout(CHECK_TIMEOUT).connect(connector).ok());
+    }
+
+    let up = match client {
+        Some(ref mut c) => c.is_valid(CHECK_TIMEOUT).is_ok(),
+        None => false,
+    };
+    if !up {
+        *client = None;
+    }
+    up
+}
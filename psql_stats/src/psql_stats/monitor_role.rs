@@ -0,0 +1,130 @@
+
+use tracing::warn;
+
+use super::import::quote_ident;
+use super::transaction::read_only;
+use super::{Connection, PGError};
+
+/// Name of the role `generate-monitor-role` creates unless given another
+pub const DEFAULT_MONITOR_ROLE: &str = "psql_stats_monitor";
+
+/// Statements creating `role`, skipped when it already exists so they can be run again to bring its grants up to
+/// date, and granting what the whole server shares: `pg_monitor` and `CONNECT` on each of `databases`
+fn server_statements(role: &str, databases: &[String]) -> String {
+    let mut sql = format!(
+        "DO $$\n\
+         BEGIN\n    \
+             CREATE ROLE {role} LOGIN;\n\
+         EXCEPTION WHEN duplicate_object THEN\n    \
+             RAISE NOTICE 'the role already exists, updating its grants';\n\
+         END\n\
+         $$;\n\
+         \n\
+         -- Sessions and their queries, locks, replication, autovacuum, pg_stat_statements and every setting\n\
+         GRANT pg_monitor TO {role};\n",
+        role = role
+    );
+    if !databases.is_empty() {
+        sql += "\n-- --all-databases connects to every database, which PUBLIC may no longer be allowed to\n";
+        for database in databases {
+            sql += &format!("GRANT CONNECT ON DATABASE {} TO {};\n", quote_ident(database), role);
+        }
+    }
+    sql
+}
+
+/// Statements granting `role` read access to `pg_file_settings`, for `config-file`. Every database has its own
+/// `pg_catalog`, so they are run in each one.
+fn database_statements(role: &str) -> String {
+    format!(
+        "GRANT SELECT ON pg_catalog.pg_file_settings TO {role};\n\
+         GRANT EXECUTE ON FUNCTION pg_catalog.pg_show_all_file_settings() TO {role};\n",
+        role = role
+    )
+}
+
+/// Writes the psql script creating `role`, a login role able to run every collector without being a superuser, with
+/// a comment above each grant saying what needs it: `pg_monitor` for sessions, queries, locks, replication and
+/// `pg_stat_statements` of every role, `CONNECT` on each of `databases` for `--all-databases`, and read access to
+/// `pg_file_settings` for `config-file`, granted in each of `databases` in turn with `\connect`. <br>
+/// `role-auth`, which reads password hashes, and the commands reading or changing table contents or other sessions
+/// are left out, with the grants they would need commented out.
+pub fn monitor_role_sql(role: &str, databases: &[String]) -> String {
+    let role = quote_ident(role);
+    let mut sql = format!(
+        "-- Login role for psql_stats, able to run every collector without being a superuser\n\
+         -- Set its password afterwards with \\password {} in psql, so it is not written to the server log\n",
+        role
+    );
+    sql += &server_statements(&role, databases);
+
+    sql += "\n-- config-file: the entries of postgresql.conf and the files it includes, in every database\n";
+    if databases.is_empty() {
+        sql += &database_statements(&role);
+    }
+    for database in databases {
+        sql += &format!("\\connect {}\n", quote_ident(database));
+        sql += &database_statements(&role);
+    }
+
+    sql += &format!(
+        "\n-- role-auth reads password hashes from pg_authid and is left to superusers on purpose.\n\
+         -- peek, count --exact and export-table read table contents, which pg_monitor does not allow. To use them:\n\
+         -- GRANT pg_read_all_data TO {role};  (Postgres 14 and later)\n\
+         -- cancel and terminate stop other roles' sessions. To use them:\n\
+         -- GRANT pg_signal_backend TO {role};\n",
+        role = role
+    );
+    sql
+}
+
+/// Reports why the statements of `monitor_role_sql` failed, `PermissionDenied` when the role connected as may not
+/// create roles or grant `pg_monitor`
+fn execute_error(error: postgres::Error) -> PGError {
+    PGError::query("creating the monitoring role", error)
+}
+
+impl Connection {
+    /// Creates `role`, or updates its grants, as `monitor_role_sql` would: the role and its server wide grants in a
+    /// single transaction, then the grants of `pg_catalog` in every database that accepts connections, reconnecting
+    /// to each in turn. <br>
+    /// Needs a superuser, or a role with `CREATEROLE` that may grant `pg_monitor`, and returns `PermissionDenied`
+    /// otherwise, without changing anything. Databases that cannot be connected to are skipped with a warning.
+    pub fn create_monitor_role(&mut self, role: &str) -> Result<(), PGError> {
+        if read_only() {
+            return Err(PGError::ReadOnly);
+        }
+        let databases = self.databases()?;
+        let role = quote_ident(role);
+
+        let client = match &mut self.client {
+            Some(c) => c,
+            None => return Err(PGError::ClientEmpty),
+        };
+        let mut transaction = client.transaction().map_err(execute_error)?;
+        transaction.batch_execute(&server_statements(&role, &databases)).map_err(execute_error)?;
+        transaction.commit().map_err(execute_error)?;
+
+        let original = self.dbname.clone();
+        for database in &databases {
+            if *database != self.dbname {
+                if let Err(e) = self.switch_database(database) {
+                    warn!("skipping database \"{}\": {}", database, e);
+                    continue;
+                }
+            }
+            let granted = match &mut self.client {
+                Some(c) => c.batch_execute(&database_statements(&role)).map_err(execute_error),
+                None => Err(PGError::ClientEmpty),
+            };
+            if let Err(e) = granted {
+                warn!("could not grant access to pg_file_settings in database \"{}\": {}", database, e);
+            }
+        }
+
+        if self.dbname != original {
+            self.switch_database(&original)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,224 @@
+use std::time::Duration;
+
+use serde_json::{json, Value};
+
+use super::humanize::human_duration;
+use super::schema::{CollectorOutput, SCHEMA_VERSION};
+use super::table::{render_json_rows, render_table, TableOptions};
+use super::{Config, QueryResult};
+
+/// Formats results are printed in: an aligned table for people, JSON for tools like jq, or CSV for spreadsheets
+pub const OUTPUT_FORMATS: &[&str] = &["table", "json", "csv"];
+
+/// Prints results in the output format chosen with `--format` or in the `Config`, so every command gives the same
+/// shape of output for the same format. <br>
+/// Tables follow the `\pset` options and may carry a heading and timing, JSON is pretty printed with any metadata
+/// next to the `rows`, and CSV is only ever a header line followed by the rows, so it can be loaded as is.
+pub struct OutputFormatter<'a> {
+    format: &'a str,
+    options: &'a TableOptions,
+    timing: bool,
+}
+
+impl<'a> OutputFormatter<'a> {
+    pub fn new(config: &'a Config) -> OutputFormatter<'a> {
+        OutputFormatter {
+            format: config.output_format.as_str(),
+            options: &config.table,
+            timing: config.timing,
+        }
+    }
+
+    /// Puts `heading` above `body` in the table format, the other formats are printed without it so they can be
+    /// parsed
+    pub fn titled(&self, heading: &str, body: String) -> String {
+        match self.format {
+            "table" => format!("{}:\n{}", heading, body),
+            _ => body,
+        }
+    }
+
+    /// Renders rows returned by `Connection::query_json`: an aligned table (see `render_json_rows`), pretty printed
+    /// JSON, or CSV with a column per key of the first row
+    pub fn rows(&self, rows: &Value) -> String {
+        match self.format {
+            "json" => serde_json::to_string_pretty(rows).expect("Failed to serialize rows"),
+            "csv" => {
+                let columns: Vec<String> = match rows.as_array().and_then(|r| r.first()).and_then(|r| r.as_object()) {
+                    Some(first) => first.keys().cloned().collect(),
+                    None => Vec::new(),
+                };
+                csv(&columns, rows)
+            }
+            _ => render_json_rows(rows, self.options),
+        }
+    }
+
+    /// Renders `rows` in the table format as one table per value of their `key` column, under `heading` followed by
+    /// that value, dropping the column from the tables. The other formats render them as `rows` does, `key` included.
+    pub fn grouped(&self, heading: &str, rows: &Value, key: &str) -> String {
+        if self.format != "table" {
+            return self.rows(rows);
+        }
+        let mut groups: Vec<(String, Vec<Value>)> = Vec::new();
+        for row in rows.as_array().into_iter().flatten() {
+            let value = row[key].as_str().unwrap_or("").to_string();
+            let mut row = row.clone();
+            if let Some(columns) = row.as_object_mut() {
+                columns.shift_remove(key);
+            }
+            match groups.last_mut() {
+                Some((last, group)) if *last == value => group.push(row),
+                _ => groups.push((value, vec![row])),
+            }
+        }
+        if groups.is_empty() {
+            return self.titled(heading, self.rows(rows));
+        }
+        groups
+            .into_iter()
+            .map(|(value, group)| self.titled(&format!("{} {}", heading, value), self.rows(&Value::Array(group))))
+            .collect::<Vec<String>>()
+            .join("\n")
+    }
+
+    /// Renders rows as `rows` does, along with how long it took to get them when `timing` is on: a `Time: ...` line
+    /// under a table, or a `duration_ms` next to the `rows` in JSON
+    pub fn timed_rows(&self, rows: &Value, elapsed: Duration) -> String {
+        match (self.timing, self.format) {
+            (true, "json") => serde_json::to_string_pretty(&json!({ "rows": rows, "duration_ms": milliseconds(elapsed) }))
+                .expect("Failed to serialize rows"),
+            (true, "table") => format!("{}\n\t{}", self.rows(rows), format_timing(elapsed, self.options.human)),
+            _ => self.rows(rows),
+        }
+    }
+
+    /// Renders the rows of the collector called `name` as `timed_rows` does, or as `rows` does for rows recorded
+    /// earlier, without an `elapsed` time. In JSON they are wrapped in a `CollectorOutput`, whose shape is described
+    /// by the `schema` command.
+    pub fn collector_rows(&self, name: &str, heading: &str, rows: &Value, elapsed: Option<Duration>) -> String {
+        match (self.format, elapsed) {
+            ("json", _) => CollectorOutput {
+                schema_version: SCHEMA_VERSION,
+                collector: name,
+                heading,
+                rows,
+                duration_ms: elapsed.filter(|_| self.timing).map(milliseconds),
+            }
+            .to_json(),
+            (_, Some(elapsed)) => self.timed_rows(rows, elapsed),
+            (_, None) => self.rows(rows),
+        }
+    }
+
+    /// Renders the result of a custom query: its rows as `timed_rows` does, headed by its columns even when there are
+    /// none, or for a statement returning nothing, such as an `UPDATE`, how many rows it changed. <br>
+    /// With the `metadata` option, rows are followed by `result_footer`, and in JSON `row_count`, `truncated` and the
+    /// `columns` with their types are given next to the `rows`.
+    pub fn query_result(&self, result: &QueryResult) -> String {
+        let metadata = self.options.metadata && !result.columns.is_empty();
+        match self.format {
+            "json" => {
+                let mut output = match (result.columns.is_empty(), self.timing || metadata) {
+                    (true, _) => json!({ "rows_affected": result.affected }),
+                    (false, true) => json!({ "rows": result.rows }),
+                    (false, false) => result.rows.clone(),
+                };
+                if metadata {
+                    output["row_count"] = json!(result.affected);
+                    output["truncated"] = json!(result.truncated);
+                    output["columns"] = result
+                        .columns
+                        .iter()
+                        .zip(&result.types)
+                        .map(|(name, ty)| json!({ "name": name, "type": ty.name(), "oid": ty.oid() }))
+                        .collect();
+                }
+                if self.timing {
+                    output["duration_ms"] = json!(milliseconds(result.elapsed));
+                }
+                return serde_json::to_string_pretty(&output).expect("Failed to serialize rows");
+            }
+            "csv" if result.columns.is_empty() => return format!("rows_affected\n{}", result.affected),
+            "csv" => return csv(&result.columns, &result.rows),
+            _ => {}
+        }
+
+        let mut text = match (result.columns.is_empty(), result.rows.as_array().map(|r| r.is_empty())) {
+            (true, _) => format!("Query OK, {} rows affected", result.affected),
+            (false, Some(true)) => render_table(&result.columns, &[]),
+            (false, _) => self.rows(&result.rows),
+        };
+        if metadata {
+            text += &format!("\n{}", result_footer(result));
+        }
+        if self.timing {
+            let indent = if result.columns.is_empty() { "" } else { "\t" };
+            text += &format!("\n{}{}", indent, format_timing(result.elapsed, self.options.human));
+        }
+        text
+    }
+}
+
+/// Writes `rows` as CSV under a header of `columns`. Text is written as is and nested JSON as JSON, `NULL` is left
+/// empty, and fields holding a comma, quote or line break are quoted.
+fn csv(columns: &[String], rows: &Value) -> String {
+    let mut lines = vec![columns.iter().map(|c| csv_field(c)).collect::<Vec<String>>().join(",")];
+    for row in rows.as_array().into_iter().flatten() {
+        lines.push(csv_record(columns, row));
+    }
+    lines.join("\n")
+}
+
+/// One line of CSV holding the values of `row` under `columns`, as `csv` writes them
+pub(crate) fn csv_record(columns: &[String], row: &Value) -> String {
+    let fields: Vec<String> = columns
+        .iter()
+        .map(|c| match &row[c] {
+            Value::Null => String::new(),
+            Value::String(s) => csv_field(s),
+            value => csv_field(&value.to_string()),
+        })
+        .collect();
+    fields.join(",")
+}
+
+pub(crate) fn csv_field(text: &str) -> String {
+    match text.contains([',', '"', '\n', '\r']) {
+        true => format!("\"{}\"", text.replace('"', "\"\"")),
+        false => text.to_string(),
+    }
+}
+
+/// Describes a query result below its table, like psql's footer: whether rows were left out by `max_rows`, then
+/// every column with its type and the type's oid
+fn result_footer(result: &QueryResult) -> String {
+    let mut lines = Vec::new();
+    if result.truncated {
+        lines.push(format!(
+            "\tOnly the first {} rows are shown, the query returned more (see \\pset max_rows)",
+            result.affected
+        ));
+    }
+    let width = result.columns.iter().map(|c| c.chars().count()).max().unwrap_or(0);
+    lines.push("\tColumns:".to_string());
+    for (name, ty) in result.columns.iter().zip(&result.types) {
+        lines.push(format!("\t  {:<width$}  {} (oid {})", name, ty.name(), ty.oid(), width = width));
+    }
+    lines.join("\n")
+}
+
+/// Formats how long a statement took the way psql's `\timing` does, e.g. `Time: 12.345 ms`, adding the duration in
+/// minutes and seconds from a second on when `human` is set
+fn format_timing(elapsed: Duration, human: bool) -> String {
+    let mut text = format!("Time: {:.3} ms", milliseconds(elapsed));
+    if human && elapsed.as_secs() > 0 {
+        text += &format!(" ({})", human_duration(elapsed.as_secs_f64()));
+    }
+    text
+}
+
+/// Milliseconds with microsecond precision, as reported in JSON
+fn milliseconds(elapsed: Duration) -> f64 {
+    (elapsed.as_secs_f64() * 1_000_000.0).round() / 1000.0
+}
@@ -0,0 +1,70 @@
+use std::env;
+use std::io::{self, IsTerminal, Write};
+use std::process::{Command, Stdio};
+use std::sync::atomic::AtomicBool;
+use std::sync::Arc;
+
+use ratatui::crossterm::terminal;
+use signal_hook::consts::SIGINT;
+use tracing::error;
+
+use super::table::TableOptions;
+
+/// Pager used when `PAGER` is not set
+#[cfg(unix)]
+const DEFAULT_PAGER: &str = "less";
+#[cfg(not(unix))]
+const DEFAULT_PAGER: &str = "more";
+
+/// Options `less` is started with unless `LESS` is set: colors are shown rather than escaped, and long lines are
+/// scrolled sideways instead of wrapped, so wide tables stay aligned
+const LESS_OPTIONS: &str = "-RS";
+
+/// Prints `text`, through the pager when `TableOptions::pager` is on, the output is a terminal and `text` has more
+/// lines than fit on it, as far as the terminal tells its height. <br>
+/// The pager is `PAGER`, run by the shell so it may carry options, or `less` when it is not set, and none when it is
+/// set but empty, as psql does. Ctrl-C in the pager is left to the pager, it does not end the program.
+pub fn print_paged(text: &str, options: &TableOptions) {
+    let pager = env::var("PAGER").unwrap_or_else(|_| DEFAULT_PAGER.to_string());
+    let height = match terminal::size() {
+        Ok((_, rows)) if rows > 0 => rows as usize,
+        _ => usize::MAX,
+    };
+    // The prompt shown after the output takes two more lines
+    if !options.pager || pager.trim().is_empty() || !io::stdout().is_terminal() || text.lines().count() + 2 <= height {
+        println!("{}", text);
+        return;
+    }
+
+    #[cfg(unix)]
+    let mut command = Command::new("sh");
+    #[cfg(unix)]
+    command.arg("-c").arg(&pager);
+    #[cfg(not(unix))]
+    let mut command = Command::new("cmd");
+    #[cfg(not(unix))]
+    command.arg("/C").arg(&pager);
+    if env::var_os("LESS").is_none() {
+        command.env("LESS", LESS_OPTIONS);
+    }
+
+    let mut child = match command.stdin(Stdio::piped()).spawn() {
+        Ok(child) => child,
+        Err(e) => {
+            error!("could not start the pager {}: {}", pager, e);
+            println!("{}", text);
+            return;
+        }
+    };
+    let signal = signal_hook::flag::register(SIGINT, Arc::new(AtomicBool::new(false))).ok();
+    if let Some(mut stdin) = child.stdin.take() {
+        // The pager closes its input when quit before reaching the end
+        let _ = writeln!(stdin, "{}", text);
+    }
+    if let Err(e) = child.wait() {
+        error!("{}", e);
+    }
+    if let Some(id) = signal {
+        signal_hook::low_level::unregister(id);
+    }
+}
@@ -0,0 +1,82 @@
+use super::versions::QueryVariant;
+
+/// Query showing how parallel query is configured and used right now: the worker limits, the parallel workers
+/// running and the queries they help, and a note when parallelism is turned off or nothing uses it. <br>
+/// Parallel workers come out of `max_worker_processes` and `max_parallel_workers`, and a single query gets at most
+/// `max_parallel_workers_per_gather` of them.
+pub const PARALLEL_QUERY: &str = r#"
+    WITH workers AS (
+        SELECT count(*) AS active_workers,
+               count(DISTINCT leader_pid) AS parallel_queries
+        FROM pg_stat_activity
+        WHERE backend_type = 'parallel worker'
+    )
+    SELECT current_setting('max_worker_processes')::int AS max_worker_processes,
+           current_setting('max_parallel_workers')::int AS max_parallel_workers,
+           current_setting('max_parallel_workers_per_gather')::int AS max_parallel_workers_per_gather,
+           current_setting('max_parallel_maintenance_workers')::int AS max_parallel_maintenance_workers,
+           w.active_workers,
+           w.parallel_queries,
+           CASE WHEN current_setting('max_parallel_workers')::int = 0
+                  OR current_setting('max_parallel_workers_per_gather')::int = 0
+                THEN 'parallel query is turned off'
+                WHEN w.active_workers = 0
+                THEN 'no query is running in parallel right now'
+           END AS note
+    FROM workers w
+"#;
+
+/// `PARALLEL_QUERY` before Postgres 13, which did not show the `leader_pid` of parallel workers, so the queries they
+/// help cannot be counted
+const PARALLEL_BEFORE_13_QUERY: &str = r#"
+    WITH workers AS (
+        SELECT count(*) AS active_workers,
+               NULL::bigint AS parallel_queries
+        FROM pg_stat_activity
+        WHERE backend_type = 'parallel worker'
+    )
+    SELECT current_setting('max_worker_processes')::int AS max_worker_processes,
+           current_setting('max_parallel_workers')::int AS max_parallel_workers,
+           current_setting('max_parallel_workers_per_gather')::int AS max_parallel_workers_per_gather,
+           current_setting('max_parallel_maintenance_workers')::int AS max_parallel_maintenance_workers,
+           w.active_workers,
+           w.parallel_queries,
+           CASE WHEN current_setting('max_parallel_workers')::int = 0
+                  OR current_setting('max_parallel_workers_per_gather')::int = 0
+                THEN 'parallel query is turned off'
+                WHEN w.active_workers = 0
+                THEN 'no query is running in parallel right now'
+           END AS note
+    FROM workers w
+"#;
+
+/// The forms of `PARALLEL_QUERY` for older servers, see `Collector::variants`
+pub const PARALLEL_VARIANTS: &[QueryVariant] = &[QueryVariant {
+    before: 130000,
+    query: PARALLEL_BEFORE_13_QUERY,
+}];
+
+/// Query listing the statements of `pg_stat_statements` that planned parallel workers, with how many they asked for
+/// and how many could be started, those short of workers first. <br>
+/// The counts are only kept from `pg_stat_statements` 1.12 (Postgres 18) on. The columns are read through `to_jsonb`
+/// so that older versions, which lack them, return no rows rather than failing.
+pub const PARALLEL_STATEMENTS_QUERY: &str = r#"
+    WITH statements AS (
+        SELECT s.queryid AS query_id,
+               s.calls,
+               (to_jsonb(s) ->> 'parallel_workers_to_launch')::bigint AS workers_to_launch,
+               (to_jsonb(s) ->> 'parallel_workers_launched')::bigint AS workers_launched,
+               s.query
+        FROM pg_stat_statements s
+    )
+    SELECT query_id,
+           calls,
+           workers_to_launch,
+           workers_launched,
+           round(100.0 * workers_launched / workers_to_launch, 1) AS launched_percent,
+           left(regexp_replace(query, '\s+', ' ', 'g'), 80) AS query
+    FROM statements
+    WHERE workers_to_launch > 0
+    ORDER BY workers_to_launch - workers_launched DESC, workers_to_launch DESC
+    LIMIT 20
+"#;
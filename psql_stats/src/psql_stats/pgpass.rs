@@ -0,0 +1,94 @@
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::Once;
+
+use directories::BaseDirs;
+use tracing::warn;
+
+/// Makes the warning about a password file others can read show once, not on every connection
+static PERMISSIONS_WARNING: Once = Once::new();
+
+/// Path of the password file libpq reads: `$PGPASSFILE` when set, otherwise `~/.pgpass`, or
+/// `%APPDATA%\postgresql\pgpass.conf` on Windows
+fn pgpass_path() -> Option<PathBuf> {
+    if let Some(path) = env::var_os("PGPASSFILE").filter(|p| !p.is_empty()) {
+        return Some(PathBuf::from(path));
+    }
+    let dirs = BaseDirs::new()?;
+    match cfg!(windows) {
+        true => Some(dirs.config_dir().join("postgresql").join("pgpass.conf")),
+        false => Some(dirs.home_dir().join(".pgpass")),
+    }
+}
+
+/// Splits a line of the password file into its fields at the colons, `\:` and `\\` standing for a colon and a
+/// backslash
+fn split_fields(line: &str) -> Vec<String> {
+    let mut fields = vec![String::new()];
+    let mut chars = line.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => fields.last_mut().expect("always one field").extend(chars.next()),
+            ':' => fields.push(String::new()),
+            c => fields.last_mut().expect("always one field").push(c),
+        }
+    }
+    fields
+}
+
+/// Returns the password of the first line of a password file, in the `hostname:port:database:username:password`
+/// format, matching `host`, `port`, `dbname` and `user`, where `*` matches anything. <br>
+/// As in libpq, connections over a unix socket match the hostname `localhost`.
+fn find_password(contents: &str, host: &str, port: &str, dbname: &str, user: &str) -> Option<String> {
+    let host = match host.starts_with('/') || host.starts_with('@') || host.is_empty() {
+        true => "localhost",
+        false => host,
+    };
+    contents
+        .lines()
+        .filter(|line| !line.trim_start().starts_with('#'))
+        .map(split_fields)
+        .filter(|fields| fields.len() >= 5)
+        .find(|fields| {
+            [host, port, dbname, user]
+                .iter()
+                .zip(fields)
+                .all(|(value, field)| field == "*" || field == value)
+        })
+        .map(|fields| fields[4..].join(":"))
+}
+
+/// Whether others may read the password file at `path`, in which case libpq ignores it, and so does this
+#[cfg(unix)]
+fn readable_by_others(path: &std::path::Path) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+    fs::metadata(path).is_ok_and(|m| m.permissions().mode() & 0o077 != 0)
+}
+
+#[cfg(not(unix))]
+fn readable_by_others(_path: &std::path::Path) -> bool {
+    false
+}
+
+/// Returns the password to use for a connection that was not given one, following libpq: `$PGPASSWORD`, then the
+/// first matching line of the password file, see `find_password`. <br>
+/// A password file group or others can read is skipped, with a warning the first time, as libpq does.
+pub(crate) fn fallback_password(host: &str, port: &str, dbname: &str, user: &str) -> Option<String> {
+    if let Some(password) = env::var("PGPASSWORD").ok().filter(|p| !p.is_empty()) {
+        return Some(password);
+    }
+
+    let path = pgpass_path()?;
+    let contents = fs::read_to_string(&path).ok()?;
+    if readable_by_others(&path) {
+        PERMISSIONS_WARNING.call_once(|| {
+            warn!(
+                "password file {} has group or world access; permissions should be u=rw (0600) or less",
+                path.display()
+            )
+        });
+        return None;
+    }
+    find_password(&contents, host, port, dbname, user)
+}
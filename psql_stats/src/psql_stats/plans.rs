@@ -0,0 +1,440 @@
+use std::time::Instant;
+
+use colored::Colorize;
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use tracing::{error, warn};
+
+use super::auth::hex;
+use super::history::{unix_now, History};
+use super::humanize::human_duration;
+use super::logging::log_query;
+use super::query_result::describe_query_error;
+use super::transaction::read_only;
+use super::{query_context, reason, Connection, PGError, TransactionState};
+
+/// Name plans are recorded under in the history, in place of a collector's
+const PLAN_RECORD: &str = "plan";
+
+/// Hex digits of the SHA-256 of a normalized query kept as its fingerprint
+const FINGERPRINT_LENGTH: usize = 16;
+
+/// How many times more, or fewer, rows a node returned than the planner estimated before it is flagged, as a plan
+/// built on estimates that far off likely picked the wrong join or scan
+const MISESTIMATE_FACTOR: f64 = 10.0;
+
+/// Savepoint `explain_analyze` runs the statement behind inside a transaction opened with `begin`
+const EXPLAIN_SAVEPOINT: &str = "psql_stats_explain";
+
+/// Reduces `sql` to what stays the same between runs of the same query: literals become `?`, comments are dropped,
+/// whitespace is collapsed and everything outside double quotes is lowercased, so `WHERE id = 42` and
+/// `where id=7 -- again` share a fingerprint
+fn normalize_query(sql: &str) -> String {
+    let mut normalized = String::new();
+    let mut chars = sql.trim().trim_end_matches(';').chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '\'' => {
+                // '' inside a string is an escaped quote
+                loop {
+                    match chars.next() {
+                        Some('\'') if chars.peek() == Some(&'\'') => {
+                            chars.next();
+                        }
+                        Some('\'') | None => break,
+                        _ => {}
+                    }
+                }
+                normalized.push('?');
+            }
+            '"' => {
+                normalized.push(c);
+                for c in chars.by_ref() {
+                    normalized.push(c);
+                    if c == '"' {
+                        break;
+                    }
+                }
+            }
+            '-' if chars.peek() == Some(&'-') => while chars.next_if(|c| *c != '\n').is_some() {},
+            // Whitespace only matters between two words, `id = 7` and `id=7` are the same
+            c if c.is_whitespace() => {
+                while chars.next_if(|c| c.is_whitespace()).is_some() {}
+                if normalized.ends_with(is_word) && chars.peek().is_some_and(|c| is_word(*c) || *c == '\'') {
+                    normalized.push(' ');
+                }
+            }
+            // Digits right after a letter belong to a name, and after a $ to a placeholder
+            c if c.is_ascii_digit() && !normalized.ends_with(|p: char| is_word(p) && p != '?') => {
+                while chars.next_if(|c| c.is_ascii_digit() || *c == '.').is_some() {}
+                normalized.push('?');
+            }
+            c => normalized.extend(c.to_lowercase()),
+        }
+    }
+    normalized.trim_end().to_string()
+}
+
+/// Whether `c` is part of a name, keyword, placeholder or replaced literal, which a space must keep apart
+fn is_word(c: char) -> bool {
+    c.is_alphanumeric() || matches!(c, '_' | '$' | '"' | '?')
+}
+
+/// Fingerprint of `sql`, the start of the SHA-256 of its `normalize_query` form, which plans are recorded under
+fn query_fingerprint(sql: &str) -> String {
+    hex(&Sha256::digest(normalize_query(sql).as_bytes()))[..FINGERPRINT_LENGTH].to_string()
+}
+
+/// One node of a plan, described and indented as EXPLAIN shows it, with its estimated total cost and rows
+struct PlanNode {
+    depth: usize,
+    label: String,
+    cost: f64,
+    rows: f64,
+    /// Whether the node reads every row of a table
+    seq_scan: bool,
+    /// What running the node took, only in the plan of an `EXPLAIN ANALYZE`
+    actual: Option<Actual>,
+}
+
+/// What running one node of a plan took, as `EXPLAIN ANALYZE` measures it
+struct Actual {
+    /// Milliseconds spent in the node and its children, over all its loops
+    time: f64,
+    /// Rows returned per loop, comparable with the estimated rows
+    rows: f64,
+    /// How many times the node ran, 0 when the plan never needed it
+    loops: f64,
+    /// Shared buffers found in memory and read from disk, or the OS cache
+    hit: i64,
+    read: i64,
+}
+
+impl Actual {
+    /// How many times more, or fewer, rows the node returned than the `estimated` ones, 1 when spot on
+    fn misestimate(&self, estimated: f64) -> f64 {
+        let (estimated, actual) = (estimated.max(1.0), self.rows.max(1.0));
+        (estimated / actual).max(actual / estimated)
+    }
+}
+
+impl PlanNode {
+    /// What two plans must agree on for a node to count as unchanged, estimates aside
+    fn key(&self) -> (usize, &str) {
+        (self.depth, &self.label)
+    }
+}
+
+/// Flattens the nodes of the `Plan` of an `EXPLAIN (FORMAT JSON)` into `nodes`, parents before their children
+fn plan_nodes(plan: &Value, depth: usize, nodes: &mut Vec<PlanNode>) {
+    let text = |key: &str| plan[key].as_str().filter(|s| !s.is_empty());
+
+    let mut label = String::new();
+    if plan["Parallel Aware"].as_bool() == Some(true) {
+        label += "Parallel ";
+    }
+    label += text("Node Type").unwrap_or("?");
+    if let Some(strategy) = text("Strategy").filter(|s| *s != "Plain") {
+        label += &format!(" ({})", strategy);
+    }
+    if let Some(join) = text("Join Type").filter(|j| *j != "Inner") {
+        label += &format!(" ({} join)", join.to_lowercase());
+    }
+    if let Some(index) = text("Index Name") {
+        label += &format!(" using {}", index);
+    }
+    if let Some(relation) = text("Relation Name").or(text("CTE Name")).or(text("Function Name")) {
+        label += &format!(" on {}", relation);
+        if let Some(alias) = text("Alias").filter(|a| *a != relation) {
+            label += &format!(" {}", alias);
+        }
+    }
+
+    let actual = plan["Actual Loops"].as_f64().map(|loops| Actual {
+        time: plan["Actual Total Time"].as_f64().unwrap_or(0.0) * loops,
+        rows: plan["Actual Rows"].as_f64().unwrap_or(0.0),
+        loops,
+        hit: plan["Shared Hit Blocks"].as_i64().unwrap_or(0),
+        read: plan["Shared Read Blocks"].as_i64().unwrap_or(0),
+    });
+    nodes.push(PlanNode {
+        depth,
+        label,
+        cost: plan["Total Cost"].as_f64().unwrap_or(0.0),
+        rows: plan["Plan Rows"].as_f64().unwrap_or(0.0),
+        seq_scan: text("Node Type") == Some("Seq Scan"),
+        actual,
+    });
+    for child in plan["Plans"].as_array().into_iter().flatten() {
+        plan_nodes(child, depth + 1, nodes);
+    }
+}
+
+/// How the nodes of two plans line up: a node of both, or one only the earlier or only the current plan has
+enum Change<'a> {
+    Same(&'a PlanNode, &'a PlanNode),
+    Removed(&'a PlanNode),
+    Added(&'a PlanNode),
+}
+
+/// Lines up the nodes of `before` and `after` along their longest common subsequence, so a node swapped for another
+/// shows as one removed and one added while the rest of the tree stays matched
+fn diff_nodes<'a>(before: &'a [PlanNode], after: &'a [PlanNode]) -> Vec<Change<'a>> {
+    // common[i][j] is the length of the longest common subsequence of before[i..] and after[j..]
+    let mut common = vec![vec![0usize; after.len() + 1]; before.len() + 1];
+    for i in (0..before.len()).rev() {
+        for j in (0..after.len()).rev() {
+            common[i][j] = match before[i].key() == after[j].key() {
+                true => common[i + 1][j + 1] + 1,
+                false => common[i + 1][j].max(common[i][j + 1]),
+            };
+        }
+    }
+
+    let (mut i, mut j) = (0, 0);
+    let mut changes = Vec::new();
+    while i < before.len() || j < after.len() {
+        if i < before.len() && j < after.len() && before[i].key() == after[j].key() {
+            changes.push(Change::Same(&before[i], &after[j]));
+            i += 1;
+            j += 1;
+        } else if i < before.len() && (j == after.len() || common[i + 1][j] >= common[i][j + 1]) {
+            changes.push(Change::Removed(&before[i]));
+            i += 1;
+        } else {
+            changes.push(Change::Added(&after[j]));
+            j += 1;
+        }
+    }
+    changes
+}
+
+/// Formats an estimate that may have changed as `before -> after`, or once if it did not
+fn estimate(before: f64, after: f64, decimals: usize) -> String {
+    let (before, after) = (format!("{:.*}", decimals, before), format!("{:.*}", decimals, after));
+    match before == after {
+        true => before,
+        false => format!("{} -> {}", before, after).yellow().to_string(),
+    }
+}
+
+/// Renders the nodes of a plan as an indented tree with their estimates
+fn render_plan(nodes: &[PlanNode]) -> String {
+    let lines: Vec<String> = nodes
+        .iter()
+        .map(|n| format!("  {}{}  (cost {:.2}, rows {:.0})", "  ".repeat(n.depth), n.label, n.cost, n.rows))
+        .collect();
+    lines.join("\n")
+}
+
+/// Renders the plan of an `EXPLAIN (FORMAT JSON)`, or with `ANALYZE`, as an indented tree with the estimated cost and
+/// rows of every node, and with `ANALYZE` the time it took, the rows it returned per loop, its loops and the shared
+/// buffers it found in memory and read. Sequential scans are shown in yellow, and nodes returning
+/// `MISESTIMATE_FACTOR` times more or fewer rows than estimated in red. Ends with the planning and execution time and
+/// a line counting both.
+pub fn render_explain(plan: &Value) -> String {
+    let mut nodes = Vec::new();
+    plan_nodes(&plan[0]["Plan"], 0, &mut nodes);
+
+    let (mut seq_scans, mut misestimates) = (0, 0);
+    let mut lines = Vec::new();
+    for node in &nodes {
+        let label = match node.seq_scan {
+            true => {
+                seq_scans += 1;
+                node.label.yellow().to_string()
+            }
+            false => node.label.clone(),
+        };
+        let mut line = format!("  {}{}  (cost {:.2}, rows {:.0})", "  ".repeat(node.depth), label, node.cost, node.rows);
+        match &node.actual {
+            Some(actual) if actual.loops == 0.0 => line += "  (never executed)",
+            Some(actual) => {
+                line += &format!(
+                    "  (actual {:.3} ms, rows {:.0}, loops {:.0}, buffers hit {} read {})",
+                    actual.time, actual.rows, actual.loops, actual.hit, actual.read
+                );
+                let factor = actual.misestimate(node.rows);
+                if factor >= MISESTIMATE_FACTOR {
+                    misestimates += 1;
+                    line += &format!("  rows off by {:.0}x", factor).red().bold().to_string();
+                }
+            }
+            None => {}
+        }
+        lines.push(line);
+    }
+
+    lines.push(String::new());
+    let time = |key: &str| plan[0][key].as_f64();
+    if let (Some(planning), Some(execution)) = (time("Planning Time"), time("Execution Time")) {
+        lines.push(format!("Planning {:.3} ms, execution {:.3} ms", planning, execution));
+    }
+    lines.push(format!(
+        "{} sequential {}, {} {} with rows off by {:.0}x or more",
+        seq_scans,
+        if seq_scans == 1 { "scan" } else { "scans" },
+        misestimates,
+        if misestimates == 1 { "node" } else { "nodes" },
+        MISESTIMATE_FACTOR
+    ));
+    lines.join("\n")
+}
+
+/// Renders the changes between two plans as an indented tree: nodes only the earlier plan has in red behind a `-`,
+/// those only the current plan has in green behind a `+`, and the estimates of the others in yellow where they moved.
+/// Ends with a line counting the changes.
+fn render_diff(before: &[PlanNode], after: &[PlanNode]) -> String {
+    let mut lines = Vec::new();
+    let (mut removed, mut added) = (0, 0);
+    for change in diff_nodes(before, after) {
+        let line = match change {
+            Change::Same(b, a) => format!(
+                "  {}{}  (cost {}, rows {})",
+                "  ".repeat(a.depth),
+                a.label,
+                estimate(b.cost, a.cost, 2),
+                estimate(b.rows, a.rows, 0)
+            ),
+            Change::Removed(b) => {
+                removed += 1;
+                format!("- {}{}  (cost {:.2}, rows {:.0})", "  ".repeat(b.depth), b.label, b.cost, b.rows)
+                    .red()
+                    .to_string()
+            }
+            Change::Added(a) => {
+                added += 1;
+                format!("+ {}{}  (cost {:.2}, rows {:.0})", "  ".repeat(a.depth), a.label, a.cost, a.rows)
+                    .green()
+                    .to_string()
+            }
+        };
+        lines.push(line);
+    }
+
+    let total = |nodes: &[PlanNode]| nodes.first().map(|n| n.cost).unwrap_or(0.0);
+    lines.push(String::new());
+    lines.push(match (removed, added) {
+        (0, 0) => format!("Plan unchanged, total cost {}", estimate(total(before), total(after), 2)),
+        _ => format!(
+            "{} {} removed, {} added, total cost {}",
+            removed,
+            if removed == 1 { "node" } else { "nodes" },
+            added,
+            estimate(total(before), total(after), 2)
+        )
+        .bold()
+        .to_string(),
+    });
+    lines.join("\n")
+}
+
+impl Connection {
+    /// Returns the plan the server would use for `sql`, as `EXPLAIN (FORMAT JSON)` gives it, without running it
+    pub fn explain(&mut self, sql: &str) -> Result<Value, PGError> {
+        let explain = format!("EXPLAIN (FORMAT JSON) {}", sql.trim().trim_end_matches(';'));
+        match &mut self.client {
+            Some(c) => match c.query_one(&explain, &[]) {
+                Ok(row) => row.try_get::<_, Value>(0).map_err(|e| PGError::query(query_context(&explain), e)),
+                Err(e) => {
+                    error!("{}", describe_query_error(&explain, &e));
+                    Err(PGError::QueryFailed)
+                }
+            },
+            None => Err(PGError::ClientEmpty),
+        }
+    }
+
+    /// Returns the plan of `sql` with what every node took, as `EXPLAIN (ANALYZE, BUFFERS, FORMAT JSON)` gives it. <br>
+    /// ANALYZE runs the statement, so it runs in a transaction that is rolled back afterwards, or inside one opened
+    /// with `begin` behind a savepoint that is, and explaining an `UPDATE` changes nothing. In read-only mode the
+    /// transaction is read only too.
+    pub fn explain_analyze(&mut self, sql: &str) -> Result<Value, PGError> {
+        let explain = format!("EXPLAIN (ANALYZE, BUFFERS, FORMAT JSON) {}", sql.trim().trim_end_matches(';'));
+        let (start, undo) = match self.transaction {
+            TransactionState::Idle if read_only() => ("BEGIN READ ONLY".to_string(), "ROLLBACK".to_string()),
+            TransactionState::Idle => ("BEGIN".to_string(), "ROLLBACK".to_string()),
+            _ => (
+                format!("SAVEPOINT {}", EXPLAIN_SAVEPOINT),
+                format!("ROLLBACK TO SAVEPOINT {0}; RELEASE SAVEPOINT {0}", EXPLAIN_SAVEPOINT),
+            ),
+        };
+        self.queries += 1;
+        let client = match &mut self.client {
+            Some(c) => c,
+            None => return Err(PGError::ClientEmpty),
+        };
+
+        client
+            .batch_execute(&start)
+            .map_err(|e| PGError::query("starting the transaction EXPLAIN ANALYZE is rolled back in", e))?;
+        let ran = Instant::now();
+        let plan = match client.query_one(&explain, &[]) {
+            Ok(row) => {
+                log_query(&explain, ran.elapsed(), Ok::<u64, PGError>(1));
+                row.try_get::<_, Value>(0).map_err(|e| PGError::query(query_context(&explain), e))
+            }
+            Err(e) => {
+                log_query(&explain, ran.elapsed(), Err(reason(&e)));
+                error!("{}", describe_query_error(&explain, &e));
+                Err(PGError::QueryFailed)
+            }
+        };
+        let undone = client
+            .batch_execute(&undo)
+            .map_err(|e| PGError::query("rolling back what EXPLAIN ANALYZE ran", e));
+        let plan = plan?;
+        undone.map(|_| plan)
+    }
+
+    /// Compares the current plan of `sql` with one recorded in `history` for the same query fingerprint, the most
+    /// recent with `back` 1, the one before it with 2 and so on, then records the current plan for later
+    /// comparisons, e.g. before and after an `ANALYZE` or an upgrade. <br>
+    /// Returns the changes rendered by `render_diff` below a heading saying which capture they are compared with,
+    /// or the current plan alone when none was recorded yet.
+    pub fn plan_diff(&mut self, history: &mut History, sql: &str, back: usize) -> Result<String, PGError> {
+        let fingerprint = query_fingerprint(sql);
+        let plan = self.explain(sql)?;
+        let mut current = Vec::new();
+        plan_nodes(&plan[0]["Plan"], 0, &mut current);
+
+        let label = self.label();
+        let captures: Vec<(u64, Value)> = history
+            .records(&label, PLAN_RECORD)
+            .into_iter()
+            .filter(|(_, data)| data["fingerprint"] == fingerprint.as_str())
+            .collect();
+
+        let record = json!({"fingerprint": fingerprint, "query": sql.trim(), "plan": plan});
+        if let Err(e) = history.append(&label, PLAN_RECORD, &record) {
+            warn!("could not record the plan in the history: {}", e);
+        }
+
+        let index = match captures.len().checked_sub(back.max(1)) {
+            Some(index) => index,
+            None if captures.is_empty() => {
+                return Ok(format!(
+                    "No earlier plan of this query (fingerprint {}) was recorded, the current one is saved to \
+                     compare with next time:\n\n{}",
+                    fingerprint,
+                    render_plan(&current)
+                ));
+            }
+            None => 0,
+        };
+        let (captured_at, data) = &captures[index];
+        let mut earlier = Vec::new();
+        plan_nodes(&data["plan"][0]["Plan"], 0, &mut earlier);
+
+        Ok(format!(
+            "Plan of query {} captured {} ago (capture {} of {}{}) -> now:\n\n{}",
+            fingerprint,
+            human_duration(unix_now().saturating_sub(*captured_at) as f64),
+            captures.len() - index,
+            captures.len(),
+            if captures.len() > 1 { ", --back picks another" } else { "" },
+            render_diff(&earlier, &current)
+        ))
+    }
+}
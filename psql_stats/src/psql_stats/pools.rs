@@ -0,0 +1,166 @@
+use std::collections::BTreeMap;
+
+use serde_json::{json, Value};
+
+use super::history::History;
+use super::humanize::human_duration;
+use super::output::OutputFormatter;
+use super::{Config, Connection, PGError};
+
+/// Collector whose samples in the history `pool_advice` reads
+const STATES_COLLECTOR: &str = "connection-states";
+
+/// Headroom added to the 95th percentile of the connections an application has active at once to size its pool
+const POOL_HEADROOM: f64 = 1.25;
+
+/// Connections an application must reach at its peak before it is told to use a pooler, fewer are not worth one
+const POOLER_MIN_CONNECTIONS: u64 = 10;
+
+/// What was sampled of one application, role and database, one value per sample in the history
+#[derive(Default)]
+struct Usage {
+    connections: Vec<u64>,
+    active: Vec<u64>,
+    idle: u64,
+    idle_in_transaction: u64,
+}
+
+/// The value below which 95% of `values` fall
+fn percentile_95(values: &[u64]) -> u64 {
+    let mut sorted = values.to_vec();
+    sorted.sort_unstable();
+    let index = ((sorted.len() as f64 * 0.95).ceil() as usize).saturating_sub(1);
+    sorted.get(index).copied().unwrap_or(0)
+}
+
+/// Average of `values`, rounded to a decimal
+fn average(values: &[u64]) -> f64 {
+    let sum: u64 = values.iter().sum();
+    (10.0 * sum as f64 / values.len().max(1) as f64).round() / 10.0
+}
+
+impl Connection {
+    /// Estimates how many connections the applications of this connection use at once, from the `connection-states`
+    /// samples recorded in `history`, see `pool_advice`, against the connections `max_connections` leaves available.
+    /// <br>
+    /// Returns `NoRecordedSample` when fewer than two samples were recorded.
+    pub fn pool_advice(&mut self, history: &History) -> Result<Value, PGError> {
+        let records = history.records(&self.label(), STATES_COLLECTOR);
+        if records.len() < 2 {
+            return Err(PGError::NoRecordedSample);
+        }
+        let limits = self.connection_limits()?;
+        Ok(pool_advice(&records, &limits))
+    }
+}
+
+/// Works out from samples of the `connection-states` collector, taken over time, how many connections every
+/// application, role and database has open and how many of those are active at once, on average, at the 95th
+/// percentile and at the peak. <br>
+/// Each is recommended a pool of the 95th percentile of its active connections plus a quarter, and told to use a pooler
+/// when it keeps more than twice that open, at least `POOLER_MIN_CONNECTIONS` of them, as most then sit idle. Sessions
+/// left idle in a transaction are called out as well, as they hold on to their connection, and their locks, whatever
+/// the pool. <br>
+/// The `summary` compares the whole server with the connections available per `Connection::connection_limits`.
+pub fn pool_advice(records: &[(u64, Value)], limits: &Value) -> Value {
+    let samples = records.len();
+    let mut usage: BTreeMap<(String, String, String), Usage> = BTreeMap::new();
+    let mut total_connections = Vec::with_capacity(samples);
+    let mut total_active = Vec::with_capacity(samples);
+    for (index, (_, rows)) in records.iter().enumerate() {
+        let (mut connections, mut active) = (0, 0);
+        for row in rows.as_array().into_iter().flatten() {
+            let text = |column: &str| row[column].as_str().unwrap_or("").to_string();
+            let entry = usage.entry((text("application"), text("user"), text("database"))).or_default();
+            // Applications missing from the earlier samples had no connections then
+            entry.connections.resize(index, 0);
+            entry.active.resize(index, 0);
+            let count = |column: &str| row[column].as_u64().unwrap_or(0);
+            entry.connections.push(count("connections"));
+            entry.active.push(count("active"));
+            entry.idle += count("idle");
+            entry.idle_in_transaction += count("idle_in_transaction");
+            connections += count("connections");
+            active += count("active");
+        }
+        total_connections.push(connections);
+        total_active.push(active);
+    }
+
+    let mut recommended_total = 0;
+    let mut applications: Vec<(u64, Value)> = usage
+        .into_iter()
+        .map(|((application, user, database), mut usage)| {
+            usage.connections.resize(samples, 0);
+            usage.active.resize(samples, 0);
+            let peak_connections = usage.connections.iter().copied().max().unwrap_or(0);
+            let p95_active = percentile_95(&usage.active);
+            let recommended = ((p95_active as f64 * POOL_HEADROOM).ceil() as u64).max(1);
+            recommended_total += recommended;
+            let opened: u64 = usage.connections.iter().sum();
+
+            let mut advice = Vec::new();
+            if peak_connections >= POOLER_MIN_CONNECTIONS && peak_connections > 2 * recommended {
+                advice.push(format!("use a pooler, {} connections would do", recommended));
+            }
+            if usage.idle_in_transaction > 0 {
+                advice.push("sessions sit idle in a transaction, holding their connection".to_string());
+            }
+            let row = json!({
+                "application": application,
+                "user": user,
+                "database": database,
+                "avg_connections": average(&usage.connections),
+                "peak_connections": peak_connections,
+                "avg_active": average(&usage.active),
+                "p95_active": p95_active,
+                "peak_active": usage.active.iter().copied().max().unwrap_or(0),
+                "idle_percent": (1000.0 * usage.idle as f64 / opened.max(1) as f64).round() / 10.0,
+                "recommended_pool": recommended,
+                "advice": advice.join(", "),
+            });
+            (peak_connections, row)
+        })
+        .collect();
+    applications.sort_by_key(|(peak, _)| std::cmp::Reverse(*peak));
+
+    let first = records.first().map(|r| r.0).unwrap_or(0);
+    let last = records.last().map(|r| r.0).unwrap_or(0);
+    let available = limits["available"].as_u64().unwrap_or(0);
+    let peak_connections = total_connections.iter().copied().max().unwrap_or(0);
+    json!({
+        "summary": {
+            "samples": samples,
+            "over": human_duration(last.saturating_sub(first) as f64),
+            "max_connections": limits["max_connections"],
+            "available": available,
+            "avg_connections": average(&total_connections),
+            "peak_connections": peak_connections,
+            "peak_used_percent": (1000.0 * peak_connections as f64 / available.max(1) as f64).round() / 10.0,
+            "avg_active": average(&total_active),
+            "p95_active": percentile_95(&total_active),
+            "peak_active": total_active.iter().copied().max().unwrap_or(0),
+            "recommended_pools_total": recommended_total,
+        },
+        "applications": applications.into_iter().map(|(_, row)| row).collect::<Vec<Value>>(),
+    })
+}
+
+/// Renders what `pool_advice` returns: in JSON as one object, otherwise the summary and the applications each in
+/// their own table
+pub fn render_pool_advice(advice: &Value, config: &Config) -> String {
+    if config.output_format == "json" {
+        return serde_json::to_string_pretty(advice).expect("Failed to serialize the pool advice");
+    }
+    let formatter = OutputFormatter::new(config);
+    let heading = format!(
+        "Connections over {} ({} samples)",
+        advice["summary"]["over"].as_str().unwrap_or(""),
+        advice["summary"]["samples"]
+    );
+    [
+        formatter.titled(&heading, formatter.rows(&json!([advice["summary"]]))),
+        formatter.titled("Pool sizes by application, user and database", formatter.rows(&advice["applications"])),
+    ]
+    .join("\n")
+}
@@ -0,0 +1,259 @@
+use super::{Connection, PGError};
+
+/// Query telling whether the relation `$1` exists and whether the current role has the privilege `$2` on it, on the
+/// whole table or, for the privileges that can be granted per column, on at least one column
+const RELATION_PRIVILEGE_QUERY: &str = r#"
+    SELECT r.oid IS NOT NULL,
+           has_table_privilege(r.oid, $2)
+           OR ($2 IN ('SELECT', 'INSERT', 'UPDATE') AND has_any_column_privilege(r.oid, $2))
+    FROM (SELECT to_regclass($1) AS oid) AS r
+"#;
+
+/// A relation a query will fail on
+pub struct PrivilegeWarning {
+    pub relation: String,
+    /// The privilege the statement needs on it, e.g. `SELECT`
+    pub privilege: &'static str,
+    /// Whether the relation does not exist at all, rather than being off limits
+    pub missing: bool,
+}
+
+impl PrivilegeWarning {
+    pub fn describe(&self, user: &str) -> String {
+        match self.missing {
+            true => format!("Relation {} does not exist", self.relation),
+            false => format!(
+                "Role {} lacks the {} privilege on {}, the query will fail with a permission error",
+                user, self.privilege, self.relation
+            ),
+        }
+    }
+}
+
+impl Connection {
+    /// Finds the relations `query` reads or writes (see `referenced_relations`) that the connected role cannot use
+    /// as the statement needs, or that do not exist, before the query is run. <br>
+    /// The statement is not parsed by the server, so relations only referenced through views or functions are not
+    /// checked, and an unusual statement may not be understood at all: no warning does not guarantee success.
+    pub fn precheck_query(&mut self, query: &str) -> Result<Vec<PrivilegeWarning>, PGError> {
+        let client = match &mut self.client {
+            Some(c) => c,
+            None => return Err(PGError::ClientEmpty),
+        };
+
+        let mut warnings = Vec::new();
+        for (relation, privilege) in referenced_relations(query) {
+            let row = match client.query_one(RELATION_PRIVILEGE_QUERY, &[&relation, &privilege]) {
+                Ok(r) => r,
+                // A name to_regclass cannot parse, most likely one we misread
+                Err(_) => continue,
+            };
+            let (exists, allowed): (bool, Option<bool>) = (row.get(0), row.get(1));
+            if !exists || allowed == Some(false) {
+                warnings.push(PrivilegeWarning {
+                    relation,
+                    privilege,
+                    missing: !exists,
+                });
+            }
+        }
+        Ok(warnings)
+    }
+}
+
+/// Lists the relations a statement names after `FROM`, `JOIN`, `INTO`, `UPDATE` and `TRUNCATE`, with the privilege
+/// needed on each, without duplicates. <br>
+/// Names given to common table expressions, subqueries and set-returning functions are skipped. String literals,
+/// comments and quoted identifiers are tokenized properly, so their contents are never taken for keywords.
+pub(crate) fn referenced_relations(query: &str) -> Vec<(String, &'static str)> {
+    let tokens = tokenize(query);
+    let keyword = |i: usize, word: &str| tokens.get(i).map(|t| t.eq_ignore_ascii_case(word)).unwrap_or(false);
+
+    // Names defined by WITH name AS ( or , name AS ( are not relations
+    let mut cte_names = Vec::new();
+    for i in 0..tokens.len() {
+        let defines = keyword(i + 1, "as") && keyword(i + 2, "(") || keyword(i + 1, "(") && is_column_list(&tokens, i + 1);
+        if defines && i > 0 && (keyword(i - 1, "with") || keyword(i - 1, "recursive") || keyword(i - 1, ",")) {
+            cte_names.push(tokens[i].to_lowercase());
+        }
+    }
+
+    let mut relations: Vec<(String, &'static str)> = Vec::new();
+    // For every open parenthesis, whether it holds a query, as opposed to e.g. the arguments of
+    // extract(year FROM ts), whose FROM names no relation
+    let mut parentheses: Vec<bool> = Vec::new();
+    let mut i = 0;
+    while i < tokens.len() {
+        let in_query = parentheses.last().copied().unwrap_or(true);
+        let previous = |word: &str| i > 0 && keyword(i - 1, word);
+        let privilege = match tokens[i].to_ascii_lowercase().as_str() {
+            "(" => {
+                parentheses.push(keyword(i + 1, "select") || keyword(i + 1, "with") || keyword(i + 1, "values"));
+                None
+            }
+            ")" => {
+                parentheses.pop();
+                None
+            }
+            _ if !in_query => None,
+            "from" if previous("delete") => Some("DELETE"),
+            "from" if previous("distinct") => None,
+            "from" | "join" => Some("SELECT"),
+            "into" if previous("insert") => Some("INSERT"),
+            // FOR UPDATE locks rows and ON CONFLICT DO UPDATE updates the table already named by INSERT
+            "update" if !previous("for") && !previous("key") && !previous("do") => Some("UPDATE"),
+            "truncate" => Some("TRUNCATE"),
+            _ => None,
+        };
+        i += 1;
+        let privilege = match privilege {
+            Some(p) => p,
+            None => continue,
+        };
+
+        // FROM a, b lists several relations, each possibly aliased
+        loop {
+            while keyword(i, "only") || keyword(i, "table") || keyword(i, "lateral") {
+                i += 1;
+            }
+            let (name, next) = match qualified_name(&tokens, i) {
+                Some(n) => n,
+                None => break,
+            };
+            i = next;
+            // A function call, such as generate_series(1, 10), is not a relation, while INSERT INTO t (a, b) lists
+            // columns
+            if privilege == "SELECT" && keyword(i, "(") {
+                break;
+            }
+            let simple = name.to_lowercase();
+            if !cte_names.contains(&simple) && !relations.iter().any(|(r, p)| *r == name && *p == privilege) {
+                relations.push((name, privilege));
+            }
+
+            if keyword(i, "as") {
+                i += 1;
+            }
+            if tokens.get(i).map(|t| is_identifier(t) && !is_reserved(t)).unwrap_or(false) {
+                i += 1;
+            }
+            if privilege != "SELECT" && privilege != "TRUNCATE" || !keyword(i, ",") {
+                break;
+            }
+            i += 1;
+        }
+    }
+    relations
+}
+
+/// Splits SQL into words, quoted identifiers and single punctuation characters, dropping whitespace, comments and
+/// the contents of string literals
+pub(crate) fn tokenize(query: &str) -> Vec<String> {
+    let chars: Vec<char> = query.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        if c.is_whitespace() {
+            i += 1;
+        } else if c == '-' && chars.get(i + 1) == Some(&'-') {
+            while i < chars.len() && chars[i] != '\n' {
+                i += 1;
+            }
+        } else if c == '/' && chars.get(i + 1) == Some(&'*') {
+            i += 2;
+            while i < chars.len() && !(chars[i] == '*' && chars.get(i + 1) == Some(&'/')) {
+                i += 1;
+            }
+            i += 2;
+        } else if c == '\'' {
+            // Doubled quotes inside a literal simply close and reopen it
+            i += 1;
+            while i < chars.len() && chars[i] != '\'' {
+                i += 1;
+            }
+            i += 1;
+            tokens.push("''".to_string());
+        } else if c == '$' && !chars.get(i + 1).map(|n| n.is_ascii_digit()).unwrap_or(false) {
+            // Dollar quoting: $$ ... $$ or $tag$ ... $tag$
+            let end = chars[i + 1..].iter().position(|c| *c == '$').map(|p| i + 1 + p);
+            let tag: String = match end {
+                Some(e) => chars[i..=e].iter().collect(),
+                None => break,
+            };
+            let rest: String = chars[i + tag.len()..].iter().collect();
+            let close = rest.find(&tag).map(|p| rest[..p].chars().count()).unwrap_or(rest.chars().count());
+            i += tag.chars().count() * 2 + close;
+            tokens.push("''".to_string());
+        } else if c == '"' {
+            let start = i;
+            i += 1;
+            while i < chars.len() {
+                if chars[i] == '"' && chars.get(i + 1) == Some(&'"') {
+                    i += 2;
+                } else if chars[i] == '"' {
+                    break;
+                } else {
+                    i += 1;
+                }
+            }
+            i += 1;
+            tokens.push(chars[start..i.min(chars.len())].iter().collect());
+        } else if c.is_alphanumeric() || c == '_' {
+            let start = i;
+            while i < chars.len() && (chars[i].is_alphanumeric() || chars[i] == '_' || chars[i] == '$') {
+                i += 1;
+            }
+            tokens.push(chars[start..i].iter().collect());
+        } else {
+            tokens.push(c.to_string());
+            i += 1;
+        }
+    }
+    tokens
+}
+
+/// Reads a possibly schema qualified name starting at `tokens[i]`, returning it and the index after it
+pub(crate) fn qualified_name(tokens: &[String], mut i: usize) -> Option<(String, usize)> {
+    let mut name = String::new();
+    loop {
+        let part = tokens.get(i).filter(|t| is_identifier(t) && !is_reserved(t))?;
+        name += part;
+        i += 1;
+        if tokens.get(i).map(|t| t == ".").unwrap_or(false) {
+            name += ".";
+            i += 1;
+        } else {
+            return Some((name, i));
+        }
+    }
+}
+
+fn is_identifier(token: &str) -> bool {
+    token.starts_with('"') || token.chars().next().map(|c| c.is_alphabetic() || c == '_').unwrap_or(false)
+}
+
+/// Whether `tokens[open]`, an opening parenthesis, starts a list of column names closed by `) AS (`, as in
+/// `WITH name (a, b) AS (...)`
+fn is_column_list(tokens: &[String], open: usize) -> bool {
+    match tokens[open..].iter().position(|t| t == ")") {
+        Some(close) => {
+            let after = open + close + 1;
+            tokens.get(after).map(|t| t.eq_ignore_ascii_case("as")).unwrap_or(false)
+                && tokens.get(after + 1).map(|t| t == "(").unwrap_or(false)
+        }
+        None => false,
+    }
+}
+
+/// Keywords that can follow a relation name, and so are never an alias
+fn is_reserved(token: &str) -> bool {
+    const RESERVED: &[&str] = &[
+        "where", "join", "inner", "left", "right", "full", "cross", "natural", "on", "using", "group", "order",
+        "having", "limit", "offset", "union", "intersect", "except", "window", "for", "set", "values", "select",
+        "returning", "default", "as", "with", "lateral", "only", "fetch", "tablesample", "cascade",
+        "restrict", "restart", "continue", "overriding", "do", "and", "or", "not", "when", "then", "from",
+    ];
+    RESERVED.contains(&token.to_ascii_lowercase().as_str())
+}
+
@@ -0,0 +1,86 @@
+use postgres::Client;
+
+use super::PGError;
+
+/// Whether the connected role is a superuser, whether it has the privileges of `pg_monitor`, which include
+/// `pg_read_all_stats` and `pg_read_all_settings`, and whether it may read `pg_file_settings`
+const PRIVILEGES_QUERY: &str = r#"
+    SELECT rolsuper,
+           rolsuper OR pg_has_role(current_user, 'pg_monitor', 'USAGE') AS monitor,
+           rolsuper OR (has_table_privilege('pg_catalog.pg_file_settings', 'SELECT')
+                        AND has_function_privilege('pg_catalog.pg_show_all_file_settings()', 'EXECUTE')) AS file_settings
+    FROM pg_roles
+    WHERE rolname = current_user
+"#;
+
+/// What a collector or command needs to see all of its results
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Privilege {
+    /// Works for any role
+    None,
+    /// Runs for any role, but without `pg_monitor` other users' sessions and query text are hidden
+    Monitor,
+    /// Fails for anyone but a superuser or a role granted `pg_file_settings`, see `monitor_role_sql`
+    FileSettings,
+    /// Fails for anyone but a superuser
+    Superuser,
+}
+
+/// Privileges of the role connected as, probed on connect by `probe`
+#[derive(Clone, Copy, Default)]
+pub struct Privileges {
+    /// Whether the probe succeeded, nothing is hidden or refused when the privileges are unknown
+    pub probed: bool,
+    pub superuser: bool,
+    pub monitor: bool,
+    pub file_settings: bool,
+}
+
+impl Privileges {
+    /// Whether a collector needing `required` can be run at all
+    pub fn allows(&self, required: Privilege) -> bool {
+        match required {
+            _ if !self.probed => true,
+            Privilege::Superuser => self.superuser,
+            Privilege::FileSettings => self.file_settings,
+            Privilege::None | Privilege::Monitor => true,
+        }
+    }
+
+    /// Returns `SuperuserRequired` if a collector needing `required` cannot be run, so it is refused with a hint
+    /// instead of failing with a bare `QueryError`
+    pub fn check(&self, required: Privilege) -> Result<(), PGError> {
+        match self.allows(required) {
+            true => Ok(()),
+            false => Err(PGError::SuperuserRequired),
+        }
+    }
+
+    /// Whether a collector needing `required` only shows part of its results
+    pub fn limited(&self, required: Privilege) -> bool {
+        self.probed && required == Privilege::Monitor && !self.monitor
+    }
+}
+
+/// Reads the privileges of the role `client` is connected as. If the probe fails, for example on servers older than
+/// Postgres 10 which have no `pg_monitor`, the privileges are left unknown.
+pub(crate) fn probe(client: &mut Client) -> Privileges {
+    match client.query_one(PRIVILEGES_QUERY, &[]) {
+        Ok(row) => Privileges {
+            probed: true,
+            superuser: row.try_get(0).unwrap_or(false),
+            monitor: row.try_get(1).unwrap_or(false),
+            file_settings: row.try_get(2).unwrap_or(false),
+        },
+        Err(_) => Privileges::default(),
+    }
+}
+
+/// Hint printed above results that are limited to the user's own sessions, see `Privileges::limited`
+pub fn monitor_hint(user: &str) -> String {
+    format!(
+        "Note: only your own sessions are shown in full, this requires the pg_monitor role (an administrator can run \
+         GRANT pg_monitor TO {};)",
+        user
+    )
+}
@@ -0,0 +1,338 @@
+use serde::{Deserialize, Deserializer, Serialize};
+use serde_json::{json, Value};
+use tracing::{error, warn};
+
+use super::auth::AUTH_METHODS;
+use super::collectors::find_collector;
+use super::failover::TARGET_SESSION_ATTRS;
+use super::tls::SSLMODES;
+use super::{connections_file, secrets, Connection, PGError};
+
+/// Fields of a saved connection `edit-connection` can change. The name is changed with `rename-connection`, and the
+/// keyring entry is only ever set by saving with `--use-keyring`.
+pub const EDITABLE_FIELDS: &[&str] = &[
+    "host",
+    "port",
+    "user",
+    "dbname",
+    "sslmode",
+    "sslrootcert",
+    "sslcert",
+    "sslkey",
+    "auth",
+    "aws_region",
+    "target_session_attrs",
+    "ssh_host",
+    "ssh_user",
+    "ssh_key",
+    "collectors",
+];
+
+/// A saved connection, as an entry of the connections file holds it. <br>
+/// Fields missing from the entry are empty, meaning their defaults, so entries written by hand only need what they
+/// change. The password is never saved, only the name of the keyring entry holding it, if any.
+#[derive(Serialize, Deserialize, Default, Clone, Debug, PartialEq)]
+#[serde(default)]
+pub struct ConnectionProfile {
+    pub connection_name: String,
+    pub host: String,
+    /// A port, or a comma separated list of them, one per host. A number is taken as well, as TOML written by hand
+    /// is likely to have one
+    #[serde(deserialize_with = "text_or_number")]
+    pub port: String,
+    pub user: String,
+    pub dbname: String,
+    pub sslmode: String,
+    pub sslrootcert: String,
+    pub sslcert: String,
+    pub sslkey: String,
+    pub auth: String,
+    pub aws_region: String,
+    pub target_session_attrs: String,
+    pub ssh_host: String,
+    pub ssh_user: String,
+    pub ssh_key: String,
+    /// Collectors run when the connection is loaded
+    pub collectors: Vec<String>,
+    /// Keyring entry the password was saved in with `--use-keyring`, empty for none
+    pub keyring_entry: String,
+}
+
+impl ConnectionProfile {
+    /// Reads an entry of the connections file. Returns `MalformedConnectionsFile`, naming the entry, when a field
+    /// holds something other than what it should, e.g. a list where the host belongs.
+    pub(crate) fn from_value(entry: &Value) -> Result<ConnectionProfile, PGError> {
+        ConnectionProfile::deserialize(entry).map_err(|e| PGError::MalformedConnectionsFile {
+            reason: match entry["connection_name"].as_str() {
+                Some(name) => format!("saved connection {}: {}", name, e),
+                None => format!("saved connection without a connection_name: {}", e),
+            },
+        })
+    }
+
+    /// The entry of the connections file holding the connection
+    pub(crate) fn to_value(&self) -> Value {
+        serde_json::to_value(self).expect("Failed to serialize connection profile")
+    }
+}
+
+/// Reads a field that is text, or a number written without quotes, as text
+fn text_or_number<'de, D: Deserializer<'de>>(deserializer: D) -> Result<String, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum TextOrNumber {
+        Text(String),
+        Number(u64),
+    }
+    match TextOrNumber::deserialize(deserializer)? {
+        TextOrNumber::Text(text) => Ok(text),
+        TextOrNumber::Number(number) => Ok(number.to_string()),
+    }
+}
+
+/// The saved connections in the connections file `file`, or `MalformedConnectionsFile` when it has no list of them
+pub(crate) fn profiles(file: &Value) -> Result<&Vec<Value>, PGError> {
+    file["connections"].as_array().ok_or_else(not_a_list)
+}
+
+/// Same as `profiles`, for adding, changing or removing saved connections
+pub(crate) fn profiles_mut(file: &mut Value) -> Result<&mut Vec<Value>, PGError> {
+    file.get_mut("connections").and_then(|c| c.as_array_mut()).ok_or_else(not_a_list)
+}
+
+fn not_a_list() -> PGError {
+    PGError::MalformedConnectionsFile {
+        reason: "connections is not a list of saved connections".to_string(),
+    }
+}
+
+/// The saved connection named `name` in the connections file `file`, or `MatchNotFound`
+pub(crate) fn find(file: &Value, name: &str) -> Result<ConnectionProfile, PGError> {
+    let saved = profiles(file)?;
+    ConnectionProfile::from_value(&saved[position(saved, name)?])
+}
+
+/// Index of the saved connection named `name` in `profiles`, or `MatchNotFound`
+pub(crate) fn position(profiles: &[Value], name: &str) -> Result<usize, PGError> {
+    profiles
+        .iter()
+        .position(|p| p["connection_name"].as_str() == Some(name))
+        .ok_or(PGError::MatchNotFound)
+}
+
+/// Checks `value` for the `field` of a saved connection and converts it to what the file holds: a list of names for
+/// `collectors`, given separated by commas, and the text itself for the others. <br>
+/// Returns the reason a value is refused, e.g. an `sslmode` libpq does not know or a collector that does not exist.
+fn field_value(field: &str, value: &str) -> Result<Value, String> {
+    let one_of = |allowed: &[&str]| match value.is_empty() || allowed.contains(&value) {
+        true => Ok(json!(value)),
+        false => Err(format!("{} must be one of: {}, or empty for the default", field, allowed.join(", "))),
+    };
+    match field {
+        "host" | "user" | "dbname" if value.is_empty() => Err(format!("{} cannot be empty", field)),
+        "port" if !value.split(',').all(|p| p.trim().is_empty() || p.trim().parse::<u16>().is_ok()) => {
+            Err(format!("invalid port {}, expected a number or a comma separated list of them", value))
+        }
+        "sslmode" => one_of(SSLMODES),
+        "auth" => one_of(AUTH_METHODS),
+        "target_session_attrs" => one_of(TARGET_SESSION_ATTRS),
+        "collectors" => {
+            let names: Vec<&str> = value.split(',').map(|n| n.trim()).filter(|n| !n.is_empty()).collect();
+            match names.iter().find(|n| find_collector(n).is_none()) {
+                Some(unknown) => Err(format!("there is no collector named {}", unknown)),
+                None => Ok(json!(names)),
+            }
+        }
+        field if EDITABLE_FIELDS.contains(&field) => Ok(json!(value)),
+        _ => Err(format!("unknown field {}, expected one of: {}", field, EDITABLE_FIELDS.join(", "))),
+    }
+}
+
+impl Connection {
+    /// Builds the `Connection` a saved connection describes, without connecting and without a password
+    pub fn from_profile(profile: &ConnectionProfile) -> Connection {
+        Connection {
+            host: profile.host.clone(),
+            port: profile.port.clone(),
+            user: profile.user.clone(),
+            dbname: profile.dbname.clone(),
+            sslmode: profile.sslmode.clone(),
+            sslrootcert: profile.sslrootcert.clone(),
+            sslcert: profile.sslcert.clone(),
+            sslkey: profile.sslkey.clone(),
+            auth: profile.auth.clone(),
+            aws_region: profile.aws_region.clone(),
+            target_session_attrs: profile.target_session_attrs.clone(),
+            ssh_host: profile.ssh_host.clone(),
+            ssh_user: profile.ssh_user.clone(),
+            ssh_key: profile.ssh_key.clone(),
+            collectors: profile.collectors.clone(),
+            ..Default::default()
+        }
+    }
+
+    /// The saved connection describing this `Connection` under `connection_name`, without a keyring entry
+    pub fn profile(&self, connection_name: &str) -> ConnectionProfile {
+        ConnectionProfile {
+            connection_name: connection_name.to_string(),
+            host: self.host.clone(),
+            port: self.port.clone(),
+            user: self.user.clone(),
+            dbname: self.dbname.clone(),
+            sslmode: self.sslmode.clone(),
+            sslrootcert: self.sslrootcert.clone(),
+            sslcert: self.sslcert.clone(),
+            sslkey: self.sslkey.clone(),
+            auth: self.auth.clone(),
+            aws_region: self.aws_region.clone(),
+            target_session_attrs: self.target_session_attrs.clone(),
+            ssh_host: self.ssh_host.clone(),
+            ssh_user: self.ssh_user.clone(),
+            ssh_key: self.ssh_key.clone(),
+            collectors: self.collectors.clone(),
+            keyring_entry: String::new(),
+        }
+    }
+}
+
+/// Lists the saved connections, one row each with their server, role, database, TLS mode, authentication method, the
+/// collectors run when loading them and whether their password is in the keyring
+pub fn list_profiles() -> Result<Value, PGError> {
+    let file = connections_file::load_or_new(&connections_file::path())?;
+    let mut rows = Vec::new();
+    for entry in profiles(&file)? {
+        let p = ConnectionProfile::from_value(entry)?;
+        rows.push(json!({
+            "connection_name": p.connection_name,
+            "host": p.host,
+            "port": p.port,
+            "user": p.user,
+            "dbname": p.dbname,
+            "sslmode": p.sslmode,
+            "auth": p.auth,
+            "collectors": p.collectors.join(", "),
+            "password": if p.keyring_entry.is_empty() { "" } else { "keyring" },
+        }));
+    }
+    Ok(Value::Array(rows))
+}
+
+/// Deletes the saved connection `name`, or returns `MatchNotFound`. <br>
+/// A password it was saved with in the keyring is removed as well, unless another saved connection uses the same
+/// entry. The connection is deleted even if the keyring cannot be reached.
+pub fn delete_profile(name: &str) -> Result<(), PGError> {
+    let path = connections_file::path();
+    let mut file = connections_file::load_or_new(&path)?;
+    let saved = profiles_mut(&mut file)?;
+    let removed = saved.remove(position(saved, name)?);
+    connections_file::save(&path, &file)?;
+
+    let entry = removed["keyring_entry"].as_str().unwrap_or("");
+    let shared = profiles(&file)?.iter().any(|p| p["keyring_entry"].as_str() == Some(entry));
+    if !entry.is_empty() && !shared && secrets::delete_password(entry).is_err() {
+        warn!("remove the keyring entry {} of service psql_stats yourself", entry);
+    }
+    Ok(())
+}
+
+/// Renames the saved connection `name` to `new_name`, returning `MatchNotFound` if there is no connection named `name`
+/// and `DuplicateConnection` if `new_name` is taken
+pub fn rename_profile(name: &str, new_name: &str) -> Result<(), PGError> {
+    let path = connections_file::path();
+    let mut file = connections_file::load_or_new(&path)?;
+    let saved = profiles_mut(&mut file)?;
+    let index = position(saved, name)?;
+    if name != new_name && position(saved, new_name).is_ok() {
+        return Err(PGError::DuplicateConnection);
+    }
+    saved[index]["connection_name"] = json!(new_name);
+    connections_file::save(&path, &file)
+}
+
+/// Sets `field`, one of `EDITABLE_FIELDS`, of the saved connection `name` to `value`, checked by `field_value`. <br>
+/// Returns `MatchNotFound` if there is no such connection and `InvalidProfileField`, after printing why, if the field
+/// or value is refused.
+pub fn edit_profile(name: &str, field: &str, value: &str) -> Result<(), PGError> {
+    let path = connections_file::path();
+    let mut file = connections_file::load_or_new(&path)?;
+    let saved = profiles_mut(&mut file)?;
+    let index = position(saved, name)?;
+    let value = field_value(field, value.trim()).map_err(|e| {
+        error!("{}", e);
+        PGError::InvalidProfileField
+    })?;
+    saved[index][field] = value;
+    connections_file::save(&path, &file)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fields_are_read_by_name() {
+        let entry = json!({
+            "connection_name": "staging",
+            "host": "db.internal",
+            "port": "5433",
+            "user": "reporting",
+            "dbname": "shop",
+            "collectors": ["replication"],
+        });
+        let connection = Connection::from_profile(&ConnectionProfile::from_value(&entry).unwrap());
+        assert_eq!(connection.host, "db.internal");
+        assert_eq!(connection.port, "5433");
+        assert_eq!(connection.user, "reporting");
+        assert_eq!(connection.dbname, "shop");
+        assert_eq!(connection.collectors, vec!["replication"]);
+    }
+
+    #[test]
+    fn missing_fields_are_left_empty() {
+        let profile = ConnectionProfile::from_value(&json!({"connection_name": "local", "dbname": "shop"})).unwrap();
+        assert_eq!(profile.dbname, "shop");
+        assert_eq!(profile.host, "");
+        assert_eq!(profile.port, "");
+        assert!(profile.collectors.is_empty());
+        assert_eq!(profile.keyring_entry, "");
+    }
+
+    #[test]
+    fn ports_written_as_numbers_are_read() {
+        let profile = ConnectionProfile::from_value(&json!({"connection_name": "local", "port": 5433})).unwrap();
+        assert_eq!(profile.port, "5433");
+    }
+
+    #[test]
+    fn fields_of_the_wrong_type_name_the_connection() {
+        match ConnectionProfile::from_value(&json!({"connection_name": "staging", "host": ["a", "b"]})) {
+            Err(PGError::MalformedConnectionsFile { reason }) => assert!(reason.contains("staging"), "{}", reason),
+            other => panic!("expected MalformedConnectionsFile, got {:?}", other),
+        }
+        assert!(matches!(
+            ConnectionProfile::from_value(&json!("not a connection")),
+            Err(PGError::MalformedConnectionsFile { .. })
+        ));
+    }
+
+    #[test]
+    fn profiles_round_trip_through_values() {
+        let connection = Connection {
+            host: "db.internal".to_string(),
+            dbname: "shop".to_string(),
+            password: "secret".to_string(),
+            ..Default::default()
+        };
+        let value = connection.profile("staging").to_value();
+        assert!(value.get("password").is_none());
+        assert_eq!(ConnectionProfile::from_value(&value).unwrap(), connection.profile("staging"));
+    }
+
+    #[test]
+    fn find_reports_missing_connections() {
+        let file = json!({"connections": [{"connection_name": "staging"}]});
+        assert_eq!(find(&file, "staging").unwrap().connection_name, "staging");
+        assert!(matches!(find(&file, "production"), Err(PGError::MatchNotFound)));
+        assert!(matches!(find(&json!({}), "staging"), Err(PGError::MalformedConnectionsFile { .. })));
+    }
+}
@@ -0,0 +1,166 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use serde_json::Value;
+use signal_hook::consts::SIGINT;
+
+use super::{Connection, PGError};
+
+/// Width, in characters, of the progress bar
+const BAR_WIDTH: f64 = 30.0;
+
+/// Server version from which `pg_stat_progress_create_index` reports the progress of index builds
+const INDEX_PROGRESS_VERSION: i32 = 120000;
+
+/// Progress of every running CREATE INDEX / REINDEX, joined with how long the statement has been running
+const INDEX_PROGRESS_QUERY: &str = r#"
+    SELECT p.pid,
+           p.datname,
+           p.relid::regclass::text AS table_name,
+           p.index_relid::regclass::text AS index_name,
+           p.command,
+           p.phase,
+           p.lockers_total,
+           p.lockers_done,
+           p.current_locker_pid,
+           p.blocks_total,
+           p.blocks_done,
+           p.tuples_total,
+           p.tuples_done,
+           date_trunc('second', now() - a.query_start)::text AS running_for
+    FROM pg_stat_progress_create_index p
+    LEFT JOIN pg_stat_activity a ON a.pid = p.pid
+    ORDER BY p.pid
+"#;
+
+impl Connection {
+    /// Shows the progress of running CREATE INDEX (CONCURRENTLY) and REINDEX commands, refreshing every
+    /// `interval` until no build is left or the user presses Ctrl-C. <br>
+    /// The ETA of each build is estimated from how fast its current phase advanced since the previous refresh. Needs
+    /// Postgres 12 or newer.
+    pub fn watch_index_builds(&mut self, interval: Duration) -> Result<(), PGError> {
+        self.require_version(INDEX_PROGRESS_VERSION)?;
+
+        // Ctrl-C stops the watch instead of the whole program
+        let stop = Arc::new(AtomicBool::new(false));
+        let signal = signal_hook::flag::register(SIGINT, Arc::clone(&stop)).ok();
+
+        // Work done per pid and phase at the previous refresh, to estimate the rate
+        let mut previous: HashMap<(i64, String), (f64, Instant)> = HashMap::new();
+        let mut seen_any = false;
+        let mut result = Ok(());
+
+        while !stop.load(Ordering::Relaxed) {
+            let builds = match self.query_json(INDEX_PROGRESS_QUERY) {
+                Ok(Value::Array(b)) => b,
+                Ok(_) => Vec::new(),
+                Err(e) => {
+                    result = Err(e);
+                    break;
+                }
+            };
+
+            if builds.is_empty() {
+                if seen_any {
+                    println!("All index builds have finished.");
+                } else {
+                    println!("No index builds in progress.");
+                }
+                break;
+            }
+            seen_any = true;
+
+            // Clear the screen and move the cursor to the top left
+            print!("\x1B[2J\x1B[H");
+            println!("Index builds in progress (Ctrl-C to stop watching):\n");
+            for build in &builds {
+                println!("{}", render_build(build, &mut previous));
+            }
+
+            thread::sleep(interval);
+        }
+
+        if let Some(id) = signal {
+            signal_hook::low_level::unregister(id);
+        }
+        result
+    }
+}
+
+/// Renders a single row of `INDEX_PROGRESS_QUERY`, updating `previous` with the work done so far
+fn render_build(build: &Value, previous: &mut HashMap<(i64, String), (f64, Instant)>) -> String {
+    let pid = build["pid"].as_i64().unwrap_or(0);
+    let phase = build["phase"].as_str().unwrap_or("").to_string();
+    let number = |key: &str| build[key].as_f64().unwrap_or(0.0);
+
+    // Phases either scan blocks or process tuples, use whichever this phase reports
+    let (done, total, unit) = if number("blocks_total") > 0.0 {
+        (number("blocks_done"), number("blocks_total"), "blocks")
+    } else {
+        (number("tuples_done"), number("tuples_total"), "tuples")
+    };
+
+    let mut lines = vec![format!(
+        "\tpid {}  {}  {} on {}  running {}",
+        pid,
+        build["command"].as_str().unwrap_or(""),
+        build["index_name"].as_str().unwrap_or("-"),
+        build["table_name"].as_str().unwrap_or("-"),
+        build["running_for"].as_str().unwrap_or("-"),
+    )];
+    lines.push(format!("\t  phase:   {}", phase));
+
+    if total > 0.0 {
+        let fraction = (done / total).min(1.0);
+        let filled = (fraction * BAR_WIDTH).round() as usize;
+        lines.push(format!(
+            "\t  {:<8} {} / {} ({:.1}%) [{}{}]",
+            format!("{}:", unit),
+            done,
+            total,
+            fraction * 100.0,
+            "#".repeat(filled),
+            ".".repeat(BAR_WIDTH as usize - filled),
+        ));
+    }
+
+    if number("lockers_total") > 0.0 {
+        let current = match build["current_locker_pid"].as_i64() {
+            Some(p) if p != 0 => format!(" (waiting on pid {})", p),
+            _ => "".to_string(),
+        };
+        lines.push(format!(
+            "\t  lockers: waited on {} / {}{}",
+            number("lockers_done"),
+            number("lockers_total"),
+            current
+        ));
+    }
+
+    let now = Instant::now();
+    let eta = match previous.get(&(pid, phase.clone())) {
+        Some((before, at)) if done > *before => {
+            let rate = (done - before) / now.duration_since(*at).as_secs_f64();
+            format_eta((total - done) / rate)
+        }
+        _ if total > 0.0 => "estimating...".to_string(),
+        _ => "unknown for this phase".to_string(),
+    };
+    lines.push(format!("\t  ETA for this phase: {}", eta));
+    previous.insert((pid, phase), (done, now));
+
+    lines.join("\n")
+}
+
+/// Formats a number of seconds as e.g. "2m 10s"
+fn format_eta(seconds: f64) -> String {
+    let seconds = seconds.max(0.0).round() as u64;
+    match seconds {
+        s if s >= 3600 => format!("{}h {}m", s / 3600, (s % 3600) / 60),
+        s if s >= 60 => format!("{}m {}s", s / 60, s % 60),
+        s => format!("{}s", s),
+    }
+}
@@ -0,0 +1,94 @@
+use std::collections::HashSet;
+
+use serde_json::{json, Value};
+
+use super::precheck::referenced_relations;
+use super::{Connection, PGError};
+
+/// Most scanned partitions listed by name for a table, the others are only counted
+const LISTED_PARTITIONS: usize = 10;
+
+/// Query describing the partitioned table `$1`: its name, its partition key and the name of each of its leaf
+/// partitions, those of sub-partitioned partitions included. Returns no rows when `$1` is not a partitioned table.
+const PARTITIONS_QUERY: &str = r#"
+    SELECT t.oid::regclass::text AS table_name,
+           pg_get_partkeydef(t.oid) AS partition_key,
+           c.relname AS partition
+    FROM pg_class t
+    LEFT JOIN LATERAL (SELECT relid FROM pg_partition_tree(t.oid) WHERE isleaf) p ON true
+    LEFT JOIN pg_class c ON c.oid = p.relid
+    WHERE t.oid = to_regclass($1)
+    AND t.relkind = 'p'
+    ORDER BY c.relname
+"#;
+
+/// Adds the relations the nodes of `plan`, and of its children, scan or modify to `relations`
+fn plan_relations(plan: &Value, relations: &mut HashSet<String>) {
+    if let Some(relation) = plan["Relation Name"].as_str() {
+        relations.insert(relation.to_string());
+    }
+    for child in plan["Plans"].as_array().into_iter().flatten() {
+        plan_relations(child, relations);
+    }
+}
+
+impl Connection {
+    /// Checks whether `sql` lets the planner prune partitions: for every partitioned table the statement names, how
+    /// many leaf partitions it has, how many the plan of `EXPLAIN` still scans and how many were pruned, with the
+    /// partition key so a query that does not filter on it stands out. The statement itself is not run. <br>
+    /// Partitions pruned when the executor starts, e.g. for a `$1` compared with the key, count as pruned, while those
+    /// only skipped while the query runs, for values read from another table, count as scanned. <br>
+    /// Returns `NotPartitioned` when the statement names no partitioned table.
+    pub fn partition_pruning(&mut self, sql: &str) -> Result<Value, PGError> {
+        let mut tables: Vec<(String, String, Vec<String>)> = Vec::new();
+        for (relation, _) in referenced_relations(sql) {
+            let rows = match self.query_json_with(PARTITIONS_QUERY, &[&relation]) {
+                Ok(Value::Array(rows)) if !rows.is_empty() => rows,
+                // Not partitioned, or a name to_regclass cannot parse, most likely one we misread
+                _ => continue,
+            };
+            let name = rows[0]["table_name"].as_str().unwrap_or(&relation).to_string();
+            if tables.iter().any(|(t, _, _)| *t == name) {
+                continue;
+            }
+            let key = rows[0]["partition_key"].as_str().unwrap_or("").to_string();
+            let partitions = rows.iter().filter_map(|r| r["partition"].as_str().map(|p| p.to_string())).collect();
+            tables.push((name, key, partitions));
+        }
+        if tables.is_empty() {
+            return Err(PGError::NotPartitioned);
+        }
+
+        let plan = self.explain(sql)?;
+        let mut scanned_relations = HashSet::new();
+        plan_relations(&plan[0]["Plan"], &mut scanned_relations);
+
+        let rows = tables
+            .into_iter()
+            .map(|(table, key, partitions)| {
+                let scanned: Vec<&String> = partitions.iter().filter(|p| scanned_relations.contains(*p)).collect();
+                let pruned = partitions.len() - scanned.len();
+                let mut listed: Vec<&str> = scanned.iter().take(LISTED_PARTITIONS).map(|p| p.as_str()).collect();
+                let more = format!("and {} more", scanned.len().saturating_sub(LISTED_PARTITIONS));
+                if scanned.len() > LISTED_PARTITIONS {
+                    listed.push(&more);
+                }
+                let note = match (scanned.len(), pruned) {
+                    (0, _) if !partitions.is_empty() => Some("every partition pruned, nothing is read"),
+                    (_, 0) if partitions.len() > 1 => Some("no partition pruned, the query does not filter on the key"),
+                    _ => None,
+                };
+                json!({
+                    "table": table,
+                    "partition_key": key,
+                    "partitions": partitions.len(),
+                    "scanned": scanned.len(),
+                    "pruned": pruned,
+                    "scanned_partitions": listed.join(", "),
+                    "note": note,
+                })
+            })
+            .collect();
+        Ok(Value::Array(rows))
+    }
+}
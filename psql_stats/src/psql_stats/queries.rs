@@ -0,0 +1,116 @@
+use std::error::Error;
+
+use bytes::BytesMut;
+use postgres::types::{to_sql_checked, Format, IsNull, ToSql, Type};
+
+/// A value bound to a `$n` placeholder, sent as text for the server to parse into whatever type the statement
+/// expects there, the way psql's `\bind` does. `None` binds `NULL`. <br>
+/// The value never becomes part of the SQL, so it cannot change what the statement does however it is written.
+#[derive(Debug)]
+pub struct TextParam(pub Option<String>);
+
+impl ToSql for TextParam {
+    fn to_sql(&self, _: &Type, out: &mut BytesMut) -> Result<IsNull, Box<dyn Error + Sync + Send>> {
+        match &self.0 {
+            Some(value) => {
+                out.extend_from_slice(value.as_bytes());
+                Ok(IsNull::No)
+            }
+            None => Ok(IsNull::Yes),
+        }
+    }
+
+    fn accepts(_: &Type) -> bool {
+        true
+    }
+
+    fn encode_format(&self, _: &Type) -> Format {
+        Format::Text
+    }
+
+    to_sql_checked!();
+}
+
+/// The options and statement of a `query` command: <br>
+/// `query [--param <value> | --null ...] [--save <name> | --export <file>] <sql | saved query name>`
+pub struct QueryArgs {
+    /// Values for `$1`, `$2`, ... in order
+    pub params: Vec<TextParam>,
+    /// Name to save the statement under instead of running it
+    pub save: Option<String>,
+    /// File to write the rows to instead of showing them, see `Connection::export_query`
+    pub export: Option<String>,
+    /// The statement, or the name of a saved query, as typed
+    pub sql: String,
+}
+
+impl QueryArgs {
+    /// Parses what follows `query` on the command line. Options come first and their values may be quoted with
+    /// single or double quotes to hold spaces, everything after them is the statement, kept exactly as typed.
+    pub fn parse(text: &str) -> Result<QueryArgs, String> {
+        let mut args = QueryArgs {
+            params: Vec::new(),
+            save: None,
+            export: None,
+            sql: String::new(),
+        };
+        let mut rest = text.trim_start();
+        loop {
+            let (option, after) = next_word(rest);
+            match option.as_str() {
+                "--param" => {
+                    if after.trim().is_empty() {
+                        return Err("--param needs a value".to_string());
+                    }
+                    let (value, after) = next_word(after);
+                    args.params.push(TextParam(Some(value)));
+                    rest = after;
+                }
+                "--null" => {
+                    args.params.push(TextParam(None));
+                    rest = after;
+                }
+                "--save" => {
+                    let (name, after) = next_word(after);
+                    if name.is_empty() {
+                        return Err("--save needs a name".to_string());
+                    }
+                    args.save = Some(name);
+                    rest = after;
+                }
+                "--export" => {
+                    let (file, after) = next_word(after);
+                    if file.is_empty() {
+                        return Err("--export needs a file".to_string());
+                    }
+                    args.export = Some(file);
+                    rest = after;
+                }
+                _ => break,
+            }
+        }
+
+        args.sql = rest.trim().trim_end_matches(';').trim_end().to_string();
+        if args.sql.is_empty() {
+            return Err("no query given".to_string());
+        }
+        Ok(args)
+    }
+}
+
+/// Splits the first word off `text`, unquoting it if it is quoted, and returns it with the rest of the text
+fn next_word(text: &str) -> (String, &str) {
+    let text = text.trim_start();
+    let quote = match text.chars().next() {
+        Some(q @ ('\'' | '"')) => q,
+        _ => {
+            let end = text.find(char::is_whitespace).unwrap_or(text.len());
+            return (text[..end].to_string(), &text[end..]);
+        }
+    };
+
+    match text[1..].find(quote) {
+        Some(end) => (text[1..end + 1].to_string(), &text[end + 2..]),
+        None => (text[1..].to_string(), ""),
+    }
+}
@@ -0,0 +1,270 @@
+use std::error::Error;
+use std::time::Duration;
+
+use chrono::{DateTime, NaiveDate, NaiveDateTime, NaiveTime, Utc};
+use postgres::error::ErrorPosition;
+use postgres::row::Row;
+use postgres::types::{FromSql, Kind, Type};
+use serde_json::{Map, Value};
+
+use super::auth::hex;
+use super::timezone::format_timestamp;
+
+/// The outcome of a custom query
+pub struct QueryResult {
+    /// Names of the columns returned, empty for statements returning no rows such as `UPDATE` without `RETURNING`
+    pub columns: Vec<String>,
+    /// Types of the columns, in the same order
+    pub types: Vec<Type>,
+    /// The rows returned, as a JSON array of objects keyed by `columns`
+    pub rows: Value,
+    /// Rows returned, or inserted, updated or deleted
+    pub affected: u64,
+    /// Whether more rows were returned than the `max_rows` read
+    pub truncated: bool,
+    /// Wall-clock time from sending the statement to receiving its last row
+    pub elapsed: Duration,
+}
+
+/// The bytes of a value as the server sent them in the binary format, accepted for every type
+struct RawValue<'a>(&'a [u8]);
+
+impl<'a> FromSql<'a> for RawValue<'a> {
+    fn from_sql(_: &Type, raw: &'a [u8]) -> Result<Self, Box<dyn Error + Sync + Send>> {
+        Ok(RawValue(raw))
+    }
+
+    fn accepts(_: &Type) -> bool {
+        true
+    }
+}
+
+/// Names of the columns of a statement, with duplicates such as two `?column?` numbered so each is a distinct key
+pub(crate) fn column_names<'a>(names: impl Iterator<Item = &'a str>) -> Vec<String> {
+    let mut columns: Vec<String> = Vec::new();
+    for name in names {
+        let mut unique = name.to_string();
+        let mut n = 1;
+        while columns.contains(&unique) {
+            n += 1;
+            unique = format!("{}_{}", name, n);
+        }
+        columns.push(unique);
+    }
+    columns
+}
+
+/// Converts a row of any query into a JSON object keyed by `columns`. <br>
+/// Booleans and numbers, `numeric` included, become JSON booleans and numbers, `json` and `jsonb` stay JSON, and
+/// text, dates, times, intervals and UUIDs are written as Postgres prints them. Values of other types are shown as
+/// `(<type> value)`.
+pub(crate) fn row_to_json(row: &Row, columns: &[String]) -> Value {
+    let mut object = Map::new();
+    for (i, (column, name)) in row.columns().iter().zip(columns).enumerate() {
+        let value = match row.try_get::<_, Option<RawValue>>(i) {
+            Ok(Some(RawValue(raw))) => decode(column.type_(), raw)
+                .unwrap_or_else(|| Value::String(format!("({} value)", column.type_().name()))),
+            _ => Value::Null,
+        };
+        object.insert(name.clone(), value);
+    }
+    Value::Object(object)
+}
+
+/// Formats a failed query the way psql does: the message, the line of the query it points at with a caret under the
+/// position, and any detail or hint
+pub(crate) fn describe_query_error(query: &str, error: &postgres::Error) -> String {
+    let db = match error.as_db_error() {
+        Some(d) => d,
+        None => return error.to_string(),
+    };
+
+    let mut text = db.message().to_string();
+    if let Some(ErrorPosition::Original(position)) = db.position() {
+        // The position counts characters from 1
+        let before: String = query.chars().take((*position as usize).saturating_sub(1)).collect();
+        let line_number = before.matches('\n').count() + 1;
+        let column = before.rsplit('\n').next().unwrap_or("").chars().count();
+        let line = query.lines().nth(line_number - 1).unwrap_or("");
+        let prefix = format!("LINE {}: ", line_number);
+        text += &format!("\n{}{}\n{}^", prefix, line, " ".repeat(prefix.len() + column));
+    }
+    if let Some(detail) = db.detail() {
+        text += &format!("\nDETAIL: {}", detail);
+    }
+    if let Some(hint) = db.hint() {
+        text += &format!("\nHINT: {}", hint);
+    }
+    text
+}
+
+fn decode(ty: &Type, raw: &[u8]) -> Option<Value> {
+    let value = match *ty {
+        Type::BOOL => Value::Bool(*raw.first()? != 0),
+        Type::INT2 => Value::from(i16::from_be_bytes(raw.try_into().ok()?)),
+        Type::INT4 => Value::from(i32::from_be_bytes(raw.try_into().ok()?)),
+        Type::INT8 => Value::from(i64::from_be_bytes(raw.try_into().ok()?)),
+        Type::OID => Value::from(u32::from_be_bytes(raw.try_into().ok()?)),
+        Type::FLOAT4 => float(f32::from_be_bytes(raw.try_into().ok()?) as f64),
+        Type::FLOAT8 => float(f64::from_be_bytes(raw.try_into().ok()?)),
+        // Kept as text when a JSON number would print differently, e.g. losing the trailing zero of 12.50
+        Type::NUMERIC => {
+            let text = decode_numeric(raw)?;
+            match serde_json::from_str::<Value>(&text) {
+                Ok(Value::Number(n)) if n.to_string() == text => Value::Number(n),
+                _ => Value::String(text),
+            }
+        }
+        Type::JSON => serde_json::from_slice(raw).ok()?,
+        // jsonb starts with a version byte
+        Type::JSONB => serde_json::from_slice(raw.get(1..)?).ok()?,
+        Type::TIMESTAMPTZ => {
+            Value::String(format_timestamp(DateTime::<Utc>::from_sql(ty, raw).ok()?))
+        }
+        Type::TIMESTAMP => Value::String(NaiveDateTime::from_sql(ty, raw).ok()?.to_string()),
+        Type::DATE => Value::String(NaiveDate::from_sql(ty, raw).ok()?.to_string()),
+        Type::TIME => Value::String(NaiveTime::from_sql(ty, raw).ok()?.to_string()),
+        Type::INTERVAL => Value::String(decode_interval(raw)?),
+        Type::UUID => Value::String(decode_uuid(raw)?),
+        Type::BYTEA => Value::String(format!("\\x{}", hex(raw))),
+        Type::TEXT | Type::VARCHAR | Type::BPCHAR | Type::NAME | Type::CHAR | Type::UNKNOWN | Type::XML => {
+            Value::String(String::from_utf8_lossy(raw).into_owned())
+        }
+        _ if matches!(ty.kind(), Kind::Array(_)) => decode_array(ty, raw)?,
+        // Enums, and extension types such as citext, are sent as their text
+        _ if matches!(ty.kind(), Kind::Enum(_)) || ty.name() == "citext" => {
+            Value::String(String::from_utf8_lossy(raw).into_owned())
+        }
+        _ => return None,
+    };
+    Some(value)
+}
+
+/// Decodes an array, of any number of dimensions, into nested JSON arrays of its decoded elements
+fn decode_array(ty: &Type, raw: &[u8]) -> Option<Value> {
+    let element_type = match ty.kind() {
+        Kind::Array(t) => t,
+        _ => return None,
+    };
+    let int = |offset: usize| raw.get(offset..offset + 4).map(|b| i32::from_be_bytes([b[0], b[1], b[2], b[3]]));
+
+    // Dimension count, a flag for NULLs and the element type, then the size and lower bound of every dimension
+    let dimensions = int(0)? as usize;
+    if dimensions == 0 {
+        return Some(Value::Array(Vec::new()));
+    }
+    let sizes: Vec<usize> = (0..dimensions).map(|d| int(12 + d * 8).map(|s| s as usize)).collect::<Option<_>>()?;
+    let mut offset = 12 + dimensions * 8;
+    let mut elements = Vec::new();
+    for _ in 0..sizes.iter().product::<usize>() {
+        let length = int(offset)?;
+        offset += 4;
+        if length < 0 {
+            elements.push(Value::Null);
+            continue;
+        }
+        let bytes = raw.get(offset..offset + length as usize)?;
+        offset += length as usize;
+        elements.push(
+            decode(element_type, bytes).unwrap_or_else(|| Value::String(format!("({} value)", element_type.name()))),
+        );
+    }
+
+    // Group the elements from the innermost dimension outwards
+    for size in sizes.iter().skip(1).rev() {
+        elements = elements.chunks(*size).map(|c| Value::Array(c.to_vec())).collect();
+    }
+    Some(Value::Array(elements))
+}
+
+/// JSON has no NaN or infinities, those are kept as text
+fn float(value: f64) -> Value {
+    match serde_json::Number::from_f64(value) {
+        Some(n) => Value::Number(n),
+        None => Value::String(value.to_string()),
+    }
+}
+
+/// Writes out a `numeric` from its binary format: a sign, the weight of the first digit and digits in base 10000
+fn decode_numeric(raw: &[u8]) -> Option<String> {
+    let word = |i: usize| raw.get(i * 2..i * 2 + 2).map(|b| i16::from_be_bytes([b[0], b[1]]));
+    let (count, weight, sign, scale) = (word(0)? as usize, word(1)? as i64, word(2)? as u16, word(3)? as usize);
+    let digits: Vec<i64> = (0..count).map(|i| word(4 + i).map(|d| d as i64)).collect::<Option<_>>()?;
+    let digit = |index: i64| match index {
+        i if i >= 0 && (i as usize) < digits.len() => digits[i as usize],
+        _ => 0,
+    };
+
+    match sign {
+        0xC000 => return Some("NaN".to_string()),
+        0xD000 => return Some("Infinity".to_string()),
+        0xF000 => return Some("-Infinity".to_string()),
+        _ => {}
+    }
+
+    let mut text = String::new();
+    if sign == 0x4000 {
+        text.push('-');
+    }
+    if weight < 0 {
+        text.push('0');
+    } else {
+        text += &digit(0).to_string();
+        for index in 1..=weight {
+            text += &format!("{:04}", digit(index));
+        }
+    }
+    if scale > 0 {
+        let mut fraction = String::new();
+        let mut index = weight + 1;
+        while fraction.len() < scale {
+            fraction += &format!("{:04}", digit(index));
+            index += 1;
+        }
+        text.push('.');
+        text += &fraction[..scale];
+    }
+    Some(text)
+}
+
+/// Writes out an `interval` from its microseconds, days and months the way Postgres prints it, e.g.
+/// `1 year 2 mons 3 days 04:05:06.5`
+fn decode_interval(raw: &[u8]) -> Option<String> {
+    let micros = i64::from_be_bytes(raw.get(0..8)?.try_into().ok()?);
+    let days = i32::from_be_bytes(raw.get(8..12)?.try_into().ok()?);
+    let months = i32::from_be_bytes(raw.get(12..16)?.try_into().ok()?);
+
+    let plural = |n: i32, unit: &str| match n {
+        1 | -1 => format!("{} {}", n, unit),
+        _ => format!("{} {}s", n, unit),
+    };
+    let mut parts = Vec::new();
+    if months / 12 != 0 {
+        parts.push(plural(months / 12, "year"));
+    }
+    if months % 12 != 0 {
+        parts.push(plural(months % 12, "mon"));
+    }
+    if days != 0 {
+        parts.push(plural(days, "day"));
+    }
+    if micros != 0 || parts.is_empty() {
+        let sign = if micros < 0 { "-" } else { "" };
+        let micros = micros.unsigned_abs();
+        let seconds = micros / 1_000_000;
+        let mut clock = format!("{}{:02}:{:02}:{:02}", sign, seconds / 3600, seconds % 3600 / 60, seconds % 60);
+        if micros % 1_000_000 != 0 {
+            clock += format!(".{:06}", micros % 1_000_000).trim_end_matches('0');
+        }
+        parts.push(clock);
+    }
+    Some(parts.join(" "))
+}
+
+fn decode_uuid(raw: &[u8]) -> Option<String> {
+    if raw.len() != 16 {
+        return None;
+    }
+    let hex = hex(raw);
+    Some(format!("{}-{}-{}-{}-{}", &hex[0..8], &hex[8..12], &hex[12..16], &hex[16..20], &hex[20..32]))
+}
@@ -0,0 +1,94 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use colored::Colorize;
+use signal_hook::consts::SIGINT;
+use tracing::{error, info};
+
+use super::humanize::human_duration;
+use super::Connection;
+
+/// How long the server may take to answer the check whether the connection is still alive
+const CHECK_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// Wait before the first attempt to reconnect, doubled after every failed one
+const FIRST_DELAY: Duration = Duration::from_secs(1);
+
+/// Longest wait between two attempts, so a long outage is still noticed to be over soon after
+const MAX_DELAY: Duration = Duration::from_secs(30);
+
+/// How often a wait checks whether Ctrl-C was pressed
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// The wait before attempt `attempt`, counting from 1: `FIRST_DELAY` doubled for every attempt before it, at most
+/// `MAX_DELAY`
+fn backoff(attempt: u32) -> Duration {
+    FIRST_DELAY
+        .checked_mul(2u32.saturating_pow(attempt.saturating_sub(1)))
+        .unwrap_or(MAX_DELAY)
+        .min(MAX_DELAY)
+}
+
+/// Waits `delay`, cut short when `interrupted` is set. Returns whether the whole delay passed.
+fn wait(delay: Duration, interrupted: &AtomicBool) -> bool {
+    let started = Instant::now();
+    while started.elapsed() < delay {
+        if interrupted.load(Ordering::Relaxed) {
+            return false;
+        }
+        thread::sleep(POLL_INTERVAL.min(delay - started.elapsed()));
+    }
+    !interrupted.load(Ordering::Relaxed)
+}
+
+impl Connection {
+    /// Checks that the connection is still alive before a command runs on it, and when the server went away, e.g.
+    /// restarted or failed over, connects again up to `attempts` times, waiting 1s, 2s, 4s and so on, at most 30s,
+    /// before each, so the command then runs as if nothing happened. <br>
+    /// Connections that were never made, or were closed on purpose, are left alone, as is everything when `attempts`
+    /// is 0. Ctrl-C gives up waiting, leaving reconnecting to option 7.
+    pub fn reconnect_if_dropped(&mut self, attempts: u32) {
+        let dropped = match self.client.as_mut() {
+            Some(c) => c.is_closed() || c.is_valid(CHECK_TIMEOUT).is_err(),
+            None => false,
+        };
+        if !dropped || attempts == 0 {
+            return;
+        }
+
+        info!("the connection to {} was lost", self.label());
+        println!("{}", "Connection lost".red().bold());
+        let interrupted = Arc::new(AtomicBool::new(false));
+        let signal = signal_hook::flag::register(SIGINT, Arc::clone(&interrupted)).ok();
+        for attempt in 1..=attempts {
+            let delay = backoff(attempt);
+            println!(
+                "{}",
+                format!(
+                    "Reconnecting in {} (attempt {} of {}, Ctrl-C to stop)",
+                    human_duration(delay.as_secs_f64()),
+                    attempt,
+                    attempts
+                )
+                .yellow()
+            );
+            if !wait(delay, &interrupted) {
+                println!("Stopped reconnecting, use option 7 to try again.");
+                break;
+            }
+            self.connect();
+            if self.client.is_some() {
+                println!("{}", "Reconnected".green().bold());
+                break;
+            }
+            if attempt == attempts {
+                error!("could not reconnect after {} attempts, use option 7 to try again", attempts);
+            }
+        }
+        if let Some(id) = signal {
+            signal_hook::low_level::unregister(id);
+        }
+    }
+}
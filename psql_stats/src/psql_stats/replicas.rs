@@ -0,0 +1,155 @@
+use serde_json::{json, Map, Value};
+
+use super::{Connection, PGError};
+
+/// Standbys streaming from this server, with how far behind they are as seen from the primary
+pub const REPLICATION_QUERY: &str = r#"
+    SELECT application_name,
+           host(client_addr) AS client_addr,
+           state,
+           sync_state,
+           date_trunc('milliseconds', replay_lag)::text AS replay_lag,
+           pg_wal_lsn_diff(
+               CASE WHEN pg_is_in_recovery() THEN pg_last_wal_receive_lsn() ELSE pg_current_wal_lsn() END,
+               replay_lsn
+           )::bigint AS lag_bytes
+    FROM pg_stat_replication
+    ORDER BY application_name
+"#;
+
+/// Recovery status and client load of the server connected to
+const NODE_STATUS_QUERY: &str = r#"
+    SELECT pg_is_in_recovery() AS in_recovery,
+           CASE WHEN pg_is_in_recovery()
+                THEN date_trunc('seconds', now() - pg_last_xact_replay_timestamp())::text
+           END AS replay_delay,
+           (SELECT count(*) FROM pg_stat_activity
+            WHERE backend_type = 'client backend' AND state = 'active' AND pid <> pg_backend_pid()) AS active_queries,
+           (SELECT count(*) FROM pg_stat_activity WHERE backend_type = 'client backend') AS connections
+"#;
+
+impl Connection {
+    /// Compares this server with its replicas, one row per node with the server connected to first. <br>
+    /// Replicas are discovered from `pg_stat_replication`, plus the `configured` standbys given as `host` or
+    /// `host:port`. Each replica is connected to with the same credentials to read its recovery status, replay delay
+    /// and query load, replicas that cannot be reached are still listed. <br>
+    /// Discovered standbys are assumed to listen on the same port as this server unless a configured entry for the
+    /// same host gives another one.
+    pub fn replicas(&mut self, configured: &[String]) -> Result<Value, PGError> {
+        let streaming = match self.query_json(REPLICATION_QUERY)? {
+            Value::Array(rows) => rows,
+            _ => Vec::new(),
+        };
+
+        let node = format!("{}:{}", self.host, self.port);
+        let mut nodes = vec![self.node_row(&node, "connected", &Value::Null)];
+
+        let configured: Vec<(String, String)> = configured
+            .iter()
+            .map(|entry| match entry.split_once(':') {
+                Some((h, p)) => (h.to_string(), p.to_string()),
+                None => (entry.to_string(), self.port.clone()),
+            })
+            .collect();
+
+        // pg_stat_replication only knows the standby's address, take the port from a configured entry if there is one
+        for standby in &streaming {
+            let host = standby["client_addr"].as_str().unwrap_or("").to_string();
+            let port = configured
+                .iter()
+                .find(|(h, _)| *h == host)
+                .map(|(_, p)| p.clone())
+                .unwrap_or_else(|| self.port.clone());
+            nodes.push(self.replica_row(&host, &port, "streaming", standby));
+        }
+
+        for (host, port) in &configured {
+            let known = streaming.iter().any(|s| s["client_addr"] == host.as_str());
+            if !known {
+                nodes.push(self.replica_row(host, port, "configured", &Value::Null));
+            }
+        }
+
+        Ok(Value::Array(nodes))
+    }
+
+    /// Connects to the replica at `host`:`port` and builds its row, see `node_row`
+    fn replica_row(&self, host: &str, port: &str, source: &str, standby: &Value) -> Value {
+        // Standbys streaming over a unix socket have no address we could connect to
+        if host.is_empty() {
+            let mut row = Self::base_row("(unix socket)", source, standby);
+            row.insert("error".to_string(), json!("no address to connect to"));
+            return Value::Object(row);
+        }
+
+        let mut replica = Connection {
+            client: None,
+            host: host.to_string(),
+            dbname: self.dbname.clone(),
+            user: self.user.clone(),
+            port: port.to_string(),
+            password: self.password.clone(),
+            sslmode: self.sslmode.clone(),
+            sslrootcert: self.sslrootcert.clone(),
+            sslcert: self.sslcert.clone(),
+            sslkey: self.sslkey.clone(),
+            auth: self.auth.clone(),
+            aws_region: self.aws_region.clone(),
+            target_session_attrs: String::new(),
+            ssh_host: self.ssh_host.clone(),
+            ssh_user: self.ssh_user.clone(),
+            ssh_key: self.ssh_key.clone(),
+            tunnel: None,
+            connected_to: None,
+            collectors: Vec::new(),
+            privileges: Default::default(),
+            capabilities: Default::default(),
+            server_version: 0,
+            transaction: Default::default(),
+            schema: self.schema.clone(),
+            search_path: String::new(),
+            timezone: String::new(),
+            queries: 0,
+        };
+        replica.connect();
+        let row = replica.node_row(&format!("{}:{}", host, port), source, standby);
+        replica.close();
+        row
+    }
+
+    /// Builds the comparison row for the server this `Connection` is connected to
+    fn node_row(&mut self, node: &str, source: &str, standby: &Value) -> Value {
+        let mut row = Self::base_row(node, source, standby);
+        if self.client.is_none() {
+            row.insert("error".to_string(), json!("could not connect"));
+            return Value::Object(row);
+        }
+
+        match self.query_json(NODE_STATUS_QUERY) {
+            Ok(Value::Array(status)) => {
+                if let Some(Value::Object(columns)) = status.into_iter().next() {
+                    row.extend(columns);
+                }
+            }
+            Ok(_) => {}
+            Err(e) => {
+                row.insert("error".to_string(), json!(e.to_string()));
+            }
+        }
+        Value::Object(row)
+    }
+
+    /// Columns every row has, so nodes line up in the table whether or not they could be reached
+    fn base_row(node: &str, source: &str, standby: &Value) -> Map<String, Value> {
+        let mut row = Map::new();
+        row.insert("node".to_string(), json!(node));
+        row.insert("source".to_string(), json!(source));
+        for key in ["application_name", "state", "sync_state", "replay_lag", "lag_bytes"] {
+            row.insert(key.to_string(), standby.get(key).cloned().unwrap_or(Value::Null));
+        }
+        for key in ["in_recovery", "replay_delay", "active_queries", "connections", "error"] {
+            row.insert(key.to_string(), Value::Null);
+        }
+        row
+    }
+}
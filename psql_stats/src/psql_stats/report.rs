@@ -0,0 +1,340 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use chrono::Local;
+use serde_json::{json, Map, Value};
+use tracing::error;
+
+use super::collectors::COLLECTORS;
+use super::concurrent::{ConcurrentSessions, SessionTarget, MAX_SESSIONS};
+use super::healthcheck::{overall_status, Thresholds};
+use super::history::unix_now;
+use super::{Connection, PGError};
+
+impl Connection {
+    /// Runs every collector and health check and combines the results into a single JSON report, along with the
+    /// `key_metrics` the metrics database keeps. <br>
+    /// With `all_databases` per-database collectors are run in every database and their rows combined. <br>
+    /// A collector that fails does not abort the report, its section contains the error instead. Collectors the
+    /// connected role lacks the privileges for, or the database the extension, are listed under `skipped` with the
+    /// reason, and collectors reading whole relations are left out. <br>
+    /// The collectors run at the same time over a few sessions of their own, see `ConcurrentSessions`, or one after
+    /// another on this connection with `all_databases` or when those sessions cannot be opened.
+    pub fn report(&mut self, all_databases: bool, thresholds: &Thresholds) -> Value {
+        let mut collectors = Vec::new();
+        let mut skipped = Map::new();
+        for collector in COLLECTORS.iter().filter(|c| !c.full_scan) {
+            let available = self.privileges.check(collector.requires);
+            match available.and_then(|_| self.capabilities.check(collector.extension)) {
+                Ok(_) => collectors.push(collector),
+                Err(e) => {
+                    skipped.insert(collector.name.to_string(), json!(e.to_string()));
+                }
+            }
+        }
+
+        let concurrent = match all_databases {
+            true => None,
+            false => SessionTarget::of(self)
+                .and_then(|target| ConcurrentSessions::open(&target, MAX_SESSIONS))
+                .ok(),
+        };
+        let results = match concurrent {
+            Some(sessions) => {
+                self.queries += collectors.len() as u64;
+                let queries: Vec<&str> = collectors.iter().map(|c| c.query_for(self.server_version)).collect();
+                sessions.query_json(&queries)
+            }
+            None => collectors.iter().map(|c| self.collect(c, all_databases)).collect(),
+        };
+        let mut sections = Map::new();
+        for (collector, result) in collectors.iter().zip(results) {
+            let section = match result {
+                Ok(rows) => rows,
+                Err(e) => json!({ "error": e.with_context(collector.context()).to_string() }),
+            };
+            sections.insert(collector.name.to_string(), section);
+        }
+
+        let key_metrics = match self.key_metrics() {
+            Ok(metrics) => metrics,
+            Err(e) => json!({ "error": e.to_string() }),
+        };
+        let checks = self.healthcheck(thresholds);
+        let health: Vec<Value> = checks
+            .iter()
+            .map(|c| json!({ "name": c.name, "status": c.status.to_string(), "message": c.message }))
+            .collect();
+
+        json!({
+            "generated_at": unix_now(),
+            "generated_at_local": Local::now().format("%Y-%m-%d %H:%M:%S %z").to_string(),
+            "connection": {
+                "host": &self.host,
+                "port": &self.port,
+                "user": &self.user,
+                "dbname": &self.dbname,
+            },
+            "key_metrics": key_metrics,
+            "health": {
+                "status": overall_status(&checks).to_string(),
+                "checks": health,
+            },
+            "sections": sections,
+            "skipped": skipped,
+        })
+    }
+}
+
+/// Writes `report` to `path`, or when it is a directory or not given, to a file in it (or the current directory) named
+/// after the time, e.g. `psql_stats-report-20240131-142500.json`, so reports taken over time sort in order. <br>
+/// The report is written in YAML with `yaml` or when the file is named `.yaml` or `.yml`, in JSON otherwise. Returns
+/// the path written, or `JSONWriteFileError` when the file cannot be written.
+pub fn write_report(report: &Value, yaml: bool, path: Option<&str>) -> Result<String, PGError> {
+    let named_yaml = path.and_then(|p| Path::new(p).extension()).is_some_and(|e| e == "yaml" || e == "yml");
+    let format = if yaml || named_yaml { "yaml" } else { "json" };
+    let name = format!("psql_stats-report-{}.{}", Local::now().format("%Y%m%d-%H%M%S"), format);
+    let path = match path {
+        Some(dir) if Path::new(dir).is_dir() => Path::new(dir).join(name).to_string_lossy().into_owned(),
+        Some(file) => file.to_string(),
+        None => name,
+    };
+    match fs::write(&path, render_report(report, format)) {
+        Ok(_) => Ok(path),
+        Err(e) => {
+            error!("{}", e);
+            Err(PGError::JSONWriteFileError)
+        }
+    }
+}
+
+/// Writes out `report` in `format`, `json` or `yaml`
+pub fn render_report(report: &Value, format: &str) -> String {
+    match format {
+        "yaml" => to_yaml(report),
+        _ => serde_json::to_string_pretty(report).expect("Failed to serialize report"),
+    }
+}
+
+/// Writes `value` out as a YAML document, in block style with every string quoted, which is enough for a report and
+/// avoids pulling in a YAML library
+fn to_yaml(value: &Value) -> String {
+    let mut text = String::from("---\n");
+    match value {
+        Value::Object(map) if !map.is_empty() => write_yaml_map(&mut text, map, 0),
+        Value::Array(items) if !items.is_empty() => write_yaml_list(&mut text, items, 0),
+        _ => text += &format!("{}\n", yaml_scalar(value)),
+    }
+    text
+}
+
+/// Writes the entries of `map`, indented by `indent` spaces, nested collections on the following lines
+fn write_yaml_map(text: &mut String, map: &Map<String, Value>, indent: usize) {
+    for (key, value) in map {
+        text.push_str(&format!("{:indent$}{}:", "", yaml_string(key), indent = indent));
+        write_yaml_value(text, value, indent);
+    }
+}
+
+/// Writes the items of `items`, indented by `indent` spaces, the first entry of a map on the line of its dash
+fn write_yaml_list(text: &mut String, items: &[Value], indent: usize) {
+    for item in items {
+        text.push_str(&format!("{:indent$}-", "", indent = indent));
+        match item {
+            Value::Object(map) if !map.is_empty() => {
+                let mut entries = String::new();
+                write_yaml_map(&mut entries, map, indent + 2);
+                text.push(' ');
+                text.push_str(&entries[indent + 2..]);
+            }
+            _ => write_yaml_value(text, item, indent),
+        }
+    }
+}
+
+/// Writes the value of a map entry or list item after its key or dash
+fn write_yaml_value(text: &mut String, value: &Value, indent: usize) {
+    match value {
+        Value::Object(map) if !map.is_empty() => {
+            text.push('\n');
+            write_yaml_map(text, map, indent + 2);
+        }
+        Value::Array(items) if !items.is_empty() => {
+            text.push('\n');
+            write_yaml_list(text, items, indent + 2);
+        }
+        _ => text.push_str(&format!(" {}\n", yaml_scalar(value))),
+    }
+}
+
+/// A value that is not a non-empty collection, as YAML
+fn yaml_scalar(value: &Value) -> String {
+    match value {
+        Value::Null => "null".to_string(),
+        Value::Bool(b) => b.to_string(),
+        Value::Number(n) => n.to_string(),
+        Value::String(s) => yaml_string(s),
+        Value::Array(_) => "[]".to_string(),
+        Value::Object(_) => "{}".to_string(),
+    }
+}
+
+/// A string as a double quoted YAML scalar, whose escapes are those of JSON
+fn yaml_string(s: &str) -> String {
+    Value::String(s.to_string()).to_string()
+}
+
+/// Replaces identifying values in a report so it can be shared without leaking schema details. <br>
+/// Database, table, host and user names are replaced with salted hashes such as `table_1a2b3c4d`. The same name
+/// always maps to the same token within one `Anonymizer`, so relationships in the report stay readable, but the
+/// random salt stops tokens from being reversed by hashing guessed names. Literals in query text are replaced with `?`.
+pub struct Anonymizer {
+    salt: u64,
+    tokens: HashMap<String, String>,
+}
+
+impl Default for Anonymizer {
+    fn default() -> Self {
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_nanos() as u64)
+            .unwrap_or(0);
+        Anonymizer {
+            salt: nanos ^ ((std::process::id() as u64) << 32),
+            tokens: HashMap::new(),
+        }
+    }
+}
+
+impl Anonymizer {
+    /// Anonymizes `report` in place
+    pub fn anonymize(&mut self, report: &mut Value) {
+        // Names are collected first so they can also be replaced where they appear inside query text
+        self.replace_identifiers(report);
+        self.scrub_queries(report);
+    }
+
+    /// Returns the token for a `category` of name, remembering it for replacing the name inside query text
+    fn token(&mut self, category: &str, value: &str) -> String {
+        let token = format!("{}_{:08x}", category, fnv1a(self.salt, value) as u32);
+        // Query text does not tell us what kind of name a word is, so the first token seen for a name is used there
+        self.tokens.entry(value.to_string()).or_insert_with(|| token.clone());
+        token
+    }
+
+    fn replace_identifiers(&mut self, value: &mut Value) {
+        match value {
+            Value::Object(map) => {
+                for (key, v) in map.iter_mut() {
+                    match (identifier_category(key), v.as_str()) {
+                        (Some(category), Some(s)) => {
+                            *v = Value::String(self.token(category, s));
+                        }
+                        _ => self.replace_identifiers(v),
+                    }
+                }
+            }
+            Value::Array(items) => {
+                for v in items {
+                    self.replace_identifiers(v);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn scrub_queries(&self, value: &mut Value) {
+        match value {
+            Value::Object(map) => {
+                for (key, v) in map.iter_mut() {
+                    match v {
+                        Value::String(s) if key == "query" => *s = self.scrub_query(s),
+                        _ => self.scrub_queries(v),
+                    }
+                }
+            }
+            Value::Array(items) => {
+                for v in items {
+                    self.scrub_queries(v);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Replaces string and numeric literals with `?` and known names with their tokens
+    fn scrub_query(&self, query: &str) -> String {
+        let mut output = String::new();
+        let mut chars = query.chars().peekable();
+
+        while let Some(c) = chars.next() {
+            if c == '\'' {
+                // Skip to the closing quote, '' is an escaped quote inside the literal
+                while let Some(inner) = chars.next() {
+                    if inner == '\'' {
+                        if chars.peek() == Some(&'\'') {
+                            chars.next();
+                        } else {
+                            break;
+                        }
+                    }
+                }
+                output.push('?');
+            } else if c.is_alphabetic() || c == '_' || c == '"' {
+                let mut word = String::from(c);
+                while let Some(&next) = chars.peek() {
+                    if next.is_alphanumeric() || next == '_' || (c == '"' && next != '"') {
+                        word.push(next);
+                        chars.next();
+                    } else {
+                        break;
+                    }
+                }
+                if c == '"' && chars.peek() == Some(&'"') {
+                    chars.next();
+                    word.push('"');
+                }
+                let bare = word.trim_matches('"');
+                match self.tokens.get(bare) {
+                    Some(t) => output.push_str(t),
+                    None => output.push_str(&word),
+                }
+            } else if c.is_ascii_digit() {
+                while let Some(&next) = chars.peek() {
+                    if next.is_ascii_digit() || next == '.' {
+                        chars.next();
+                    } else {
+                        break;
+                    }
+                }
+                output.push('?');
+            } else {
+                output.push(c);
+            }
+        }
+        output
+    }
+}
+
+/// Returns which kind of identifier a report key holds, if it holds one
+fn identifier_category(key: &str) -> Option<&'static str> {
+    match key {
+        "dbname" | "datname" | "db" | "database" => Some("db"),
+        "user" | "usename" => Some("user"),
+        "host" => Some("host"),
+        "table_name" | "relname" => Some("table"),
+        _ => None,
+    }
+}
+
+/// 64 bit FNV-1a hash of `value`, seeded with `salt`
+fn fnv1a(salt: u64, value: &str) -> u64 {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for byte in salt.to_le_bytes().iter().chain(value.as_bytes()) {
+        hash ^= *byte as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    hash
+}
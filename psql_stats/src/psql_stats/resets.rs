@@ -0,0 +1,195 @@
+use std::fs;
+use std::path::PathBuf;
+
+use chrono::Local;
+use postgres::error::SqlState;
+use serde_json::{json, Value};
+use tracing::error;
+
+use super::capabilities::ExtensionState;
+use super::history::unix_now;
+use super::transaction::read_only;
+use super::{Config, Connection, PGError};
+
+/// Name of the file in the config directory recording when the statistics of each connection were last reset
+const RESETS_FILE: &str = "stats_resets.json";
+
+/// Values of `reset-stats`, see `StatsReset`
+pub const RESET_TARGETS: &[&str] = &["database", "bgwriter", "statements"];
+
+/// Which cumulative statistics to zero before a measurement window, see `Connection::reset_stats`
+#[derive(Clone, Copy)]
+pub enum StatsReset {
+    /// The counters of the current database and its tables, indexes and functions, with `pg_stat_reset()`
+    Database,
+    /// The counters of the background writer and checkpoints, shared by the whole server
+    Bgwriter,
+    /// The statistics `pg_stat_statements` keeps of every statement
+    Statements,
+}
+
+impl StatsReset {
+    /// The reset named `name`, one of `RESET_TARGETS`
+    pub fn from_name(name: &str) -> Option<StatsReset> {
+        match name {
+            "database" => Some(StatsReset::Database),
+            "bgwriter" => Some(StatsReset::Bgwriter),
+            "statements" => Some(StatsReset::Statements),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            StatsReset::Database => "database",
+            StatsReset::Bgwriter => "bgwriter",
+            StatsReset::Statements => "statements",
+        }
+    }
+
+    /// Asks whether to go ahead, saying what is lost and for whom
+    pub fn question(&self) -> &'static str {
+        match self {
+            StatsReset::Database => {
+                "Reset the statistics of this database? The scans, tuples, cache hits and vacuum counts of its tables \
+                 and indexes start from zero, for every user and monitoring tool."
+            }
+            StatsReset::Bgwriter => {
+                "Reset the background writer statistics? The checkpoint and buffer counts of the whole server start \
+                 from zero, for every user and monitoring tool."
+            }
+            StatsReset::Statements => {
+                "Reset pg_stat_statements? The calls and timings of every statement, in every database, are discarded."
+            }
+        }
+    }
+
+    /// Says what was done
+    pub fn done(&self) -> &'static str {
+        match self {
+            StatsReset::Database => "Reset the statistics of this database.",
+            StatsReset::Bgwriter => "Reset the background writer statistics.",
+            StatsReset::Statements => "Reset the statistics of pg_stat_statements.",
+        }
+    }
+
+    fn statement(&self) -> &'static str {
+        match self {
+            StatsReset::Database => "SELECT pg_stat_reset()",
+            StatsReset::Bgwriter => "SELECT pg_stat_reset_shared('bgwriter')",
+            StatsReset::Statements => "SELECT pg_stat_statements_reset()",
+        }
+    }
+
+    /// The collectors whose counters the reset zeroes, so snapshots measuring them across it can say so
+    pub(crate) fn collectors(&self) -> &'static [&'static str] {
+        match self {
+            StatsReset::Database => &["write-churn", "toast-reads", "cache-stats", "table-activity", "database-stats"],
+            StatsReset::Bgwriter => &["bgwriter"],
+            StatsReset::Statements => &[],
+        }
+    }
+}
+
+impl Connection {
+    /// Zeroes the statistics `reset` names, e.g. before a load test, and records when in the resets file, see
+    /// `recorded_resets`. <br>
+    /// Returns `ReadOnly` in read-only mode, `ResetDenied` when the role may not run the reset function, and for
+    /// `StatsReset::Statements` `StatementsUnavailable` or `StatementsNotPreloaded` without the extension.
+    pub fn reset_stats(&mut self, reset: StatsReset) -> Result<(), PGError> {
+        if read_only() {
+            return Err(PGError::ReadOnly);
+        }
+        if matches!(reset, StatsReset::Statements)
+            && self.capabilities.state("pg_stat_statements") == Some(&ExtensionState::NotPreloaded)
+        {
+            return Err(PGError::StatementsNotPreloaded);
+        }
+        let client = match &mut self.client {
+            Some(c) => c,
+            None => return Err(PGError::ClientEmpty),
+        };
+        match client.execute(reset.statement(), &[]) {
+            Ok(_) => {}
+            Err(e) if e.code() == Some(&SqlState::INSUFFICIENT_PRIVILEGE) => return Err(PGError::ResetDenied),
+            Err(e) if matches!(reset, StatsReset::Statements) && e.code() == Some(&SqlState::UNDEFINED_FUNCTION) => {
+                return Err(PGError::StatementsUnavailable)
+            }
+            Err(e) => return Err(PGError::query(format!("resetting the {} statistics", reset.name()), e)),
+        }
+        record_reset(&self.label(), reset);
+        Ok(())
+    }
+}
+
+fn resets_path() -> PathBuf {
+    Config::directory().join(RESETS_FILE)
+}
+
+fn read_resets() -> Value {
+    fs::read_to_string(resets_path())
+        .ok()
+        .and_then(|text| serde_json::from_str::<Value>(&text).ok())
+        .filter(|resets| resets.is_object())
+        .unwrap_or_else(|| json!({}))
+}
+
+/// Records that `reset` was done on the connection `label` now, replacing the time of the previous one. A resets file
+/// that cannot be written is reported without failing the reset, which has already happened.
+fn record_reset(label: &str, reset: StatsReset) {
+    let mut resets = read_resets();
+    resets[label][reset.name()] = json!({
+        "reset_at": unix_now(),
+        "set_at": Local::now().format("%Y-%m-%d %H:%M:%S").to_string(),
+    });
+    let text = serde_json::to_string_pretty(&resets).expect("Failed to serialize the resets");
+    let path = resets_path();
+    if let Err(e) = fs::create_dir_all(Config::directory()).and_then(|_| fs::write(&path, text)) {
+        error!("could not record the reset in {}: {}", path.display(), e);
+    }
+}
+
+/// When the statistics of the connection `label` were last reset with `reset-stats`, by reset name: `reset_at`, in
+/// seconds since the epoch, and `set_at`, in local time. Empty when they never were.
+pub(crate) fn recorded_resets(label: &str) -> Value {
+    match read_resets().get(label) {
+        Some(resets) if resets.is_object() => resets.clone(),
+        _ => json!({}),
+    }
+}
+
+/// The reset zeroing the counters of `collector`, and the local time it was done at, when `resets`, as
+/// `recorded_resets` returns them, has it done after `taken_at`
+pub(crate) fn reset_after(resets: &Value, collector: &str, taken_at: u64) -> Option<(&'static str, String)> {
+    let reset = RESET_TARGETS
+        .iter()
+        .filter_map(|name| StatsReset::from_name(name))
+        .find(|reset| reset.collectors().contains(&collector))?;
+    let recorded = &resets[reset.name()];
+    match recorded["reset_at"].as_u64() {
+        Some(reset_at) if reset_at > taken_at => {
+            Some((reset.name(), recorded["set_at"].as_str().unwrap_or("").to_string()))
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_resets_after_the_snapshot_of_the_collector_count() {
+        let resets = json!({
+            "database": {"reset_at": 200, "set_at": "2026-10-15 09:00:00"},
+            "bgwriter": {"reset_at": 50, "set_at": "2026-10-15 08:00:00"},
+        });
+        assert_eq!(
+            reset_after(&resets, "table-activity", 100),
+            Some(("database", "2026-10-15 09:00:00".to_string()))
+        );
+        assert_eq!(reset_after(&resets, "table-activity", 300), None);
+        assert_eq!(reset_after(&resets, "bgwriter", 100), None);
+        assert_eq!(reset_after(&json!({}), "bgwriter", 0), None);
+    }
+}
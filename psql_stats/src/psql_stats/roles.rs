@@ -0,0 +1,60 @@
+use serde_json::Value;
+
+use super::{Connection, PGError};
+
+/// Query listing the roles of the server, other than the predefined `pg_` ones, with their attributes and the roles
+/// they are members of. A connection limit of `NULL` means unlimited, as does a `valid_until` of `NULL`.
+pub const ROLES_QUERY: &str = r#"
+    SELECT r.rolname AS role,
+           r.rolsuper AS superuser,
+           r.rolcanlogin AS login,
+           r.rolreplication AS replication,
+           r.rolcreaterole AS create_role,
+           r.rolcreatedb AS create_db,
+           NULLIF(r.rolconnlimit, -1) AS connection_limit,
+           r.rolvaliduntil::text AS valid_until,
+           (SELECT string_agg(g.rolname, ', ' ORDER BY g.rolname)
+            FROM pg_auth_members m
+            JOIN pg_roles g ON g.oid = m.roleid
+            WHERE m.member = r.oid) AS member_of
+    FROM pg_roles r
+    WHERE r.rolname NOT LIKE 'pg\_%'
+    ORDER BY r.rolname
+"#;
+
+/// Query listing the privileges granted on the tables and views outside the system schemas, one row per grantee and
+/// table with its privileges. <br>
+/// `information_schema.role_table_grants` only shows the grants the current role gave or received, directly or
+/// through a role it is a member of, so a superuser sees them all.
+pub const TABLE_GRANTS_QUERY: &str = r#"
+    SELECT table_schema AS schema,
+           table_name,
+           grantee,
+           string_agg(privilege_type, ', ' ORDER BY privilege_type) AS privileges,
+           string_agg(CASE WHEN is_grantable = 'YES' THEN privilege_type END, ', ' ORDER BY privilege_type)
+               AS grantable,
+           string_agg(DISTINCT grantor, ', ') AS granted_by
+    FROM information_schema.role_table_grants
+    WHERE table_schema NOT IN ('pg_catalog', 'information_schema')
+    GROUP BY table_schema, table_name, grantee
+    ORDER BY table_schema, table_name, grantee
+"#;
+
+impl Connection {
+    /// Lists the roles with their attributes and memberships, see `ROLES_QUERY`
+    pub fn get_roles(&mut self) -> Result<Value, PGError> {
+        self.query_json(ROLES_QUERY)
+    }
+
+    /// Lists the privileges granted on the tables of `schema`, or of every schema but the system ones, see
+    /// `TABLE_GRANTS_QUERY`
+    pub fn get_table_grants(&mut self, schema: Option<&str>) -> Result<Value, PGError> {
+        match schema {
+            Some(schema) => {
+                let query = format!("SELECT * FROM ({}) g WHERE schema = $1", TABLE_GRANTS_QUERY);
+                self.query_json_with(&query, &[&schema])
+            }
+            None => self.query_json(TABLE_GRANTS_QUERY),
+        }
+    }
+}
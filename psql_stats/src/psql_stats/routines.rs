@@ -0,0 +1,96 @@
+use serde_json::Value;
+
+use super::{Connection, PGError};
+
+/// Query listing the functions and procedures outside the system schemas, with their arguments, what they return,
+/// their language and volatility, and whether they run with the privileges of their owner
+pub const ROUTINES_QUERY: &str = r#"
+    SELECT n.nspname AS schema,
+           p.proname AS name,
+           pg_get_function_arguments(p.oid) AS arguments,
+           pg_get_function_result(p.oid) AS returns,
+           CASE p.prokind
+                WHEN 'f' THEN 'function'
+                WHEN 'p' THEN 'procedure'
+                WHEN 'a' THEN 'aggregate'
+                WHEN 'w' THEN 'window'
+           END AS kind,
+           l.lanname AS language,
+           CASE p.provolatile
+                WHEN 'i' THEN 'immutable'
+                WHEN 's' THEN 'stable'
+                WHEN 'v' THEN 'volatile'
+           END AS volatility,
+           p.prosecdef AS security_definer
+    FROM pg_proc p
+    JOIN pg_namespace n ON n.oid = p.pronamespace
+    JOIN pg_language l ON l.oid = p.prolang
+    WHERE n.nspname NOT IN ('pg_catalog', 'information_schema')
+    AND n.nspname NOT LIKE 'pg\_%'
+    ORDER BY n.nspname, p.proname, pg_get_function_arguments(p.oid)
+"#;
+
+/// Query finding the functions and procedures named `$1`, optionally schema qualified, among the overloads visible
+/// on the search path when it is not, with their signature, language and source. Functions with a SQL standard
+/// body keep no source, their whole definition is given instead.
+const ROUTINE_SOURCE_QUERY: &str = r#"
+    SELECT p.oid::regprocedure::text, l.lanname, CASE p.prosrc WHEN '' THEN pg_get_functiondef(p.oid) ELSE p.prosrc END
+    FROM pg_proc p
+    JOIN pg_namespace n ON n.oid = p.pronamespace
+    JOIN pg_language l ON l.oid = p.prolang,
+    parse_ident($1) AS name
+    WHERE p.proname = name[array_length(name, 1)]
+    AND CASE array_length(name, 1)
+            WHEN 1 THEN pg_function_is_visible(p.oid)
+            WHEN 2 THEN n.nspname = name[1]
+            ELSE false
+        END
+    ORDER BY p.oid::regprocedure::text
+"#;
+
+/// Same as `ROUTINE_SOURCE_QUERY` for a single overload given with its argument types, e.g. `add(integer, integer)`
+const ROUTINE_SIGNATURE_SOURCE_QUERY: &str = r#"
+    SELECT p.oid::regprocedure::text, l.lanname, CASE p.prosrc WHEN '' THEN pg_get_functiondef(p.oid) ELSE p.prosrc END
+    FROM pg_proc p
+    JOIN pg_language l ON l.oid = p.prolang
+    WHERE p.oid = to_regprocedure($1)
+"#;
+
+impl Connection {
+    /// Lists the functions and procedures of `schema`, or of every schema but the system ones, see `ROUTINES_QUERY`
+    pub fn get_routines(&mut self, schema: Option<&str>) -> Result<Value, PGError> {
+        match schema {
+            Some(schema) => {
+                let query = format!("SELECT * FROM ({}) r WHERE schema = $1", ROUTINES_QUERY);
+                self.query_json_with(&query, &[&schema])
+            }
+            None => self.query_json(ROUTINES_QUERY),
+        }
+    }
+
+    /// Returns the source of the function or procedure `name`, as a heading with its signature and language
+    /// followed by its body, for every overload of it, or a single one when written with its argument types. <br>
+    /// For functions written in C, or built in, the source is the name of the function in the library. <br>
+    /// Returns `MatchNotFound` when no function has that name.
+    pub fn routine_source(&mut self, name: &str) -> Result<Vec<String>, PGError> {
+        let client = match &mut self.client {
+            Some(c) => c,
+            None => return Err(PGError::ClientEmpty),
+        };
+        let query = match name.contains('(') {
+            true => ROUTINE_SIGNATURE_SOURCE_QUERY,
+            false => ROUTINE_SOURCE_QUERY,
+        };
+        match client.query(query, &[&name]) {
+            Ok(rows) if !rows.is_empty() => Ok(rows
+                .iter()
+                .map(|r| {
+                    let (signature, language, source): (String, String, String) = (r.get(0), r.get(1), r.get(2));
+                    format!("{} (language {}):\n{}", signature, language, source.trim())
+                })
+                .collect()),
+            // parse_ident refuses names that cannot be an identifier, which cannot be a function either
+            _ => Err(PGError::MatchNotFound),
+        }
+    }
+}
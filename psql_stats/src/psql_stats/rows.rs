@@ -0,0 +1,206 @@
+use std::time::{Duration, Instant};
+
+use postgres::error::SqlState;
+use serde_json::Value;
+
+use super::humanize::{human_duration, thousands};
+use super::versions::{pick, QueryVariant};
+use super::{Connection, PGError};
+
+/// Estimated rows from which `peek` samples the table instead of reading its first rows
+const PEEK_SAMPLE_THRESHOLD: i64 = 100_000;
+
+/// How many more rows than asked for the sample aims at, as `TABLESAMPLE SYSTEM` picks whole pages and may come up
+/// short
+const PEEK_OVERSAMPLING: f64 = 10.0;
+
+/// Fewest pages the sample aims at, so the rows shown come from several parts of the table
+const PEEK_MIN_PAGES: f64 = 20.0;
+
+/// Query summing the planner's row estimates of the table `$1`, or of its leaf partitions when it is partitioned. <br>
+/// `reltuples` is -1 for a table never vacuumed or analyzed, those are counted separately rather than summed.
+const ROW_ESTIMATE_QUERY: &str = r#"
+    WITH rel AS (SELECT $1::text::regclass AS oid)
+    SELECT (sum(c.reltuples) FILTER (WHERE c.reltuples >= 0))::bigint,
+           count(*) FILTER (WHERE c.reltuples < 0),
+           max(greatest(s.last_analyze, s.last_autoanalyze, s.last_vacuum, s.last_autovacuum))::text
+    FROM pg_class c
+    LEFT JOIN pg_stat_all_tables s ON s.relid = c.oid
+    WHERE c.oid IN (SELECT oid FROM rel UNION SELECT relid FROM pg_partition_tree((SELECT oid FROM rel)) WHERE isleaf)
+    AND c.relkind IN ('r', 'm')
+"#;
+
+/// `ROW_ESTIMATE_QUERY` for Postgres 11, which has no `pg_partition_tree`: the partitions are found by walking
+/// `pg_inherits` down from the partitioned tables
+const ROW_ESTIMATE_BEFORE_12_QUERY: &str = r#"
+    WITH RECURSIVE tree AS (
+        SELECT $1::text::regclass::oid AS oid
+        UNION
+        SELECT i.inhrelid
+        FROM tree t
+        JOIN pg_class p ON p.oid = t.oid AND p.relkind = 'p'
+        JOIN pg_inherits i ON i.inhparent = t.oid
+    )
+    SELECT (sum(c.reltuples) FILTER (WHERE c.reltuples >= 0))::bigint,
+           count(*) FILTER (WHERE c.reltuples < 0),
+           max(greatest(s.last_analyze, s.last_autoanalyze, s.last_vacuum, s.last_autovacuum))::text
+    FROM pg_class c
+    LEFT JOIN pg_stat_all_tables s ON s.relid = c.oid
+    WHERE c.oid IN (SELECT oid FROM tree)
+    AND c.relkind IN ('r', 'm')
+"#;
+
+/// Forms of `ROW_ESTIMATE_QUERY` for older servers
+const ROW_ESTIMATE_VARIANTS: &[QueryVariant] = &[QueryVariant {
+    before: 120000,
+    query: ROW_ESTIMATE_BEFORE_12_QUERY,
+}];
+
+/// The planner's idea of how many rows a table holds
+pub struct RowEstimate {
+    /// Sum of the estimates, `None` when no part of the table has one
+    pub rows: Option<i64>,
+    /// Tables, or partitions, never vacuumed or analyzed and so without an estimate
+    pub unanalyzed: i64,
+    /// When the estimate was last refreshed by a vacuum or analyze
+    pub refreshed_at: Option<String>,
+}
+
+impl RowEstimate {
+    /// Describes the estimate for `table` in a sentence, pointing to `--exact` when there is none. `human` adds
+    /// thousands separators.
+    pub fn describe(&self, table: &str, human: bool) -> String {
+        let rows = match self.rows {
+            Some(r) => r,
+            None => {
+                return format!(
+                    "{} has never been vacuumed or analyzed, so there is no estimate. Run ANALYZE {}; or count {} --exact.",
+                    table, table, table
+                )
+            }
+        };
+
+        let mut text = format!("~{} rows in {} (planner estimate", format_count(rows, human), table);
+        if let Some(at) = &self.refreshed_at {
+            text += &format!(", last refreshed {}", at);
+        }
+        text += ")";
+        match self.unanalyzed {
+            0 => {}
+            1 => text += "\n1 partition has never been vacuumed or analyzed and is not included.",
+            n => text += &format!("\n{} partitions have never been vacuumed or analyzed and are not included.", n),
+        }
+        text
+    }
+}
+
+impl Connection {
+    /// Returns the planner's row estimate for `table` (optionally schema qualified) from `pg_class.reltuples`, which
+    /// is instant whatever the size of the table. <br>
+    /// Returns `MatchNotFound` if there is no such table.
+    pub fn estimate_rows(&mut self, table: &str) -> Result<RowEstimate, PGError> {
+        let query = pick(self.server_version, ROW_ESTIMATE_QUERY, ROW_ESTIMATE_VARIANTS);
+        let client = match &mut self.client {
+            Some(c) => c,
+            None => return Err(PGError::ClientEmpty),
+        };
+
+        match client.query_one(query, &[&table]) {
+            Ok(row) => Ok(RowEstimate {
+                rows: row.get(0),
+                unanalyzed: row.get(1),
+                refreshed_at: row.get(2),
+            }),
+            Err(e) => Err(missing_relation(format!("estimating the rows of {}", table), e)),
+        }
+    }
+
+    /// Counts the rows of `table` with `SELECT count(*)`, which reads the whole table, and returns the count with the
+    /// time it took
+    pub fn count_rows(&mut self, table: &str) -> Result<(i64, Duration), PGError> {
+        let client = match &mut self.client {
+            Some(c) => c,
+            None => return Err(PGError::ClientEmpty),
+        };
+
+        // Resolving the name through regclass checks it exists and gives it back safely quoted
+        let relation: String = match client.query_one("SELECT $1::text::regclass::text", &[&table]) {
+            Ok(row) => row.get(0),
+            Err(e) => return Err(missing_relation(format!("looking up {}", table), e)),
+        };
+
+        let start = Instant::now();
+        match client.query_one(format!("SELECT count(*) FROM {}", relation).as_str(), &[]) {
+            Ok(row) => Ok((row.get(0), start.elapsed())),
+            Err(e) => Err(PGError::query(format!("counting the rows of {}", relation), e)),
+        }
+    }
+}
+
+impl Connection {
+    /// Returns up to `limit` rows of `table` (optionally schema qualified), and whether they were sampled. <br>
+    /// Tables estimated above `PEEK_SAMPLE_THRESHOLD` rows are read with `TABLESAMPLE SYSTEM`, which only visits a
+    /// few random pages, so the rows come from across the table rather than from wherever it starts. Views and
+    /// smaller tables give their first rows.
+    pub fn peek(&mut self, table: &str, limit: i64) -> Result<(Value, bool), PGError> {
+        let estimate = self.estimate_rows(table)?.rows.unwrap_or(0);
+        let client = match &mut self.client {
+            Some(c) => c,
+            None => return Err(PGError::ClientEmpty),
+        };
+        let (relation, pages): (String, i32) = match client.query_one(
+            "SELECT oid::regclass::text, relpages FROM pg_class WHERE oid = $1::text::regclass",
+            &[&table],
+        ) {
+            Ok(row) => (row.get(0), row.get(1)),
+            Err(e) => return Err(missing_relation(format!("looking up {}", table), e)),
+        };
+
+        if estimate > PEEK_SAMPLE_THRESHOLD {
+            // Partitioned tables have no pages of their own, their rows are enough to go by
+            let rows_share = limit as f64 * PEEK_OVERSAMPLING / estimate as f64;
+            let pages_share = PEEK_MIN_PAGES / f64::from(pages.max(1));
+            let percent = (rows_share.max(pages_share) * 100.0).min(100.0);
+
+            // The sampled pages are read in order, shuffling picks rows from all of them
+            let sampled = self.query_json_with(
+                &format!(
+                    "SELECT * FROM {} TABLESAMPLE SYSTEM ({}) ORDER BY random() LIMIT $1",
+                    relation, percent
+                ),
+                &[&limit],
+            )?;
+            // A sample can miss every page holding rows, e.g. when the estimate is far off, so fall back to reading
+            if sampled.as_array().map(|r| r.len() as i64 == limit).unwrap_or(false) {
+                return Ok((sampled, true));
+            }
+        }
+        let rows = self.query_json_with(&format!("SELECT * FROM {} LIMIT $1", relation), &[&limit])?;
+        Ok((rows, false))
+    }
+}
+
+/// `MatchNotFound` when resolving a table name failed because there is no such table or schema, the error saying why
+/// the query failed otherwise, see `PGError::query`
+fn missing_relation(context: String, error: postgres::Error) -> PGError {
+    match error.code() {
+        Some(&SqlState::UNDEFINED_TABLE | &SqlState::INVALID_SCHEMA_NAME) => PGError::MatchNotFound,
+        _ => PGError::query(context, error),
+    }
+}
+
+/// Formats a row count, with thousands separators when `human` is set
+pub fn format_count(rows: i64, human: bool) -> String {
+    match human {
+        true => thousands(&rows.to_string()),
+        false => rows.to_string(),
+    }
+}
+
+/// Formats the time an exact count took, e.g. `1m 3s`, or in seconds with `human` off
+pub fn format_elapsed(elapsed: Duration, human: bool) -> String {
+    match human {
+        true => human_duration(elapsed.as_secs_f64()),
+        false => format!("{:.3}s", elapsed.as_secs_f64()),
+    }
+}
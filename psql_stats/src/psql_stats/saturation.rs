@@ -0,0 +1,128 @@
+use colored::Colorize;
+use serde_json::{json, Value};
+
+use super::healthcheck::{Check, Status, Thresholds};
+use super::output::OutputFormatter;
+use super::style::warning_sign;
+use super::{Config, Connection, PGError};
+
+/// Query comparing the client connections with the limit: `max_connections`, the slots kept for superusers by
+/// `superuser_reserved_connections` and for `pg_use_reserved_connections` members by `reserved_connections`
+/// (Postgres 16), and what is left for every other role, with how much of it is used. <br>
+/// `reserved_connections` is read with `current_setting(..., true)` so that older versions, which lack it, count 0.
+const CONNECTION_LIMITS_QUERY: &str = r#"
+    WITH limits AS (
+        SELECT current_setting('max_connections')::int AS max_connections,
+               current_setting('superuser_reserved_connections')::int AS superuser_reserved,
+               coalesce(current_setting('reserved_connections', true)::int, 0) AS reserved,
+               (SELECT count(*) FROM pg_stat_activity WHERE backend_type = 'client backend')::int AS connections
+    )
+    SELECT max_connections,
+           superuser_reserved,
+           reserved,
+           max_connections - superuser_reserved - reserved AS available,
+           connections,
+           round(100.0 * connections / nullif(max_connections - superuser_reserved - reserved, 0), 1) AS used_percent
+    FROM limits
+"#;
+
+/// Query counting the client connections by state: `active`, `idle`, `idle in transaction` and the others, most first
+const CONNECTION_STATES_QUERY: &str = r#"
+    SELECT coalesce(state, 'unknown') AS state,
+           count(*) AS connections,
+           round(100.0 * count(*) / sum(count(*)) OVER (), 1) AS percent
+    FROM pg_stat_activity
+    WHERE backend_type = 'client backend'
+    GROUP BY state
+    ORDER BY connections DESC, state
+"#;
+
+/// Query counting the client connections of every role and database, most first, by state, with the role's and the
+/// database's own connection limit (`ALTER ROLE ... CONNECTION LIMIT`), empty when there is none
+const CONNECTION_USERS_QUERY: &str = r#"
+    SELECT a.usename AS user,
+           a.datname AS database,
+           count(*) AS connections,
+           count(*) FILTER (WHERE a.state = 'active') AS active,
+           count(*) FILTER (WHERE a.state = 'idle') AS idle,
+           count(*) FILTER (WHERE a.state LIKE 'idle in transaction%') AS idle_in_transaction,
+           nullif((SELECT rolconnlimit FROM pg_roles WHERE oid = a.usesysid), -1) AS user_limit,
+           nullif((SELECT datconnlimit FROM pg_database WHERE oid = a.datid), -1) AS database_limit
+    FROM pg_stat_activity a
+    WHERE a.backend_type = 'client backend'
+    GROUP BY a.usename, a.usesysid, a.datname, a.datid
+    ORDER BY connections DESC, a.usename, a.datname
+"#;
+
+impl Connection {
+    /// Compares the client connections with the limit, see `CONNECTION_LIMITS_QUERY`
+    pub fn connection_limits(&mut self) -> Result<Value, PGError> {
+        match self.query_json(CONNECTION_LIMITS_QUERY)? {
+            Value::Array(mut rows) if !rows.is_empty() => Ok(rows.remove(0)),
+            _ => Err(PGError::UnexpectedResult),
+        }
+    }
+
+    /// Reports how close the server is to running out of connections: the `connection_limits` under `limits`, and
+    /// the connections by state and by role and database under `states` and `users`
+    pub fn connection_saturation(&mut self) -> Result<Value, PGError> {
+        let limits = self.connection_limits()?;
+        let states = self.query_json(CONNECTION_STATES_QUERY)?;
+        let users = self.query_json(CONNECTION_USERS_QUERY)?;
+        Ok(json!({ "limits": limits, "states": states, "users": users }))
+    }
+}
+
+/// Whether the connections in use exceed `Thresholds::connections_warn_percent` of those available to roles that are
+/// not superusers, as `Connection::connection_limits` gives them, and whether they exceed
+/// `Thresholds::connections_crit_percent`, by default once all are taken and new connections are refused. A pool
+/// growing, or sessions left idle, must be dealt with before then.
+pub fn saturation_check(limits: &Value, thresholds: &Thresholds) -> Check {
+    let used = limits["used_percent"].as_f64().unwrap_or(0.0);
+    let message = format!(
+        "{} of {} connections in use ({}%), {} more reserved",
+        limits["connections"],
+        limits["available"],
+        used,
+        limits["max_connections"].as_i64().unwrap_or(0) - limits["available"].as_i64().unwrap_or(0)
+    );
+    let status = if used >= thresholds.connections_crit_percent {
+        Status::Crit
+    } else if used >= thresholds.connections_warn_percent {
+        Status::Warn
+    } else {
+        Status::Ok
+    };
+    Check {
+        name: "connections",
+        status,
+        message,
+    }
+}
+
+/// Renders what `Connection::connection_saturation` returns: in JSON as one object, otherwise the limits, the
+/// connections by state and by role and database each in its own table, followed by a warning when
+/// `saturation_check` finds too many connections in use
+pub fn render_saturation(report: &Value, config: &Config, thresholds: &Thresholds) -> String {
+    if config.output_format == "json" {
+        return serde_json::to_string_pretty(report).expect("Failed to serialize the report");
+    }
+    let formatter = OutputFormatter::new(config);
+    let mut sections = vec![
+        formatter.titled("Connection limits", formatter.rows(&json!([report["limits"]]))),
+        formatter.titled("Connections by state", formatter.rows(&report["states"])),
+        formatter.titled("Connections by user and database", formatter.rows(&report["users"])),
+    ];
+    let check = saturation_check(&report["limits"], thresholds);
+    let warning = format!("{} {}", warning_sign(), check.message);
+    match check.status {
+        Status::Crit => sections.push(format!("{}, new connections are refused", warning).red().to_string()),
+        Status::Warn => sections.push(
+            format!("{}, above the {}% warning threshold", warning, thresholds.connections_warn_percent)
+                .yellow()
+                .to_string(),
+        ),
+        Status::Ok => {}
+    }
+    sections.join("\n")
+}
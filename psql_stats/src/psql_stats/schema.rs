@@ -0,0 +1,153 @@
+use postgres::types::{Kind, Type};
+use serde::Serialize;
+use serde_json::{json, Map, Value};
+
+use super::collectors::{Collector, COLLECTORS};
+use super::{Connection, PGError};
+
+/// Version of the JSON printed for collectors, raised whenever a field or column is removed, renamed or changes type,
+/// so automation can tell it is reading a shape it knows. New fields and columns do not change it.
+pub const SCHEMA_VERSION: u32 = 1;
+
+/// What a collector prints in the JSON output format, whether run from the menu or as a command
+#[derive(Serialize)]
+pub struct CollectorOutput<'a> {
+    /// Always `SCHEMA_VERSION`
+    pub schema_version: u32,
+    /// Name of the collector, as used to run it
+    pub collector: &'a str,
+    /// What the rows are, the collector's title with how the rows were taken, e.g. as rates or offline
+    pub heading: &'a str,
+    /// One object per row, with a key per column, see `json_schema`
+    pub rows: &'a Value,
+    /// How long the collector took, when timing is on and the rows were not read from the history
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub duration_ms: Option<f64>,
+}
+
+impl CollectorOutput<'_> {
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("Failed to serialize collector output")
+    }
+}
+
+/// Maps a column type to the JSON Schema of its values as `query_json` returns them: `json_agg` writes numbers and
+/// booleans as such, arrays as arrays, `json` as is and everything else, timestamps and intervals included, as text
+fn value_schema(ty: &Type) -> Value {
+    if let Kind::Array(element) = ty.kind() {
+        return json!({ "type": "array", "items": value_schema(element) });
+    }
+    match *ty {
+        Type::BOOL => json!({ "type": "boolean" }),
+        Type::INT2 | Type::INT4 | Type::INT8 | Type::OID | Type::XID => json!({ "type": "integer" }),
+        Type::FLOAT4 | Type::FLOAT8 | Type::NUMERIC => json!({ "type": "number" }),
+        Type::JSON | Type::JSONB => json!({}),
+        Type::TIMESTAMPTZ | Type::TIMESTAMP => json!({ "type": "string", "format": "date-time" }),
+        Type::DATE => json!({ "type": "string", "format": "date" }),
+        _ => json!({ "type": "string" }),
+    }
+}
+
+/// Allows `null` next to the values of `schema`, as any column may be `NULL`
+fn nullable(schema: Value) -> Value {
+    match schema.get("type").cloned() {
+        Some(ty) => {
+            let mut schema = schema;
+            schema["type"] = json!([ty, "null"]);
+            schema
+        }
+        None => schema,
+    }
+}
+
+/// Builds the schema of one row from its `(column, schema)` pairs. Every column is always present, along with
+/// `database` when the collector is run in every database with `--all-databases`.
+fn row_schema(columns: Vec<(String, Value)>, per_database: bool) -> Value {
+    let required: Vec<String> = columns.iter().map(|(name, _)| name.clone()).collect();
+    let mut properties = Map::new();
+    if per_database {
+        properties.insert("database".to_string(), json!({ "type": "string" }));
+    }
+    for (name, schema) in columns {
+        properties.insert(name, nullable(schema));
+    }
+    json!({ "type": "object", "properties": properties, "required": required })
+}
+
+impl Connection {
+    /// Describes the rows of `collector` by preparing its query, without running it, and reading the type of every
+    /// column. Collectors with a `Delta` may print rates instead, see `delta::rates`, which the schema allows too.
+    fn collector_schema(&mut self, collector: &Collector) -> Result<Value, PGError> {
+        let client = match &mut self.client {
+            Some(c) => c,
+            None => return Err(PGError::ClientEmpty),
+        };
+        let statement = client
+            .prepare(collector.query_for(self.server_version))
+            .map_err(|e| PGError::query(collector.context(), e))?;
+        let columns = statement
+            .columns()
+            .iter()
+            .map(|c| (c.name().to_string(), value_schema(c.type_())))
+            .collect();
+
+        let mut rows = row_schema(columns, collector.per_database);
+        if let Some(delta) = &collector.delta {
+            let rates = statement
+                .columns()
+                .iter()
+                .filter(|c| delta.keys.contains(&c.name()))
+                .map(|c| (c.name().to_string(), value_schema(c.type_())))
+                .chain(
+                    delta
+                        .counters
+                        .iter()
+                        .map(|counter| (format!("{}_per_sec", counter), json!({ "type": "number" }))),
+                )
+                .collect();
+            rows = json!({ "anyOf": [rows, row_schema(rates, collector.per_database)] });
+        }
+
+        Ok(json!({
+            "title": collector.title,
+            "type": "object",
+            "properties": {
+                "schema_version": { "const": SCHEMA_VERSION },
+                "collector": { "const": collector.name },
+                "heading": { "type": "string" },
+                "rows": { "type": "array", "items": rows },
+                "duration_ms": { "type": "number" },
+            },
+            "required": ["schema_version", "collector", "heading", "rows"],
+        }))
+    }
+
+    /// Returns the JSON Schema (draft 2020-12) of what collectors print in the JSON output format, see
+    /// `CollectorOutput`, with a definition per collector, or of a single `collector`. <br>
+    /// Column types are read from the server, so the schema matches the queries run against it. <br>
+    /// Collectors needing an extension the database does not have are left out, their query cannot be prepared. <br>
+    /// Returns `MatchNotFound` for an unknown collector.
+    pub fn json_schema(&mut self, collector: Option<&str>) -> Result<Value, PGError> {
+        let collectors: Vec<&Collector> = match collector {
+            Some(name) => vec![COLLECTORS.iter().find(|c| c.name == name).ok_or(PGError::MatchNotFound)?],
+            None => COLLECTORS.iter().filter(|c| self.capabilities.allows(c.extension)).collect(),
+        };
+
+        let mut definitions = Map::new();
+        for collector in &collectors {
+            definitions.insert(collector.name.to_string(), self.collector_schema(collector)?);
+        }
+        let references: Vec<Value> = collectors
+            .iter()
+            .map(|c| json!({ "$ref": format!("#/$defs/{}", c.name) }))
+            .collect();
+
+        Ok(json!({
+            "$schema": "https://json-schema.org/draft/2020-12/schema",
+            "title": "psql_stats collector output",
+            "schema_version": SCHEMA_VERSION,
+            "oneOf": references,
+            "$defs": definitions,
+        }))
+    }
+}
@@ -0,0 +1,271 @@
+use std::fs;
+use std::time::{Duration, Instant};
+
+use colored::Colorize;
+use tracing::error;
+
+use super::output::OutputFormatter;
+use super::rows::format_elapsed;
+use super::{one_line, Config, Connection, PGError};
+
+/// Characters of a statement shown next to its outcome when a file is run
+const STATEMENT_WIDTH: usize = 60;
+
+/// A statement of a SQL file, and the line of the file it starts on
+pub struct ScriptStatement {
+    pub line: usize,
+    pub sql: String,
+}
+
+/// Outcome of running a SQL file: how many of its statements ran, how many failed and how long it all took
+pub struct ScriptSummary {
+    pub statements: usize,
+    pub succeeded: usize,
+    pub failed: usize,
+    pub elapsed: Duration,
+}
+
+impl ScriptSummary {
+    /// One line telling how the run went, e.g. `Ran 4 of 6 statements in 412ms, 1 failed`
+    pub fn describe(&self, human: bool) -> String {
+        let mut text = format!(
+            "Ran {} of {} statements in {}",
+            self.succeeded + self.failed,
+            self.statements,
+            format_elapsed(self.elapsed, human)
+        );
+        if self.failed > 0 {
+            text += &format!(", {} failed", self.failed);
+        }
+        text
+    }
+}
+
+/// Reads the SQL file at `path` and splits it into its statements, see `split_statements`
+pub fn read_script(path: &str) -> Result<Vec<ScriptStatement>, PGError> {
+    match fs::read_to_string(path) {
+        Ok(script) => Ok(split_statements(&script)),
+        Err(e) => {
+            error!("could not read {}: {}", path, e);
+            Err(PGError::ScriptError)
+        }
+    }
+}
+
+/// Splits `script` into its statements at the semicolons ending them, as psql does. Semicolons inside string
+/// literals, quoted identifiers, dollar quoted bodies, comments and `BEGIN ATOMIC ... END` function bodies do not end
+/// a statement. Comments before a statement are left out, and so are statements holding nothing else. <br>
+/// psql's backslash commands, such as `\set` or `\i`, are not understood and are sent to the server as they are.
+pub(crate) fn split_statements(script: &str) -> Vec<ScriptStatement> {
+    let chars: Vec<char> = script.chars().collect();
+    let mut statements = Vec::new();
+    // Where the statement being read starts, in characters and lines
+    let mut start: Option<(usize, usize)> = None;
+    let mut line = 1;
+    let mut previous_word = String::new();
+    let mut atomic = false;
+    let mut case_depth = 0;
+
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        let next = chars.get(i + 1).copied();
+        if c.is_whitespace() {
+            if c == '\n' {
+                line += 1;
+            }
+            i += 1;
+            continue;
+        }
+        if c == '-' && next == Some('-') {
+            while i < chars.len() && chars[i] != '\n' {
+                i += 1;
+            }
+            continue;
+        }
+        if c == '/' && next == Some('*') {
+            // Block comments nest in Postgres
+            let from = i;
+            let mut depth = 0;
+            while i < chars.len() {
+                match (chars[i], chars.get(i + 1)) {
+                    ('/', Some('*')) => {
+                        depth += 1;
+                        i += 2;
+                    }
+                    ('*', Some('/')) => {
+                        depth -= 1;
+                        i += 2;
+                        if depth == 0 {
+                            break;
+                        }
+                    }
+                    _ => i += 1,
+                }
+            }
+            line += newlines(&chars[from..i.min(chars.len())]);
+            continue;
+        }
+
+        start.get_or_insert((i, line));
+        if c == ';' && !atomic {
+            if let Some((from, from_line)) = start.take() {
+                let sql: String = chars[from..i].iter().collect();
+                if !sql.trim().is_empty() {
+                    statements.push(ScriptStatement {
+                        line: from_line,
+                        sql: sql.trim().to_string(),
+                    });
+                }
+            }
+            previous_word.clear();
+            i += 1;
+            continue;
+        }
+
+        let from = i;
+        if c.is_alphanumeric() || c == '_' {
+            while i < chars.len() && (chars[i].is_alphanumeric() || chars[i] == '_' || chars[i] == '$') {
+                i += 1;
+            }
+            let word: String = chars[from..i].iter().collect::<String>().to_lowercase();
+            // E'...' strings take backslash escapes
+            if word == "e" && chars.get(i) == Some(&'\'') {
+                i = end_of_quoted(&chars, i, true);
+            }
+            match word.as_str() {
+                "atomic" if previous_word == "begin" => atomic = true,
+                "case" if atomic => case_depth += 1,
+                "end" if atomic && case_depth > 0 => case_depth -= 1,
+                "end" if atomic => atomic = false,
+                _ => {}
+            }
+            previous_word = word;
+        } else if c == '\'' || c == '"' {
+            i = end_of_quoted(&chars, i, false);
+        } else if let Some(tag) = dollar_tag(&chars, i) {
+            let body_start = i + tag.len();
+            let close = (body_start..chars.len())
+                .find(|&j| chars[j..].starts_with(&tag))
+                .map(|j| j + tag.len());
+            i = close.unwrap_or(chars.len());
+        } else {
+            i += 1;
+        }
+        line += newlines(&chars[from..i]);
+    }
+
+    if let Some((from, from_line)) = start {
+        let sql: String = chars[from..].iter().collect();
+        if !sql.trim().is_empty() {
+            statements.push(ScriptStatement {
+                line: from_line,
+                sql: sql.trim().to_string(),
+            });
+        }
+    }
+    statements
+}
+
+/// Index after the quoted string or identifier starting at `chars[i]`, whose quote is closed by the next one. A
+/// doubled quote closes it and opens it again at once. With `escapes`, a backslash escapes the character after it.
+fn end_of_quoted(chars: &[char], i: usize, escapes: bool) -> usize {
+    let quote = chars[i];
+    let mut j = i + 1;
+    while j < chars.len() && chars[j] != quote {
+        j += if escapes && chars[j] == '\\' { 2 } else { 1 };
+    }
+    (j + 1).min(chars.len())
+}
+
+/// The opening `$$` or `$tag$` of a dollar quoted string at `chars[i]`, not a `$1` placeholder
+fn dollar_tag(chars: &[char], i: usize) -> Option<Vec<char>> {
+    if chars[i] != '$' || chars.get(i + 1).is_some_and(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    let mut j = i + 1;
+    while j < chars.len() && (chars[j].is_alphanumeric() || chars[j] == '_') {
+        j += 1;
+    }
+    match chars.get(j) {
+        Some('$') => Some(chars[i..=j].to_vec()),
+        _ => None,
+    }
+}
+
+fn newlines(chars: &[char]) -> usize {
+    chars.iter().filter(|c| **c == '\n').count()
+}
+
+impl Connection {
+    /// Runs `statements` one after the other as custom queries, see `Connection::custom_query`, printing how long
+    /// each took and how many rows it returned or changed, and the rows themselves for those returning some. <br>
+    /// The first statement failing stops the run unless `continue_on_error` is set, the statements after it are not
+    /// sent. Statements run in autocommit, each in its own transaction, unless the file opens one itself with
+    /// `BEGIN`.
+    pub fn run_script(
+        &mut self,
+        statements: &[ScriptStatement],
+        continue_on_error: bool,
+        config: &Config,
+    ) -> ScriptSummary {
+        let formatter = OutputFormatter::new(config);
+        let start = Instant::now();
+        let mut summary = ScriptSummary {
+            statements: statements.len(),
+            succeeded: 0,
+            failed: 0,
+            elapsed: Duration::ZERO,
+        };
+        // Progress goes to stderr unless the output is a table, so the rows printed stay parseable
+        let report = |text: String| match config.output_format.as_str() {
+            "table" => println!("{}", text),
+            _ => eprintln!("{}", text),
+        };
+
+        for (n, statement) in statements.iter().enumerate() {
+            let position = format!("[{}/{}] line {}", n + 1, statements.len(), statement.line);
+            let shown = one_line(&statement.sql, STATEMENT_WIDTH);
+            let sent = Instant::now();
+            match self.custom_query(&statement.sql, &[], config.table.max_rows) {
+                Ok(result) => {
+                    summary.succeeded += 1;
+                    let outcome = match result.columns.is_empty() {
+                        true => format!("{} rows affected", result.affected),
+                        false => format!("{} rows", result.affected),
+                    };
+                    report(format!(
+                        "{}: {} - {} in {}",
+                        position.green(),
+                        shown,
+                        outcome,
+                        format_elapsed(result.elapsed, config.table.human)
+                    ));
+                    if result.rows.as_array().is_some_and(|r| !r.is_empty()) {
+                        println!("{}", formatter.rows(&result.rows));
+                    }
+                }
+                Err(e) => {
+                    summary.failed += 1;
+                    // The server's error was printed already, with the line of the statement it points at
+                    let reason = match e {
+                        PGError::QueryFailed => String::new(),
+                        e => format!(": {}", e),
+                    };
+                    report(format!(
+                        "{}: {} - failed after {}{}",
+                        position.red(),
+                        shown,
+                        format_elapsed(sent.elapsed(), config.table.human),
+                        reason
+                    ));
+                    if !continue_on_error {
+                        break;
+                    }
+                }
+            }
+        }
+        summary.elapsed = start.elapsed();
+        summary
+    }
+}
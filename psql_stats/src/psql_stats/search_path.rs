@@ -0,0 +1,72 @@
+use postgres::Client;
+use tracing::{error, warn};
+
+use super::{reason, Connection, PGError};
+
+/// Looks up the session's `search_path`, the schemas unqualified table names are looked for in, in order. Empty if
+/// it cannot be read.
+pub(crate) fn probe(client: &mut Client) -> String {
+    client
+        .query_one("SHOW search_path", &[])
+        .ok()
+        .and_then(|row| row.try_get::<_, String>(0).ok())
+        .unwrap_or_default()
+}
+
+/// Puts `schema` at the front of the session's `search_path`, unless it is empty, and returns the `search_path` the
+/// session ends up with, see `probe`. A schema that does not exist is kept in the path, as `SET` keeps it, and
+/// skipped when names are looked up.
+pub(crate) fn apply(client: &mut Client, schema: &str) -> String {
+    if !schema.is_empty() {
+        let set = client.query_one(
+            "SELECT set_config('search_path', quote_ident($1) || ', ' || current_setting('search_path'), false)",
+            &[&schema],
+        );
+        if let Err(e) = set {
+            warn!("could not put {} in the search_path: {}", schema, reason(&e));
+        }
+    }
+    probe(client)
+}
+
+impl Connection {
+    /// Sets the session's `search_path` to `schemas`, a comma separated list as `SET search_path` takes it, or back
+    /// to the server's default for `default`, and returns the new value. <br>
+    /// The list is passed to `set_config` as a value, so it can only ever set the search path. Schemas that do not
+    /// exist are accepted, as they are by `SET`, and skipped when names are looked up.
+    pub fn set_search_path(&mut self, schemas: &str) -> Result<String, PGError> {
+        let client = match &mut self.client {
+            Some(c) => c,
+            None => return Err(PGError::ClientEmpty),
+        };
+        let result = match schemas.trim() {
+            "default" => client.query_one(
+                "SELECT set_config('search_path', reset_val, false) FROM pg_settings WHERE name = 'search_path'",
+                &[],
+            ),
+            schemas => client.query_one("SELECT set_config('search_path', $1, false)", &[&schemas]),
+        };
+        match result.and_then(|row| row.try_get::<_, String>(0)) {
+            Ok(search_path) => {
+                self.search_path = search_path.clone();
+                Ok(search_path)
+            }
+            Err(e) => {
+                error!("{}", e.as_db_error().map(|db| db.message().to_string()).unwrap_or(e.to_string()));
+                Err(PGError::QueryFailed)
+            }
+        }
+    }
+
+    /// Reads the `search_path` again after a custom query that may have changed it, such as `SET search_path` or
+    /// `RESET ALL`, so the status line stays right
+    pub(crate) fn track_search_path(&mut self, query: &str) {
+        let query = query.to_lowercase();
+        let first_word = query.split_whitespace().next().unwrap_or("");
+        if matches!(first_word, "set" | "reset" | "discard") || query.contains("search_path") {
+            if let Some(client) = &mut self.client {
+                self.search_path = probe(client);
+            }
+        }
+    }
+}
@@ -0,0 +1,50 @@
+use keyring::Entry;
+use tracing::error;
+
+use super::{Connection, PGError};
+
+/// Service the passwords are stored under in the system keyring: the macOS Keychain, the Windows Credential Manager
+/// or the Secret Service (GNOME Keyring, KWallet) on Linux
+const KEYRING_SERVICE: &str = "psql_stats";
+
+impl Connection {
+    /// Name of the keyring entry holding this connection's password, `user@host:port`, so profiles of the same role
+    /// on the same server share one entry
+    pub(crate) fn keyring_entry(&self) -> String {
+        format!("{}@{}:{}", self.user, self.host, self.port)
+    }
+}
+
+/// Stores `password` in the system keyring as `entry`, replacing what was stored there before. <br>
+/// Returns `KeyringError` when there is no keyring, e.g. on a server without a Secret Service running.
+pub(crate) fn store_password(entry: &str, password: &str) -> Result<(), PGError> {
+    Entry::new(KEYRING_SERVICE, entry)
+        .and_then(|e| e.set_password(password))
+        .map_err(|e| {
+            error!("could not store the password in the keyring: {}", e);
+            PGError::KeyringError
+        })
+}
+
+/// Reads the password stored in the system keyring as `entry` by `store_password`. <br>
+/// Returns `KeyringError` when there is no such entry or no keyring.
+pub(crate) fn load_password(entry: &str) -> Result<String, PGError> {
+    Entry::new(KEYRING_SERVICE, entry)
+        .and_then(|e| e.get_password())
+        .map_err(|e| {
+            error!("could not read the password of {} from the keyring: {}", entry, e);
+            PGError::KeyringError
+        })
+}
+
+/// Removes the password stored in the system keyring as `entry`, if there is one. <br>
+/// Returns `KeyringError` when there is no keyring.
+pub(crate) fn delete_password(entry: &str) -> Result<(), PGError> {
+    match Entry::new(KEYRING_SERVICE, entry).and_then(|e| e.delete_credential()) {
+        Ok(_) | Err(keyring::Error::NoEntry) => Ok(()),
+        Err(e) => {
+            error!("could not remove the password of {} from the keyring: {}", entry, e);
+            Err(PGError::KeyringError)
+        }
+    }
+}
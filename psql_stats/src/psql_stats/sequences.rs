@@ -0,0 +1,126 @@
+use colored::Colorize;
+use serde_json::Value;
+
+use super::healthcheck::{above, Check, Status, Thresholds};
+use super::output::OutputFormatter;
+use super::style::warning_sign;
+use super::{Config, Connection, PGError};
+
+/// Query listing the sequences owned by, or generating the identity of, a `smallint`, `integer` or `bigint` column,
+/// with how much of the values they may still give out is used up, most used first. <br>
+/// The limit is the lower of the sequence's own maximum and the largest value the column holds, so a `bigint`
+/// sequence feeding an `integer` column, the usual case of tables created with `serial`, counts against the
+/// column's 2147483647. Descending sequences count towards their minimum instead. Sequences never used, or that the
+/// role has no `SELECT` or `USAGE` privilege on, have no `last_value` and are listed last.
+pub const SEQUENCES_QUERY: &str = r#"
+    SELECT s.schemaname AS schema,
+           s.sequencename AS sequence,
+           format('%I.%I', tn.nspname, t.relname) AS table,
+           a.attname AS column,
+           format_type(a.atttypid, NULL) AS column_type,
+           s.last_value,
+           l.limit_value,
+           round(100.0 * s.last_value / l.limit_value, 2)::float8 AS used_percent,
+           abs(l.limit_value - s.last_value) AS remaining
+    FROM pg_sequences s
+    JOIN pg_namespace n ON n.nspname = s.schemaname
+    JOIN pg_class c ON c.relnamespace = n.oid AND c.relname = s.sequencename
+    JOIN pg_depend d ON d.classid = 'pg_class'::regclass AND d.objid = c.oid
+                    AND d.refclassid = 'pg_class'::regclass AND d.refobjsubid > 0 AND d.deptype IN ('a', 'i')
+    JOIN pg_class t ON t.oid = d.refobjid
+    JOIN pg_namespace tn ON tn.oid = t.relnamespace
+    JOIN pg_attribute a ON a.attrelid = t.oid AND a.attnum = d.refobjsubid
+    CROSS JOIN LATERAL (
+        SELECT CASE WHEN s.increment_by > 0
+                    THEN least(s.max_value, CASE a.atttypid WHEN 'int2'::regtype THEN 32767
+                                                            WHEN 'int4'::regtype THEN 2147483647
+                                                            ELSE 9223372036854775807 END)
+                    ELSE greatest(s.min_value, CASE a.atttypid WHEN 'int2'::regtype THEN -32768
+                                                               WHEN 'int4'::regtype THEN -2147483648
+                                                               ELSE -9223372036854775808 END)
+               END AS limit_value
+    ) l
+    WHERE a.atttypid IN ('int2'::regtype, 'int4'::regtype, 'int8'::regtype)
+    ORDER BY used_percent DESC NULLS LAST, s.schemaname, s.sequencename
+"#;
+
+impl Connection {
+    /// Lists the sequences of integer columns with how much of their values is used up, see `SEQUENCES_QUERY`
+    pub fn get_sequence_usage(&mut self) -> Result<Value, PGError> {
+        self.query_json(SEQUENCES_QUERY)
+    }
+}
+
+/// Whether the most used up sequence, the first of `rows` as `Connection::get_sequence_usage` returns them, passed
+/// `Thresholds::sequence_warn_percent` or `Thresholds::sequence_crit_percent` of its values. Once all are used,
+/// every insert into its table fails until the column, or the sequence, is widened to `bigint`.
+pub(crate) fn sequences_check(rows: &Value, thresholds: &Thresholds) -> Check {
+    let over_warn = sequences_over(rows, thresholds.sequence_warn_percent).len();
+    match rows.get(0).filter(|r| r["used_percent"].is_number()) {
+        Some(worst) => {
+            let used = worst["used_percent"].as_f64().unwrap_or(0.0);
+            let mut message = format!(
+                "{}% of the values of {} used up, feeding {}.{} ({})",
+                used,
+                worst["sequence"].as_str().unwrap_or("?"),
+                worst["table"].as_str().unwrap_or("?"),
+                worst["column"].as_str().unwrap_or("?"),
+                worst["column_type"].as_str().unwrap_or("?")
+            );
+            if over_warn > 1 {
+                message.push_str(&format!(", {} sequences above {}%", over_warn, thresholds.sequence_warn_percent));
+            }
+            Check {
+                name: "sequences",
+                status: above(used, thresholds.sequence_warn_percent, thresholds.sequence_crit_percent),
+                message,
+            }
+        }
+        None => Check {
+            name: "sequences",
+            status: Status::Ok,
+            message: "no sequence of an integer column has been used".to_string(),
+        },
+    }
+}
+
+/// The sequences of `rows` that used up at least `percent` of their values
+fn sequences_over(rows: &Value, percent: f64) -> Vec<&Value> {
+    rows.as_array()
+        .into_iter()
+        .flatten()
+        .filter(|r| r["used_percent"].as_f64().is_some_and(|used| used >= percent))
+        .collect()
+}
+
+/// Renders what `Connection::get_sequence_usage` returns: in JSON or CSV as is, otherwise as a table followed by a
+/// warning for every sequence above `Thresholds::sequence_warn_percent`, in red once above
+/// `Thresholds::sequence_crit_percent`
+pub fn render_sequences(rows: &Value, config: &Config, thresholds: &Thresholds) -> String {
+    let formatter = OutputFormatter::new(config);
+    if config.output_format != "table" {
+        return formatter.rows(rows);
+    }
+    let mut sections = vec![formatter.titled("Sequences of integer columns, most used up first", formatter.rows(rows))];
+    for row in sequences_over(rows, thresholds.sequence_warn_percent) {
+        let used = row["used_percent"].as_f64().unwrap_or(0.0);
+        let mut warning = format!(
+            "{} {}.{} used up {}% of its values, {} left for {}.{}",
+            warning_sign(),
+            row["schema"].as_str().unwrap_or("?"),
+            row["sequence"].as_str().unwrap_or("?"),
+            used,
+            row["remaining"],
+            row["table"].as_str().unwrap_or("?"),
+            row["column"].as_str().unwrap_or("?")
+        );
+        if row["column_type"] != "bigint" {
+            warning.push_str(", widen the column to bigint before inserts fail");
+        }
+        sections.push(match used >= thresholds.sequence_crit_percent {
+            true => warning.red().to_string(),
+            false => warning.yellow().to_string(),
+        });
+    }
+    sections.join("\n")
+}
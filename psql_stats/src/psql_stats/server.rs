@@ -0,0 +1,368 @@
+use std::io::{BufRead, BufReader, ErrorKind, Write};
+use std::net::{TcpListener, TcpStream};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use notify::{Event, RecommendedWatcher, RecursiveMode, Watcher};
+use serde_json::json;
+use tracing::{error, warn};
+
+use super::alerts::Notifier;
+use super::daemon::{notify, Watchdog};
+use super::collectors::find_collector;
+use super::defaults::{Defaults, Overrides};
+use super::healthcheck::Thresholds;
+use super::history::History;
+use super::telemetry::ErrorStats;
+use super::{Config, Connection, PGError};
+
+/// How long a configuration file must be left unchanged before it is read again, as editors and `cp` write a file in
+/// several steps
+const CONFIG_SETTLE_TIME: Duration = Duration::from_millis(250);
+
+/// What `serve` keeps between requests. Reloading the configuration only replaces `config` and reconfigures the
+/// `notifier`, the history file and the failure counts carry on, so the metrics stay continuous.
+struct ServerState {
+    config: Config,
+    history: Option<History>,
+    errors: ErrorStats,
+    notifier: Option<Notifier>,
+}
+
+/// Notices changes to `config.json` and `psql_stats.toml` through a `notify` watcher on the directories they are in,
+/// so files replaced by an editor or created while serving are seen as well as those written in place
+struct ConfigWatcher {
+    files: [PathBuf; 2],
+    events: Receiver<notify::Result<Event>>,
+    /// Watches for as long as it is kept
+    _watcher: Option<RecommendedWatcher>,
+    /// When a configuration file last changed, while waiting for `CONFIG_SETTLE_TIME` to pass without another change
+    changed_at: Option<Instant>,
+}
+
+impl ConfigWatcher {
+    /// Starts watching `files`, warning that changes to them are not picked up when their directory cannot be
+    /// watched, e.g. because it does not exist
+    fn new(files: [PathBuf; 2]) -> ConfigWatcher {
+        let (sender, events) = mpsc::channel();
+        let watcher = notify::recommended_watcher(sender).and_then(|mut watcher| {
+            for directory in files.iter().filter_map(|f| f.parent()) {
+                watcher.watch(directory, RecursiveMode::NonRecursive)?;
+            }
+            Ok(watcher)
+        });
+        let watcher = match watcher {
+            Ok(w) => Some(w),
+            Err(e) => {
+                warn!("could not watch the configuration files for changes, restart to apply them: {}", e);
+                None
+            }
+        };
+        ConfigWatcher {
+            files,
+            events,
+            _watcher: watcher,
+            changed_at: None,
+        }
+    }
+
+    /// Whether a configuration file was written, created, replaced or removed since last asked, once no other
+    /// change followed for `CONFIG_SETTLE_TIME`, so a file is not read half written
+    fn changed(&mut self) -> bool {
+        while let Ok(event) = self.events.try_recv() {
+            match event {
+                // Reading the files to reload them is an access event, which must not trigger another reload
+                Ok(event) if event.kind.is_access() => {}
+                Ok(event) if event.paths.iter().any(|p| self.files.contains(p)) => {
+                    self.changed_at = Some(Instant::now())
+                }
+                Ok(_) => {}
+                Err(e) => warn!("could not watch the configuration files for changes: {}", e),
+            }
+        }
+        match self.changed_at {
+            Some(at) if at.elapsed() >= CONFIG_SETTLE_TIME => {
+                self.changed_at = None;
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+/// Starts a small HTTP server on `addr` exposing the collectors as JSON REST endpoints at `GET /v1/<name>`,
+/// e.g. `/v1/uptime`, `/v1/tables` or `/v1/activity`, every one of them unless `Config::serve_collectors` names
+/// some. <br>
+/// Requests are handled one at a time over the single `Connection` until `shutdown` is set. <br>
+/// When started by systemd, readiness is reported through `sd_notify`, and the watchdog pinged once a request was
+/// handled or waiting for one timed out, so a loop that hangs is restarted. <br>
+/// If a `History` is given, every successfully collected response is also appended to it. <br>
+/// Failed collectors are counted per target and kind of failure, listed at `GET /v1/errors` and exported in the
+/// Prometheus text format at `GET /metrics`, see `ErrorStats`. <br>
+/// With `Config::alert_webhook` set, the `thresholds` are checked between requests and alerts posted, see
+/// `Notifier::poll`. <br>
+/// Changes to `config.json` and `psql_stats.toml` are watched for and picked up while serving, without a restart: the collectors
+/// served, the alert webhook, cooldown and interval, and the thresholds, with the values given on the command line,
+/// `overrides`, applied over them again.
+pub fn serve(
+    connection: &mut Connection,
+    addr: &str,
+    shutdown: &AtomicBool,
+    config: Config,
+    thresholds: &Thresholds,
+    overrides: &Overrides,
+    history: Option<History>,
+) -> Result<(), PGError> {
+    let listener = match TcpListener::bind(addr) {
+        Ok(l) => l,
+        Err(e) => {
+            error!("{}", e);
+            return Err(PGError::ServerError);
+        }
+    };
+
+    // Polling the listener lets us notice the shutdown flag between requests
+    if let Err(e) = listener.set_nonblocking(true) {
+        error!("{}", e);
+        return Err(PGError::ServerError);
+    }
+
+    println!("Serving statistics on http://{}", addr);
+    notify("READY=1");
+    let mut watchdog = Watchdog::from_env();
+
+    let mut state = ServerState {
+        notifier: Notifier::new(&config, thresholds),
+        config,
+        history,
+        errors: ErrorStats::default(),
+    };
+    warn_unknown_collectors(&state.config);
+    let mut watcher = ConfigWatcher::new([Config::path(), Defaults::path()]);
+    while !shutdown.load(Ordering::Relaxed) {
+        if watcher.changed() {
+            reload_config(&mut state, overrides);
+        }
+        if let Some(notifier) = state.notifier.as_mut() {
+            notifier.poll(connection);
+        }
+        match listener.accept() {
+            Ok((s, _)) => {
+                if let Err(e) = s.set_nonblocking(false) {
+                    error!("{}", e);
+                    continue;
+                }
+                if let Err(e) = handle_request(connection, s, &mut state) {
+                    error!("{}", e);
+                }
+                watchdog.ping();
+            }
+            Err(ref e) if e.kind() == ErrorKind::WouldBlock => {
+                thread::sleep(Duration::from_millis(100));
+                watchdog.ping();
+            }
+            Err(e) => {
+                error!("{}", e);
+            }
+        }
+    }
+
+    notify("STOPPING=1");
+    println!("Shutting down.");
+    Ok(())
+}
+
+/// Reads the configuration files again, see `Overrides::reload`, keeping the current configuration when
+/// `config.json` cannot be read
+fn reload_config(state: &mut ServerState, overrides: &Overrides) {
+    match overrides.reload() {
+        Some((config, thresholds)) => {
+            println!("Reloaded the configuration from {}", Config::directory().display());
+            apply_config(state, config, &thresholds);
+        }
+        None => error!(
+            "could not read {}, keeping the current configuration",
+            Config::path().display()
+        ),
+    }
+}
+
+/// Serves with `config` and `thresholds` from now on. Alerts already posted are remembered unless the webhook was
+/// removed, see `Notifier::reconfigure`.
+fn apply_config(state: &mut ServerState, config: Config, thresholds: &Thresholds) {
+    warn_unknown_collectors(&config);
+    match state.notifier.as_mut() {
+        Some(notifier) if config.alert_webhook.is_some() => notifier.reconfigure(&config, thresholds),
+        _ => state.notifier = Notifier::new(&config, thresholds),
+    }
+    state.config = config;
+}
+
+/// Warns about the names in `Config::serve_collectors` that are not collectors, they are never served
+fn warn_unknown_collectors(config: &Config) {
+    for name in config.serve_collectors.iter().filter(|n| find_collector(n).is_none()) {
+        warn!("serve_collectors names {}, which is not a collector", name);
+    }
+}
+
+/// Reads a single request from `stream`, runs the matching collector and writes back the response
+fn handle_request(connection: &mut Connection, mut stream: TcpStream, state: &mut ServerState) -> std::io::Result<()> {
+    stream.set_read_timeout(Some(Duration::new(5, 0)))?;
+
+    let mut reader = BufReader::new(&stream);
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+
+    // Drain the headers, we do not need any of them
+    let mut header = String::new();
+    while reader.read_line(&mut header)? > 0 && header.trim() != "" {
+        header.clear();
+    }
+
+    let mut parts = request_line.split_whitespace();
+    let method = parts.next().unwrap_or("");
+    let path = parts.next().unwrap_or("").split('?').next().unwrap_or("");
+
+    // The failure counts are served as Prometheus metrics, the collectors as JSON
+    if method == "GET" && path == "/metrics" {
+        return write_response(&mut stream, "200 OK", "text/plain; version=0.0.4", &state.errors.metrics());
+    }
+
+    let (status, body) = if method != "GET" {
+        ("405 Method Not Allowed", json!({ "error": "only GET is supported" }))
+    } else if path == "/v1/errors" {
+        ("200 OK", state.errors.summary())
+    } else {
+        let served = &state.config.serve_collectors;
+        let collector = path
+            .strip_prefix("/v1/")
+            .and_then(find_collector)
+            .filter(|c| served.is_empty() || served.iter().any(|n| n == c.name));
+
+        // Reconnect after the server went away, which also renews short-lived credentials such as IAM tokens
+        let closed = connection.client.as_ref().map(|c| c.is_closed()).unwrap_or(true);
+        if collector.is_some() && closed {
+            connection.connect();
+        }
+
+        match collector {
+            Some(c) => {
+                let result = connection.collect(c, false);
+                state.errors.record(&connection.label(), c.name, result.as_ref().err());
+                match result {
+                    Ok(rows) => {
+                        if let Some(h) = &mut state.history {
+                            if let Err(e) = h.append(&connection.label(), c.name, &rows) {
+                                error!("could not write history: {}", e);
+                            }
+                        }
+                        ("200 OK", rows)
+                    }
+                    Err(PGError::ClientEmpty) => (
+                        "503 Service Unavailable",
+                        json!({ "error": PGError::ClientEmpty.to_string() }),
+                    ),
+                    Err(e @ (PGError::SuperuserRequired | PGError::PermissionDenied { .. })) => {
+                        ("403 Forbidden", json!({ "error": e.to_string() }))
+                    }
+                    Err(e @ PGError::QueryTimeout { .. }) => ("504 Gateway Timeout", json!({ "error": e.to_string() })),
+                    Err(e) if e.is_connection_lost() => ("503 Service Unavailable", json!({ "error": e.to_string() })),
+                    Err(e) => ("500 Internal Server Error", json!({ "error": e.to_string() })),
+                }
+            }
+            None => ("404 Not Found", json!({ "error": "unknown endpoint" })),
+        }
+    };
+
+    write_response(&mut stream, status, "application/json", &body.to_string())
+}
+
+/// Writes `body` as an HTTP response with the given status line and content type
+fn write_response(stream: &mut TcpStream, status: &str, content_type: &str, body: &str) -> std::io::Result<()> {
+    let response = format!(
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        content_type,
+        body.len(),
+        body
+    );
+    stream.write_all(response.as_bytes())?;
+    stream.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+
+    #[test]
+    fn reloading_applies_new_thresholds_and_collectors() {
+        let config = Config {
+            alert_webhook: Some("http://127.0.0.1:9/alerts".to_string()),
+            ..Default::default()
+        };
+        let mut state = ServerState {
+            notifier: Notifier::new(&config, &Thresholds::default()),
+            config,
+            history: None,
+            errors: ErrorStats::default(),
+        };
+
+        let mut reloaded = Config {
+            alert_webhook: Some("http://127.0.0.1:9/alerts".to_string()),
+            serve_collectors: vec!["uptime".to_string()],
+            ..Default::default()
+        };
+        let mut thresholds = Thresholds::default();
+        assert!(thresholds.set("long_query_warn_secs", 30.0));
+        // A flag given on the command line still wins over the files read again
+        let overrides = Overrides {
+            thresholds: vec![("long_query_crit_secs", 90.0)],
+            ..Default::default()
+        };
+        overrides.apply(&mut reloaded, &mut thresholds);
+        apply_config(&mut state, reloaded, &thresholds);
+
+        let notifier = state.notifier.as_ref().expect("The webhook is still set");
+        assert_eq!(notifier.thresholds().long_query_warn_secs, 30.0);
+        assert_eq!(notifier.thresholds().long_query_crit_secs, 90.0);
+        assert_eq!(state.config.serve_collectors, vec!["uptime"]);
+
+        apply_config(&mut state, Config::default(), &thresholds);
+        assert!(state.notifier.is_none());
+    }
+
+    #[test]
+    fn watcher_notices_files_written_and_created() {
+        let directory = std::env::temp_dir().join(format!("psql_stats-{}-watched", std::process::id()));
+        let _ = fs::remove_dir_all(&directory);
+        fs::create_dir_all(&directory).unwrap();
+        let (config, defaults) = (directory.join("config.json"), directory.join("psql_stats.toml"));
+        fs::write(&config, "{}").unwrap();
+        let mut watcher = ConfigWatcher::new([config.clone(), defaults.clone()]);
+
+        let changed_within = |watcher: &mut ConfigWatcher, wait: Duration| {
+            let start = Instant::now();
+            while start.elapsed() < wait {
+                if watcher.changed() {
+                    return true;
+                }
+                thread::sleep(Duration::from_millis(20));
+            }
+            false
+        };
+        fs::write(directory.join("unrelated.txt"), "").unwrap();
+        fs::read_to_string(&config).unwrap();
+        assert!(!changed_within(&mut watcher, Duration::from_millis(500)));
+        fs::write(&config, "{\"color\": false}").unwrap();
+        assert!(changed_within(&mut watcher, Duration::from_secs(5)));
+        fs::write(&defaults, "watch_interval = 5\n").unwrap();
+        assert!(changed_within(&mut watcher, Duration::from_secs(5)));
+        assert!(!watcher.changed());
+        fs::remove_dir_all(directory).unwrap();
+    }
+}
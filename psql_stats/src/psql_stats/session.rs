@@ -0,0 +1,109 @@
+use std::io::{self, IsTerminal};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+#[cfg(unix)]
+use std::thread;
+#[cfg(unix)]
+use std::time::Duration;
+use std::time::Instant;
+
+use tracing::error;
+
+use super::humanize::{human_duration, thousands};
+
+/// How often SIGTERM wakes up the prompt until it does, see `watch_sigterm`
+#[cfg(unix)]
+const WAKE_INTERVAL: Duration = Duration::from_millis(100);
+
+/// An interactive session in the menu, counting the commands run so they can be summed up when it ends, and watching
+/// for SIGTERM so a service manager or `kill` ends it as cleanly as option 0 does. <br>
+/// SIGTERM while the menu waits at the prompt wakes the prompt up, as Ctrl-C does, while a command that is running is
+/// left to finish first, see `terminated`. When the input or output is not a terminal the prompt cannot be woken up,
+/// and SIGTERM ends the program at once as before.
+pub struct Session {
+    started: Instant,
+    commands: u64,
+    /// Set once SIGTERM was received
+    terminated: Arc<AtomicBool>,
+    /// Set while the menu waits at the prompt, see `prompting`
+    at_prompt: Arc<AtomicBool>,
+}
+
+impl Session {
+    /// Starts the session now and the thread watching for SIGTERM
+    pub fn start() -> Session {
+        let terminated = Arc::new(AtomicBool::new(false));
+        let at_prompt = Arc::new(AtomicBool::new(false));
+        if io::stdin().is_terminal() && io::stdout().is_terminal() {
+            watch_sigterm(&terminated, &at_prompt);
+        }
+        Session {
+            started: Instant::now(),
+            commands: 0,
+            terminated,
+            at_prompt,
+        }
+    }
+
+    /// Marks whether the menu is waiting at the prompt, so SIGTERM can wake it up
+    pub fn prompting(&self, waiting: bool) {
+        self.at_prompt.store(waiting, Ordering::SeqCst);
+    }
+
+    /// Counts a command run from the menu
+    pub fn command_run(&mut self) {
+        self.commands += 1;
+    }
+
+    /// Whether SIGTERM was received, after which the menu exits instead of showing the prompt again
+    pub fn terminated(&self) -> bool {
+        self.terminated.load(Ordering::SeqCst)
+    }
+
+    /// Sums up the session: how long it lasted, the commands run and the `queries` executed on its connections
+    pub fn summary(&self, queries: u64) -> String {
+        format!(
+            "Session summary: {} command{} run, {} quer{} executed in {}",
+            thousands(&self.commands.to_string()),
+            if self.commands == 1 { "" } else { "s" },
+            thousands(&queries.to_string()),
+            if queries == 1 { "y" } else { "ies" },
+            human_duration(self.started.elapsed().as_secs_f64())
+        )
+    }
+}
+
+/// Spawns the thread setting `terminated` on SIGTERM and, for as long as `at_prompt` is set, sending the main thread,
+/// the one calling this, SIGINT, the only signal the prompt wakes up for. <br>
+/// SIGINT is sent again every `WAKE_INTERVAL` since the prompt misses it when it arrives while the main thread is still
+/// handling SIGTERM.
+#[cfg(unix)]
+fn watch_sigterm(terminated: &Arc<AtomicBool>, at_prompt: &Arc<AtomicBool>) {
+    use nix::sys::pthread::{pthread_kill, pthread_self};
+    use nix::sys::signal::Signal;
+    use signal_hook::consts::SIGTERM;
+    use signal_hook::iterator::Signals;
+
+    let mut signals = match Signals::new([SIGTERM]) {
+        Ok(s) => s,
+        Err(e) => {
+            error!("could not register signal handler: {}", e);
+            return;
+        }
+    };
+    let main_thread = pthread_self();
+    let (terminated, at_prompt) = (Arc::clone(terminated), Arc::clone(at_prompt));
+    thread::spawn(move || {
+        for _ in signals.forever() {
+            terminated.store(true, Ordering::SeqCst);
+            while at_prompt.load(Ordering::SeqCst) {
+                let _ = pthread_kill(main_thread, Signal::SIGINT);
+                thread::sleep(WAKE_INTERVAL);
+            }
+        }
+    });
+}
+
+/// SIGTERM cannot be watched for here, it ends the program at once
+#[cfg(not(unix))]
+fn watch_sigterm(_terminated: &Arc<AtomicBool>, _at_prompt: &Arc<AtomicBool>) {}
@@ -0,0 +1,189 @@
+use colored::Colorize;
+use serde_json::Value;
+
+use super::{Connection, PGError};
+
+/// Settings changed from their built-in default, with where the current value comes from, and settings changed in
+/// the configuration files that only take effect after a restart. <br>
+/// `sourcefile` and `sourceline` are only visible to superusers and members of pg_read_all_settings.
+pub const SETTINGS_QUERY: &str = r#"
+    SELECT name,
+           setting,
+           unit,
+           source,
+           CASE WHEN sourcefile IS NOT NULL THEN sourcefile || ':' || sourceline END AS location,
+           context,
+           pending_restart
+    FROM pg_settings
+    WHERE source NOT IN ('default', 'override') OR pending_restart
+    ORDER BY pending_restart DESC,
+             CASE source
+                  WHEN 'configuration file' THEN 1
+                  WHEN 'command line' THEN 2
+                  WHEN 'environment variable' THEN 3
+                  WHEN 'database' THEN 4
+                  WHEN 'user' THEN 5
+                  WHEN 'database user' THEN 6
+                  ELSE 7
+             END,
+             name
+"#;
+
+/// Settings attached to a role, a database or a role in one database with ALTER ROLE / ALTER DATABASE ... SET,
+/// which take effect for new sessions whatever postgresql.conf says
+pub const SETTING_OVERRIDES_QUERY: &str = r#"
+    SELECT CASE WHEN s.setdatabase = 0 THEN 'role'
+                WHEN s.setrole = 0 THEN 'database'
+                ELSE 'role in database'
+           END AS scope,
+           d.datname AS database,
+           r.rolname AS role,
+           split_part(c.config, '=', 1) AS name,
+           substr(c.config, strpos(c.config, '=') + 1) AS setting
+    FROM pg_db_role_setting s
+    LEFT JOIN pg_database d ON d.oid = s.setdatabase
+    LEFT JOIN pg_roles r ON r.oid = s.setrole
+    CROSS JOIN LATERAL unnest(s.setconfig) c(config)
+    ORDER BY 1, 2 NULLS FIRST, 3 NULLS FIRST, 4
+"#;
+
+/// Every entry in the configuration files, in the order the server reads them. <br>
+/// `status` tells whether the entry is in effect, has an error, or is overridden by a later entry for the same
+/// parameter, in which case `overridden_by` points at the entry that wins. Only readable by superusers.
+pub const FILE_SETTINGS_QUERY: &str = r#"
+    WITH entries AS (
+        SELECT seqno,
+               sourcefile || ':' || sourceline AS location,
+               name,
+               setting,
+               applied,
+               error,
+               last_value(sourcefile || ':' || sourceline) OVER (
+                   PARTITION BY name ORDER BY seqno ROWS BETWEEN UNBOUNDED PRECEDING AND UNBOUNDED FOLLOWING
+               ) AS last_location,
+               count(*) OVER (PARTITION BY name) AS occurrences
+        FROM pg_file_settings
+    )
+    SELECT location,
+           name,
+           setting,
+           CASE WHEN error IS NOT NULL THEN 'error'
+                WHEN occurrences > 1 AND location <> last_location THEN 'overridden'
+                WHEN applied THEN 'applied'
+                ELSE 'not applied'
+           END AS status,
+           CASE WHEN occurrences > 1 AND location <> last_location THEN last_location END AS overridden_by,
+           error
+    FROM entries
+    ORDER BY error IS NULL, name, seqno
+"#;
+
+/// Every setting of `pg_settings` whose name contains `$1`, ignoring case, or all of them when `$1` is NULL, with its
+/// current value and unit, the value the server would have without any configuration (`default`), where the current
+/// value comes from and whether a changed value waits for a restart. `changed` tells whether the value differs from
+/// the default, both being given in the setting's unit.
+const BROWSE_SETTINGS_QUERY: &str = r#"
+    SELECT name,
+           setting,
+           unit,
+           boot_val AS default,
+           setting IS DISTINCT FROM boot_val AS changed,
+           source,
+           context,
+           pending_restart,
+           short_desc AS description
+    FROM pg_settings
+    WHERE $1::text IS NULL OR strpos(lower(name), lower($1)) > 0
+    ORDER BY name
+"#;
+
+/// Names of the settings changed in the configuration files that are waiting for a server restart
+const PENDING_RESTART_QUERY: &str = "SELECT name FROM pg_settings WHERE pending_restart ORDER BY name";
+
+impl Connection {
+    /// Returns the names of the settings whose new value will only be applied by restarting the server
+    pub fn pending_restart(&mut self) -> Result<Vec<String>, PGError> {
+        match &mut self.client {
+            Some(ref mut c) => match c.query(PENDING_RESTART_QUERY, &[]) {
+                Ok(rows) => Ok(rows.iter().filter_map(|r| r.try_get(0).ok()).collect()),
+                Err(e) => Err(PGError::query("listing the settings pending a restart", e)),
+            },
+
+            // Client is empty, cannot run a query
+            None => Err(PGError::ClientEmpty),
+        }
+    }
+
+    /// Lists the server settings from `pg_settings`, only those whose name contains `filter` when given, e.g. `wal`,
+    /// with their value, unit, default, source and whether a restart is pending, see `BROWSE_SETTINGS_QUERY`
+    pub fn get_settings(&mut self, filter: Option<&str>) -> Result<Value, PGError> {
+        self.query_json_with(BROWSE_SETTINGS_QUERY, &[&filter])
+    }
+
+    /// Returns the role and database specific setting overrides, see `SETTING_OVERRIDES_QUERY`. <br>
+    /// When `role` or `database` is given only the overrides applying to that role or database are returned,
+    /// including those for the role in one particular database.
+    pub fn setting_overrides(
+        &mut self,
+        role: Option<&str>,
+        database: Option<&str>,
+    ) -> Result<Value, PGError> {
+        let query = format!(
+            "SELECT * FROM ({}) o WHERE ($1::text IS NULL OR o.role = $1) AND ($2::text IS NULL OR o.database = $2)",
+            SETTING_OVERRIDES_QUERY
+        );
+        self.query_json_with(&query, &[&role, &database])
+    }
+}
+
+/// Styles rows of `BROWSE_SETTINGS_QUERY` for the terminal: settings changed from their default are shown in yellow,
+/// and those waiting for a restart in red
+pub fn highlight_changed_settings(rows: &mut Value) {
+    for row in rows.as_array_mut().into_iter().flatten() {
+        let pending = row["pending_restart"] == Value::Bool(true);
+        let changed = row["changed"] == Value::Bool(true);
+        for key in ["name", "setting"] {
+            let text = match &row[key] {
+                Value::String(s) => s.clone(),
+                _ => continue,
+            };
+            let styled = match (pending, changed) {
+                (true, _) => text.red().bold().to_string(),
+                (false, true) => text.yellow().to_string(),
+                (false, false) => continue,
+            };
+            row[key] = Value::String(styled);
+        }
+    }
+}
+
+/// Styles rows of `FILE_SETTINGS_QUERY` for the terminal: entries with an error are shown in red and the values
+/// of overridden entries are struck through, so typos and shadowed values stand out before a restart
+pub fn highlight_file_settings(rows: &mut Value) {
+    let rows = match rows.as_array_mut() {
+        Some(r) => r,
+        None => return,
+    };
+
+    for row in rows {
+        let status = row["status"].as_str().unwrap_or("").to_string();
+        let row = match row.as_object_mut() {
+            Some(r) => r,
+            None => continue,
+        };
+
+        for (key, value) in row.iter_mut() {
+            let text = match value {
+                Value::String(s) => s.clone(),
+                _ => continue,
+            };
+            let styled = match status.as_str() {
+                "error" => text.red().bold().to_string(),
+                "overridden" if key == "setting" => text.strikethrough().to_string(),
+                "overridden" => text.dimmed().to_string(),
+                _ => continue,
+            };
+            *value = Value::String(styled);
+        }
+    }
+}
@@ -0,0 +1,59 @@
+use serde_json::Value;
+
+use super::{Connection, PGError};
+
+/// Query listing every database, largest first, with its owner, default tablespace, size and share of the size of all
+/// of them. <br>
+/// Databases the role may not connect to have no size, unless it is a member of `pg_read_all_stats`.
+pub const DATABASE_SIZES_QUERY: &str = r#"
+    WITH sizes AS (
+        SELECT d.datname AS database,
+               pg_get_userbyid(d.datdba) AS owner,
+               t.spcname AS tablespace,
+               CASE WHEN has_database_privilege(d.oid, 'CONNECT')
+                      OR pg_has_role('pg_read_all_stats', 'MEMBER')
+                    THEN pg_database_size(d.oid)
+               END AS size_bytes
+        FROM pg_database d
+        JOIN pg_tablespace t ON t.oid = d.dattablespace
+    )
+    SELECT database,
+           owner,
+           tablespace,
+           size_bytes,
+           round(100.0 * size_bytes / nullif(sum(size_bytes) OVER (), 0), 1) AS percent
+    FROM sizes
+    ORDER BY size_bytes DESC NULLS LAST, database
+"#;
+
+/// Query listing every tablespace, largest first, with its owner, location on disk, size and the databases using it
+/// by default. The built-in `pg_default` and `pg_global` live in the data directory and have no location of their own.
+/// <br>
+/// Tablespaces the role has no `CREATE` privilege on have no size, unless it is a member of `pg_read_all_stats`.
+pub const TABLESPACE_SIZES_QUERY: &str = r#"
+    SELECT t.spcname AS tablespace,
+           pg_get_userbyid(t.spcowner) AS owner,
+           coalesce(nullif(pg_tablespace_location(t.oid), ''), '(data directory)') AS location,
+           CASE WHEN has_tablespace_privilege(t.oid, 'CREATE')
+                  OR pg_has_role('pg_read_all_stats', 'MEMBER')
+                  OR t.oid = (SELECT dattablespace FROM pg_database WHERE datname = current_database())
+                THEN pg_tablespace_size(t.oid)
+           END AS size_bytes,
+           (SELECT string_agg(d.datname, ', ' ORDER BY d.datname)
+            FROM pg_database d
+            WHERE d.dattablespace = t.oid) AS default_for
+    FROM pg_tablespace t
+    ORDER BY size_bytes DESC NULLS LAST, t.spcname
+"#;
+
+impl Connection {
+    /// Lists the databases by size, see `DATABASE_SIZES_QUERY`
+    pub fn get_database_sizes(&mut self) -> Result<Value, PGError> {
+        self.query_json(DATABASE_SIZES_QUERY)
+    }
+
+    /// Lists the tablespaces by size with their location, see `TABLESPACE_SIZES_QUERY`
+    pub fn get_tablespace_sizes(&mut self) -> Result<Value, PGError> {
+        self.query_json(TABLESPACE_SIZES_QUERY)
+    }
+}
@@ -0,0 +1,71 @@
+use postgres::error::SqlState;
+use serde_json::Value;
+
+use super::transaction::read_only;
+use super::{Connection, PGError};
+
+/// Query listing the replication slots with how much WAL each keeps the server from removing, inactive slots holding
+/// WAL back first, as they are what fills the disk when a standby or subscriber is gone for good. <br>
+/// `wal_status` (Postgres 13) and `inactive_since` (Postgres 17) are read through `to_jsonb` so that older versions,
+/// which lack them, show them empty rather than failing.
+pub const SLOTS_QUERY: &str = r#"
+    SELECT s.slot_name,
+           s.slot_type,
+           s.plugin,
+           s.database,
+           s.active,
+           s.active_pid,
+           s.temporary,
+           to_jsonb(s) ->> 'wal_status' AS wal_status,
+           date_trunc('second', now() - (to_jsonb(s) ->> 'inactive_since')::timestamptz)::text AS inactive_for,
+           pg_wal_lsn_diff(
+               CASE WHEN pg_is_in_recovery() THEN pg_last_wal_receive_lsn() ELSE pg_current_wal_lsn() END,
+               s.restart_lsn
+           )::bigint AS retained_bytes,
+           CASE WHEN NOT s.active AND s.restart_lsn IS NOT NULL THEN 'inactive, holding back WAL'
+                WHEN to_jsonb(s) ->> 'wal_status' = 'lost' THEN 'lost, its consumer cannot resume'
+           END AS note
+    FROM pg_replication_slots s
+    ORDER BY s.active, retained_bytes DESC NULLS LAST, s.slot_name
+"#;
+
+impl Connection {
+    /// Lists the replication slots, see `SLOTS_QUERY`
+    pub fn get_replication_slots(&mut self) -> Result<Value, PGError> {
+        self.query_json(SLOTS_QUERY)
+    }
+
+    /// Looks up the replication slot `name` as `get_replication_slots` lists it, to be shown before it is dropped.
+    /// <br>
+    /// Returns `MatchNotFound` when there is no such slot.
+    pub fn describe_slot(&mut self, name: &str) -> Result<Value, PGError> {
+        let query = format!("SELECT * FROM ({}) s WHERE slot_name = $1", SLOTS_QUERY);
+        let rows = self.query_json_with(&query, &[&name])?;
+        if rows.as_array().is_none_or(|r| r.is_empty()) {
+            return Err(PGError::MatchNotFound);
+        }
+        Ok(rows)
+    }
+
+    /// Drops the replication slot `name` with `pg_drop_replication_slot`, letting the server remove the WAL it
+    /// retained. Its consumer, a standby or a logical replication subscriber, can no longer resume from where it left
+    /// off. <br>
+    /// Returns `SlotActive` while a consumer is connected to the slot, `MatchNotFound` when there is no such slot and
+    /// `SlotDropDenied` unless the role is a superuser or has the `REPLICATION` attribute.
+    pub fn drop_slot(&mut self, name: &str) -> Result<(), PGError> {
+        if read_only() {
+            return Err(PGError::ReadOnly);
+        }
+        let client = match &mut self.client {
+            Some(c) => c,
+            None => return Err(PGError::ClientEmpty),
+        };
+        match client.execute("SELECT pg_drop_replication_slot($1)", &[&name]) {
+            Ok(_) => Ok(()),
+            Err(e) if e.code() == Some(&SqlState::OBJECT_IN_USE) => Err(PGError::SlotActive),
+            Err(e) if e.code() == Some(&SqlState::UNDEFINED_OBJECT) => Err(PGError::MatchNotFound),
+            Err(e) if e.code() == Some(&SqlState::INSUFFICIENT_PRIVILEGE) => Err(PGError::SlotDropDenied),
+            Err(e) => Err(PGError::query(format!("dropping the replication slot {}", name), e)),
+        }
+    }
+}
@@ -0,0 +1,94 @@
+use std::fs;
+
+use chrono::Local;
+use serde_json::{json, Map, Value};
+use tracing::error;
+
+use super::collectors::{find_collector, Collector};
+use super::delta::{changes, rates};
+use super::history::unix_now;
+use super::humanize::human_duration;
+use super::marks::sample_counters;
+use super::resets::{recorded_resets, reset_after};
+use super::{Connection, PGError};
+
+/// Version of the snapshot file format, increased whenever its layout changes
+const SNAPSHOT_VERSION: u64 = 1;
+
+/// Samples every collector with cumulative counters, see `sample_counters`, and writes them to the file at `path` with
+/// the connection they were taken on and when, so `diff_snapshot` can measure the counters against them later, e.g.
+/// from a cron job after the program exited. When the statistics were last reset with `reset-stats` is written
+/// along with them. <br>
+/// Returns how many collectors were sampled, or `SnapshotError` when the file cannot be written.
+pub fn save_snapshot(connection: &mut Connection, all_databases: bool, path: &str) -> Result<usize, PGError> {
+    let samples = sample_counters(connection, all_databases)?;
+    let mut collectors = Map::new();
+    for (collector, rows) in &samples {
+        collectors.insert(collector.name.to_string(), rows.clone());
+    }
+    let snapshot = json!({
+        "version": SNAPSHOT_VERSION,
+        "connection": connection.label(),
+        "all_databases": all_databases,
+        "taken_at": unix_now(),
+        "set_at": Local::now().format("%Y-%m-%d %H:%M:%S").to_string(),
+        "samples": collectors,
+        "resets": recorded_resets(&connection.label()),
+    });
+    let text = serde_json::to_string_pretty(&snapshot).expect("Failed to serialize the snapshot");
+    match fs::write(path, text) {
+        Ok(_) => Ok(samples.len()),
+        Err(e) => {
+            error!("{}", e);
+            Err(PGError::SnapshotError)
+        }
+    }
+}
+
+/// Samples the collectors of the snapshot at `path` again, or only `collector`, and returns a heading along with how
+/// much their counters grew since the snapshot, for each of them, or with `per_second` how fast they grew on average.
+/// The heading says when the counters were reset with `reset-stats` after the snapshot, as they count from then. <br>
+/// Returns `SnapshotError` when the file is not a snapshot this version can read and `SnapshotOnOtherConnection` when
+/// it was taken on another connection than `connection`.
+pub fn diff_snapshot(
+    connection: &mut Connection,
+    path: &str,
+    collector: Option<&Collector>,
+    per_second: bool,
+) -> Result<Vec<(String, Value)>, PGError> {
+    let snapshot = match fs::read_to_string(path).map(|text| serde_json::from_str::<Value>(&text)) {
+        Ok(Ok(snapshot)) if snapshot["version"].as_u64().is_some_and(|v| v <= SNAPSHOT_VERSION) => snapshot,
+        _ => return Err(PGError::SnapshotError),
+    };
+    if snapshot["connection"].as_str() != Some(connection.label().as_str()) {
+        return Err(PGError::SnapshotOnOtherConnection);
+    }
+
+    let taken_at = snapshot["taken_at"].as_u64().unwrap_or(0);
+    let seconds = unix_now().saturating_sub(taken_at) as f64;
+    let set_at = snapshot["set_at"].as_str().unwrap_or("");
+    let all_databases = snapshot["all_databases"].as_bool().unwrap_or(false);
+    let resets = recorded_resets(&connection.label());
+    let mut results = Vec::new();
+    for (name, before) in snapshot["samples"].as_object().into_iter().flatten() {
+        let sampled = match find_collector(name) {
+            Some(c) if collector.is_none_or(|wanted| wanted.name == c.name) => c,
+            _ => continue,
+        };
+        let delta = match sampled.delta.as_ref() {
+            Some(delta) => delta,
+            None => continue,
+        };
+        let now = connection.collect(sampled, all_databases)?;
+        let (rows, measure) = match per_second {
+            true => (rates(before, &now, delta, seconds), "per second"),
+            false => (changes(before, &now, delta), "changes"),
+        };
+        let mut heading = format!("{} {} since the snapshot of {} ({} ago)", name, measure, set_at, human_duration(seconds));
+        if let Some((reset, reset_at)) = reset_after(&resets, name, taken_at) {
+            heading = format!("{}, counted from the {} statistics reset of {}", heading, reset, reset_at);
+        }
+        results.push((heading, rows));
+    }
+    Ok(results)
+}
@@ -0,0 +1,128 @@
+use colored::Colorize;
+use serde_json::{json, Value};
+
+use super::humanize::human_bytes;
+use super::output::OutputFormatter;
+use super::style::warning_sign;
+use super::{Config, Connection, PGError};
+
+/// Query listing the temporary files written by every database since its statistics were reset, most bytes first,
+/// with their average size and the `work_mem` sorts and hashes may use before spilling to them. <br>
+/// `log_temp_files` is given as well, as logging them is how to find the statements spilling without
+/// `pg_stat_statements`.
+pub const TEMP_FILES_QUERY: &str = r#"
+    SELECT datname AS database,
+           temp_files,
+           temp_bytes,
+           temp_bytes / nullif(temp_files, 0) AS avg_file_bytes,
+           round(100.0 * temp_bytes / nullif(sum(temp_bytes) OVER (), 0), 1) AS percent,
+           current_setting('work_mem') AS work_mem,
+           current_setting('log_temp_files') AS log_temp_files,
+           date_trunc('second', stats_reset)::text AS since
+    FROM pg_stat_database
+    WHERE datname IS NOT NULL
+    ORDER BY temp_bytes DESC, datname
+"#;
+
+/// Query listing the 20 statements of `pg_stat_statements` writing the most to temporary files, those writing none
+/// left out, with how much they write per call, their share of all temporary file writes and their mean time. <br>
+/// What a statement writes per call is about how much more memory its sorts and hashes would have needed. The
+/// execution time is read through `to_jsonb`, as it was renamed in `pg_stat_statements` 1.8.
+pub const TEMP_STATEMENTS_QUERY: &str = r#"
+    WITH statements AS (
+        SELECT s.queryid AS query_id,
+               s.calls,
+               s.temp_blks_written * current_setting('block_size')::bigint AS written_bytes,
+               s.temp_blks_read * current_setting('block_size')::bigint AS read_bytes,
+               coalesce((j ->> 'mean_exec_time')::float8, (j ->> 'mean_time')::float8) AS mean_ms,
+               s.query
+        FROM pg_stat_statements s, to_jsonb(s) AS j
+    )
+    SELECT query_id,
+           calls,
+           written_bytes,
+           read_bytes,
+           written_bytes / nullif(calls, 0) AS written_per_call_bytes,
+           round(100.0 * written_bytes / nullif(sum(written_bytes) OVER (), 0), 1) AS percent,
+           round(mean_ms::numeric, 2) AS mean_ms,
+           left(regexp_replace(query, '\s+', ' ', 'g'), 80) AS query
+    FROM statements
+    WHERE written_bytes > 0
+    ORDER BY written_bytes DESC
+    LIMIT 20
+"#;
+
+impl Connection {
+    /// Reports what spills to temporary files because it does not fit in `work_mem`: the `TEMP_FILES_QUERY` rows
+    /// under `databases` and, when `pg_stat_statements` can be used, the `TEMP_STATEMENTS_QUERY` rows of the
+    /// statements writing them under `statements`, null otherwise
+    pub fn spill_report(&mut self) -> Result<Value, PGError> {
+        let databases = self.query_json(TEMP_FILES_QUERY)?;
+        let statements = match self.capabilities.allows(Some("pg_stat_statements")) {
+            // The extension may still be missing when the probe on connect failed
+            true => match self.query_json(TEMP_STATEMENTS_QUERY) {
+                Ok(rows) => rows,
+                Err(PGError::MissingObject { .. }) => Value::Null,
+                Err(e) => return Err(e),
+            },
+            false => Value::Null,
+        };
+        Ok(json!({ "databases": databases, "statements": statements }))
+    }
+}
+
+/// Renders what `Connection::spill_report` returns: in JSON as one object, otherwise the databases and the
+/// statements each in its own table, followed by what to tune: `work_mem` for the statements spilling, and
+/// `log_temp_files` to find them when `pg_stat_statements` cannot
+pub fn render_spill_report(report: &Value, config: &Config) -> String {
+    if config.output_format == "json" {
+        return serde_json::to_string_pretty(report).expect("Failed to serialize the report");
+    }
+    let formatter = OutputFormatter::new(config);
+    let databases = &report["databases"];
+    let mut sections = vec![formatter.titled("Temporary files by database", formatter.rows(databases))];
+    let first = databases.get(0);
+    let work_mem = first.and_then(|d| d["work_mem"].as_str()).unwrap_or("?");
+    let spilled: i64 = databases.as_array().into_iter().flatten().filter_map(|d| d["temp_bytes"].as_i64()).sum();
+
+    match report["statements"].as_array() {
+        Some(statements) => {
+            let heading = "Statements writing the most to temporary files";
+            sections.push(formatter.titled(heading, formatter.rows(&report["statements"])));
+            if !statements.is_empty() {
+                sections.push(
+                    format!(
+                        "{} {} statement(s) spill to disk with work_mem = {}. Raise work_mem to about what a \
+                         statement writes per call, with SET work_mem for its session or ALTER ROLE for its role, \
+                         keeping work_mem times the connections running such statements within the memory free",
+                        warning_sign(),
+                        statements.len(),
+                        work_mem
+                    )
+                    .yellow()
+                    .to_string(),
+                );
+            }
+        }
+        None if spilled > 0 => {
+            let hint = match first.and_then(|d| d["log_temp_files"].as_str()) {
+                Some("-1") => ", or set log_temp_files = 0 to log them",
+                _ => ", or look for them in the server log, as log_temp_files logs them",
+            };
+            sections.push(
+                format!(
+                    "{} {} written to temporary files with work_mem = {}. CREATE EXTENSION pg_stat_statements \
+                     to see which statements spill{}",
+                    warning_sign(),
+                    human_bytes(spilled),
+                    work_mem,
+                    hint
+                )
+                .yellow()
+                .to_string(),
+            );
+        }
+        None => {}
+    }
+    sections.join("\n")
+}
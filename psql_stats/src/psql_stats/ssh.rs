@@ -0,0 +1,184 @@
+use std::io::{BufRead, BufReader};
+use std::net::{TcpListener, TcpStream};
+use std::process::{Child, Command, Stdio};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use tracing::{error, info};
+
+use super::humanize::human_duration;
+use super::{timeouts, Connection, PGError};
+
+/// How often opening a tunnel checks whether its local port accepts connections yet
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// How long opening a tunnel waits for it to be ready when `--connect-timeout 0` sets no limit, so an ssh that
+/// keeps running without ever forwarding cannot hold the program forever
+const FALLBACK_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Lines of ssh's messages kept to explain why a tunnel could not be opened
+const KEPT_MESSAGES: usize = 10;
+
+/// A local port forward opened with `ssh -L` through a bastion host, for databases only reachable from it. <br>
+/// Connections to `127.0.0.1:local_port` reach `host`:`port` as the bastion sees it. The `ssh` process is stopped
+/// when the `Tunnel` is dropped.
+pub struct Tunnel {
+    /// Host, or unix socket directory, of the database on the far side of the tunnel
+    pub host: String,
+    pub port: String,
+    /// Port on 127.0.0.1 forwarded to the database
+    pub local_port: u16,
+    child: Child,
+}
+
+impl Tunnel {
+    /// Starts `ssh` forwarding a free local port to `host`:`port` through `ssh_host`, given as `host` or
+    /// `host:port`, as `ssh_user` with the private key `ssh_key`, either empty for ssh's own defaults, and waits
+    /// until the forward is ready. <br>
+    /// ssh runs without prompting, so the key must not need a passphrase an agent does not provide, and the
+    /// bastion's host key must be known already. When ssh gives up, or the forward is not ready within the connect
+    /// timeout, `FALLBACK_TIMEOUT` without one, ssh is stopped, what it said is logged and `TunnelError` returned.
+    pub fn open(ssh_host: &str, ssh_user: &str, ssh_key: &str, host: &str, port: &str) -> Result<Tunnel, PGError> {
+        let local_port = match TcpListener::bind("127.0.0.1:0").and_then(|l| l.local_addr()) {
+            Ok(address) => address.port(),
+            Err(e) => {
+                error!("could not find a free local port: {}", e);
+                return Err(PGError::TunnelError);
+            }
+        };
+        // ssh can forward to a unix socket as well, as Postgres names it in the socket directory
+        let target = match host.starts_with('/') {
+            true => format!("{}/.s.PGSQL.{}", host.trim_end_matches('/'), port),
+            false => format!("{}:{}", host, port),
+        };
+
+        let mut command = Command::new("ssh");
+        command
+            .args(["-N", "-o", "ExitOnForwardFailure=yes", "-o", "BatchMode=yes"])
+            .arg("-L")
+            .arg(format!("127.0.0.1:{}:{}", local_port, target));
+        if let Some(seconds) = timeouts::connect_timeout() {
+            command.arg("-o").arg(format!("ConnectTimeout={}", seconds));
+        }
+        if !ssh_key.is_empty() {
+            command.arg("-i").arg(ssh_key);
+        }
+        if !ssh_user.is_empty() {
+            command.arg("-l").arg(ssh_user);
+        }
+        let bastion = match ssh_host.rsplit_once(':') {
+            Some((name, ssh_port)) if ssh_port.parse::<u16>().is_ok() => {
+                command.arg("-p").arg(ssh_port);
+                name
+            }
+            _ => ssh_host,
+        };
+        command.arg("--").arg(bastion);
+
+        let mut child = match command.stdin(Stdio::null()).stdout(Stdio::null()).stderr(Stdio::piped()).spawn() {
+            Ok(c) => c,
+            Err(e) => {
+                error!("could not run ssh, is OpenSSH installed? {}", e);
+                return Err(PGError::TunnelError);
+            }
+        };
+        let messages = keep_messages(&mut child);
+        let timeout = timeouts::connect_timeout().map_or(FALLBACK_TIMEOUT, Duration::from_secs);
+        let deadline = Instant::now() + timeout;
+
+        loop {
+            if let Ok(Some(status)) = child.try_wait() {
+                // Let the thread reading its messages catch up with everything ssh said before exiting
+                thread::sleep(POLL_INTERVAL);
+                let said = messages.lock().map(|m| m.join("\n")).unwrap_or_default();
+                match said.is_empty() {
+                    true => error!("ssh to {} exited ({}) before the tunnel was ready", ssh_host, status),
+                    false => error!("ssh to {} exited ({}) before the tunnel was ready: {}", ssh_host, status, said),
+                }
+                return Err(PGError::TunnelError);
+            }
+            if TcpStream::connect(("127.0.0.1", local_port)).is_ok() {
+                info!("opened an SSH tunnel through {} to {} on local port {}", ssh_host, target, local_port);
+                return Ok(Tunnel {
+                    host: host.to_string(),
+                    port: port.to_string(),
+                    local_port,
+                    child,
+                });
+            }
+            if Instant::now() >= deadline {
+                let _ = child.kill();
+                let _ = child.wait();
+                let said = messages.lock().map(|m| m.join("\n")).unwrap_or_default();
+                let waited = human_duration(timeout.as_secs_f64());
+                match said.is_empty() {
+                    true => error!("ssh to {} did not open the tunnel within {}", ssh_host, waited),
+                    false => error!("ssh to {} did not open the tunnel within {}: {}", ssh_host, waited, said),
+                }
+                return Err(PGError::TunnelError);
+            }
+            thread::sleep(POLL_INTERVAL);
+        }
+    }
+
+    /// Whether the `ssh` process is still forwarding, it exits e.g. when the bastion restarts
+    pub fn is_open(&mut self) -> bool {
+        matches!(self.child.try_wait(), Ok(None))
+    }
+}
+
+impl Drop for Tunnel {
+    fn drop(&mut self) {
+        info!("closed the SSH tunnel to {}:{}", self.host, self.port);
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+/// Reads what `child` writes on stderr on a thread of its own, so ssh never blocks on a full pipe, keeping the last
+/// `KEPT_MESSAGES` lines
+fn keep_messages(child: &mut Child) -> Arc<Mutex<Vec<String>>> {
+    let messages = Arc::new(Mutex::new(Vec::new()));
+    if let Some(stderr) = child.stderr.take() {
+        let kept = Arc::clone(&messages);
+        thread::spawn(move || {
+            for line in BufReader::new(stderr).lines().map_while(Result::ok) {
+                if let Ok(mut kept) = kept.lock() {
+                    kept.push(line);
+                    let excess = kept.len().saturating_sub(KEPT_MESSAGES);
+                    kept.drain(..excess);
+                }
+            }
+        });
+    }
+    messages
+}
+
+impl Connection {
+    /// Makes sure there is a tunnel through `ssh_host` to `host`:`port` when one is set, reusing the open one when it
+    /// already leads there, see `Tunnel::open`
+    pub(crate) fn open_tunnel(&mut self, host: &str, port: &str) -> Result<(), PGError> {
+        if self.ssh_host.is_empty() {
+            self.tunnel = None;
+            return Ok(());
+        }
+        if let Some(tunnel) = self.tunnel.as_mut() {
+            if tunnel.host == host && tunnel.port == port && tunnel.is_open() {
+                return Ok(());
+            }
+        }
+        // The previous tunnel is stopped first, so a stale one never outlives the attempt to replace it
+        self.tunnel = None;
+        self.tunnel = Some(Tunnel::open(&self.ssh_host, &self.ssh_user, &self.ssh_key, host, port)?);
+        Ok(())
+    }
+
+    /// The local port connecting to `host`:`port` goes through, when the open tunnel leads there
+    pub(crate) fn tunnel_port(&self, host: &str, port: &str) -> Option<u16> {
+        self.tunnel
+            .as_ref()
+            .filter(|t| t.host == host && t.port == port)
+            .map(|t| t.local_port)
+    }
+}
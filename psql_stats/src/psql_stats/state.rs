@@ -0,0 +1,99 @@
+use std::fs;
+
+use serde_json::{json, Value};
+use tracing::error;
+
+use super::history::unix_now;
+use super::profiles::{position, profiles};
+use super::{connections_file, Config, PGError};
+
+/// Marks a file as a state archive written by `backup_state`
+const ARCHIVE_FORMAT: &str = "psql_stats-state";
+
+/// Version of the archive format written by this program. <br>
+/// 1: the configuration as in `config.json`, saved queries included, and the connections file without keyring entries
+const ARCHIVE_VERSION: u64 = 1;
+
+/// Writes the configuration, saved queries included, and the saved connections to a single JSON archive at `path`, to
+/// be restored with `restore_state` on another workstation. <br>
+/// Passwords are never in the connections file, and the names of keyring entries are left out as well, as the keyring
+/// stays behind. Returns the number of connections archived.
+pub fn backup_state(path: &str) -> Result<usize, PGError> {
+    let config = Config::load().unwrap_or_default();
+    let mut connections = connections_file::load_or_new(&connections_file::path())?;
+
+    let mut in_keyring = 0;
+    for profile in connections["connections"].as_array_mut().into_iter().flatten() {
+        if !profile["keyring_entry"].as_str().unwrap_or("").is_empty() {
+            in_keyring += 1;
+        }
+        profile["keyring_entry"] = json!("");
+    }
+    let count = profiles(&connections)?.len();
+
+    let archive = json!({
+        "format": ARCHIVE_FORMAT,
+        "version": ARCHIVE_VERSION,
+        "created_at": unix_now(),
+        "config": config.to_json(),
+        "connections": connections,
+    });
+    let text = serde_json::to_string_pretty(&archive).expect("Failed to serialize state");
+    if let Err(e) = fs::write(path, text) {
+        error!("could not write {}: {}", path, e);
+        return Err(PGError::JSONWriteFileError);
+    }
+    if in_keyring > 0 {
+        println!(
+            "Note: the passwords of {} connection(s) stay in this workstation's keyring, save them again with \
+             --use-keyring after restoring",
+            in_keyring
+        );
+    }
+    Ok(count)
+}
+
+/// Restores an archive written by `backup_state` at `path`: the configuration replaces the current one, kept as
+/// `config.json.bak`, and the connections are added to the saved ones, replacing those of the same name. <br>
+/// Returns the number of connections restored, or `InvalidStateArchive` if the file is not an archive this version
+/// can read.
+pub fn restore_state(path: &str) -> Result<usize, PGError> {
+    let archive = fs::read_to_string(path)
+        .map_err(|e| e.to_string())
+        .and_then(|text| serde_json::from_str::<Value>(&text).map_err(|e| e.to_string()))
+        .map_err(|e| {
+            error!("could not read {}: {}", path, e);
+            PGError::InvalidStateArchive
+        })?;
+    if archive["format"] != ARCHIVE_FORMAT || archive["version"].as_u64().is_none_or(|v| v > ARCHIVE_VERSION) {
+        return Err(PGError::InvalidStateArchive);
+    }
+    let archived = connections_file::migrate(archive["connections"].clone())?;
+
+    let config_path = Config::path();
+    if config_path.exists() {
+        let backup = config_path.with_extension("json.bak");
+        if let Err(e) = fs::copy(&config_path, &backup) {
+            error!("could not back up {}: {}", config_path.display(), e);
+            return Err(PGError::JSONWriteFileError);
+        }
+    }
+    Config::from_json(&archive["config"]).save()?;
+
+    let connections_path = connections_file::path();
+    let mut file = connections_file::load_or_new(&connections_path)?;
+    let restored = profiles(&archived)?;
+    if let Some(saved) = file["connections"].as_array_mut() {
+        for profile in restored {
+            let name = profile["connection_name"].as_str().unwrap_or("");
+            match position(saved, name) {
+                Ok(index) => saved[index] = profile.clone(),
+                Err(_) => saved.push(profile.clone()),
+            }
+        }
+    }
+    if !restored.is_empty() {
+        connections_file::save(&connections_path, &file)?;
+    }
+    Ok(restored.len())
+}
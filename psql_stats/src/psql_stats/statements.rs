@@ -0,0 +1,102 @@
+use serde_json::Value;
+
+use super::capabilities::ExtensionState;
+use super::{shorten_queries, Connection, PGError};
+
+/// Query returning the version of `pg_stat_statements` installed in the current database, if it is
+const EXTENSION_VERSION_QUERY: &str = "SELECT extversion FROM pg_extension WHERE extname = 'pg_stat_statements'";
+
+/// A version of `pg_stat_statements`, as its major and minor numbers
+type Version = (u32, u32);
+
+/// Latest version of `pg_stat_statements` a column is still reported for
+const CURRENT: Version = (u32::MAX, 0);
+
+/// Columns of `pg_stat_statements` reported for every statement, in order, each with the extension versions that
+/// have it, from the first up to but excluding the last, so only those the installed version has are asked for.
+/// Times are in milliseconds.
+const COLUMNS: &[(Version, Version, &str)] = &[
+    ((1, 0), CURRENT, "calls"),
+    ((1, 0), CURRENT, "rows"),
+    // 1.8 split planning from execution and renamed total_time and mean_time
+    ((1, 0), (1, 8), "round(total_time::numeric, 2) AS total_ms"),
+    ((1, 8), CURRENT, "round(total_exec_time::numeric, 2) AS total_ms"),
+    ((1, 0), (1, 8), "round(mean_time::numeric, 2) AS mean_ms"),
+    ((1, 8), CURRENT, "round(mean_exec_time::numeric, 2) AS mean_ms"),
+    ((1, 8), CURRENT, "wal_records"),
+    ((1, 8), CURRENT, "wal_fpi"),
+    ((1, 8), CURRENT, "wal_bytes"),
+    (
+        (1, 10),
+        (1, 11),
+        "round((jit_generation_time + jit_inlining_time + jit_optimization_time + jit_emission_time)::numeric, 2) \
+         AS jit_ms",
+    ),
+    (
+        (1, 11),
+        CURRENT,
+        "round((jit_generation_time + jit_inlining_time + jit_optimization_time + jit_emission_time \
+         + jit_deform_time)::numeric, 2) AS jit_ms",
+    ),
+    // Before 1.11 a single pair of timings covered shared and local blocks together
+    ((1, 0), (1, 11), "round(blk_read_time::numeric, 2) AS blk_read_ms"),
+    ((1, 0), (1, 11), "round(blk_write_time::numeric, 2) AS blk_write_ms"),
+    ((1, 11), CURRENT, "round(shared_blk_read_time::numeric, 2) AS shared_blk_read_ms"),
+    ((1, 11), CURRENT, "round(shared_blk_write_time::numeric, 2) AS shared_blk_write_ms"),
+    ((1, 11), CURRENT, "round(local_blk_read_time::numeric, 2) AS local_blk_read_ms"),
+    ((1, 11), CURRENT, "round(local_blk_write_time::numeric, 2) AS local_blk_write_ms"),
+    ((1, 10), CURRENT, "round(temp_blk_read_time::numeric, 2) AS temp_blk_read_ms"),
+    ((1, 10), CURRENT, "round(temp_blk_write_time::numeric, 2) AS temp_blk_write_ms"),
+];
+
+/// Parses an extension version such as `1.10` into its major and minor numbers
+fn parse_version(version: &str) -> Option<Version> {
+    let (major, minor) = version.split_once('.').unwrap_or((version, "0"));
+    Some((major.parse().ok()?, minor.parse().ok()?))
+}
+
+/// Builds the query over `pg_stat_statements` with the `COLUMNS` of the installed `version`, slowest first
+fn statements_query(version: Version) -> String {
+    let columns: Vec<&str> = COLUMNS
+        .iter()
+        .filter(|(since, until, _)| *since <= version && version < *until)
+        .map(|(_, _, column)| *column)
+        .collect();
+    let total = match version >= (1, 8) {
+        true => "total_exec_time",
+        false => "total_time",
+    };
+    format!(
+        "SELECT queryid AS query_id, {}, query FROM pg_stat_statements ORDER BY {} DESC LIMIT $1",
+        columns.join(", "),
+        total
+    )
+}
+
+impl Connection {
+    /// Lists the `limit` statements that took the most time from `pg_stat_statements`, with their calls, rows and
+    /// execution time and, where the installed version of the extension has them, the WAL they generated (1.8),
+    /// their JIT compilation time and temporary file block timings (1.10), and separate shared and local block
+    /// timings (1.11, before which one pair covers both). Block timings need `track_io_timing`. <br>
+    /// Queries are put on one line and cut off at `query_width` characters, 0 keeps them whole. <br>
+    /// Returns the version of the extension with the rows, `StatementsUnavailable` if it is not installed, or
+    /// `StatementsNotPreloaded` if the probe on connect found it missing from `shared_preload_libraries`.
+    pub fn get_statement_stats(&mut self, limit: i64, query_width: usize) -> Result<(String, Value), PGError> {
+        if self.capabilities.state("pg_stat_statements") == Some(&ExtensionState::NotPreloaded) {
+            return Err(PGError::StatementsNotPreloaded);
+        }
+        let version = match &mut self.client {
+            Some(c) => match c.query_opt(EXTENSION_VERSION_QUERY, &[]) {
+                Ok(Some(row)) => row.get::<_, String>(0),
+                Ok(None) => return Err(PGError::StatementsUnavailable),
+                Err(e) => return Err(PGError::query("reading the version of pg_stat_statements", e)),
+            },
+            None => return Err(PGError::ClientEmpty),
+        };
+        let parsed = parse_version(&version).ok_or(PGError::StatementsUnavailable)?;
+
+        let mut rows = self.query_json_with(&statements_query(parsed), &[&limit])?;
+        shorten_queries(&mut rows, query_width);
+        Ok((version, rows))
+    }
+}
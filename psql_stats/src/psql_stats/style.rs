@@ -0,0 +1,59 @@
+use std::env;
+use std::io::{self, IsTerminal};
+use std::sync::OnceLock;
+
+/// Values of `--color`: color the output when it goes to a terminal, always, or never
+pub const COLOR_MODES: &[&str] = &["auto", "always", "never"];
+
+/// Whether output is written with ASCII characters only, see `set_style`
+static ASCII: OnceLock<bool> = OnceLock::new();
+
+/// Chooses how everything printed from now on looks. <br>
+/// `color` is one of `COLOR_MODES`. `auto` colors the output when the configuration allows it (`config_color`),
+/// stdout is a terminal and `NO_COLOR` is not set, so output piped into a file or a CI log holds no escape
+/// sequences, while `always` and `never` force it either way. <br>
+/// The signs and lines drawn with Unicode, such as the `⚠` heading warnings, are written in ASCII with `ascii`, and
+/// when the locale does not use UTF-8, as terminals and logs set to another encoding would show them garbled. Only
+/// the first call counts.
+pub fn set_style(color: &str, config_color: bool, ascii: bool) {
+    let colored = match color {
+        "always" => true,
+        "never" => false,
+        _ => config_color && !no_color() && io::stdout().is_terminal(),
+    };
+    colored::control::set_override(colored);
+    let _ = ASCII.set(ascii || !utf8_locale());
+}
+
+/// Whether `NO_COLOR` asks for no colors, see <https://no-color.org>: set to anything but an empty string
+fn no_color() -> bool {
+    env::var_os("NO_COLOR").is_some_and(|v| !v.is_empty())
+}
+
+/// Whether the locale the program runs in writes text as UTF-8, read from the first of `LC_ALL`, `LC_CTYPE` and
+/// `LANG` set, as `less` does. Windows consoles are taken to show Unicode.
+fn utf8_locale() -> bool {
+    if cfg!(windows) {
+        return true;
+    }
+    let locale = ["LC_ALL", "LC_CTYPE", "LANG"]
+        .iter()
+        .filter_map(|name| env::var(name).ok())
+        .find(|value| !value.is_empty())
+        .unwrap_or_default()
+        .to_lowercase();
+    locale.contains("utf-8") || locale.contains("utf8")
+}
+
+/// `unicode`, or `ascii` when output is written with ASCII characters only, see `set_style`
+pub(crate) fn glyph(unicode: &'static str, ascii: &'static str) -> &'static str {
+    match ASCII.get().copied().unwrap_or(false) {
+        true => ascii,
+        false => unicode,
+    }
+}
+
+/// Sign heading the warnings printed under reports and before queries, `⚠` or `!`
+pub fn warning_sign() -> &'static str {
+    glyph("\u{26A0}", "!")
+}
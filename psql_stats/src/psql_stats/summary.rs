@@ -0,0 +1,95 @@
+use colored::Colorize;
+
+use super::capabilities::{ExtensionState, STAT_EXTENSIONS};
+use super::healthcheck::{Status, Thresholds};
+use super::humanize::{human_bytes, human_duration, parse_interval};
+use super::{Connection, PGError};
+
+/// Most healthcheck warnings the summary lists, worst first
+const SUMMARY_WARNINGS: usize = 3;
+
+/// Query gathering what the startup summary shows: the server version and uptime, the size of the current database,
+/// the client connections against `max_connections`, and whether the server is a primary, with the standbys streaming
+/// from it, or a standby, with how far behind it replays
+const SUMMARY_QUERY: &str = r#"
+    SELECT current_setting('server_version') AS version,
+           date_trunc('second', now() - pg_postmaster_start_time())::text AS uptime,
+           pg_database_size(current_database()) AS database_bytes,
+           (SELECT count(*) FROM pg_stat_activity WHERE backend_type = 'client backend') AS connections,
+           current_setting('max_connections')::int AS max_connections,
+           pg_is_in_recovery() AS in_recovery,
+           (SELECT count(*) FROM pg_stat_replication) AS standbys,
+           CASE WHEN pg_is_in_recovery()
+                THEN date_trunc('second', now() - pg_last_xact_replay_timestamp())::text
+           END AS replay_delay
+"#;
+
+impl Connection {
+    /// Builds the screen shown after connecting in place of the help menu: the server version, uptime, database size,
+    /// connection usage and replication role, the enabled statistics extensions, and the `SUMMARY_WARNINGS` worst
+    /// problems the healthcheck finds with `thresholds`. <br>
+    /// Returns `ClientEmpty` when not connected and `QueryError` when the server cannot be queried.
+    pub fn startup_summary(&mut self, thresholds: &Thresholds) -> Result<String, PGError> {
+        if self.client.is_none() {
+            return Err(PGError::ClientEmpty);
+        }
+        let rows = self.query_json(SUMMARY_QUERY)?;
+        let row = &rows[0];
+        let count = |key: &str| row[key].as_i64().unwrap_or(0);
+
+        let uptime = row["uptime"].as_str().and_then(parse_interval).map(human_duration);
+        let (connections, max_connections) = (count("connections"), count("max_connections"));
+        let role = match (row["in_recovery"].as_bool(), count("standbys")) {
+            (Some(true), _) => match row["replay_delay"].as_str().and_then(parse_interval) {
+                Some(delay) => format!("standby, last replayed transaction {} ago", human_duration(delay)),
+                None => "standby, nothing replayed yet".to_string(),
+            },
+            (_, 0) => "primary, no standbys streaming".to_string(),
+            (_, standbys) => format!("primary, {} standby(s) streaming", standbys),
+        };
+        let enabled: Vec<&str> = STAT_EXTENSIONS
+            .iter()
+            .filter(|e| matches!(self.capabilities.state(e.name), Some(ExtensionState::Enabled(_))))
+            .map(|e| e.name)
+            .collect();
+
+        let mut lines = vec![
+            format!("{} {}", "psql_stats".bold(), self.label()),
+            format!("    Version:      PostgreSQL {}", row["version"].as_str().unwrap_or("")),
+            format!("    Uptime:       {}", uptime.unwrap_or_default()),
+            format!("    Database:     {}, {}", self.dbname, human_bytes(count("database_bytes"))),
+            format!(
+                "    Connections:  {} of {} ({}%)",
+                connections,
+                max_connections,
+                100 * connections / max_connections.max(1)
+            ),
+            format!("    Replication:  {}", role),
+        ];
+        if self.capabilities.probed {
+            let enabled = match enabled.is_empty() {
+                true => "none, see capabilities".to_string(),
+                false => enabled.join(", "),
+            };
+            lines.push(format!("    Extensions:   {}", enabled));
+        }
+
+        let mut checks = self.healthcheck(thresholds);
+        let total = checks.len();
+        checks.retain(|c| c.status != Status::Ok);
+        checks.sort_by_key(|c| std::cmp::Reverse(c.status));
+        if checks.is_empty() {
+            lines.push(format!("    Health:       {}", format!("all {} checks passed", total).green()));
+        }
+        for (i, check) in checks.iter().take(SUMMARY_WARNINGS).enumerate() {
+            let warning = format!("{}: {}", check.name, check.message);
+            let warning = match check.status {
+                Status::Crit => warning.red().bold(),
+                _ => warning.yellow(),
+            };
+            let heading = if i == 0 { "Health:" } else { "" };
+            lines.push(format!("    {:<13} {}", heading, warning));
+        }
+        Ok(lines.join("\n"))
+    }
+}
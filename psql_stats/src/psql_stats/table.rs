@@ -0,0 +1,367 @@
+use serde_json::{json, Value};
+
+use super::highlight::{paint, parse_rules, row_color, HighlightRule};
+use super::humanize::{human_bytes, human_duration, parse_interval, thousands};
+use super::locale::Locale;
+use super::PGError;
+
+/// How the table renderer lays out cells, kept in the `Config` and changed for the session with `\pset`
+#[derive(Clone)]
+pub struct TableOptions {
+    /// Widest a text column may get, 0 for no limit
+    pub max_width: usize,
+    /// Appended to text cut off at `max_width`
+    pub truncation_marker: String,
+    /// Shown in place of `NULL`
+    pub null_display: String,
+    /// Whether text longer than `max_width` continues on the following lines instead of being cut off
+    pub wrap: bool,
+    /// Whether byte counts, intervals and large numbers are made easier to read, see `humanize_cell`
+    pub human: bool,
+    /// Rules coloring rows whose values cross a threshold, see `HighlightRule`
+    pub highlight: Vec<HighlightRule>,
+    /// Most rows of a custom query read and shown, 0 for no limit
+    pub max_rows: usize,
+    /// Whether custom queries are followed by their row count and the type of every column, see `result_footer`
+    pub metadata: bool,
+    /// How numbers and dates are written when `human` is on, `None` for `1,234.5` and ISO 8601 dates
+    pub locale: Option<Locale>,
+    /// Whether query results and collectors taller than the terminal are shown through the pager, see `print_paged`
+    pub pager: bool,
+}
+
+impl Default for TableOptions {
+    fn default() -> Self {
+        TableOptions {
+            max_width: 0,
+            truncation_marker: "\u{2026}".to_string(),
+            null_display: "".to_string(),
+            wrap: false,
+            human: true,
+            highlight: Vec::new(),
+            max_rows: 0,
+            metadata: false,
+            locale: None,
+            pager: true,
+        }
+    }
+}
+
+impl TableOptions {
+    /// Reads the options from the `table` object of the configuration file, missing or invalid values fall back to
+    /// their defaults
+    pub fn from_json(value: &Value) -> TableOptions {
+        let defaults = TableOptions::default();
+        TableOptions {
+            max_width: value["max_width"]
+                .as_u64()
+                .map(|w| w as usize)
+                .unwrap_or(defaults.max_width),
+            truncation_marker: value["truncation_marker"]
+                .as_str()
+                .map(|m| m.to_string())
+                .unwrap_or(defaults.truncation_marker),
+            null_display: value["null_display"]
+                .as_str()
+                .map(|n| n.to_string())
+                .unwrap_or(defaults.null_display),
+            wrap: value["wrap"].as_bool().unwrap_or(defaults.wrap),
+            human: value["human"].as_bool().unwrap_or(defaults.human),
+            highlight: parse_rules(&value["highlight"]),
+            max_rows: value["max_rows"]
+                .as_u64()
+                .map(|r| r as usize)
+                .unwrap_or(defaults.max_rows),
+            metadata: value["metadata"].as_bool().unwrap_or(defaults.metadata),
+            locale: value["locale"].as_str().and_then(Locale::find),
+            pager: value["pager"].as_bool().unwrap_or(defaults.pager),
+        }
+    }
+
+    pub fn to_json(&self) -> Value {
+        json!({
+            "max_width": self.max_width,
+            "truncation_marker": self.truncation_marker,
+            "null_display": self.null_display,
+            "wrap": self.wrap,
+            "human": self.human,
+            "highlight": self.highlight.iter().map(|r| r.text.clone()).collect::<Vec<String>>(),
+            "max_rows": self.max_rows,
+            "metadata": self.metadata,
+            "locale": self.locale.map(|l| l.name),
+            "pager": self.pager,
+        })
+    }
+
+    /// Changes the option `name` to `value`, as typed after `\pset`. <br>
+    /// `max_width` takes a number of characters and `max_rows` a number of rows (0 for no limit), `wrap`, `human`,
+    /// `metadata` and `pager` take on or off, `truncation_marker` and `null_display` take any text, or nothing to clear
+    /// them, and `locale` one of `LOCALES`, or off.
+    pub fn set(&mut self, name: &str, value: &str) -> Result<(), PGError> {
+        match name {
+            "max_width" => self.max_width = value.parse().map_err(|_| PGError::InvalidPrintSetting)?,
+            "truncation_marker" => self.truncation_marker = value.to_string(),
+            "null_display" | "null" => self.null_display = value.to_string(),
+            "wrap" => self.wrap = on_off(value)?,
+            "human" => self.human = on_off(value)?,
+            "max_rows" => self.max_rows = value.parse().map_err(|_| PGError::InvalidPrintSetting)?,
+            "metadata" => self.metadata = on_off(value)?,
+            "pager" => self.pager = on_off(value)?,
+            "locale" => {
+                self.locale = match value {
+                    "off" | "" => None,
+                    name => Some(Locale::find(name).ok_or(PGError::InvalidPrintSetting)?),
+                }
+            }
+            _ => return Err(PGError::InvalidPrintSetting),
+        }
+        Ok(())
+    }
+
+    /// Lists every option with its current value, one per line
+    pub fn describe(&self) -> String {
+        let limit = |value: usize| match value {
+            0 => "0 (no limit)".to_string(),
+            v => v.to_string(),
+        };
+        [
+            format!("\tmax_width         {}", limit(self.max_width)),
+            format!("\ttruncation_marker \"{}\"", self.truncation_marker),
+            format!("\tnull_display      \"{}\"", self.null_display),
+            format!("\twrap              {}", if self.wrap { "on" } else { "off" }),
+            format!("\thuman             {}", if self.human { "on" } else { "off" }),
+            format!("\tmax_rows          {}", limit(self.max_rows)),
+            format!("\tmetadata          {}", if self.metadata { "on" } else { "off" }),
+            format!("\tlocale            {}", self.locale.map(|l| l.name).unwrap_or("off")),
+            format!("\tpager             {}", if self.pager { "on" } else { "off" }),
+        ]
+        .into_iter()
+        .chain(self.highlight.iter().map(|r| format!("\thighlight         {}", r.text)))
+        .collect::<Vec<String>>()
+        .join("\n")
+    }
+}
+
+fn on_off(value: &str) -> Result<bool, PGError> {
+    match value {
+        "on" | "true" => Ok(true),
+        "off" | "false" => Ok(false),
+        _ => Err(PGError::InvalidPrintSetting),
+    }
+}
+
+/// Renders rows returned by `Connection::query_json` as an aligned text table, one column per key of the first
+/// row, followed by the number of rows. <br>
+/// `NULL` values are shown as the `null_display` option and nested JSON values are printed as JSON. Only text is
+/// truncated or wrapped at `max_width`: numbers, booleans and JSON are always shown in full, since a cut off number
+/// would silently read as a different one. With the `human` option, values are formatted by `humanize_cell` first.
+/// Rows matching one of the `highlight` rules are colored.
+pub fn render_json_rows(rows: &Value, options: &TableOptions) -> String {
+    let rows = match rows.as_array() {
+        Some(r) => r,
+        None => return format!("\t{}", rows),
+    };
+
+    let headers: Vec<String> = match rows.first().and_then(|r| r.as_object()) {
+        Some(first) => first.keys().cloned().collect(),
+        None => return "\t(0 rows)".to_string(),
+    };
+
+    let cells: Vec<Vec<Vec<String>>> = rows
+        .iter()
+        .map(|row| {
+            let mut cells: Vec<Vec<String>> = headers
+                .iter()
+                .map(|h| match (&row[h], options.human) {
+                    (Value::Null, _) => vec![options.null_display.clone()],
+                    (value, true) => match humanize_cell(h, value, options.locale) {
+                        Some(text) => vec![text],
+                        None => fit_value(value, options),
+                    },
+                    (value, false) => fit_value(value, options),
+                })
+                .collect();
+            if let Some(color) = row_color(&options.highlight, row) {
+                paint(&mut cells, color);
+            }
+            cells
+        })
+        .collect();
+
+    render_table(&headers, &cells)
+}
+
+fn fit_value(value: &Value, options: &TableOptions) -> Vec<String> {
+    match value {
+        Value::String(s) => fit(s, options),
+        v => vec![v.to_string()],
+    }
+}
+
+/// Formats `value` of the column `column` to be easier to read, with the separators and date formats of `locale`
+/// when there is one, or returns `None` to show it as it is: <br>
+/// - numbers in columns named `size` or ending in `_bytes` or `_size` as KB, MB, GB, ... <br>
+/// - intervals as days, hours and minutes, e.g. `3d 4h 12m` <br>
+/// - other numbers with thousands separators, except identifiers such as `pid` or `oid` and ports <br>
+/// - dates and timestamps as the `locale` writes them
+fn humanize_cell(column: &str, value: &Value, locale: Option<Locale>) -> Option<String> {
+    let localized = |text: String| match locale {
+        Some(locale) => locale.number(&text),
+        None => text,
+    };
+    match value {
+        Value::Number(n) => {
+            let is_size = column == "size" || column.ends_with("_bytes") || column.ends_with("_size");
+            if is_size {
+                if let Some(bytes) = n.as_i64() {
+                    return Some(localized(human_bytes(bytes)));
+                }
+            }
+            let is_identifier = ["id", "pid", "oid", "port"].contains(&column)
+                || ["_pid", "_oid", "_id", "_port"].iter().any(|suffix| column.ends_with(suffix));
+            match is_identifier {
+                true => None,
+                false => Some(localized(thousands(&n.to_string()))),
+            }
+        }
+        Value::String(s) => match parse_interval(s) {
+            Some(seconds) => Some(human_duration(seconds)),
+            None => locale.and_then(|locale| locale.date_time(s)),
+        },
+        _ => None,
+    }
+}
+
+/// Cuts `text` down to `max_width`, or splits it into lines of at most `max_width` when wrapping. <br>
+/// Wrapped text also starts a new line at every line break it contains, so multi-line query text stays readable.
+fn fit(text: &str, options: &TableOptions) -> Vec<String> {
+    if options.wrap {
+        return text
+            .lines()
+            .flat_map(|line| match options.max_width {
+                0 => vec![line.to_string()],
+                w => split_visible(line, w),
+            })
+            .collect();
+    }
+
+    let marker_width = visible_width(&options.truncation_marker);
+    if options.max_width == 0 || visible_width(text) <= options.max_width || options.max_width <= marker_width {
+        return vec![text.to_string()];
+    }
+    let kept = split_visible(text, options.max_width - marker_width).remove(0);
+    vec![format!("{}{}", kept, options.truncation_marker)]
+}
+
+/// Renders `rows` under `headers` as an aligned text table followed by the number of rows. <br>
+/// Each cell holds one or more lines, a row is as tall as its tallest cell. Cells may be styled with ANSI escape
+/// sequences, they are not counted towards the column widths.
+pub fn render_table(headers: &[String], rows: &[Vec<Vec<String>>]) -> String {
+    let mut widths: Vec<usize> = headers.iter().map(|h| visible_width(h)).collect();
+    for row in rows {
+        for (i, cell) in row.iter().enumerate() {
+            for line in cell {
+                widths[i] = widths[i].max(visible_width(line));
+            }
+        }
+    }
+
+    let format_line = |cells: &[&str]| -> String {
+        let padded: Vec<String> = cells
+            .iter()
+            .zip(&widths)
+            .map(|(c, w)| format!("{}{}", c, " ".repeat(w - visible_width(c))))
+            .collect();
+        format!("\t{}", padded.join(" | ").trim_end())
+    };
+
+    let header: Vec<&str> = headers.iter().map(|h| h.as_str()).collect();
+    let mut lines = vec![format_line(&header)];
+    let separator: Vec<String> = widths.iter().map(|w| "-".repeat(*w)).collect();
+    lines.push(format!("\t{}", separator.join("-+-")));
+    for row in rows {
+        let height = row.iter().map(|cell| cell.len()).max().unwrap_or(0).max(1);
+        for i in 0..height {
+            let cells: Vec<&str> = row
+                .iter()
+                .map(|cell| cell.get(i).map(|l| l.as_str()).unwrap_or(""))
+                .collect();
+            lines.push(format_line(&cells));
+        }
+    }
+    lines.push(format!(
+        "\t({} row{})",
+        rows.len(),
+        if rows.len() == 1 { "" } else { "s" }
+    ));
+    lines.join("\n")
+}
+
+/// Cuts `text` down to `width` characters on screen and pads it with spaces to exactly that width, so lines can be
+/// placed side by side
+pub(crate) fn fit_width(text: &str, width: usize) -> String {
+    let cut = split_visible(text, width).remove(0);
+    let padding = width.saturating_sub(visible_width(&cut));
+    format!("{}{}", cut, " ".repeat(padding))
+}
+
+/// Number of characters `text` takes up on screen, skipping ANSI escape sequences such as colors
+fn visible_width(text: &str) -> usize {
+    let mut width = 0;
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c == '\x1B' {
+            // Skip to the letter ending the sequence, e.g. the 'm' of "\x1B[31m"
+            for c in chars.by_ref() {
+                if c.is_ascii_alphabetic() {
+                    break;
+                }
+            }
+        } else {
+            width += 1;
+        }
+    }
+    width
+}
+
+/// Splits `text` into pieces at most `width` characters wide on screen. <br>
+/// ANSI styles carry over: a piece that ends while a style is active resets it, and the next piece starts by applying
+/// it again, so every piece can be printed on its own line.
+fn split_visible(text: &str, width: usize) -> Vec<String> {
+    let mut pieces = Vec::new();
+    let mut piece = String::new();
+    let mut piece_width = 0;
+    let mut active = String::new();
+    let mut chars = text.chars();
+
+    while let Some(c) = chars.next() {
+        if c == '\x1B' {
+            let mut sequence = c.to_string();
+            for c in chars.by_ref() {
+                sequence.push(c);
+                if c.is_ascii_alphabetic() {
+                    break;
+                }
+            }
+            if sequence == "\x1B[0m" {
+                active.clear();
+            } else {
+                active.push_str(&sequence);
+            }
+            piece.push_str(&sequence);
+            continue;
+        }
+
+        if piece_width == width {
+            if !active.is_empty() {
+                piece.push_str("\x1B[0m");
+            }
+            pieces.push(std::mem::replace(&mut piece, active.clone()));
+            piece_width = 0;
+        }
+        piece.push(c);
+        piece_width += 1;
+    }
+    pieces.push(piece);
+    pieces
+}
@@ -0,0 +1,121 @@
+use std::collections::BTreeMap;
+
+use serde_json::{json, Value};
+
+use super::history::unix_now;
+use super::PGError;
+
+/// Kinds collector failures are counted as, see `error_kind`
+const ERROR_KINDS: &[&str] = &["timeout", "permission", "connection", "unavailable", "query"];
+
+/// Sorts a collector failure into one of `ERROR_KINDS`: the query ran into `statement_timeout`, the role lacks a
+/// privilege, the server could not be reached, the collector needs an extension the database lacks, or anything else
+/// the server refused
+fn error_kind(error: &PGError) -> &'static str {
+    match error {
+        PGError::QueryTimeout { .. } => "timeout",
+        PGError::SuperuserRequired | PGError::PermissionDenied { .. } => "permission",
+        PGError::ClientEmpty | PGError::ConnectionError | PGError::ConnectionLost { .. } => "connection",
+        PGError::ExtensionRequired
+        | PGError::StatementsUnavailable
+        | PGError::StatementsNotPreloaded
+        | PGError::VersionRequired { .. } => "unavailable",
+        _ => "query",
+    }
+}
+
+/// What was counted for one collector of one target
+#[derive(Default)]
+struct Counts {
+    requests: u64,
+    /// Failures by kind, indexed as `ERROR_KINDS`
+    failures: [u64; 5],
+    /// The most recent failure, as its message and the Unix time it happened at
+    last_error: Option<(String, u64)>,
+}
+
+/// The requests and failures of every collector served with `--serve`, per target, the connection they ran on, so a
+/// flaky target stands out from a healthy one. Served at `/v1/errors` and as Prometheus metrics at `/metrics`.
+#[derive(Default)]
+pub struct ErrorStats {
+    counts: BTreeMap<(String, String), Counts>,
+}
+
+impl ErrorStats {
+    /// Counts a request for `collector` on `target`, and `error` when it failed
+    pub fn record(&mut self, target: &str, collector: &str, error: Option<&PGError>) {
+        let counts = self.counts.entry((target.to_string(), collector.to_string())).or_default();
+        counts.requests += 1;
+        if let Some(error) = error {
+            let kind = ERROR_KINDS.iter().position(|k| *k == error_kind(error)).unwrap_or(ERROR_KINDS.len() - 1);
+            counts.failures[kind] += 1;
+            counts.last_error = Some((error.to_string(), unix_now()));
+        }
+    }
+
+    /// Lists every target and collector that failed at least once, those failing most often first, with their
+    /// requests, failures by kind, the most frequent kind and the last error with how long ago it happened
+    pub fn summary(&self) -> Value {
+        let mut rows: Vec<(u64, Value)> = self
+            .counts
+            .iter()
+            .filter_map(|((target, collector), counts)| {
+                let failures: u64 = counts.failures.iter().sum();
+                let (message, at) = counts.last_error.as_ref()?;
+                let most_frequent = (0..ERROR_KINDS.len()).max_by_key(|i| counts.failures[*i]).unwrap_or(0);
+                let mut row = json!({
+                    "target": target,
+                    "collector": collector,
+                    "requests": counts.requests,
+                    "failures": failures,
+                    "failure_percent": (1000 * failures / counts.requests) as f64 / 10.0,
+                    "most_frequent": ERROR_KINDS[most_frequent],
+                });
+                for (kind, count) in ERROR_KINDS.iter().zip(counts.failures) {
+                    row[*kind] = json!(count);
+                }
+                row["last_error"] = json!(message);
+                row["last_error_seconds_ago"] = json!(unix_now().saturating_sub(*at));
+                Some((failures, row))
+            })
+            .collect();
+        rows.sort_by_key(|(failures, _)| std::cmp::Reverse(*failures));
+        Value::Array(rows.into_iter().map(|(_, row)| row).collect())
+    }
+
+    /// Writes the counts in the Prometheus text format: `psql_stats_collector_requests_total` per target and
+    /// collector, and `psql_stats_collector_errors_total` per target, collector and kind of failure
+    pub fn metrics(&self) -> String {
+        let mut text = String::from(
+            "# HELP psql_stats_collector_requests_total Collector requests served.\n\
+             # TYPE psql_stats_collector_requests_total counter\n",
+        );
+        for ((target, collector), counts) in &self.counts {
+            text += &format!(
+                "psql_stats_collector_requests_total{{target=\"{}\",collector=\"{}\"}} {}\n",
+                escape_label(target),
+                collector,
+                counts.requests
+            );
+        }
+        text += "# HELP psql_stats_collector_errors_total Collector requests that failed, by kind of failure.\n\
+                 # TYPE psql_stats_collector_errors_total counter\n";
+        for ((target, collector), counts) in &self.counts {
+            for (kind, count) in ERROR_KINDS.iter().zip(counts.failures) {
+                text += &format!(
+                    "psql_stats_collector_errors_total{{target=\"{}\",collector=\"{}\",kind=\"{}\"}} {}\n",
+                    escape_label(target),
+                    collector,
+                    kind,
+                    count
+                );
+            }
+        }
+        text
+    }
+}
+
+/// Escapes a Prometheus label value: backslashes, double quotes and line breaks
+fn escape_label(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
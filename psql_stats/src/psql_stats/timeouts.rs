@@ -0,0 +1,56 @@
+use std::sync::OnceLock;
+
+use postgres::Client;
+use tracing::error;
+
+use super::humanize::parse_duration;
+
+/// Default of `--connect-timeout`, long enough for a server across the world, short enough not to look hung
+pub const DEFAULT_CONNECT_TIMEOUT: &str = "10s";
+
+/// Default of `--statement-timeout`, no limit, as statistics of a large database can take a while to gather
+pub const DEFAULT_STATEMENT_TIMEOUT: &str = "0";
+
+/// How long connecting and every statement may take in seconds, see `set_timeouts`. 0 for no limit
+static TIMEOUTS: OnceLock<(u64, u64)> = OnceLock::new();
+
+/// Parses the value of `--connect-timeout` or `--statement-timeout` into seconds: 0 for no limit, or a duration, see
+/// `parse_duration`
+pub fn parse_timeout(text: &str) -> Result<u64, String> {
+    match text.trim() {
+        "0" => Ok(0),
+        text => parse_duration(text).ok_or_else(|| "expected 0 or a duration such as 90, 30s, 5m or 1h".to_string()),
+    }
+}
+
+/// Limits every session opened from now on to `connect` seconds for reaching a host, the `connect_timeout` of the
+/// connection string, and `statement` seconds for each statement, after which the server cancels it, 0 for no
+/// limit. <br>
+/// So a host that cannot be reached, or a custom query that runs away, gives the prompt back instead of holding it
+/// forever. Only the first call counts.
+pub fn set_timeouts(connect: u64, statement: u64) {
+    let _ = TIMEOUTS.set((connect, statement));
+}
+
+/// How many seconds connecting to a host may take, none for no limit
+pub(crate) fn connect_timeout() -> Option<u64> {
+    TIMEOUTS.get().map(|t| t.0).filter(|&seconds| seconds > 0)
+}
+
+/// The statement setting the `statement_timeout` of a session to the one chosen with `set_timeouts`, none when none
+/// was, leaving the server's
+pub(crate) fn session_statement() -> Option<String> {
+    TIMEOUTS
+        .get()
+        .filter(|t| t.1 > 0)
+        .map(|&(_, seconds)| format!("SET statement_timeout = '{}s'", seconds))
+}
+
+/// Sets the `statement_timeout` of the session of `client`, see `session_statement`
+pub(crate) fn apply(client: &mut Client) {
+    if let Some(statement) = session_statement() {
+        if let Err(e) = client.batch_execute(&statement) {
+            error!("{}", e);
+        }
+    }
+}
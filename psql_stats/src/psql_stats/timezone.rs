@@ -0,0 +1,84 @@
+use std::sync::OnceLock;
+
+use chrono::{DateTime, Offset, Utc};
+use chrono_tz::Tz;
+use postgres::Client;
+use tracing::error;
+
+/// Value of `--timezone` showing timestamps in the time zone of the machine the program runs on
+pub const LOCAL_TIMEZONE: &str = "local";
+
+/// Value of `--timezone` keeping the time zone the server is configured with
+pub const SERVER_TIMEZONE: &str = "server";
+
+/// The time zone every session is set to on connect, see `set_timezone`. None keeps the server's
+static TIMEZONE: OnceLock<Option<Tz>> = OnceLock::new();
+
+/// Resolves `zone`, the value of `--timezone`, to the time zone sessions are set to: the zone of this machine for
+/// `local`, as the tz database names it, e.g. `Europe/Berlin`, none for `server`, and any other name of the tz
+/// database as given, e.g. `UTC` or `America/New_York`. <br>
+/// `local` keeps the server's when the machine's zone cannot be found.
+fn resolve_timezone(zone: &str) -> Result<Option<Tz>, String> {
+    match zone {
+        SERVER_TIMEZONE => Ok(None),
+        LOCAL_TIMEZONE => Ok(iana_time_zone::get_timezone().ok().and_then(|name| name.parse().ok())),
+        zone => zone
+            .parse::<Tz>()
+            .map(Some)
+            .map_err(|_| "unknown time zone, expected local, server or a name such as UTC or Europe/Berlin".to_string()),
+    }
+}
+
+/// Parses the value of `--timezone`, see `resolve_timezone`
+pub fn parse_timezone(zone: &str) -> Result<String, String> {
+    resolve_timezone(zone).map(|_| zone.to_string())
+}
+
+/// Chooses the time zone every session opened from now on shows its timestamps in, see `resolve_timezone`, so the
+/// start of a query, the last vacuum and the replay timestamp all read in the same zone whatever the server is set to.
+/// Only the first call counts.
+pub fn set_timezone(zone: &str) {
+    let _ = TIMEZONE.set(resolve_timezone(zone).unwrap_or(None));
+}
+
+/// The statement setting the `TimeZone` of a session to the zone chosen with `set_timezone`, none when none was.
+/// Names of the tz database hold no quotes.
+pub(crate) fn session_statement() -> Option<String> {
+    match TIMEZONE.get() {
+        Some(Some(zone)) => Some(format!("SET TimeZone = '{}'", zone.name())),
+        _ => None,
+    }
+}
+
+/// Sets the `TimeZone` of the session of `client` to the zone chosen with `set_timezone`, and returns the zone the
+/// session shows timestamps in, the server's when none was chosen. Empty if it cannot be read.
+pub(crate) fn apply(client: &mut Client) -> String {
+    if let Some(statement) = session_statement() {
+        if let Err(e) = client.batch_execute(&statement) {
+            error!("{}", e);
+        }
+    }
+    client
+        .query_one("SHOW TimeZone", &[])
+        .ok()
+        .and_then(|row| row.try_get::<_, String>(0).ok())
+        .unwrap_or_default()
+}
+
+/// Writes `at` the way the server writes a timestamp with time zone, in the zone chosen with `set_timezone`, or in
+/// UTC when none was, e.g. `2024-01-31 15:25:00.5+01`
+pub(crate) fn format_timestamp(at: DateTime<Utc>) -> String {
+    let (text, offset) = match TIMEZONE.get() {
+        Some(Some(zone)) => {
+            let local = at.with_timezone(zone);
+            (local.format("%Y-%m-%d %H:%M:%S%.f").to_string(), local.offset().fix().local_minus_utc())
+        }
+        _ => (at.format("%Y-%m-%d %H:%M:%S%.f").to_string(), 0),
+    };
+    let sign = if offset < 0 { '-' } else { '+' };
+    let (hours, minutes) = (offset.abs() / 3600, offset.abs() % 3600 / 60);
+    match minutes {
+        0 => format!("{}{}{:02}", text, sign, hours),
+        minutes => format!("{}{}{:02}:{:02}", text, sign, hours, minutes),
+    }
+}
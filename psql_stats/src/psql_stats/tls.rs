@@ -0,0 +1,74 @@
+use std::fs;
+
+use native_tls::{Certificate, Identity, TlsConnector};
+use postgres_native_tls::MakeTlsConnector;
+use tracing::error;
+
+use super::{Connection, PGError};
+
+/// Values accepted for `sslmode`, with the same meaning as in libpq
+pub const SSLMODES: &[&str] = &["disable", "prefer", "require", "verify-ca", "verify-full"];
+
+impl Connection {
+    /// The `sslmode` given to the driver, which only decides whether TLS is used. The certificate checks of
+    /// `verify-ca` and `verify-full` are made by the connector from `tls_connector`.
+    pub(crate) fn driver_sslmode(&self) -> &'static str {
+        match self.sslmode.as_str() {
+            "disable" => "disable",
+            "" | "prefer" => "prefer",
+            _ => "require",
+        }
+    }
+
+    /// Builds the TLS connector for this connection's `sslmode`, `sslrootcert`, `sslcert` and `sslkey`. <br>
+    /// Like libpq, `prefer` and `require` encrypt the connection without checking the server's certificate, unless
+    /// `require` is given a root certificate, `verify-ca` checks the certificate is signed by a trusted authority and
+    /// `verify-full` also checks it was issued for the host connected to. The system's trusted authorities are used,
+    /// along with the one in `sslrootcert`. `sslcert` and `sslkey` are PEM files for client certificate
+    /// authentication. <br>
+    /// Returns `TlsError` when a file cannot be read or is not a valid certificate or key.
+    pub(crate) fn tls_connector(&self) -> Result<MakeTlsConnector, PGError> {
+        let mut builder = TlsConnector::builder();
+
+        if !self.sslrootcert.is_empty() {
+            let pem = read(&self.sslrootcert)?;
+            let certificate = Certificate::from_pem(&pem).map_err(|e| tls_error(&self.sslrootcert, e))?;
+            builder.add_root_certificate(certificate);
+        }
+
+        match (self.sslcert.is_empty(), self.sslkey.is_empty()) {
+            (true, true) => {}
+            (false, false) => {
+                let (certificate, key) = (read(&self.sslcert)?, read(&self.sslkey)?);
+                let identity = Identity::from_pkcs8(&certificate, &key).map_err(|e| tls_error(&self.sslkey, e))?;
+                builder.identity(identity);
+            }
+            _ => {
+                error!("a client certificate needs both --sslcert and --sslkey");
+                return Err(PGError::TlsError);
+            }
+        }
+
+        let verify_ca = match self.sslmode.as_str() {
+            "verify-ca" | "verify-full" => true,
+            "require" => !self.sslrootcert.is_empty(),
+            _ => false,
+        };
+        builder.danger_accept_invalid_certs(!verify_ca);
+        builder.danger_accept_invalid_hostnames(self.sslmode != "verify-full");
+
+        match builder.build() {
+            Ok(connector) => Ok(MakeTlsConnector::new(connector)),
+            Err(e) => Err(tls_error("TLS connector", e)),
+        }
+    }
+}
+
+fn read(path: &str) -> Result<Vec<u8>, PGError> {
+    fs::read(path).map_err(|e| tls_error(path, e))
+}
+
+fn tls_error(what: &str, error: impl std::fmt::Display) -> PGError {
+    error!("{}: {}", what, error);
+    PGError::TlsError
+}
@@ -0,0 +1,218 @@
+use std::sync::OnceLock;
+
+use postgres::error::SqlState;
+use postgres::Client;
+use tracing::error;
+
+use super::{Connection, PGError, QueryResult, TextParam};
+
+/// Savepoint set before every protected custom query, see `Connection::protected_query`
+const SAVEPOINT: &str = "psql_stats_on_error_rollback";
+
+/// Whether read-only mode is on, see `set_read_only`
+static READ_ONLY: OnceLock<bool> = OnceLock::new();
+
+/// Turns read-only mode on or off for every session opened from now on, see `Config::read_only`: custom queries run
+/// in a read only transaction that is rolled back, see `Connection::read_only_query`, sessions default to read only
+/// transactions, so those opened with `begin` are as well, and the commands that write refuse with `ReadOnly`. Only
+/// the first call counts.
+pub fn set_read_only(read_only: bool) {
+    let _ = READ_ONLY.set(read_only);
+}
+
+/// Whether read-only mode is on, see `set_read_only`
+pub(crate) fn read_only() -> bool {
+    READ_ONLY.get().copied().unwrap_or(false)
+}
+
+/// The statement making the transactions of a session read only by default, in read-only mode only
+pub(crate) fn read_only_statement() -> Option<&'static str> {
+    read_only().then_some("SET default_transaction_read_only = on")
+}
+
+/// Makes the transactions of the session of `client` read only by default in read-only mode
+pub(crate) fn apply_read_only(client: &mut Client) {
+    if let Some(statement) = read_only_statement() {
+        if let Err(e) = client.batch_execute(statement) {
+            error!("{}", e);
+        }
+    }
+}
+
+/// Whether `query` could let the session or its transaction write: starting or setting a transaction `READ WRITE`,
+/// or changing `default_transaction_read_only` or `transaction_read_only`, with `SET`, `RESET`, `set_config`, or
+/// `RESET ALL` and `DISCARD ALL`, which reset them along with everything else. <br>
+/// Judged by the words of the statement, so one merely mentioning them, e.g. in a string, is refused as well.
+fn lifts_read_only(query: &str) -> bool {
+    let words = query.split_whitespace().collect::<Vec<&str>>().join(" ").to_lowercase();
+    ["read write", "transaction_read_only", "reset all", "discard all"]
+        .iter()
+        .any(|phrase| words.contains(phrase))
+}
+
+/// Whether `query` starts or ends a transaction, or sets a savepoint, itself, judging by its first word
+fn controls_transaction(query: &str) -> bool {
+    let first_word = query.split_whitespace().next().unwrap_or("").to_lowercase();
+    matches!(
+        first_word.as_str(),
+        "begin" | "start" | "commit" | "end" | "rollback" | "abort" | "savepoint" | "release" | "prepare"
+    )
+}
+
+/// Whether statements run inside a transaction opened with `begin`, shown in the prompt like psql's `*` and `!`
+#[derive(Default, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum TransactionState {
+    /// Every statement is committed as it runs
+    #[default]
+    Idle,
+    /// Statements are kept until `commit` or undone by `rollback`
+    Open,
+    /// A statement failed, everything until `rollback` is refused
+    Failed,
+}
+
+impl Connection {
+    /// Opens a transaction, so the custom queries and commands that follow can be checked before they are committed.
+    /// <br> Returns `TransactionOpen` if one is already open.
+    pub fn begin(&mut self) -> Result<(), PGError> {
+        if self.transaction != TransactionState::Idle {
+            return Err(PGError::TransactionOpen);
+        }
+        let statement = match read_only() {
+            true => "BEGIN READ ONLY",
+            false => "BEGIN",
+        };
+        self.run_transaction_statement(statement)?;
+        self.transaction = TransactionState::Open;
+        Ok(())
+    }
+
+    /// Commits the open transaction and returns what the server did, `COMMIT`, or `ROLLBACK` when a statement in the
+    /// transaction had failed. <br>
+    /// Returns `NoTransaction` if no transaction is open.
+    pub fn commit(&mut self) -> Result<&'static str, PGError> {
+        let outcome = match self.transaction {
+            TransactionState::Idle => return Err(PGError::NoTransaction),
+            TransactionState::Open => "COMMIT",
+            TransactionState::Failed => "ROLLBACK",
+        };
+        self.run_transaction_statement("COMMIT")?;
+        self.transaction = TransactionState::Idle;
+        Ok(outcome)
+    }
+
+    /// Undoes everything done since `begin`. <br>
+    /// Returns `NoTransaction` if no transaction is open.
+    pub fn rollback(&mut self) -> Result<(), PGError> {
+        if self.transaction == TransactionState::Idle {
+            return Err(PGError::NoTransaction);
+        }
+        self.run_transaction_statement("ROLLBACK")?;
+        self.transaction = TransactionState::Idle;
+        Ok(())
+    }
+
+    /// Finds out whether a statement run since the last check, a custom query or a collector, failed and aborted the
+    /// open transaction, by sending an empty statement the server refuses in an aborted transaction. Costs a round
+    /// trip, and only while a transaction is open.
+    pub fn refresh_transaction(&mut self) {
+        if self.transaction != TransactionState::Open {
+            return;
+        }
+        if let Some(client) = &mut self.client {
+            if let Err(e) = client.simple_query("SELECT") {
+                if e.code() == Some(&SqlState::IN_FAILED_SQL_TRANSACTION) {
+                    self.transaction = TransactionState::Failed;
+                }
+            }
+        }
+    }
+
+    /// Runs `custom_query`, and inside an open transaction sets a savepoint first and rolls back to it if the
+    /// statement fails, so one mistake undoes only itself rather than aborting the whole transaction, like psql's
+    /// `ON_ERROR_ROLLBACK=interactive`. <br>
+    /// Statements controlling the transaction themselves, such as `COMMIT` or `SAVEPOINT`, are run as they are.
+    pub fn protected_query(
+        &mut self,
+        query: &str,
+        params: &[TextParam],
+        max_rows: usize,
+    ) -> Result<QueryResult, PGError> {
+        if self.transaction != TransactionState::Open || controls_transaction(query) {
+            return self.custom_query(query, params, max_rows);
+        }
+
+        self.run_transaction_statement(&format!("SAVEPOINT {}", SAVEPOINT))?;
+        match self.custom_query(query, params, max_rows) {
+            Ok(result) => {
+                self.run_transaction_statement(&format!("RELEASE SAVEPOINT {}", SAVEPOINT))?;
+                Ok(result)
+            }
+            Err(e) => {
+                self.run_transaction_statement(&format!("ROLLBACK TO SAVEPOINT {}", SAVEPOINT))?;
+                Err(e)
+            }
+        }
+    }
+
+    /// Runs `query` as `custom_query` does, in read-only mode, in a read only transaction that is rolled back
+    /// afterwards, so it cannot write even when the session's default was changed. <br>
+    /// Inside a transaction opened with `begin`, read only itself, and for statements controlling the transaction or
+    /// setting the session up, e.g. `SET search_path`, which must outlast it, the statement is run as it is. A
+    /// transaction the statement starts, e.g. a `BEGIN` typed at option 6, is made read only right away. <br>
+    /// Returns `ReadOnly` for statements that would let the session write, see `lifts_read_only`.
+    pub(crate) fn read_only_query(
+        &mut self,
+        query: &str,
+        params: &[TextParam],
+        max_rows: usize,
+    ) -> Result<QueryResult, PGError> {
+        let first_word = query.split_whitespace().next().unwrap_or("").to_lowercase();
+        // Only on its own, a statement following it, e.g. in `SET default_transaction_read_only = off; INSERT ...`,
+        // would run outside the read only transaction
+        let single = !query.trim().trim_end_matches(';').contains(';');
+        let sets_session = matches!(first_word.as_str(), "set" | "reset") && single;
+        if lifts_read_only(query) {
+            return Err(PGError::ReadOnly);
+        }
+        if self.transaction != TransactionState::Idle || controls_transaction(query) || sets_session {
+            let opened = self.transaction == TransactionState::Idle;
+            let result = self.run_custom_query(query, params, max_rows)?;
+            if opened && self.transaction != TransactionState::Idle {
+                self.run_transaction_statement("SET TRANSACTION READ ONLY")?;
+            }
+            return Ok(result);
+        }
+
+        self.run_transaction_statement("BEGIN READ ONLY")?;
+        let result = self.run_custom_query(query, params, max_rows);
+        let rolled_back = self.run_transaction_statement("ROLLBACK");
+        self.transaction = TransactionState::Idle;
+        rolled_back?;
+        result
+    }
+
+    /// Follows transactions started or ended by a custom query, e.g. `BEGIN` typed at option 6, so the prompt stays
+    /// right. `ROLLBACK TO SAVEPOINT` leaves the transaction open, and clears its failure.
+    pub(crate) fn track_transaction(&mut self, query: &str) {
+        let words: Vec<String> = query.split_whitespace().take(2).map(|w| w.to_lowercase()).collect();
+        let words: Vec<&str> = words.iter().map(|w| w.as_str()).collect();
+        self.transaction = match words.as_slice() {
+            ["rollback", "to"] => TransactionState::Open,
+            ["begin", ..] | ["start", "transaction"] => TransactionState::Open,
+            ["commit" | "end" | "rollback" | "abort", ..] => TransactionState::Idle,
+            _ => return,
+        };
+    }
+
+    fn run_transaction_statement(&mut self, statement: &str) -> Result<(), PGError> {
+        let client = match &mut self.client {
+            Some(c) => c,
+            None => return Err(PGError::ClientEmpty),
+        };
+        client.batch_execute(statement).map_err(|e| {
+            error!("{}", e);
+            PGError::QueryFailed
+        })
+    }
+}
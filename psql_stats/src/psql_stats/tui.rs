@@ -0,0 +1,370 @@
+use std::sync::mpsc::{self, Receiver, Sender, TryRecvError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEventKind, KeyModifiers};
+use ratatui::layout::{Constraint, Layout, Rect};
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::text::{Line, Span};
+use ratatui::widgets::{Block, Paragraph};
+use ratatui::Frame;
+
+use serde_json::Value;
+use tracing::error;
+
+use super::alerts::Notifier;
+use super::collectors::find_collector;
+use super::concurrent::{ConcurrentSessions, SessionTarget};
+use super::humanize::{human_duration, parse_interval};
+use super::table::{render_json_rows, TableOptions};
+use super::{shorten_queries, Connection, PGError};
+
+/// Panes of the terminal UI below the status line, in the order Tab moves through them, each the collector it shows
+const PANES: &[(&str, &str)] = &[
+    ("Active sessions", "activity"),
+    ("Table sizes", "table-stats"),
+    ("Replication lag", "replication"),
+];
+
+/// Characters of each session's query shown in the sessions pane, the rest is reached by scrolling sideways
+const QUERY_WIDTH: usize = 200;
+
+/// Shortest and longest refresh interval `+` and `-` go to
+const INTERVAL_RANGE: (u64, u64) = (1, 300);
+
+/// How often the screen is drawn again while a refresh is under way, so keys are answered without waiting for it
+const REFRESH_POLL: Duration = Duration::from_millis(100);
+
+/// Runs the queries of a refresh in a thread of its own, over sessions of its own, see `ConcurrentSessions`, so a slow
+/// query holds up neither the keys nor the other queries. <br>
+/// The sessions are opened on the first refresh and again after the server dropped them.
+struct Refresher {
+    requests: Sender<Vec<Result<&'static str, PGError>>>,
+    results: Receiver<Vec<Result<Value, PGError>>>,
+    /// Whether a refresh was asked for and its results have not come back yet
+    pending: bool,
+}
+
+impl Refresher {
+    fn spawn(target: SessionTarget) -> Refresher {
+        let (requests, requested) = mpsc::channel::<Vec<Result<&'static str, PGError>>>();
+        let (send_results, results) = mpsc::channel();
+        thread::spawn(move || {
+            let mut sessions: Option<ConcurrentSessions> = None;
+            for queries in requested {
+                if sessions.is_none() {
+                    sessions = ConcurrentSessions::open(&target, queries.len()).ok();
+                }
+                let runnable: Vec<&str> = queries.iter().filter_map(|q| q.as_ref().ok().copied()).collect();
+                let mut rows = match &sessions {
+                    Some(s) => s.query_json(&runnable),
+                    None => runnable.iter().map(|_| Err(PGError::ConnectionError)).collect(),
+                }
+                .into_iter();
+                let results: Vec<Result<Value, PGError>> = queries
+                    .into_iter()
+                    .map(|q| q.and_then(|_| rows.next().unwrap_or(Err(PGError::UnexpectedResult))))
+                    .collect();
+                if results.iter().any(|r| r.as_ref().is_err_and(PGError::is_connection_lost)) {
+                    sessions = None;
+                }
+                if send_results.send(results).is_err() {
+                    break;
+                }
+            }
+        });
+        Refresher {
+            requests,
+            results,
+            pending: false,
+        }
+    }
+
+    /// Asks for the rows of `queries`, unless a refresh is already under way. A query that cannot run, e.g. for lack
+    /// of privileges, gives its error back as it is.
+    fn request(&mut self, queries: Vec<Result<&'static str, PGError>>) {
+        if !self.pending && self.requests.send(queries).is_ok() {
+            self.pending = true;
+        }
+    }
+
+    /// The results of the refresh under way once they came back
+    fn finished(&mut self) -> Option<Vec<Result<Value, PGError>>> {
+        match self.results.try_recv() {
+            Ok(results) => {
+                self.pending = false;
+                Some(results)
+            }
+            Err(TryRecvError::Empty) => None,
+            Err(TryRecvError::Disconnected) => {
+                self.pending = false;
+                None
+            }
+        }
+    }
+}
+
+/// One pane of the terminal UI: the latest rows of its collector as table lines, and how far it is scrolled
+struct Pane {
+    title: &'static str,
+    collector: &'static str,
+    lines: Vec<String>,
+    /// Lines scrolled down and characters scrolled right
+    scroll: (u16, u16),
+}
+
+/// What the terminal UI shows, refreshed from the server every `interval`
+struct State {
+    label: String,
+    connected: bool,
+    uptime: String,
+    panes: Vec<Pane>,
+    /// Pane with the keyboard focus, scrolled by the arrow keys
+    focus: usize,
+    /// Whether the focused pane takes up the whole screen
+    zoomed: bool,
+    interval: Duration,
+    refreshed_at: Instant,
+}
+
+impl State {
+    /// The collectors a refresh runs: uptime, then that of every pane
+    fn collectors(&self) -> Vec<&'static str> {
+        let panes = self.panes.iter().map(|pane| pane.collector);
+        std::iter::once("uptime").chain(panes).collect()
+    }
+
+    /// The queries of the collectors a refresh runs, or why `connection` cannot run one, see `Connection::collect`
+    fn queries(&self, connection: &Connection) -> Vec<Result<&'static str, PGError>> {
+        self.collectors()
+            .into_iter()
+            .map(|name| {
+                let collector = find_collector(name).ok_or(PGError::MatchNotFound)?;
+                connection.privileges.check(collector.requires)?;
+                connection.capabilities.check(collector.extension)?;
+                Ok(collector.query_for(connection.server_version))
+            })
+            .collect()
+    }
+
+    /// Runs the uptime collector and that of every pane on `connection` itself, one after another
+    fn refresh(&mut self, connection: &mut Connection, options: &TableOptions) {
+        let results = self
+            .collectors()
+            .into_iter()
+            .map(|name| match find_collector(name) {
+                Some(collector) => connection.collect(collector, false),
+                None => Err(PGError::MatchNotFound),
+            })
+            .collect();
+        self.show(results, options);
+        self.connected = connection.client.as_ref().is_some_and(|c| !c.is_closed());
+    }
+
+    /// Shows the `results` of a refresh, in the order of `collectors`, keeping each pane scrolled where it was
+    fn show(&mut self, results: Vec<Result<Value, PGError>>, options: &TableOptions) {
+        self.connected = !results
+            .iter()
+            .any(|r| matches!(r, Err(PGError::ClientEmpty)) || r.as_ref().is_err_and(PGError::is_connection_lost));
+        let mut results = results.into_iter();
+        self.uptime = match results.next() {
+            Some(Ok(rows)) => {
+                let uptime = rows[0]["uptime"].as_str().and_then(parse_interval).map(human_duration);
+                match (uptime, rows[0]["started_at"].as_str()) {
+                    (Some(uptime), Some(started_at)) => format!("up {} (since {})", uptime, started_at),
+                    _ => "uptime unknown".to_string(),
+                }
+            }
+            Some(Err(e)) => format!("Error: {}", e),
+            None => String::new(),
+        };
+
+        for pane in &mut self.panes {
+            let rows = results.next().unwrap_or(Err(PGError::UnexpectedResult));
+            pane.lines = match rows {
+                Ok(mut rows) => {
+                    shorten_queries(&mut rows, QUERY_WIDTH);
+                    render_json_rows(&rows, options)
+                        .lines()
+                        .map(|l| l.trim_start_matches('\t').to_string())
+                        .collect()
+                }
+                Err(e) => vec![format!("Error: {}", e)],
+            };
+        }
+        self.refreshed_at = Instant::now();
+    }
+
+    /// Handles a key press, returning whether to quit
+    fn key(&mut self, code: KeyCode, modifiers: KeyModifiers) -> bool {
+        let pane = &mut self.panes[self.focus];
+        match code {
+            KeyCode::Char('q') | KeyCode::Esc => return true,
+            KeyCode::Char('c') if modifiers.contains(KeyModifiers::CONTROL) => return true,
+            KeyCode::Tab => self.focus = (self.focus + 1) % self.panes.len(),
+            KeyCode::BackTab => self.focus = (self.focus + self.panes.len() - 1) % self.panes.len(),
+            KeyCode::Char(c @ '1'..='9') => {
+                let index = c as usize - '1' as usize;
+                if index < self.panes.len() {
+                    self.focus = index;
+                }
+            }
+            KeyCode::Down | KeyCode::Char('j') => pane.scroll.0 = pane.scroll.0.saturating_add(1),
+            KeyCode::Up | KeyCode::Char('k') => pane.scroll.0 = pane.scroll.0.saturating_sub(1),
+            KeyCode::PageDown => pane.scroll.0 = pane.scroll.0.saturating_add(10),
+            KeyCode::PageUp => pane.scroll.0 = pane.scroll.0.saturating_sub(10),
+            KeyCode::Right | KeyCode::Char('l') => pane.scroll.1 = pane.scroll.1.saturating_add(8),
+            KeyCode::Left | KeyCode::Char('h') => pane.scroll.1 = pane.scroll.1.saturating_sub(8),
+            KeyCode::Home => pane.scroll = (0, 0),
+            KeyCode::Enter | KeyCode::Char('z') => self.zoomed = !self.zoomed,
+            KeyCode::Char('+') => {
+                self.interval = Duration::from_secs((self.interval.as_secs() + 1).min(INTERVAL_RANGE.1))
+            }
+            KeyCode::Char('-') => {
+                self.interval = Duration::from_secs(self.interval.as_secs().saturating_sub(1).max(INTERVAL_RANGE.0))
+            }
+            _ => {}
+        }
+        false
+    }
+
+    fn draw(&self, frame: &mut Frame) {
+        let [status, body, help] =
+            Layout::vertical([Constraint::Length(3), Constraint::Min(0), Constraint::Length(1)]).areas(frame.area());
+
+        let (state, color) = match self.connected {
+            true => ("Connected", Color::Green),
+            false => ("Disconnected", Color::Red),
+        };
+        let status_line = Line::from(vec![
+            Span::styled(state, Style::default().fg(color).add_modifier(Modifier::BOLD)),
+            format!("  {}  {}", self.label, self.uptime).into(),
+        ]);
+        frame.render_widget(Paragraph::new(status_line).block(Block::bordered().title(" Connection ")), status);
+
+        if self.zoomed {
+            self.draw_pane(frame, self.focus, body);
+        } else {
+            // Sessions along the top, as they change the most, table sizes and replication side by side below
+            let [top, bottom] = Layout::vertical([Constraint::Percentage(55), Constraint::Percentage(45)]).areas(body);
+            let [left, right] =
+                Layout::horizontal([Constraint::Percentage(60), Constraint::Percentage(40)]).areas(bottom);
+            for (index, area) in [top, left, right].into_iter().enumerate().take(self.panes.len()) {
+                self.draw_pane(frame, index, area);
+            }
+        }
+
+        let refreshed = self.refreshed_at.elapsed().as_secs();
+        frame.render_widget(
+            Paragraph::new(format!(
+                " Tab/1-{} pane  \u{2191}\u{2193}\u{2190}\u{2192} scroll  z zoom  r refresh  +/- every {}s ({}s ago)  q quit",
+                self.panes.len(),
+                self.interval.as_secs(),
+                refreshed
+            ))
+            .style(Style::default().add_modifier(Modifier::DIM)),
+            help,
+        );
+    }
+
+    fn draw_pane(&self, frame: &mut Frame, index: usize, area: Rect) {
+        let pane = &self.panes[index];
+        let border = match index == self.focus {
+            true => Style::default().fg(Color::Cyan).add_modifier(Modifier::BOLD),
+            false => Style::default(),
+        };
+        let block = Block::bordered()
+            .title(format!(" {} {} ", index + 1, pane.title))
+            .border_style(border);
+        let lines: Vec<Line> = pane.lines.iter().map(|l| Line::from(l.as_str())).collect();
+        frame.render_widget(Paragraph::new(lines).block(block).scroll(pane.scroll), area);
+    }
+}
+
+/// Shows the connection status, uptime, active sessions, table sizes and replication lag of `connection` in panes
+/// filling the terminal, refreshed every `interval` until the user presses q. <br>
+/// Tab, Shift-Tab or the pane's number moves the focus between panes, the arrow keys (or h, j, k, l) scroll the
+/// focused one, z or Enter shows it alone on the whole screen, r refreshes at once and + and - change the interval.
+/// <br>
+/// With a `notifier`, the thresholds it watches are checked on `connection` as well, see `Notifier::poll`.
+pub fn run_tui(
+    connection: &mut Connection,
+    options: &TableOptions,
+    interval: Duration,
+    mut notifier: Option<Notifier>,
+) -> Result<(), PGError> {
+    // Highlight rules color rows with escape sequences, which the panes would show as text
+    let options = TableOptions {
+        highlight: Vec::new(),
+        ..options.clone()
+    };
+    let mut state = State {
+        label: connection.label(),
+        connected: false,
+        uptime: String::new(),
+        panes: PANES
+            .iter()
+            .map(|(title, collector)| Pane {
+                title,
+                collector,
+                lines: Vec::new(),
+                scroll: (0, 0),
+            })
+            .collect(),
+        focus: 0,
+        zoomed: false,
+        interval,
+        refreshed_at: Instant::now(),
+    };
+    // Refreshes run in the background once the first one is done, unless the connection cannot be shared with them
+    state.refresh(connection, &options);
+    let mut refresher = SessionTarget::of(connection).ok().map(Refresher::spawn);
+
+    let mut terminal = ratatui::init();
+    let result = loop {
+        if let Some(results) = refresher.as_mut().and_then(|r| r.finished()) {
+            state.show(results, &options);
+        }
+        if let Some(notifier) = notifier.as_mut() {
+            notifier.poll(connection);
+        }
+        if let Err(e) = terminal.draw(|frame| state.draw(frame)) {
+            break Err(e);
+        }
+
+        let pending = refresher.as_ref().is_some_and(|r| r.pending);
+        let timeout = match pending {
+            true => REFRESH_POLL,
+            false => state.interval.saturating_sub(state.refreshed_at.elapsed()),
+        };
+        let refresh = match event::poll(timeout) {
+            Ok(true) => match event::read() {
+                Ok(Event::Key(key)) if key.kind == KeyEventKind::Press => {
+                    if key.code == KeyCode::Char('r') {
+                        true
+                    } else if state.key(key.code, key.modifiers) {
+                        break Ok(());
+                    } else {
+                        false
+                    }
+                }
+                Ok(_) => false,
+                Err(e) => break Err(e),
+            },
+            Ok(false) => !pending,
+            Err(e) => break Err(e),
+        };
+        if refresh {
+            match refresher.as_mut() {
+                Some(r) => r.request(state.queries(connection)),
+                None => state.refresh(connection, &options),
+            }
+        }
+    };
+    ratatui::restore();
+
+    result.map_err(|e| {
+        error!("{}", e);
+        PGError::TerminalError
+    })
+}
@@ -0,0 +1,63 @@
+use std::time::Duration;
+
+use serde_json::{json, Value};
+use tracing::error;
+
+use super::history::{unix_now, History};
+use super::humanize::parse_interval;
+
+/// Seconds of slack when comparing start times worked out from two samples, since uptimes are truncated to the second
+/// and the sample time is only known to the second
+const RESTART_TOLERANCE: u64 = 5;
+
+/// How long the server has been running, see `Connection::get_uptime`
+pub struct Uptime {
+    /// Time since the postmaster started
+    pub uptime: Duration,
+    /// When the postmaster started, as printed by the server
+    pub started_at: String,
+    /// The uptime as printed by the server, kept to record it in the history in the same form as the collector
+    interval: String,
+}
+
+impl Uptime {
+    /// Builds an `Uptime` from the interval and start time the server returned as text. <br>
+    /// Returns `None` if the interval cannot be parsed.
+    pub(crate) fn parse(uptime: &str, started_at: &str) -> Option<Uptime> {
+        let seconds = parse_interval(uptime)?;
+        Some(Uptime {
+            uptime: Duration::from_secs_f64(seconds.max(0.0)),
+            started_at: started_at.to_string(),
+            interval: uptime.to_string(),
+        })
+    }
+
+    /// The uptime as a row like those of the `uptime` collector, with the interval as printed by the server
+    pub fn to_json(&self) -> Value {
+        json!([{"uptime": self.interval, "started_at": self.started_at}])
+    }
+
+    /// Records this uptime in `history` as a sample of the `uptime` collector for the connection `label`, and returns
+    /// whether the server was restarted since the previous sample, if there is one to compare with
+    pub fn record(&self, history: &mut History, label: &str) -> Option<bool> {
+        let now = unix_now();
+        let previous = history.latest(label, "uptime");
+        if let Err(e) = history.append(label, "uptime", &self.to_json()) {
+            error!("could not write history: {}", e);
+        }
+
+        let (taken_at, previous) = previous?;
+        self.restarted_since(&previous, taken_at, now)
+    }
+
+    /// Checks whether the server was restarted after `previous`, an `uptime` sample taken at `taken_at` as recorded in
+    /// the history, given that this uptime was read at `now`. <br>
+    /// The server start time is worked out from each sample's uptime, so this works with any recorded sample of the
+    /// `uptime` collector. Returns `None` when the previous sample has no uptime to compare with.
+    fn restarted_since(&self, previous: &Value, taken_at: u64, now: u64) -> Option<bool> {
+        let previous_uptime = parse_interval(previous[0]["uptime"].as_str()?)? as u64;
+        let previous_start = taken_at.saturating_sub(previous_uptime);
+        let start = now.saturating_sub(self.uptime.as_secs());
+        Some(start > previous_start + RESTART_TOLERANCE)
+    }
+}
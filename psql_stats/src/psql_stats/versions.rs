@@ -0,0 +1,65 @@
+use postgres::Client;
+use tracing::warn;
+
+use super::{Connection, PGError};
+
+/// Oldest server the statistics are read from, as a `server_version_num`. Older servers are still connected to, with
+/// a warning, as collectors reading catalogs they lack fail on them
+pub const OLDEST_SUPPORTED_VERSION: i32 = 110000;
+
+/// A form of a query for servers older than `before`, a `server_version_num`, for catalogs that gained or renamed
+/// columns, or replaced a view, in that version
+pub struct QueryVariant {
+    pub before: i32,
+    pub query: &'static str,
+}
+
+/// Picks the form of a query to run on a server of `version`: the first of `variants`, oldest first, meant for
+/// servers before a version newer than it, or `query`, the form for the newest servers, when there is none. <br>
+/// An unknown `version`, 0, gets `query`, so a failed probe errors on the catalogs rather than guessing.
+pub(crate) fn pick(version: i32, query: &'static str, variants: &[QueryVariant]) -> &'static str {
+    match variants.iter().find(|v| version > 0 && version < v.before) {
+        Some(variant) => variant.query,
+        None => query,
+    }
+}
+
+/// The major version of a `server_version_num` as people write it, e.g. 16 for 160004, or 9.6 for 90624
+pub fn major_version(version: i32) -> String {
+    match version >= 100000 {
+        true => (version / 10000).to_string(),
+        false => format!("{}.{}", version / 10000, version / 100 % 100),
+    }
+}
+
+/// Reads the `server_version_num` of the server `client` is connected to, warning when it is older than
+/// `OLDEST_SUPPORTED_VERSION`. If it cannot be read the version is left unknown, 0.
+pub(crate) fn probe(client: &mut Client) -> i32 {
+    let version = match client.query_one("SELECT current_setting('server_version_num')::int", &[]) {
+        Ok(row) => row.try_get(0).unwrap_or(0),
+        Err(_) => return 0,
+    };
+    if version > 0 && version < OLDEST_SUPPORTED_VERSION {
+        warn!(
+            "Postgres {} is older than {}, the oldest version supported, some statistics may fail",
+            major_version(version),
+            major_version(OLDEST_SUPPORTED_VERSION)
+        );
+    }
+    version
+}
+
+impl Connection {
+    /// Returns `VersionRequired` when the server is older than `since`, a `server_version_num`, so what needs a newer
+    /// catalog is refused with the version it needs instead of failing on a view that does not exist. Passes when
+    /// the version is unknown.
+    pub fn require_version(&self, since: i32) -> Result<(), PGError> {
+        match self.server_version > 0 && self.server_version < since {
+            true => Err(PGError::VersionRequired {
+                required: major_version(since),
+                running: major_version(self.server_version),
+            }),
+            false => Ok(()),
+        }
+    }
+}
@@ -0,0 +1,125 @@
+use std::thread;
+use std::time::{Duration, Instant};
+
+use serde_json::Value;
+
+use super::checkpoints::CHECKPOINTER_VIEW_VERSION;
+use super::delta::{rates, Delta};
+use super::versions::{pick, QueryVariant};
+use super::{Connection, PGError};
+
+/// Server version from which `pg_stat_wal` counts the WAL generated
+const STAT_WAL_VERSION: i32 = 140000;
+
+/// Query reading the checkpoint and buffer write counters from `pg_stat_bgwriter`, before Postgres 14 had `pg_stat_wal`:
+/// checkpoints timed and requested, and the buffers written by checkpoints, by the background writer and by backends
+/// themselves, which they only do when the background writer cannot keep up
+const BGWRITER_WAL_STATS_QUERY: &str = r#"
+    SELECT checkpoints_timed,
+           checkpoints_req AS checkpoints_requested,
+           round(100.0 * checkpoints_req / nullif(checkpoints_timed + checkpoints_req, 0), 1) AS requested_percent,
+           buffers_checkpoint,
+           buffers_clean AS buffers_bgwriter,
+           buffers_backend,
+           round(100.0 * buffers_backend / nullif(buffers_checkpoint + buffers_clean + buffers_backend, 0), 1)
+               AS backend_percent,
+           NULL::bigint AS wal_records,
+           NULL::bigint AS wal_fpi,
+           NULL::bigint AS wal_bytes,
+           NULL::bigint AS wal_buffers_full,
+           date_trunc('second', stats_reset) AS stats_reset
+    FROM pg_stat_bgwriter
+"#;
+
+/// Same as `BGWRITER_WAL_STATS_QUERY` from Postgres 14 on, with the WAL records, full page images and bytes generated
+/// and how often the WAL buffers were full, from `pg_stat_wal`
+const STAT_WAL_STATS_QUERY: &str = r#"
+    SELECT b.checkpoints_timed,
+           b.checkpoints_req AS checkpoints_requested,
+           round(100.0 * b.checkpoints_req / nullif(b.checkpoints_timed + b.checkpoints_req, 0), 1) AS requested_percent,
+           b.buffers_checkpoint,
+           b.buffers_clean AS buffers_bgwriter,
+           b.buffers_backend,
+           round(100.0 * b.buffers_backend / nullif(b.buffers_checkpoint + b.buffers_clean + b.buffers_backend, 0), 1)
+               AS backend_percent,
+           w.wal_records,
+           w.wal_fpi,
+           w.wal_bytes::bigint,
+           w.wal_buffers_full,
+           date_trunc('second', least(b.stats_reset, w.stats_reset)) AS stats_reset
+    FROM pg_stat_bgwriter b, pg_stat_wal w
+"#;
+
+/// Same as `STAT_WAL_STATS_QUERY` from Postgres 17 on, where the checkpoint counters moved to `pg_stat_checkpointer`
+/// and the buffers written by backends are counted in `pg_stat_io`
+const CHECKPOINTER_WAL_STATS_QUERY: &str = r#"
+    WITH backend AS (
+        SELECT coalesce(sum(writes), 0)::bigint AS writes
+        FROM pg_stat_io
+        WHERE backend_type = 'client backend'
+    )
+    SELECT c.num_timed AS checkpoints_timed,
+           c.num_requested AS checkpoints_requested,
+           round(100.0 * c.num_requested / nullif(c.num_timed + c.num_requested, 0), 1) AS requested_percent,
+           c.buffers_written AS buffers_checkpoint,
+           b.buffers_clean AS buffers_bgwriter,
+           backend.writes AS buffers_backend,
+           round(100.0 * backend.writes / nullif(c.buffers_written + b.buffers_clean + backend.writes, 0), 1)
+               AS backend_percent,
+           w.wal_records,
+           w.wal_fpi,
+           w.wal_bytes::bigint,
+           w.wal_buffers_full,
+           date_trunc('second', least(c.stats_reset, b.stats_reset, w.stats_reset)) AS stats_reset
+    FROM pg_stat_checkpointer c, pg_stat_bgwriter b, pg_stat_wal w, backend
+"#;
+
+/// The forms of `CHECKPOINTER_WAL_STATS_QUERY` for the servers before Postgres 17, see `versions::pick`
+const WAL_STATS_VARIANTS: &[QueryVariant] = &[
+    QueryVariant {
+        before: STAT_WAL_VERSION,
+        query: BGWRITER_WAL_STATS_QUERY,
+    },
+    QueryVariant {
+        before: CHECKPOINTER_VIEW_VERSION,
+        query: STAT_WAL_STATS_QUERY,
+    },
+];
+
+/// How `wal` turns two samples of `Connection::get_wal_stats` into per-second rates
+const WAL_DELTA: Delta = Delta {
+    keys: &[],
+    counters: &[
+        "checkpoints_timed",
+        "checkpoints_requested",
+        "buffers_checkpoint",
+        "buffers_bgwriter",
+        "buffers_backend",
+        "wal_records",
+        "wal_fpi",
+        "wal_bytes",
+        "wal_buffers_full",
+    ],
+    sort_by: "wal_bytes",
+};
+
+impl Connection {
+    /// Reads the WAL and checkpoint counters since the statistics were reset: checkpoints requested against timed,
+    /// buffers written by checkpoints, the background writer and backends, and on Postgres 14 and later the WAL
+    /// records, full page images and bytes generated. The views they come from depend on the server version, see
+    /// `BGWRITER_WAL_STATS_QUERY`, `STAT_WAL_STATS_QUERY` and `CHECKPOINTER_WAL_STATS_QUERY`.
+    pub fn get_wal_stats(&mut self) -> Result<Value, PGError> {
+        let query = pick(self.server_version_num()?, CHECKPOINTER_WAL_STATS_QUERY, WAL_STATS_VARIANTS);
+        self.query_json(query)
+    }
+
+    /// Samples `get_wal_stats` twice, `seconds` apart, and returns how fast every counter grew in between, e.g. the
+    /// WAL bytes generated per second, see `delta::rates`
+    pub fn get_wal_rates(&mut self, seconds: u64) -> Result<Value, PGError> {
+        let first = self.get_wal_stats()?;
+        let started = Instant::now();
+        thread::sleep(Duration::from_secs(seconds));
+        let last = self.get_wal_stats()?;
+        Ok(rates(&first, &last, &WAL_DELTA, started.elapsed().as_secs_f64()))
+    }
+}
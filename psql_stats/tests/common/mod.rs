@@ -0,0 +1,133 @@
+//! The server the integration tests run against, and the databases of their own they create on it. <br>
+//! The server is the one `PSQL_STATS_TEST_URL` points at when it is set, e.g.
+//! `postgresql://postgres@localhost/postgres?host=/tmp`, otherwise a disposable one started in a container with
+//! testcontainers, which needs Docker. Every test creates a database of its own on it, seeded with the same tables,
+//! and drops it afterwards. When neither can be had the tests needing a server fail, unless
+//! `PSQL_STATS_SKIP_SERVER_TESTS` is set, when they are skipped with a note.
+
+use std::env;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::OnceLock;
+
+use psql_stats::Connection;
+use testcontainers_modules::postgres::Postgres;
+use testcontainers_modules::testcontainers::runners::SyncRunner;
+use testcontainers_modules::testcontainers::Container;
+
+/// Tables every test database starts with: two in `public`, which is in the default `search_path`, and one in a
+/// schema that is not
+const SEED: &str = r#"
+    CREATE TABLE public.customers (id bigint PRIMARY KEY, name text NOT NULL);
+    COMMENT ON TABLE public.customers IS 'People who ordered at least once';
+    CREATE TABLE public.products (id bigint PRIMARY KEY, price numeric NOT NULL);
+    CREATE SCHEMA sales;
+    CREATE TABLE sales.orders (id bigint PRIMARY KEY, customer_id bigint REFERENCES public.customers (id));
+    INSERT INTO public.customers VALUES (1, 'Ada'), (2, 'Grace');
+"#;
+
+/// The server the tests run against, and the container running it unless it was given with `PSQL_STATS_TEST_URL`
+pub struct Server {
+    pub uri: String,
+    _container: Option<Container<Postgres>>,
+}
+
+/// The server, started on first use and shared by every test, or `None` when there is none to run against and
+/// `PSQL_STATS_SKIP_SERVER_TESTS` asks to skip the tests needing one. Panics when there is none otherwise, so a run
+/// that tested nothing against Postgres is not reported as passing.
+pub fn server() -> Option<&'static Server> {
+    static SERVER: OnceLock<Option<Server>> = OnceLock::new();
+    let server = SERVER
+        .get_or_init(|| {
+            if let Ok(uri) = env::var("PSQL_STATS_TEST_URL") {
+                return Some(Server { uri, _container: None });
+            }
+            match Postgres::default().start() {
+                Ok(container) => {
+                    let host = container.get_host().ok()?;
+                    let port = container.get_host_port_ipv4(5432).ok()?;
+                    Some(Server {
+                        uri: format!("postgresql://postgres:postgres@{}:{}/postgres", host, port),
+                        _container: Some(container),
+                    })
+                }
+                Err(e) => {
+                    eprintln!("No server to test against, set PSQL_STATS_TEST_URL or start Docker: {}", e);
+                    None
+                }
+            }
+        })
+        .as_ref();
+    match server {
+        Some(server) => Some(server),
+        None if skip_server_tests() => {
+            eprintln!("Skipped, PSQL_STATS_SKIP_SERVER_TESTS is set");
+            None
+        }
+        None => panic!(
+            "No server to test against, set PSQL_STATS_TEST_URL or start Docker, or PSQL_STATS_SKIP_SERVER_TESTS to skip"
+        ),
+    }
+}
+
+/// Whether `PSQL_STATS_SKIP_SERVER_TESTS` is set to anything but an empty string
+fn skip_server_tests() -> bool {
+    env::var_os("PSQL_STATS_SKIP_SERVER_TESTS").is_some_and(|v| !v.is_empty())
+}
+
+/// A database of its own for a test, seeded with `SEED` and dropped when the test ends
+pub struct TestDatabase {
+    admin: Connection,
+    name: String,
+}
+
+impl TestDatabase {
+    /// Creates and seeds a database on the server, or returns `None` when there is no server
+    pub fn create() -> Option<TestDatabase> {
+        static CREATED: AtomicUsize = AtomicUsize::new(0);
+        let server = server()?;
+        let name = format!("psql_stats_test_{}_{}", std::process::id(), CREATED.fetch_add(1, Ordering::Relaxed));
+
+        let mut admin = connect_to(&server.uri, None);
+        admin
+            .client
+            .as_mut()
+            .expect("Could not connect to the test server")
+            .batch_execute(&format!("CREATE DATABASE {}", name))
+            .expect("Could not create the test database");
+        let database = TestDatabase { admin, name };
+
+        let mut seeding = database.connect();
+        seeding.client.as_mut().unwrap().batch_execute(SEED).expect("Could not seed the test database");
+        seeding.close();
+        Some(database)
+    }
+
+    /// A new `Connection` to the database, connected
+    pub fn connect(&self) -> Connection {
+        connect_to(&server().unwrap().uri, Some(&self.name))
+    }
+}
+
+impl Drop for TestDatabase {
+    fn drop(&mut self) {
+        if let Some(client) = self.admin.client.as_mut() {
+            // The sessions a failed test left open would keep the database from being dropped. DROP DATABASE cannot
+            // run in the transaction of a statement batch, so it is sent on its own
+            let terminate = "SELECT pg_terminate_backend(pid) FROM pg_stat_activity WHERE datname = $1";
+            let _ = client.execute(terminate, &[&self.name]);
+            let _ = client.batch_execute(&format!("DROP DATABASE IF EXISTS {}", self.name));
+        }
+        self.admin.close();
+    }
+}
+
+/// A `Connection` to the server at `uri`, or to its database `dbname`, connected if it could be
+pub fn connect_to(uri: &str, dbname: Option<&str>) -> Connection {
+    let mut connection = Connection::default();
+    connection.apply_uri(uri).expect("PSQL_STATS_TEST_URL is not a valid connection URI");
+    if let Some(dbname) = dbname {
+        connection.dbname = dbname.to_string();
+    }
+    connection.connect();
+    connection
+}
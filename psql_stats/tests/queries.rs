@@ -0,0 +1,210 @@
+//! Integration tests of the queries of `Connection` against a real server, see `common` for which.
+
+mod common;
+
+use std::env;
+
+use common::{connect_to, server, TestDatabase};
+use psql_stats::{Config, Connection, Menu, MenuOptions, PGError, TableScope, Thresholds, TransactionState};
+
+/// The `table_name` values of the rows `get_tables` returns
+fn table_names(rows: &serde_json::Value) -> Vec<String> {
+    rows.as_array()
+        .expect("Tables are returned as an array")
+        .iter()
+        .map(|r| r["table_name"].as_str().unwrap_or_default().to_string())
+        .collect()
+}
+
+#[test]
+fn version_is_the_servers() {
+    let database = match TestDatabase::create() {
+        Some(database) => database,
+        None => return,
+    };
+    let mut connection = database.connect();
+
+    let row = connection.version().expect("Could not read the version");
+    let version: String = row.get(0);
+    assert!(version.starts_with("PostgreSQL "), "unexpected version {}", version);
+    assert!(connection.server_version >= 90600, "server_version {} was not probed", connection.server_version);
+}
+
+#[test]
+fn sessions_go_to_the_failover_host_connected_to() {
+    let database = match TestDatabase::create() {
+        Some(database) => database,
+        None => return,
+    };
+    let mut connection = database.connect();
+    let host = connection.host.clone();
+
+    // A host nothing listens on ahead of the server's, which connecting passes over
+    connection.host = format!("/nonexistent-psql-stats,{}", host);
+    connection.connect();
+    assert!(connection.client.is_some());
+    assert_eq!(connection.connected_to.as_ref().map(|(h, _)| h.as_str()), Some(host.as_str()));
+
+    // The report's own sessions are opened to that host as well
+    let report = connection.report(false, &Thresholds::default());
+    assert!(report["sections"]["uptime"]["error"].is_null(), "{}", report["sections"]["uptime"]);
+
+    connection.close();
+    assert!(connection.connected_to.is_none());
+}
+
+#[test]
+fn the_menu_runs_lines_until_one_exits() {
+    let database = match TestDatabase::create() {
+        Some(database) => database,
+        None => return,
+    };
+    let options = MenuOptions::default();
+    let mut menu = Menu::new("test", database.connect(), Config::default(), Thresholds::default(), None, options);
+
+    assert!(menu.execute("uptime"));
+    assert!(menu.execute("begin"));
+    assert_eq!(menu.active().transaction, TransactionState::Open);
+    assert!(menu.execute("rollback"));
+    assert_eq!(menu.active().transaction, TransactionState::Idle);
+    assert!(!menu.execute("quit"));
+}
+
+#[test]
+fn uptime_counts_from_the_server_start() {
+    let database = match TestDatabase::create() {
+        Some(database) => database,
+        None => return,
+    };
+    let mut connection = database.connect();
+
+    let uptime = connection.get_uptime().expect("Could not read the uptime");
+    assert!(!uptime.started_at.is_empty());
+    // The server was started for the tests, or at least is not older than a few years
+    assert!(uptime.uptime.as_secs() < 10 * 365 * 24 * 3600, "uptime {:?}", uptime.uptime);
+    let again = connection.get_uptime().unwrap();
+    assert!(again.uptime >= uptime.uptime);
+    assert_eq!(again.started_at, uptime.started_at);
+}
+
+#[test]
+fn tables_of_the_search_path_are_listed_with_their_comment() {
+    let database = match TestDatabase::create() {
+        Some(database) => database,
+        None => return,
+    };
+    let mut connection = database.connect();
+
+    let rows = connection.get_tables(TableScope::SearchPath).expect("Could not list the tables");
+    assert_eq!(table_names(&rows), vec!["customers", "products"]);
+    let customers = &rows[0];
+    assert_eq!(customers["schema"], "public");
+    assert_eq!(customers["comment"], "People who ordered at least once");
+
+    let rows = connection.get_tables(TableScope::Schema("sales")).unwrap();
+    assert_eq!(table_names(&rows), vec!["orders"]);
+
+    connection.set_search_path("sales, public").unwrap();
+    let rows = connection.get_tables(TableScope::SearchPath).unwrap();
+    assert!(table_names(&rows).contains(&"orders".to_string()));
+}
+
+#[test]
+fn schema_is_put_first_in_the_search_path() {
+    let database = match TestDatabase::create() {
+        Some(database) => database,
+        None => return,
+    };
+    let mut connection = database.connect();
+    connection.schema = "sales".to_string();
+    connection.close();
+    connection.connect();
+
+    assert!(connection.search_path.starts_with("sales, "), "search_path {}", connection.search_path);
+    let rows = connection.query_json("SELECT count(*) AS orders FROM orders").unwrap();
+    assert_eq!(rows[0]["orders"], 0);
+}
+
+#[test]
+fn missing_relations_are_reported_as_such() {
+    let database = match TestDatabase::create() {
+        Some(database) => database,
+        None => return,
+    };
+    let mut connection = database.connect();
+
+    match connection.query_json("SELECT * FROM no_such_table") {
+        Err(PGError::MissingObject { .. }) => {}
+        other => panic!("expected MissingObject, got {:?}", other.map(|_| ())),
+    }
+    // The session is still usable after the error
+    let rows = connection.query_json("SELECT name FROM customers ORDER BY id").unwrap();
+    assert_eq!(rows[1]["name"], "Grace");
+}
+
+#[test]
+fn a_database_that_does_not_exist_leaves_the_connection_unconnected() {
+    let server = match server() {
+        Some(server) => server,
+        None => return,
+    };
+    let mut connection = connect_to(&server.uri, Some("psql_stats_no_such_database"));
+
+    assert!(connection.client.is_none());
+    assert!(matches!(connection.version(), Err(PGError::ClientEmpty)));
+}
+
+#[test]
+fn queries_without_a_connection_fail_with_client_empty() {
+    let mut connection = Connection::default();
+
+    assert!(matches!(connection.version(), Err(PGError::ClientEmpty)));
+    assert!(matches!(connection.get_uptime(), Err(PGError::ClientEmpty)));
+    assert!(matches!(connection.get_tables(TableScope::SearchPath), Err(PGError::ClientEmpty)));
+    assert!(matches!(connection.query_json("SELECT 1"), Err(PGError::ClientEmpty)));
+    assert!(matches!(connection.set_search_path("public"), Err(PGError::ClientEmpty)));
+}
+
+#[test]
+fn profiles_round_trip_through_the_connections_file() {
+    // A connections file of its own, kept away from the user's, without changing the environment or the working
+    // directory other tests run with
+    let dir = env::temp_dir().join(format!("psql_stats-profiles-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    let file = dir.join("connections.toml");
+
+    let mut saved = Connection {
+        host: "db.internal".to_string(),
+        port: "5433".to_string(),
+        user: "reporting".to_string(),
+        dbname: "shop".to_string(),
+        password: "secret".to_string(),
+        sslmode: "verify-full".to_string(),
+        sslrootcert: "/etc/ssl/root.crt".to_string(),
+        target_session_attrs: "read-only".to_string(),
+        ssh_host: "bastion:2222".to_string(),
+        collectors: vec!["replication".to_string(), "activity".to_string()],
+        ..Default::default()
+    };
+    assert!(saved.write_to_file(&file, "staging".to_string(), false).unwrap());
+    assert!(matches!(saved.write_to_file(&file, "staging".to_string(), false), Err(PGError::DuplicateConnection)));
+
+    let loaded = Connection::read_from_file(&file, "staging", String::new(), false).unwrap();
+    assert_eq!(loaded.host, saved.host);
+    assert_eq!(loaded.port, saved.port);
+    assert_eq!(loaded.user, saved.user);
+    assert_eq!(loaded.dbname, saved.dbname);
+    assert_eq!(loaded.sslmode, saved.sslmode);
+    assert_eq!(loaded.sslrootcert, saved.sslrootcert);
+    assert_eq!(loaded.target_session_attrs, saved.target_session_attrs);
+    assert_eq!(loaded.ssh_host, saved.ssh_host);
+    assert_eq!(loaded.collectors, saved.collectors);
+    // Passwords are never written to the file
+    assert_eq!(loaded.password, "");
+    assert!(loaded.client.is_none());
+
+    let missing = Connection::read_from_file(&file, "production", String::new(), false);
+    assert!(matches!(missing, Err(PGError::MatchNotFound)));
+    std::fs::remove_dir_all(dir).unwrap();
+}
@@ -0,0 +1,51 @@
+//! Integration tests of read-only mode against a real server, see `common` for which. <br>
+//! Read-only mode is chosen once for the whole process, so these tests have a binary of their own, and a single test,
+//! as the database is created and seeded before it is turned on.
+
+mod common;
+
+use common::TestDatabase;
+use psql_stats::{set_read_only, PGError, TransactionState};
+
+#[test]
+fn custom_queries_cannot_write_in_read_only_mode() {
+    let database = match TestDatabase::create() {
+        Some(database) => database,
+        None => return,
+    };
+    set_read_only(true);
+    let mut connection = database.connect();
+    let insert = "INSERT INTO customers VALUES (3, 'Linus')";
+
+    assert!(connection.custom_query(insert, &[], 0).is_err());
+
+    // Transactions cannot be started, nor the session set, to write
+    for query in [
+        "BEGIN READ WRITE",
+        "START TRANSACTION ISOLATION LEVEL SERIALIZABLE, READ WRITE",
+        "SET default_transaction_read_only = off",
+        "RESET transaction_read_only",
+        "RESET ALL",
+    ] {
+        match connection.custom_query(query, &[], 0) {
+            Err(PGError::ReadOnly) => {}
+            other => panic!("expected ReadOnly for {}, got {:?}", query, other.map(|_| ())),
+        }
+        assert_eq!(connection.transaction, TransactionState::Idle, "{} opened a transaction", query);
+        assert!(connection.custom_query(insert, &[], 0).is_err(), "wrote after {}", query);
+    }
+
+    // A transaction started by a statement is read only, as is one opened with `begin`
+    connection.custom_query("BEGIN", &[], 0).unwrap();
+    assert_eq!(connection.transaction, TransactionState::Open);
+    assert!(connection.custom_query(insert, &[], 0).is_err());
+    assert!(matches!(connection.custom_query("SET TRANSACTION READ WRITE", &[], 0), Err(PGError::ReadOnly)));
+    connection.custom_query("ROLLBACK", &[], 0).unwrap();
+
+    connection.begin().unwrap();
+    assert!(connection.custom_query(insert, &[], 0).is_err());
+    connection.rollback().unwrap();
+
+    let rows = connection.query_json("SELECT count(*) AS customers FROM customers").unwrap();
+    assert_eq!(rows[0]["customers"], 2);
+}